@@ -0,0 +1,100 @@
+//! Detection and snapshot throughput against a synthetic fleet of cgminer APIs on loopback
+//!
+//! Each fake miner is a listener answering the commands GenericCgminer sends, so the numbers
+//! cover the Client internals (connect, limiter, framing, parsing) without any real hardware.
+//! `cargo bench --bench polling`, FLEET_SIZE changes the number of fake miners (default 1000)
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::future::join_all;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+use libminer::{Client, ClientBuilder, MinerSnapshot, Vendor};
+
+const STATS: &str = include_str!("../fixtures/detection/cgminer/gekkoscience_cgminer_412.socket.json");
+const SUMMARY: &str = r#"{"STATUS":[{"STATUS":"S","When":1691234567,"Code":11,"Msg":"Summary","Description":"cgminer 4.12.0"}],"SUMMARY":[{"Elapsed":86400,"MHS av":412345.67,"MHS 5s":409876.54,"Accepted":1024,"Rejected":3}],"id":1}"#;
+const DEVS: &str = r#"{"STATUS":[{"STATUS":"S","When":1691234567,"Code":9,"Msg":"1 ASC(s)","Description":"cgminer 4.12.0"}],"DEVS":[{"ASC":0,"Name":"GSF","Temperature":52.5,"MHS 5s":409876.54}],"id":1}"#;
+const VERSION: &str = r#"{"STATUS":[{"STATUS":"S","When":1691234567,"Code":22,"Msg":"CGMiner versions","Description":"cgminer 4.12.0"}],"VERSION":[{"CGMiner":"4.12.0","API":"3.7","Type":"GekkoScience Compac F"}],"id":1}"#;
+const POOLS: &str = r#"{"STATUS":[{"STATUS":"S","When":1691234567,"Code":7,"Msg":"1 Pool(s)","Description":"cgminer 4.12.0"}],"POOLS":[{"POOL":0,"URL":"stratum+tcp://btc.pool.example:3333","User":"bench.1","Accepted":1024,"Rejected":3,"Stale":0}],"id":1}"#;
+
+/// Answer one request per connection and close it, as cgminer does
+async fn serve(listener: TcpListener) {
+    loop {
+        let (mut stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(_) => continue,
+        };
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let resp = match ["\"stats\"", "\"summary\"", "\"devs\"", "\"version\"", "\"pools\""].iter().position(|c| request.contains(c)) {
+                Some(0) => STATS,
+                Some(1) => SUMMARY,
+                Some(2) => DEVS,
+                Some(3) => VERSION,
+                Some(4) => POOLS,
+                _ => r#"{"STATUS":[{"STATUS":"E","Code":14,"Msg":"Invalid command"}]}"#,
+            };
+            let _ = stream.write_all(resp.as_bytes()).await;
+        });
+    }
+}
+
+/// Start the fake fleet, returning each miner's port on 127.0.0.1
+async fn fleet(size: usize) -> Vec<u16> {
+    let mut ports = Vec::with_capacity(size);
+    for _ in 0..size {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind fake miner");
+        ports.push(listener.local_addr().expect("fake miner address").port());
+        tokio::spawn(serve(listener));
+    }
+    ports
+}
+
+fn client(max_connections: usize) -> Client {
+    ClientBuilder::new()
+        .connect_timeout(Duration::from_secs(5))
+        .request_timeout(Duration::from_secs(5))
+        .max_connections(max_connections)
+        .enabled_vendors(&[Vendor::Cgminer])
+        .build()
+        .expect("client")
+}
+
+fn polling(c: &mut Criterion) {
+    let size = std::env::var("FLEET_SIZE").ok().and_then(|s| s.parse().ok()).unwrap_or(1000);
+    let rt = Runtime::new().expect("runtime");
+    let ports = rt.block_on(fleet(size));
+
+    let mut group = c.benchmark_group("fleet");
+    group.throughput(Throughput::Elements(size as u64));
+    group.sample_size(10);
+    // 0 is no limit
+    for max_connections in [0, 64, 256] {
+        let client = client(max_connections);
+
+        group.bench_with_input(BenchmarkId::new("detect", max_connections), &ports, |b, ports| {
+            b.to_async(&rt).iter(|| join_all(ports.iter().map(|port| client.get_miner("127.0.0.1", Some(*port)))));
+        });
+
+        group.bench_with_input(BenchmarkId::new("snapshot", max_connections), &ports, |b, ports| {
+            b.to_async(&rt).iter(|| join_all(ports.iter().map(|port| {
+                let client = client.clone();
+                async move {
+                    match client.get_miner("127.0.0.1", Some(*port)).await {
+                        Ok(miner) => Some(MinerSnapshot::take(&*miner).await),
+                        Err(_) => None,
+                    }
+                }
+            })));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, polling);
+criterion_main!(benches);
@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use std::net::IpAddr;
+
+use crate::error::Error;
+use crate::miner::{Miner, Pool, Profile, FanMode};
+
+/// A miner's configuration, to put back after a firmware flash or clone onto a replacement control board
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinerConfigBackup {
+    /// get_type of the miner the backup was taken from
+    pub vendor: String,
+    pub model: Option<String>,
+    pub pools: Vec<Pool>,
+    pub profile: Option<Profile>,
+    #[serde(default)]
+    pub fan_mode: Option<FanMode>,
+    pub dns: Option<String>,
+    /// Vendor specific settings blob (Vnish settings JSON, Antminer miner_conf and network info)
+    /// Only restored onto the same vendor
+    pub settings: Option<serde_json::Value>,
+    pub taken_at: DateTime<Utc>,
+}
+
+impl MinerConfigBackup {
+    /// The parts of a backup every vendor can provide through the Miner trait
+    pub async fn capture<M: Miner + Sync + ?Sized>(miner: &M) -> Result<Self, Error> {
+        Ok(MinerConfigBackup {
+            vendor: miner.get_type().to_string(),
+            model: miner.get_model().await.ok(),
+            pools: miner.get_pools().await?,
+            profile: miner.get_profile().await.ok(),
+            fan_mode: miner.get_fan_mode().await.ok(),
+            dns: miner.get_dns().await.ok(),
+            settings: None,
+            taken_at: Utc::now(),
+        })
+    }
+
+    /// Vendor settings, if this backup came from the given vendor
+    pub fn settings_for(&self, vendor: &str) -> Option<&serde_json::Value> {
+        if self.vendor == vendor {
+            self.settings.as_ref()
+        } else {
+            None
+        }
+    }
+
+    /// The backup's pools, with passwords the vendor didn't expose taken from known pools
+    /// with the same url and user, e.g. those in the vendor settings blob
+    pub fn pools_with_passwords(&self, known: &[Pool]) -> Vec<Pool> {
+        self.pools.iter().map(|pool| {
+            let mut pool = pool.clone();
+            if pool.password.is_none() {
+                pool.password = known.iter()
+                    .find(|k| k.url == pool.url && k.username == pool.username)
+                    .and_then(|k| k.password.clone());
+            }
+            pool
+        }).collect()
+    }
+
+    /// DNS servers from the backup, unparseable entries are dropped
+    pub fn dns_servers(&self) -> Vec<IpAddr> {
+        self.dns.as_deref().unwrap_or_default()
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|s| s.parse().ok())
+            .collect()
+    }
+
+    /// Restore pools, profile, fan mode and DNS through the Miner trait
+    /// Whatever the target doesn't support is skipped rather than failing the restore, DNS goes
+    /// last as the miner may restart its network to apply it
+    pub async fn apply<M: Miner + Send + ?Sized>(&self, miner: &mut M) -> Result<(), Error> {
+        miner.set_pools(self.pools.clone()).await?;
+        if let Some(profile) = &self.profile {
+            match miner.set_profile(profile.clone()).await {
+                Ok(()) | Err(Error::NotSupported) | Err(Error::InvalidProfile(_)) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        if let Some(mode) = self.fan_mode {
+            match miner.set_fan_mode(mode).await {
+                Ok(()) | Err(Error::NotSupported) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        let dns = self.dns_servers();
+        if !dns.is_empty() {
+            match miner.set_dns(dns).await {
+                Ok(()) | Err(Error::NotSupported) | Err(Error::InvalidConfig(_)) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let backup = MinerConfigBackup {
+            vendor: "Antminer".to_string(),
+            model: Some("s19jpro".to_string()),
+            pools: vec![Pool {
+                url: "stratum+tcp://pool.example.com:3333".to_string(),
+                username: "worker.1".to_string(),
+                password: Some("x".into()),
+            }],
+            profile: Some(Profile::Default),
+            fan_mode: Some(FanMode::Manual(60)),
+            dns: Some("1.1.1.1".to_string()),
+            settings: Some(serde_json::json!({"miner_conf": {"bitmain-fan-ctrl": false}})),
+            taken_at: Utc::now(),
+        };
+        let json = serde_json::to_string(&backup).unwrap();
+        let restored: MinerConfigBackup = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.pools[0].password.as_ref().map(|p| p.expose()), Some("x"));
+        assert_eq!(restored.profile, Some(Profile::Default));
+        assert!(restored.settings_for("Antminer").is_some());
+        assert!(restored.settings_for("Vnish").is_none());
+        assert_eq!(restored.fan_mode, Some(FanMode::Manual(60)));
+        assert_eq!(restored.dns_servers(), vec!["1.1.1.1".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn test_pools_with_passwords() {
+        let pool = |user: &str, pass: Option<&str>| Pool {
+            url: "stratum+tcp://pool.example.com:3333".to_string(),
+            username: user.to_string(),
+            password: pass.map(Into::into),
+        };
+        let backup = MinerConfigBackup {
+            vendor: "Antminer".to_string(),
+            model: None,
+            pools: vec![pool("worker.1", None), pool("worker.2", Some("y")), pool("worker.3", None)],
+            profile: None,
+            fan_mode: None,
+            dns: Some("1.1.1.1, 8.8.8.8".to_string()),
+            settings: None,
+            taken_at: Utc::now(),
+        };
+        let pools = backup.pools_with_passwords(&[pool("worker.1", Some("x")), pool("worker.2", Some("z"))]);
+        let passwords: Vec<_> = pools.iter().map(|p| p.password.as_ref().map(|p| p.expose())).collect();
+        assert_eq!(passwords, vec![Some("x"), Some("y"), None]);
+        assert_eq!(backup.dns_servers().len(), 2);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::fmt;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::miner::Miner;
+use crate::util::secret::SecretString;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Credentials {
+    pub username: String,
+    pub password: SecretString,
+}
+
+impl Credentials {
+    pub fn new(username: &str, password: &str) -> Self {
+        Credentials {
+            username: username.to_string(),
+            password: password.into(),
+        }
+    }
+}
+
+/// A MAC as lowercase colon separated pairs, whatever separators it was written with
+/// e.g. "AA-BB-CC-DD-EE-FF" and "aabb.ccdd.eeff" both become "aa:bb:cc:dd:ee:ff"
+pub fn normalize_mac(mac: &str) -> String {
+    let digits: Vec<char> = mac.chars().filter(|c| c.is_ascii_hexdigit()).map(|c| c.to_ascii_lowercase()).collect();
+    digits.chunks(2).map(|pair| pair.iter().collect::<String>()).collect::<Vec<_>>().join(":")
+}
+
+/// What a provider can key credentials on
+#[derive(Debug, Clone, Copy)]
+pub struct CredentialQuery<'a> {
+    pub ip: &'a str,
+    /// None if it isn't known without authenticating first
+    pub mac: Option<&'a str>,
+    /// get_type of the miner, e.g. "Antminer"
+    pub vendor: &'a str,
+}
+
+/// Source of credentials fetched on demand, e.g. from Vault, so they don't have to be threaded
+/// through application code
+#[async_trait]
+pub trait CredentialProvider: fmt::Debug + Send + Sync {
+    /// None if this provider has nothing for the miner
+    async fn lookup(&self, query: &CredentialQuery<'_>) -> Result<Option<Credentials>, Error>;
+}
+
+/// Fixed credentials, by IP, then MAC, then vendor, then a default
+#[derive(Debug, Clone, Default)]
+pub struct StaticCredentials {
+    by_ip: HashMap<String, Credentials>,
+    by_mac: HashMap<String, Credentials>,
+    by_vendor: HashMap<String, Credentials>,
+    default: Option<Credentials>,
+}
+
+impl StaticCredentials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ip(mut self, ip: &str, credentials: Credentials) -> Self {
+        self.by_ip.insert(ip.to_string(), credentials);
+        self
+    }
+
+    pub fn mac(mut self, mac: &str, credentials: Credentials) -> Self {
+        self.by_mac.insert(normalize_mac(mac), credentials);
+        self
+    }
+
+    /// Vendor names match case insensitively
+    pub fn vendor(mut self, vendor: &str, credentials: Credentials) -> Self {
+        self.by_vendor.insert(vendor.to_lowercase(), credentials);
+        self
+    }
+
+    pub fn default_credentials(mut self, credentials: Credentials) -> Self {
+        self.default = Some(credentials);
+        self
+    }
+
+    fn find(&self, query: &CredentialQuery<'_>) -> Option<&Credentials> {
+        self.by_ip.get(query.ip)
+            .or_else(|| query.mac.and_then(|mac| self.by_mac.get(&normalize_mac(mac))))
+            .or_else(|| self.by_vendor.get(&query.vendor.to_lowercase()))
+            .or(self.default.as_ref())
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for StaticCredentials {
+    async fn lookup(&self, query: &CredentialQuery<'_>) -> Result<Option<Credentials>, Error> {
+        Ok(self.find(query).cloned())
+    }
+}
+
+/// Credentials from environment variables, read at lookup time
+/// `{PREFIX}_{VENDOR}_USERNAME` and `_PASSWORD`, falling back to `{PREFIX}_USERNAME` and `_PASSWORD`
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    prefix: String,
+}
+
+impl EnvCredentials {
+    /// e.g. "LIBMINER" reads LIBMINER_ANTMINER_PASSWORD
+    pub fn new(prefix: &str) -> Self {
+        EnvCredentials { prefix: prefix.to_uppercase() }
+    }
+
+    fn read(&self, scope: &str) -> Option<Credentials> {
+        let var = |name: &str| std::env::var(format!("{}{}_{}", self.prefix, scope, name)).ok();
+        Some(Credentials {
+            username: var("USERNAME")?,
+            password: var("PASSWORD")?.into(),
+        })
+    }
+}
+
+#[async_trait]
+impl CredentialProvider for EnvCredentials {
+    async fn lookup(&self, query: &CredentialQuery<'_>) -> Result<Option<Credentials>, Error> {
+        Ok(self.read(&format!("_{}", query.vendor.to_uppercase())).or_else(|| self.read("")))
+    }
+}
+
+/// Look up credentials for a miner and authenticate with them
+/// The MAC is whatever the caller already knows, e.g. from the detection cache, asking the miner
+/// would itself need credentials on most firmware
+/// Returns false if the provider had nothing for it
+pub async fn authenticate(miner: &mut (dyn Miner + Send + Sync), provider: &dyn CredentialProvider, mac: Option<&str>) -> Result<bool, Error> {
+    let ip = miner.get_ip().to_string();
+    let query = CredentialQuery {
+        ip: &ip,
+        mac,
+        vendor: miner.get_type(),
+    };
+    match provider.lookup(&query).await? {
+        Some(credentials) => {
+            miner.auth(&credentials.username, credentials.password.expose()).await?;
+            Ok(true)
+        },
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_precedence() {
+        let provider = StaticCredentials::new()
+            .default_credentials(Credentials::new("admin", "admin"))
+            .vendor("Antminer", Credentials::new("root", "root"))
+            .mac("AA:BB:CC:DD:EE:FF", Credentials::new("root", "mac"))
+            .ip("10.0.0.5", Credentials::new("root", "ip"));
+        let query = |ip, mac, vendor| CredentialQuery { ip, mac, vendor };
+
+        assert_eq!(provider.find(&query("10.0.0.5", Some("aa:bb:cc:dd:ee:ff"), "Antminer")).unwrap().password.expose(), "ip");
+        assert_eq!(provider.find(&query("10.0.0.6", Some("aa:bb:cc:dd:ee:ff"), "Antminer")).unwrap().password.expose(), "mac");
+        assert_eq!(provider.find(&query("10.0.0.6", Some("AA-BB-CC-DD-EE-FF"), "Antminer")).unwrap().password.expose(), "mac");
+        assert_eq!(provider.find(&query("10.0.0.6", Some("aabb.ccdd.eeff"), "Antminer")).unwrap().password.expose(), "mac");
+        assert_eq!(provider.find(&query("10.0.0.6", None, "antminer")).unwrap().password.expose(), "root");
+        assert_eq!(provider.find(&query("10.0.0.6", None, "Whatsminer")).unwrap().username, "admin");
+        assert!(StaticCredentials::new().find(&query("10.0.0.6", None, "Whatsminer")).is_none());
+    }
+}
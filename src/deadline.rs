@@ -0,0 +1,124 @@
+use std::future::Future;
+use std::net::IpAddr;
+use chrono::FixedOffset;
+use async_trait::async_trait;
+use tokio::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::miner::{Miner, MinerError, Pool, Profile, PowerState, HealthCondition, Capabilities, PendingChange, TuningStatus, PoolCounters, LogOptions, LogEntry, SystemStats, NetworkStats, CoolingDetail, FanMode, ChipCount, BoardTemps, TempReport, Resource, Tags};
+use crate::security::SecurityIndicator;
+use crate::backup::MinerConfigBackup;
+use crate::detection::DetectionInfo;
+
+/// A point every call in a composite operation has to finish by
+/// Each sub-request otherwise gets a full request_timeout, so a dead miner costs a multiple of it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn after(budget: Duration) -> Self {
+        Deadline(Instant::now() + budget)
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Timeout if the call is still running at the deadline
+    pub async fn run<T, F>(self, call: F) -> Result<T, Error>
+        where F: Future<Output = Result<T, Error>>
+    {
+        match tokio::time::timeout_at(self.0, call).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+}
+
+/// A miner whose reads all give up at one deadline, for handing to code that makes several calls
+/// Writes run to completion, a setter cut off part way could leave the miner half configured
+pub struct DeadlineMiner {
+    miner: Box<dyn Miner + Send + Sync>,
+    deadline: Deadline,
+}
+
+impl DeadlineMiner {
+    pub fn new(miner: Box<dyn Miner + Send + Sync>, deadline: Deadline) -> DeadlineMiner {
+        DeadlineMiner { miner, deadline }
+    }
+
+    pub fn inner(&self) -> &(dyn Miner + Send + Sync) {
+        &*self.miner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut (dyn Miner + Send + Sync) {
+        &mut *self.miner
+    }
+
+    pub fn into_inner(self) -> Box<dyn Miner + Send + Sync> {
+        self.miner
+    }
+
+    pub fn deadline(&self) -> Deadline {
+        self.deadline
+    }
+
+    /// Start a new budget, e.g. for the next poll
+    pub fn reset(&mut self, deadline: Deadline) {
+        self.deadline = deadline;
+    }
+}
+
+delegate_miner!(DeadlineMiner, deadline);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let deadline = Deadline::after(Duration::from_secs(60));
+        assert!(!deadline.expired());
+        assert!(deadline.remaining() <= Duration::from_secs(60));
+        assert!(Deadline::after(Duration::ZERO).expired());
+    }
+
+    /// Takes five seconds to answer anything
+    struct SlowMiner;
+
+    #[async_trait]
+    impl Miner for SlowMiner {
+        fn new(_client: crate::Client, _ip: String, _port: u16) -> Self { SlowMiner }
+        fn get_type(&self) -> &'static str { "Slow" }
+        fn get_ip(&self) -> &str { "10.0.0.1" }
+        async fn get_model(&self) -> Result<String, Error> { unimplemented!() }
+        async fn auth(&mut self, _username: &str, _password: &str) -> Result<(), Error> { unimplemented!() }
+        async fn reboot(&mut self) -> Result<(), Error> { unimplemented!() }
+        async fn get_hashrate(&self) -> Result<f64, Error> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(100.0)
+        }
+        async fn get_power(&self) -> Result<f64, Error> { unimplemented!() }
+        async fn get_nameplate_rate(&self) -> Result<f64, Error> { unimplemented!() }
+        async fn get_temperature(&self) -> Result<f64, Error> { unimplemented!() }
+        async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> { unimplemented!() }
+        async fn get_pools(&self) -> Result<Vec<Pool>, Error> { unimplemented!() }
+        async fn set_sleep(&mut self, _sleep: bool) -> Result<(), Error> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        }
+        async fn get_mac(&self) -> Result<String, Error> { unimplemented!() }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_only_reads_bounded() {
+        let mut miner = DeadlineMiner::new(Box::new(SlowMiner), Deadline::after(Duration::from_secs(1)));
+        assert!(matches!(miner.get_hashrate().await, Err(Error::Timeout)));
+        // Past the deadline already, the write still runs to the end
+        assert!(miner.set_sleep(true).await.is_ok());
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use lazy_regex::regex;
+use serde::{Serialize, Deserialize};
+
+use crate::error::Error;
+use crate::miners::{cgminer, common};
+
+/// Which probe identified the miner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetectionMethod {
+    Http,
+    Socket,
+    /// Vendor was given by the caller, nothing was probed
+    Explicit,
+}
+
+/// Vendors detection can look for, see ClientBuilder::enabled_vendors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Vendor {
+    Antminer,
+    Avalon,
+    Vnish,
+    /// Both the Minerva and Minera interfaces
+    Minerva,
+    Whatsminer,
+    /// The GenericCgminer fallback for cgminer devices of unknown vendor
+    Cgminer,
+}
+
+/// How sure detection is of the vendor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Confidence {
+    #[default]
+    High,
+    /// Only known to speak a generic API, e.g. a GenericCgminer
+    Low,
+}
+
+/// What detection learnt about a miner, so callers don't re-fetch what was already probed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectionInfo {
+    pub method: DetectionMethod,
+    /// Firmware or interface name, e.g. "Vnish", "Minera" or the BMMiner version
+    pub firmware: Option<String>,
+    /// <title> of the web interface, if it was fetched
+    pub web_title: Option<String>,
+    /// API generation reported by the socket API, e.g. "cgminer 4.11.1"
+    pub api: Option<String>,
+    #[serde(default)]
+    pub confidence: Confidence,
+}
+
+impl DetectionInfo {
+    pub fn new(method: DetectionMethod) -> Self {
+        DetectionInfo {
+            method,
+            firmware: None,
+            web_title: None,
+            api: None,
+            confidence: Confidence::High,
+        }
+    }
+
+    pub fn firmware(mut self, firmware: &str) -> Self {
+        self.firmware = Some(firmware.to_string());
+        self
+    }
+
+    /// Keep the page's title, if it has one
+    pub fn page(mut self, body: &str) -> Self {
+        self.web_title = web_title(body);
+        self
+    }
+
+    pub fn api(mut self, api: Option<String>) -> Self {
+        self.api = api;
+        self
+    }
+
+    pub fn low_confidence(mut self) -> Self {
+        self.confidence = Confidence::Low;
+        self
+    }
+}
+
+/// Contents of the first <title> tag
+pub fn web_title(body: &str) -> Option<String> {
+    let re = regex!(r"(?is)<title>\s*(.*?)\s*</title>");
+    re.captures(body).map(|caps| caps[1].to_string()).filter(|t| !t.is_empty())
+}
+
+/// Request a detection response came back from, each signature only holds for its own probe
+#[cfg_attr(not(all(feature = "http", any(feature = "antminer", feature = "avalon", feature = "minerva", feature = "whatsminer"))), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Probe {
+    /// WWW-Authenticate header of an unauthorized HEAD /
+    Challenge,
+    /// GET http://ip/
+    Index,
+    /// GET https://ip/
+    SecureIndex,
+    /// GET /cgi-bin/luci, only looked at when it's forbidden
+    Luci,
+}
+
+/// Vendor whose signature an HTTP probe response carries
+#[cfg_attr(not(all(feature = "http", any(feature = "antminer", feature = "avalon", feature = "minerva", feature = "whatsminer"))), allow(dead_code))]
+pub(crate) fn http_signature(probe: Probe, body: &str) -> Option<Vendor> {
+    match probe {
+        Probe::Challenge => regex!(r"^[Dd]igest").is_match(body).then_some(Vendor::Antminer),
+        Probe::Index => if regex!(r"miner-dash-app").is_match(body) {
+            Some(Vendor::Vnish)
+        } else if regex!(r"<title>Avalon Device</title>").is_match(body) {
+            Some(Vendor::Avalon)
+        } else {
+            None
+        },
+        // 2 fan minervas have the title Minerva and are based off umi
+        Probe::SecureIndex => regex!(r"Minerva(.|\n)+umi").is_match(body).then_some(Vendor::Minerva),
+        Probe::Luci => regex!(r"<title>WhatsMiner").is_match(body).then_some(Vendor::Whatsminer),
+    }
+}
+
+/// DetectionInfo::firmware of a Whatsminer whose btminer has fallen back to stock cgminer
+pub(crate) const WHATSMINER_CGMINER: &str = "cgminer";
+
+/// Vendor whose signature a socket API stats response carries, with what it said about itself
+/// A working cgminer API without a known signature is Vendor::Cgminer at low confidence
+/// Minerva still needs an HTTP probe to tell its two interfaces apart
+pub(crate) fn socket_signature(resp: &str) -> Result<(Vendor, DetectionInfo), Error> {
+    if let Ok(stats_resp) = serde_json::from_str::<common::StatsResp>(resp) {
+        if stats_resp.status[0].status != common::StatusCode::SUCC {
+            return Err(Error::ApiCallFailed(stats_resp.status[0].msg.clone()));
+        }
+        let info = DetectionInfo::new(DetectionMethod::Socket)
+            .api(stats_resp.status[0].description.clone());
+        for stat in stats_resp.stats.unwrap_or_default() {
+            match stat {
+                common::Stats::AmVersion(version) => {
+                    return Ok((Vendor::Antminer, info.firmware(&format!("bmminer {}", version.bmminer))));
+                },
+                common::Stats::AvaStats(_) => return Ok((Vendor::Avalon, info)),
+                common::Stats::Dev(stat) if stat.type_.as_deref() == Some("Minerva") => {
+                    return Ok((Vendor::Minerva, info));
+                },
+                // Minerva chain sections carry enough to parse as MvStats before Dev
+                common::Stats::MvStats(stat) if stat.type_ == "Minerva" => return Ok((Vendor::Minerva, info)),
+                // Stock cgminer on a Whatsminer names its sections after the SM hashboards
+                common::Stats::Dev(stat) if regex!(r"^SM\d+$").is_match(&stat.shared.id) => {
+                    return Ok((Vendor::Whatsminer, info.firmware(WHATSMINER_CGMINER)));
+                },
+                common::Stats::Dev(_) => return Ok((Vendor::Cgminer, info.low_confidence())),
+                _ => {} // We don't care about the other stats
+            }
+        }
+        Ok((Vendor::Cgminer, info.low_confidence()))
+    } else if let Ok(status) = serde_json::from_str::<common::Status>(resp) {
+        // Whatsminer returns just the cgminer status error with invalid json and a description containing whatsminer
+        // {"STATUS":"E","When":"0","Code":23,"Msg":"Invalid JSON","Description":"whatsminer"}
+        //TODO: Don't hardcode the status code for Invalid Command
+        match status.description {
+            Some(desc) if status.status == common::StatusCode::ERROR && status.code == Some(14)
+                && desc.to_lowercase().contains("whatsminer") => {
+                Ok((Vendor::Whatsminer, DetectionInfo::new(DetectionMethod::Socket).api(Some(desc))))
+            },
+            _ => Err(Error::UnknownMinerType("Received error response but not whatsminer".into())),
+        }
+    } else if cgminer::GenericCgminer::accepts(resp) {
+        // Stats we can't model, but still a working cgminer API
+        Ok((Vendor::Cgminer, DetectionInfo::new(DetectionMethod::Socket).low_confidence()))
+    } else {
+        Err(Error::UnknownMinerType("Unable to parse response from socket API".into()))
+    }
+}
+
+/// A remembered detection result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedDetection {
+    /// get_type of the detected miner
+    pub vendor: String,
+    pub port: u16,
+    /// None until a MAC is reported with observe_mac, detection doesn't read it
+    pub mac: Option<String>,
+    pub info: Option<DetectionInfo>,
+    pub detected_at: DateTime<Utc>,
+}
+
+/// Backing store for a DetectionCache, implement this to share results between processes
+pub trait DetectionStore: Send + Sync {
+    fn get(&self, ip: &str) -> Option<CachedDetection>;
+    fn put(&self, ip: &str, entry: CachedDetection);
+    fn remove(&self, ip: &str);
+}
+
+/// In process DetectionStore
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<String, CachedDetection>>,
+}
+
+impl DetectionStore for MemoryStore {
+    fn get(&self, ip: &str) -> Option<CachedDetection> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).get(ip).cloned()
+    }
+
+    fn put(&self, ip: &str, entry: CachedDetection) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).insert(ip.to_string(), entry);
+    }
+
+    fn remove(&self, ip: &str) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).remove(ip);
+    }
+}
+
+/// Lets get_miner build known devices directly instead of re-running the probe
+/// Entries expire after the TTL, or as soon as the device at the address reports a different MAC
+/// to observe_mac
+#[derive(Clone)]
+pub struct DetectionCache {
+    store: Arc<dyn DetectionStore>,
+    ttl: Duration,
+}
+
+impl fmt::Debug for DetectionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetectionCache").field("ttl", &self.ttl).finish()
+    }
+}
+
+impl DetectionCache {
+    /// In memory cache
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_store(Arc::new(MemoryStore::default()), ttl)
+    }
+
+    pub fn with_store(store: Arc<dyn DetectionStore>, ttl: Duration) -> Self {
+        DetectionCache { store, ttl }
+    }
+
+    /// Unexpired entry for an address
+    pub fn get(&self, ip: &str) -> Option<CachedDetection> {
+        let entry = self.store.get(ip)?;
+        if entry.detected_at + self.ttl > Utc::now() {
+            Some(entry)
+        } else {
+            self.store.remove(ip);
+            None
+        }
+    }
+
+    pub fn put(&self, ip: &str, entry: CachedDetection) {
+        self.store.put(ip, entry);
+    }
+
+    pub fn invalidate(&self, ip: &str) {
+        self.store.remove(ip);
+    }
+
+    /// Record a MAC read for the address, dropping the entry if a different device now has it
+    /// Returns false if the entry was dropped
+    pub fn observe_mac(&self, ip: &str, mac: &str) -> bool {
+        let mut entry = match self.store.get(ip) {
+            Some(entry) => entry,
+            None => return true,
+        };
+        match &entry.mac {
+            Some(known) if !known.eq_ignore_ascii_case(mac) => {
+                self.store.remove(ip);
+                false
+            },
+            Some(_) => true,
+            None => {
+                entry.mac = Some(mac.to_string());
+                self.store.put(ip, entry);
+                true
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_title() {
+        assert_eq!(web_title("<html><head><title>Avalon Device</title></head></html>").as_deref(), Some("Avalon Device"));
+        assert_eq!(web_title("<TITLE>\n  WhatsMiner - LuCI\n</TITLE>").as_deref(), Some("WhatsMiner - LuCI"));
+        assert_eq!(web_title("<title></title>"), None);
+        assert_eq!(web_title("no title here"), None);
+    }
+
+    /// Recorded probe responses under fixtures/detection/<vendor>/<name>.<probe>, none holds lookalikes
+    fn fixtures() -> Vec<(String, Option<Vendor>, String, String)> {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/detection");
+        let mut fixtures = Vec::new();
+        for dir in std::fs::read_dir(&root).unwrap() {
+            let dir = dir.unwrap().path();
+            let vendor = match dir.file_name().unwrap().to_str().unwrap() {
+                "antminer" => Some(Vendor::Antminer),
+                "avalon" => Some(Vendor::Avalon),
+                "vnish" => Some(Vendor::Vnish),
+                "minerva" => Some(Vendor::Minerva),
+                "whatsminer" => Some(Vendor::Whatsminer),
+                "cgminer" => Some(Vendor::Cgminer),
+                "none" => None,
+                other => panic!("unknown fixture vendor {}", other),
+            };
+            for file in std::fs::read_dir(&dir).unwrap() {
+                let file = file.unwrap().path();
+                let name = file.file_name().unwrap().to_str().unwrap().to_string();
+                let (_, probe) = name.split_once('.').unwrap();
+                let body = std::fs::read_to_string(&file).unwrap().trim().to_string();
+                fixtures.push((name.clone(), vendor, probe.to_string(), body));
+            }
+        }
+        fixtures
+    }
+
+    fn http_probe(probe: &str) -> Option<Probe> {
+        match probe {
+            "challenge" => Some(Probe::Challenge),
+            "index.html" => Some(Probe::Index),
+            "https.html" => Some(Probe::SecureIndex),
+            "luci.html" => Some(Probe::Luci),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_detection_fixtures() {
+        let fixtures = fixtures();
+        assert!(!fixtures.is_empty());
+        for (name, vendor, probe, body) in fixtures {
+            let found = match http_probe(&probe) {
+                Some(probe) => http_signature(probe, &body),
+                None if probe == "socket.json" => socket_signature(&body).ok().map(|(vendor, _)| vendor),
+                None => panic!("unknown probe in fixture {}", name),
+            };
+            assert_eq!(found, vendor, "{} classified wrong", name);
+
+            // A response seen on one probe must not carry another vendor's signature on any other
+            for other in [Probe::Challenge, Probe::Index, Probe::SecureIndex, Probe::Luci] {
+                if let Some(found) = http_signature(other, &body) {
+                    assert_eq!(Some(found), vendor, "{} matches {:?} on {:?}", name, found, other);
+                }
+            }
+            if let Ok((found, _)) = socket_signature(&body) {
+                assert_eq!(Some(found), vendor, "{} matches {:?} on the socket API", name, found);
+            }
+        }
+    }
+
+    #[test]
+    fn test_socket_signature_info() {
+        let resp = r#"{"STATUS":[{"STATUS":"S","When":1,"Code":70,"Msg":"CGMiner stats","Description":"cgminer 1.0.0"}],"STATS":[{"BMMiner":"1.0.0","Miner":"49.0.1.3","CompileTime":"Thu Apr 13 16:20:41 CST 2023","Type":"Antminer S19j Pro"}],"id":1}"#;
+        let (vendor, info) = socket_signature(resp).unwrap();
+        assert_eq!(vendor, Vendor::Antminer);
+        assert_eq!(info.firmware.as_deref(), Some("bmminer 1.0.0"));
+        assert_eq!(info.api.as_deref(), Some("cgminer 1.0.0"));
+
+        let resp = r#"{"STATUS":[{"STATUS":"S","When":1,"Code":70,"Msg":"CGMiner stats","Description":"cgminer 4.9.2"}],"STATS":[{"STATS":0,"ID":"SM0","Elapsed":60,"Calls":0,"Wait":0.0,"Max":0.0,"Min":99999999.0}],"id":1}"#;
+        let (vendor, info) = socket_signature(resp).unwrap();
+        assert_eq!(vendor, Vendor::Whatsminer);
+        assert_eq!(info.firmware.as_deref(), Some(WHATSMINER_CGMINER));
+
+        let resp = r#"{"STATUS":[{"STATUS":"E","When":1,"Code":45,"Msg":"Access denied to 'stats' command"}],"id":1}"#;
+        assert!(matches!(socket_signature(resp), Err(Error::ApiCallFailed(_))));
+    }
+
+    fn entry(mac: Option<&str>, age: i64) -> CachedDetection {
+        CachedDetection {
+            vendor: "Antminer".to_string(),
+            port: 4028,
+            mac: mac.map(|m| m.to_string()),
+            info: None,
+            detected_at: Utc::now() - Duration::seconds(age),
+        }
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let cache = DetectionCache::new(Duration::seconds(60));
+        cache.put("10.0.0.1", entry(None, 10));
+        cache.put("10.0.0.2", entry(None, 120));
+        assert!(cache.get("10.0.0.1").is_some());
+        assert!(cache.get("10.0.0.2").is_none());
+    }
+
+    #[test]
+    fn test_observe_mac() {
+        let cache = DetectionCache::new(Duration::seconds(60));
+        cache.put("10.0.0.1", entry(None, 0));
+        assert!(cache.observe_mac("10.0.0.1", "AA:BB:CC:DD:EE:FF"));
+        assert!(cache.observe_mac("10.0.0.1", "aa:bb:cc:dd:ee:ff"));
+        assert_eq!(cache.get("10.0.0.1").unwrap().mac.as_deref(), Some("AA:BB:CC:DD:EE:FF"));
+        assert!(!cache.observe_mac("10.0.0.1", "11:22:33:44:55:66"));
+        assert!(cache.get("10.0.0.1").is_none());
+    }
+}
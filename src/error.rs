@@ -1,21 +1,27 @@
+#[cfg(feature = "http")]
 use reqwest::Error as ReqwestError;
 use thiserror::Error;
 use std::io::Error as IoError;
 use serde_json::Error as JsonError;
+#[cfg(feature = "http")]
 use digest_auth::Error as DigestAuthError;
+#[cfg(feature = "http")]
 use reqwest::header::ToStrError;
 
 #[derive(Error, Debug)]
 pub enum Error {
     // Errors bubbled from dependencies
+    #[cfg(feature = "http")]
     #[error("Reqwest error {0}")]
     RequestError(#[from] ReqwestError),
     #[error("Io error {0}")]
     IoError(#[from] IoError),
     #[error("Json error {0}")]
     ParseError(#[from] JsonError),
+    #[cfg(feature = "http")]
     #[error("Digest auth error {0}")]
     DigestAuthError(#[from] DigestAuthError),
+    #[cfg(feature = "http")]
     #[error("ToStr error")]
     ToStrError(#[from] ToStrError),
     #[error("Failed to acquire semaphore")]
@@ -24,6 +30,9 @@ pub enum Error {
     #[cfg(feature = "avalon")]
     #[error("Avalon deserializer error")]
     AvalonDeserializerError(#[from] crate::miners::avalon::DeError),
+    #[cfg(feature = "ssh")]
+    #[error("SSH error {0}")]
+    SshError(#[from] async_ssh2_tokio::Error),
 
     // Errors from this library
     // Detection errors
@@ -33,7 +42,18 @@ pub enum Error {
     UnknownMinerType(String),
     #[error("No miner detected")]
     NoMinerDetected,
+    /// Something answered but neither probe recognised it, None for a probe that wasn't tried
+    #[error("Detection failed, http: {}, socket: {}", probe_error(.http), probe_error(.socket))]
+    DetectionFailed { http: Option<Box<Error>>, socket: Option<Box<Error>> },
     
+    // Configuration errors
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+    #[error("Invalid profile: {0}")]
+    InvalidProfile(String),
+    #[error("Too many pools, {given} given but at most {max} are supported")]
+    TooManyPools { given: usize, max: usize },
+
     // Response parsing errors
     #[error("Encode error")]
     EncodingError,
@@ -43,6 +63,8 @@ pub enum Error {
     Timeout,
     #[error("Connection refused")]
     ConnectionRefused,
+    #[error("TLS error: {0}")]
+    TlsError(String),
     #[error("Failed to execute HTTP request")]
     HttpRequestFailed,
 
@@ -51,8 +73,29 @@ pub enum Error {
     TokenExpired,
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("API disabled")]
+    ApiDisabled,
+    /// The socket takes connections but the mining process behind it has stopped answering
+    /// Restarting the process clears it, see recovery() or Miner::recover
+    #[error("API deadlocked")]
+    ApiDeadlocked,
     #[error("API Call failed: {0}")]
     ApiCallFailed(String),
+    #[error("Config not applied: {0}")]
+    ConfigNotApplied(String),
+    /// None of the pools answered a stratum probe, so the write was refused
+    #[error("No pool reachable: {0}")]
+    NoReachablePool(String),
+    /// The miner refused a write, with the reason it gave
+    #[error("Config rejected: {0}")]
+    ConfigRejected(String),
+    /// The miner accepted a command but never reached the state it asked for
+    #[error("Verification timed out: {0}")]
+    VerificationTimeout(String),
+    #[error("Drawing {power}W over the {ceiling}W ceiling, rolled back: {rolled_back}")]
+    PowerCeilingExceeded { power: f64, ceiling: f64, rolled_back: bool },
+    /// The miner answered without the reading, e.g. no chains while it starts up
+    /// Getters return this rather than a zero that reads like a real measurement
     #[error("Expected return")]
     ExpectedReturn,
     #[error("Not supported")]
@@ -62,3 +105,118 @@ pub enum Error {
     #[error("Unknown model {0}")]
     UnknownModel(String),
 }
+
+fn probe_error(e: &Option<Box<Error>>) -> String {
+    e.as_ref().map_or("not tried".to_string(), |e| e.to_string())
+}
+
+impl Error {
+    /// Combine the HTTP and socket probe errors, keeping a plain Timeout or NoMinerDetected
+    /// when nothing answered either probe
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn detection_failed(http: Error, socket: Error) -> Error {
+        match (http, socket) {
+            (Error::Timeout, Error::Timeout) => Error::Timeout,
+            (Error::Timeout | Error::NoMinerDetected, Error::Timeout | Error::NoMinerDetected) => Error::NoMinerDetected,
+            // The miner was found, it just can't be used until it's restarted
+            (Error::ApiDeadlocked, _) | (_, Error::ApiDeadlocked) => Error::ApiDeadlocked,
+            (http, socket) => Error::DetectionFailed {
+                http: Some(Box::new(http)),
+                socket: Some(Box::new(socket)),
+            },
+        }
+    }
+
+    /// What to run over SSH to clear the error, if anything will
+    #[cfg(feature = "ssh")]
+    pub fn recovery(&self) -> Option<crate::ssh::Recovery> {
+        match self {
+            Error::ApiDeadlocked => Some(crate::ssh::Recovery::RestartMiner),
+            _ => None,
+        }
+    }
+
+    /// Whether the error looks like the firmware changed under the miner, e.g. endpoints that
+    /// vanished or responses in another shape. Only a hint, a flaky miner fails the same way,
+    /// so re-detect with Client::refresh once it persists
+    pub fn suggests_redetect(&self) -> bool {
+        matches!(self,
+            Error::ParseError(_)
+            | Error::InvalidResponse
+            | Error::ExpectedReturn
+            | Error::HttpRequestFailed
+            | Error::ApiDisabled
+            | Error::ConnectionRefused
+            | Error::UnknownModel(_)
+        )
+    }
+
+    /// Whether the same call could succeed if made again, nothing answered or the answer was
+    /// cut short. Auth errors and device faults are never retryable, retrying won't change them
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Timeout
+            | Error::ConnectionRefused
+            | Error::NoHostDetected
+            | Error::NoMinerDetected
+            | Error::IoError(_)
+            | Error::HttpRequestFailed
+            | Error::VerificationTimeout(_) => true,
+            #[cfg(feature = "http")]
+            Error::RequestError(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Whether the miner refused the credentials or session, auth again before retrying
+    pub fn is_auth(&self) -> bool {
+        match self {
+            Error::Unauthorized | Error::TokenExpired => true,
+            #[cfg(feature = "http")]
+            Error::RequestError(e) => matches!(e.status().map(|s| s.as_u16()), Some(401 | 403)),
+            _ => false,
+        }
+    }
+
+    /// Whether the miner answered but is in a state only a restart or a technician clears
+    pub fn is_device_fault(&self) -> bool {
+        matches!(self,
+            Error::ApiDeadlocked
+            | Error::PowerCeilingExceeded { .. }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detection_failed() {
+        assert!(matches!(Error::detection_failed(Error::Timeout, Error::Timeout), Error::Timeout));
+        assert!(matches!(Error::detection_failed(Error::Timeout, Error::NoMinerDetected), Error::NoMinerDetected));
+        match Error::detection_failed(Error::Unauthorized, Error::Timeout) {
+            Error::DetectionFailed { http: Some(http), socket: Some(socket) } => {
+                assert!(matches!(*http, Error::Unauthorized));
+                assert!(matches!(*socket, Error::Timeout));
+            },
+            e => panic!("unexpected {:?}", e),
+        }
+        assert!(matches!(Error::detection_failed(Error::UnknownMinerType("".into()), Error::ApiDeadlocked), Error::ApiDeadlocked));
+        assert!(matches!(Error::detection_failed(Error::ApiDeadlocked, Error::Timeout), Error::ApiDeadlocked));
+        let e = Error::DetectionFailed { http: Some(Box::new(Error::Unauthorized)), socket: None };
+        assert_eq!(e.to_string(), "Detection failed, http: Unauthorized, socket: not tried");
+    }
+
+    #[test]
+    fn test_classification() {
+        assert!(Error::Timeout.is_retryable());
+        assert!(Error::VerificationTimeout("10.0.0.1 hashing".into()).is_retryable());
+        assert!(!Error::Unauthorized.is_retryable());
+        assert!(!Error::ApiDeadlocked.is_retryable());
+        assert!(Error::TokenExpired.is_auth());
+        assert!(!Error::Timeout.is_auth());
+        assert!(Error::ApiDeadlocked.is_device_fault());
+        assert!(!Error::NotSupported.is_device_fault());
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::{BTreeMap, HashMap};
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::miner::{PowerState, SharedMiner};
+use crate::snapshot::MinerSnapshot;
+
+/// Width of each temperature histogram bucket in Celsius
+pub const TEMP_BUCKET_WIDTH: i32 = 5;
+
+/// Site wide totals built from a set of snapshots
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Aggregate {
+    pub miners: usize,
+    pub unreachable: usize,
+    /// TH/s
+    pub hashrate: f64,
+    /// Watts
+    pub power: f64,
+    /// J/TH across the whole site, None if nothing is hashing
+    pub efficiency: Option<f64>,
+    pub by_state: HashMap<PowerState, usize>,
+    pub by_vendor: HashMap<String, usize>,
+    pub by_model: HashMap<String, usize>,
+    /// Miners per temperature bucket, keyed by the bucket's lower bound
+    pub temperature: BTreeMap<i32, usize>,
+}
+
+impl Aggregate {
+    pub fn from_snapshots(snapshots: &[MinerSnapshot]) -> Aggregate {
+        let mut agg = Aggregate {
+            miners: snapshots.len(),
+            ..Default::default()
+        };
+
+        for snap in snapshots {
+            *agg.by_vendor.entry(snap.vendor.clone()).or_default() += 1;
+            if !snap.reachable() {
+                agg.unreachable += 1;
+                continue;
+            }
+            if let Some(model) = &snap.model {
+                *agg.by_model.entry(model.clone()).or_default() += 1;
+            }
+            if let Some(state) = snap.power_state {
+                *agg.by_state.entry(state).or_default() += 1;
+            }
+            agg.hashrate += snap.hashrate.unwrap_or(0.0);
+            agg.power += snap.power.unwrap_or(0.0);
+            if let Some(temp) = snap.temperature {
+                let bucket = (temp.floor() as i32).div_euclid(TEMP_BUCKET_WIDTH) * TEMP_BUCKET_WIDTH;
+                *agg.temperature.entry(bucket).or_default() += 1;
+            }
+        }
+
+        if agg.hashrate > 0.0 {
+            agg.efficiency = Some(agg.power / agg.hashrate);
+        }
+        agg
+    }
+}
+
+/// Snapshot every miner concurrently
+pub async fn snapshots(miners: &[SharedMiner]) -> Vec<MinerSnapshot> {
+    join_all(miners.iter().map(MinerSnapshot::take)).await
+}
+
+pub async fn aggregate(miners: &[SharedMiner]) -> Aggregate {
+    Aggregate::from_snapshots(&snapshots(miners).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(vendor: &str, hashrate: f64, power: f64, temperature: f64) -> MinerSnapshot {
+        MinerSnapshot {
+            vendor: vendor.into(),
+            hashrate: Some(hashrate),
+            power: Some(power),
+            efficiency: None,
+            temperature: Some(temperature),
+            ..MinerSnapshot::test()
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut down = snapshot("Whatsminer", 0.0, 0.0, 0.0);
+        down.error = Some("Timeout".into());
+        let snaps = vec![
+            snapshot("Antminer", 100.0, 3000.0, 62.0),
+            snapshot("Antminer", 100.0, 3400.0, 71.5),
+            down,
+        ];
+        let agg = Aggregate::from_snapshots(&snaps);
+        assert_eq!(agg.miners, 3);
+        assert_eq!(agg.unreachable, 1);
+        assert_eq!(agg.hashrate, 200.0);
+        assert_eq!(agg.power, 6400.0);
+        assert_eq!(agg.efficiency, Some(32.0));
+        assert_eq!(agg.by_vendor["Antminer"], 2);
+        assert_eq!(agg.by_vendor["Whatsminer"], 1);
+        assert_eq!(agg.by_state[&PowerState::Mining], 2);
+        assert_eq!(agg.temperature.get(&60), Some(&1));
+        assert_eq!(agg.temperature.get(&70), Some(&1));
+    }
+}
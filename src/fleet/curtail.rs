@@ -0,0 +1,321 @@
+use futures::future::join_all;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, warn};
+
+use crate::error::Error;
+use crate::miner::{Miner, PowerState, Profile, SharedMiner};
+
+/// Which miners get curtailed first
+#[derive(Debug, Clone)]
+pub enum CurtailOrder {
+    /// Miners in this order of IP, unlisted miners follow in fleet order
+    Priority(Vec<String>),
+    /// Start at this offset into the fleet and wrap around
+    /// Feed `CurtailReport::next_start` back in next time so the same miners don't take every cycle
+    RoundRobin { start: usize },
+    /// Worst J/TH first
+    LeastEfficient,
+}
+
+/// How quickly to apply the plan, switching a whole site at once can trip breakers
+#[derive(Debug, Clone)]
+pub struct RampProfile {
+    /// Time to spread the batches over
+    pub window: Duration,
+    /// Miners acted on together
+    pub batch_size: usize,
+}
+
+impl Default for RampProfile {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            batch_size: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CurtailStrategy {
+    pub order: CurtailOrder,
+    /// Switch miners to a lower power preset before putting any to sleep
+    pub derate_first: bool,
+    pub ramp: RampProfile,
+}
+
+impl Default for CurtailStrategy {
+    fn default() -> Self {
+        Self {
+            order: CurtailOrder::Priority(vec![]),
+            derate_first: true,
+            ramp: RampProfile::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CurtailAction {
+    Sleep,
+    Derate(Profile),
+}
+
+#[derive(Debug)]
+pub struct CurtailOutcome {
+    pub ip: String,
+    pub action: CurtailAction,
+    /// Expected reduction in kW
+    pub expected_kw: f64,
+    pub result: Result<(), Error>,
+}
+
+#[derive(Debug)]
+pub struct CurtailReport {
+    pub target_kw: f64,
+    /// Reduction the plan expected to reach, less than the target if the fleet couldn't cover it
+    pub planned_kw: f64,
+    /// Reduction from the actions that succeeded
+    pub achieved_kw: f64,
+    pub actions: Vec<CurtailOutcome>,
+    /// Miners left out because they couldn't be surveyed
+    pub skipped: Vec<(String, Error)>,
+    /// Offset to pass as the next `CurtailOrder::RoundRobin` start
+    pub next_start: usize,
+}
+
+/// What we need to know about a miner before deciding what to do with it
+struct Survey {
+    index: usize,
+    power: f64,
+    efficiency: f64,
+    presets: Vec<(Profile, f64)>,
+}
+
+async fn survey(index: usize, miner: &SharedMiner, derate_first: bool) -> Result<Option<Survey>, Error> {
+    // Miners that are asleep or on their way there have nothing left to give
+    if miner.get_power_state().await? != PowerState::Mining {
+        return Ok(None);
+    }
+    let power = miner.get_power().await?;
+    let efficiency = miner.get_efficiency().await.unwrap_or(0.0);
+    let presets = if derate_first {
+        miner.get_profiles().await
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|p| match p {
+                Profile::Preset { power: preset_power, .. } if preset_power < power => Some((p, preset_power)),
+                _ => None,
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+    Ok(Some(Survey { index, power, efficiency, presets }))
+}
+
+fn order_surveys(miners: &[SharedMiner], surveys: &mut [Survey], order: &CurtailOrder) {
+    match order {
+        CurtailOrder::Priority(ips) => {
+            let rank = |s: &Survey| {
+                ips.iter()
+                    .position(|ip| ip == miners[s.index].get_ip())
+                    .unwrap_or(ips.len())
+            };
+            surveys.sort_by_key(|s| (rank(s), s.index));
+        },
+        CurtailOrder::RoundRobin { start } => {
+            let len = miners.len().max(1);
+            surveys.sort_by_key(|s| (s.index + len - start % len) % len);
+        },
+        CurtailOrder::LeastEfficient => {
+            surveys.sort_by(|a, b| b.efficiency.total_cmp(&a.efficiency));
+        },
+    }
+}
+
+/// Plan derates then sleeps until the reduction (in W) is covered
+fn plan_actions(surveys: &[Survey], target: f64, derate_first: bool) -> Vec<(usize, CurtailAction, f64)> {
+    let mut remaining = target;
+    let mut actions = vec![];
+    let mut derated = vec![false; surveys.len()];
+
+    if derate_first {
+        for (i, s) in surveys.iter().enumerate() {
+            if remaining <= 0.0 {
+                break;
+            }
+            // Smallest step down that covers what's left, otherwise the biggest one available
+            let preset = s.presets.iter()
+                .filter(|(_, p)| s.power - p >= remaining)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .or_else(|| s.presets.iter().min_by(|a, b| a.1.total_cmp(&b.1)));
+            if let Some((profile, preset_power)) = preset {
+                let saving = s.power - preset_power;
+                remaining -= saving;
+                derated[i] = true;
+                actions.push((s.index, CurtailAction::Derate(profile.clone()), saving));
+            }
+        }
+    }
+
+    for (i, s) in surveys.iter().enumerate() {
+        if remaining <= 0.0 {
+            break;
+        }
+        if derated[i] {
+            continue;
+        }
+        remaining -= s.power;
+        actions.push((s.index, CurtailAction::Sleep, s.power));
+    }
+    actions
+}
+
+/// One past the furthest planned miner in round robin order, skipped miners in the window don't count
+/// An empty plan starts from the same place next time
+fn next_round_robin(start: usize, plan: &[(usize, CurtailAction, f64)], len: usize) -> usize {
+    let len = len.max(1);
+    let start = start % len;
+    match plan.iter().map(|(index, _, _)| (index + len - start) % len).max() {
+        Some(last) => (start + last + 1) % len,
+        None => start,
+    }
+}
+
+/// Reduce fleet power draw by roughly `target_kw_reduction`
+/// Miners that are asleep or transitioning are left alone, failures are recorded in the report rather than aborting
+pub async fn curtail(miners: &[SharedMiner], target_kw_reduction: f64, strategy: &CurtailStrategy) -> CurtailReport {
+    let results = join_all(
+        miners.iter().enumerate().map(|(i, m)| survey(i, m, strategy.derate_first))
+    ).await;
+
+    let mut surveys = vec![];
+    let mut skipped = vec![];
+    for (i, r) in results.into_iter().enumerate() {
+        match r {
+            Ok(Some(s)) => surveys.push(s),
+            Ok(None) => {},
+            Err(e) => {
+                warn!("Skipping {} for curtailment: {}", miners[i].get_ip(), e);
+                skipped.push((miners[i].get_ip().to_string(), e));
+            },
+        }
+    }
+    order_surveys(miners, &mut surveys, &strategy.order);
+
+    let plan = plan_actions(&surveys, target_kw_reduction * 1000.0, strategy.derate_first);
+    let planned_kw = plan.iter().map(|(_, _, w)| w).sum::<f64>() / 1000.0;
+    debug!("Curtailing {} miners for {:.1} kW of {:.1} kW", plan.len(), planned_kw, target_kw_reduction);
+
+    let next_start = match strategy.order {
+        CurtailOrder::RoundRobin { start } => next_round_robin(start, &plan, miners.len()),
+        _ => 0,
+    };
+
+    let batch_size = strategy.ramp.batch_size.max(1);
+    let batches = plan.chunks(batch_size).count();
+    let delay = match batches {
+        0 | 1 => Duration::ZERO,
+        n => strategy.ramp.window / (n - 1) as u32,
+    };
+
+    let mut actions = vec![];
+    for (n, batch) in plan.chunks(batch_size).enumerate() {
+        if n > 0 {
+            sleep(delay).await;
+        }
+        let results = join_all(batch.iter().map(|(index, action, _)| {
+            let mut miner = miners[*index].clone();
+            let action = action.clone();
+            async move {
+                match action {
+                    CurtailAction::Sleep => miner.set_sleep(true).await,
+                    CurtailAction::Derate(profile) => miner.set_profile(profile).await,
+                }
+            }
+        })).await;
+        for ((index, action, watts), result) in batch.iter().zip(results) {
+            actions.push(CurtailOutcome {
+                ip: miners[*index].get_ip().to_string(),
+                action: action.clone(),
+                expected_kw: watts / 1000.0,
+                result,
+            });
+        }
+    }
+
+    let achieved_kw = actions.iter()
+        .filter(|a| a.result.is_ok())
+        .map(|a| a.expected_kw)
+        .sum();
+
+    CurtailReport {
+        target_kw: target_kw_reduction,
+        planned_kw,
+        achieved_kw,
+        actions,
+        skipped,
+        next_start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(power: f64) -> (Profile, f64) {
+        (Profile::Preset { name: format!("{}", power), power, ths: 0.0 }, power)
+    }
+
+    fn survey(index: usize, power: f64, presets: Vec<(Profile, f64)>) -> Survey {
+        Survey { index, power, efficiency: 0.0, presets }
+    }
+
+    #[test]
+    fn test_plan_sleep() {
+        let surveys = vec![survey(0, 3000.0, vec![]), survey(1, 3000.0, vec![]), survey(2, 3000.0, vec![])];
+        let plan = plan_actions(&surveys, 5000.0, true);
+        assert_eq!(plan.len(), 2);
+        assert!(plan.iter().all(|(_, a, _)| *a == CurtailAction::Sleep));
+    }
+
+    #[test]
+    fn test_plan_derate_first() {
+        let surveys = vec![
+            survey(0, 3600.0, vec![preset(3000.0), preset(2700.0)]),
+            survey(1, 3600.0, vec![]),
+        ];
+        // A 600W step covers it, don't go all the way down
+        let plan = plan_actions(&surveys, 500.0, true);
+        assert_eq!(plan, vec![(0, CurtailAction::Derate(preset(3000.0).0), 600.0)]);
+
+        // Derate as far as possible, then sleep the rest
+        let plan = plan_actions(&surveys, 2000.0, true);
+        assert_eq!(plan, vec![
+            (0, CurtailAction::Derate(preset(2700.0).0), 900.0),
+            (1, CurtailAction::Sleep, 3600.0),
+        ]);
+    }
+
+    #[test]
+    fn test_round_robin_skipped() {
+        let miners: Vec<SharedMiner> = (0..5)
+            .map(|i| {
+                let client = crate::ClientBuilder::new().build().unwrap();
+                SharedMiner::new(Box::new(crate::miners::cgminer::GenericCgminer::new(client, format!("10.0.0.{}", i), 4028)))
+            })
+            .collect();
+        // Miner 2 was asleep so it never made it into the surveys
+        let mut surveys = vec![
+            survey(0, 3000.0, vec![]), survey(1, 3000.0, vec![]), survey(3, 3000.0, vec![]), survey(4, 3000.0, vec![]),
+        ];
+        order_surveys(&miners, &mut surveys, &CurtailOrder::RoundRobin { start: 1 });
+        let plan = plan_actions(&surveys, 5000.0, false);
+        assert_eq!(plan.iter().map(|(i, _, _)| *i).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(next_round_robin(1, &plan, miners.len()), 4);
+
+        // Wraps past the end of the fleet
+        let plan = vec![(4, CurtailAction::Sleep, 3000.0), (0, CurtailAction::Sleep, 3000.0)];
+        assert_eq!(next_round_robin(4, &plan, miners.len()), 1);
+        assert_eq!(next_round_robin(3, &[], miners.len()), 3);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::error::Error;
+use crate::miner::SharedMiner;
+use crate::snapshot::MinerSnapshot;
+use crate::fleet::snapshots;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Header row then one row per miner
+    Csv,
+    /// Array with one object per miner
+    Json,
+}
+
+const CSV_HEADER: &str = "ip,vendor,model,mac,power_state,hashrate,power,efficiency,performance_ratio,temperature,fan_speed,taken_at,error,quality,tags";
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn opt<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn csv_row(snap: &MinerSnapshot) -> String {
+    let fields = [
+        snap.ip.clone(),
+        snap.vendor.clone(),
+        opt(&snap.model),
+        opt(&snap.mac),
+        snap.power_state.map(|s| format!("{:?}", s)).unwrap_or_default(),
+        opt(&snap.hashrate),
+        opt(&snap.power),
+        opt(&snap.efficiency),
+        opt(&snap.performance_ratio),
+        opt(&snap.temperature),
+        snap.fan_speed.as_ref()
+            .map(|f| f.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(";"))
+            .unwrap_or_default(),
+        snap.taken_at.to_rfc3339(),
+        opt(&snap.error),
+        snap.quality.to_string(),
+        snap.tags.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(";"),
+    ];
+    fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",")
+}
+
+/// Render snapshots, unreachable miners are kept as rows with only the error filled in
+pub fn export_snapshots(snapshots: &[MinerSnapshot], format: ExportFormat) -> Result<String, Error> {
+    match format {
+        ExportFormat::Csv => {
+            let mut out = String::from(CSV_HEADER);
+            out.push('\n');
+            for snap in snapshots {
+                out.push_str(&csv_row(snap));
+                out.push('\n');
+            }
+            Ok(out)
+        },
+        ExportFormat::Json => Ok(serde_json::to_string(snapshots)?),
+    }
+}
+
+/// Snapshot every miner and render the result
+pub async fn export(miners: &[SharedMiner], format: ExportFormat) -> Result<String, Error> {
+    export_snapshots(&snapshots(miners).await, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::snapshot::DataQuality;
+
+    fn snapshot() -> MinerSnapshot {
+        MinerSnapshot {
+            mac: Some("AA:BB:CC:DD:EE:FF".into()),
+            hashrate: Some(95.5),
+            efficiency: Some(34.0),
+            performance_ratio: Some(0.98),
+            fan_speed: Some(vec![5400, 5520]),
+            taken_at: Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap(),
+            ..MinerSnapshot::test()
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut down = snapshot();
+        down.ip = "10.0.0.2".into();
+        down.model = None;
+        down.mac = None;
+        down.power_state = None;
+        down.hashrate = None;
+        down.power = None;
+        down.efficiency = None;
+        down.performance_ratio = None;
+        down.temperature = None;
+        down.fan_speed = None;
+        down.error = Some("Connection refused, giving up".into());
+
+        down.tags.insert("rack".into(), "12".into());
+        down.tags.insert("customer".into(), "Acme, Inc".into());
+
+        let csv = export_snapshots(&[snapshot(), down], ExportFormat::Csv).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "10.0.0.1,Antminer,s19,AA:BB:CC:DD:EE:FF,Mining,95.5,3250,34,0.98,68,5400;5520,2023-01-01T00:00:00+00:00,,Ok,");
+        assert_eq!(lines[2], "10.0.0.2,Antminer,,,,,,,,,,2023-01-01T00:00:00+00:00,\"Connection refused, giving up\",Ok,\"customer=Acme, Inc;rack=12\"");
+    }
+
+    #[test]
+    fn test_csv_suspect() {
+        let mut snap = snapshot();
+        snap.quality = DataQuality::Suspect(vec!["Efficiency 8125.0 J/TH".into(), "Temperature 655.0C".into()]);
+        let csv = export_snapshots(&[snap], ExportFormat::Csv).unwrap();
+        assert!(csv.lines().nth(1).unwrap().ends_with(",Suspect: Efficiency 8125.0 J/TH; Temperature 655.0C,"));
+    }
+
+    #[test]
+    fn test_json() {
+        let json = export_snapshots(&[snapshot()], ExportFormat::Json).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["ip"], "10.0.0.1");
+        assert_eq!(value[0]["power_state"], "Mining");
+        assert_eq!(value[0]["error"], serde_json::Value::Null);
+    }
+}
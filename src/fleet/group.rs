@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use futures::future::join_all;
+
+use crate::error::Error;
+use crate::miner::{Miner, MinerError, Pool, SharedMiner};
+use crate::Client;
+
+/// A rack, pod or any other set of miners read as one logical miner
+/// Only the read-only half of Miner is rolled up, setters return NotSupported so a
+/// dashboard can't accidentally reboot a whole pod
+/// Children that fail to answer are left out of totals, a getter only fails if every child does
+#[derive(Clone)]
+pub struct GroupMiner {
+    name: String,
+    miners: Vec<SharedMiner>,
+}
+
+impl GroupMiner {
+    pub fn from_miners(name: &str, miners: Vec<SharedMiner>) -> GroupMiner {
+        GroupMiner {
+            name: name.to_string(),
+            miners,
+        }
+    }
+
+    pub fn push(&mut self, miner: SharedMiner) {
+        self.miners.push(miner);
+    }
+
+    pub fn miners(&self) -> &[SharedMiner] {
+        &self.miners
+    }
+}
+
+/// Values from children that answered, or the first error if none did
+fn answered<T>(results: Vec<Result<T, Error>>) -> Result<Vec<T>, Error> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut first_err = None;
+    for result in results {
+        match result {
+            Ok(v) => values.push(v),
+            Err(e) => {
+                first_err.get_or_insert(e);
+            },
+        }
+    }
+    match first_err {
+        Some(e) if values.is_empty() => Err(e),
+        // An empty group has nothing to report
+        None if values.is_empty() => Err(Error::NoMinerDetected),
+        _ => Ok(values),
+    }
+}
+
+#[async_trait]
+impl Miner for GroupMiner {
+    fn new(_client: Client, ip: String, _port: u16) -> Self {
+        GroupMiner {
+            name: ip,
+            miners: vec![],
+        }
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Group"
+    }
+
+    /// The group's name, there's no single address to report
+    fn get_ip(&self) -> &str {
+        &self.name
+    }
+
+    /// The shared model, or every distinct model joined with ", " for mixed groups
+    async fn get_model(&self) -> Result<String, Error> {
+        let mut models = answered(join_all(self.miners.iter().map(|m| m.get_model())).await)?;
+        models.sort();
+        models.dedup();
+        Ok(models.join(", "))
+    }
+
+    async fn auth(&mut self, _username: &str, _password: &str) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn reboot(&mut self) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_hashrate(&self) -> Result<f64, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_hashrate())).await)?.iter().sum())
+    }
+
+    async fn get_power(&self) -> Result<f64, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_power())).await)?.iter().sum())
+    }
+
+    async fn get_nameplate_power(&self) -> Result<f64, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_nameplate_power())).await)?.iter().sum())
+    }
+
+    /// Group power over group hashrate, counting only children that reported both
+    async fn get_efficiency(&self) -> Result<f64, Error> {
+        let readings = join_all(self.miners.iter().map(|m| async move {
+            Ok::<_, Error>((m.get_power().await?, m.get_hashrate().await?))
+        })).await;
+        let (power, hashrate) = answered(readings)?
+            .into_iter()
+            .fold((0.0, 0.0), |(p, h), (power, hashrate)| (p + power, h + hashrate));
+        if hashrate > 0.0 {
+            Ok(power / hashrate)
+        } else {
+            Err(Error::ExpectedReturn)
+        }
+    }
+
+    async fn get_nameplate_rate(&self) -> Result<f64, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_nameplate_rate())).await)?.iter().sum())
+    }
+
+    /// Hottest child
+    async fn get_temperature(&self) -> Result<f64, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_temperature())).await)?
+            .into_iter()
+            .fold(f64::MIN, f64::max))
+    }
+
+    async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_fan_speed())).await)?.concat())
+    }
+
+    /// Every distinct pool in use across the group
+    async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
+        let mut pools: Vec<Pool> = Vec::new();
+        for pool in answered(join_all(self.miners.iter().map(|m| m.get_pools())).await)?.into_iter().flatten() {
+            if !pools.iter().any(|p| p.url == pool.url && p.username == pool.username) {
+                pools.push(pool);
+            }
+        }
+        Ok(pools)
+    }
+
+    /// Only asleep once every child is
+    async fn get_sleep(&self) -> Result<bool, Error> {
+        Ok(answered(join_all(self.miners.iter().map(|m| m.get_sleep())).await)?.iter().all(|s| *s))
+    }
+
+    async fn set_sleep(&mut self, _sleep: bool) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_mac(&self) -> Result<String, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
+        let errors = join_all(self.miners.iter().cloned().map(|mut m| async move {
+            m.get_errors().await
+        })).await;
+        Ok(answered(errors)?.concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answered() {
+        let v = answered(vec![Ok(1.0), Err(Error::Timeout), Ok(2.0)]).unwrap();
+        assert_eq!(v, vec![1.0, 2.0]);
+        assert!(matches!(answered::<f64>(vec![Err(Error::Timeout), Err(Error::Unauthorized)]), Err(Error::Timeout)));
+        assert!(matches!(answered::<f64>(vec![]), Err(Error::NoMinerDetected)));
+    }
+}
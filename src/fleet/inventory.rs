@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::Arc;
+use futures::future::join_all;
+#[cfg(feature = "config")]
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::error::Error;
+use crate::miner::{Miner, SharedMiner, Tags};
+use crate::fleet::template::{self, PoolTemplate};
+use crate::Client;
+use crate::credentials::{Credentials, CredentialProvider, StaticCredentials};
+
+/// A single miner from the inventory, not yet connected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FleetEntry {
+    pub ip: String,
+    pub port: Option<u16>,
+    /// Skips detection when set, see Client::get_miner_as
+    pub vendor: Option<String>,
+    pub credentials: Option<Credentials>,
+    /// Attached to the miner when it's connected
+    pub tags: Tags,
+}
+
+#[cfg(feature = "config")]
+#[derive(Debug, Deserialize)]
+struct ConfigRange {
+    range: String,
+    vendor: Option<String>,
+    port: Option<u16>,
+    /// Name of a credential set
+    credentials: Option<String>,
+    #[serde(default)]
+    tags: Tags,
+}
+
+#[cfg(feature = "config")]
+#[derive(Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    credentials: HashMap<String, Credentials>,
+    #[serde(default)]
+    miners: Vec<ConfigRange>,
+}
+
+/// Most addresses a single range may expand to, a /16
+pub const MAX_RANGE_SIZE: u32 = 65_536;
+
+/// Expand "10.0.0.5", "10.0.0.5-10.0.0.20", "10.0.0.5-20" or "10.0.0.0/24" into addresses
+/// Network and broadcast addresses are left out of CIDR ranges
+/// Ranges over MAX_RANGE_SIZE addresses, e.g. a mistyped /8, are an error
+pub fn expand_range(range: &str) -> Result<Vec<Ipv4Addr>, Error> {
+    let invalid = || Error::InvalidConfig(format!("invalid IP range {}", range));
+    let too_large = || Error::InvalidConfig(format!("IP range {} is over {} addresses", range, MAX_RANGE_SIZE));
+    let parse = |ip: &str| ip.trim().parse::<Ipv4Addr>().map_err(|_| invalid());
+    let range = range.trim();
+
+    if let Some((net, bits)) = range.split_once('/') {
+        let bits = bits.trim().parse::<u32>().map_err(|_| invalid())?;
+        if bits > 32 {
+            return Err(invalid());
+        }
+        let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+        let start = u32::from(parse(net)?) & mask;
+        let end = start | !mask;
+        if !mask >= MAX_RANGE_SIZE {
+            return Err(too_large());
+        }
+        if bits >= 31 {
+            return Ok((start..=end).map(Ipv4Addr::from).collect());
+        }
+        return Ok((start + 1..end).map(Ipv4Addr::from).collect());
+    }
+
+    if let Some((start, end)) = range.split_once('-') {
+        let start = parse(start)?;
+        let end = if end.contains('.') {
+            parse(end)?
+        } else {
+            let last = end.trim().parse::<u8>().map_err(|_| invalid())?;
+            let [a, b, c, _] = start.octets();
+            Ipv4Addr::new(a, b, c, last)
+        };
+        let (start, end) = (u32::from(start), u32::from(end));
+        if end < start {
+            return Err(invalid());
+        }
+        if end - start >= MAX_RANGE_SIZE {
+            return Err(too_large());
+        }
+        return Ok((start..=end).map(Ipv4Addr::from).collect());
+    }
+
+    Ok(vec![parse(range)?])
+}
+
+fn entries_from(range: &str, vendor: Option<String>, port: Option<u16>, credentials: Option<Credentials>, tags: Tags) -> Result<Vec<FleetEntry>, Error> {
+    Ok(expand_range(range)?
+        .into_iter()
+        .map(|ip| FleetEntry {
+            ip: ip.to_string(),
+            port,
+            vendor: vendor.clone(),
+            credentials: credentials.clone(),
+            tags: tags.clone(),
+        })
+        .collect())
+}
+
+#[cfg(feature = "config")]
+fn parse_toml(text: &str) -> Result<Vec<FleetEntry>, Error> {
+    let config: Config = toml::from_str(text).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+    parse_config(config)
+}
+
+#[cfg(feature = "config")]
+fn parse_config(config: Config) -> Result<Vec<FleetEntry>, Error> {
+    let mut entries = vec![];
+    for range in config.miners {
+        let credentials = match &range.credentials {
+            Some(name) => Some(
+                config.credentials.get(name)
+                    .cloned()
+                    .ok_or_else(|| Error::InvalidConfig(format!("unknown credentials {}", name)))?
+            ),
+            None => None,
+        };
+        entries.extend(entries_from(&range.range, range.vendor, range.port, credentials, range.tags)?);
+    }
+    Ok(entries)
+}
+
+/// Columns are range,vendor,port,username,password with a header row, trailing columns can be left off
+fn parse_csv(text: &str) -> Result<Vec<FleetEntry>, Error> {
+    let mut entries = vec![];
+    for (n, line) in text.lines().enumerate().skip(1) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let cols = line.split(',').map(|c| c.trim()).collect::<Vec<_>>();
+        let col = |i: usize| cols.get(i).filter(|c| !c.is_empty()).map(|c| c.to_string());
+        let port = match col(2) {
+            Some(p) => Some(p.parse::<u16>().map_err(|_| Error::InvalidConfig(format!("invalid port on line {}", n + 1)))?),
+            None => None,
+        };
+        let credentials = match (col(3), col(4)) {
+            (Some(username), Some(password)) => Some(Credentials { username, password: password.into() }),
+            (None, None) => None,
+            _ => return Err(Error::InvalidConfig(format!("username and password must be given together on line {}", n + 1))),
+        };
+        entries.extend(entries_from(cols[0], col(1), port, credentials, Tags::new())?);
+    }
+    Ok(entries)
+}
+
+/// An inventory of miners, each is only detected and authenticated the first time it's used
+pub struct Fleet {
+    client: Client,
+    entries: Vec<FleetEntry>,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    miners: Mutex<HashMap<String, SharedMiner>>,
+}
+
+impl Fleet {
+    pub fn new(client: Client, entries: Vec<FleetEntry>) -> Fleet {
+        Fleet {
+            client,
+            entries,
+            credentials: None,
+            miners: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fetch credentials for entries that don't carry their own
+    pub fn with_credentials(mut self, provider: Arc<dyn CredentialProvider>) -> Self {
+        self.credentials = Some(provider);
+        self
+    }
+
+    /// Load an inventory from a .toml (with the `config` feature) or .csv file
+    pub fn from_config<P: AsRef<Path>>(client: Client, path: P) -> Result<Fleet, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let entries = match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => parse_csv(&text)?,
+            #[cfg(feature = "config")]
+            Some("toml") => parse_toml(&text)?,
+            _ => return Err(Error::InvalidConfig(format!("unsupported config file {}", path.display()))),
+        };
+        Ok(Fleet::new(client, entries))
+    }
+
+    pub fn entries(&self) -> &[FleetEntry] {
+        &self.entries
+    }
+
+    /// Connect to the miner at this IP, or return the existing handle
+    pub async fn get(&self, ip: &str) -> Result<SharedMiner, Error> {
+        if let Some(miner) = self.miners.lock().await.get(ip) {
+            return Ok(miner.clone());
+        }
+        let entry = self.entries.iter()
+            .find(|e| e.ip == ip)
+            .ok_or_else(|| Error::InvalidConfig(format!("{} is not in the fleet", ip)))?;
+
+        // The entry's or the fleet's credentials replace the Client's, so each miner logs in once
+        let own = entry.credentials.clone().map(|creds| StaticCredentials::new().default_credentials(creds));
+        let provider = own.as_ref().map(|p| p as &dyn CredentialProvider).or(self.credentials.as_deref());
+        let mut miner = match provider {
+            Some(provider) => self.client.get_miner_with(&entry.ip, entry.port, entry.vendor.as_deref(), provider).await?,
+            None => match &entry.vendor {
+                Some(vendor) => self.client.get_miner_as(&entry.ip, entry.port, vendor).await?,
+                None => self.client.get_miner(&entry.ip, entry.port).await?,
+            },
+        };
+        miner.set_tags(entry.tags.clone());
+        let miner = SharedMiner::new(miner);
+        // Another task may have connected in the meantime, keep whichever got there first
+        Ok(self.miners.lock().await.entry(ip.to_string()).or_insert(miner).clone())
+    }
+
+    /// Connect every miner concurrently, unreachable miners are logged and left out
+    pub async fn miners(&self) -> Vec<SharedMiner> {
+        join_all(self.entries.iter().map(|e| self.get(&e.ip)))
+            .await
+            .into_iter()
+            .zip(&self.entries)
+            .filter_map(|(r, e)| match r {
+                Ok(m) => Some(m),
+                Err(err) => {
+                    warn!("Unable to connect to {}: {}", e.ip, err);
+                    None
+                },
+            })
+            .collect()
+    }
+
+    /// Write pools built from templates to every reachable miner, metadata holds extra variables by IP
+    /// All pools are resolved before anything is written, a miner missing a variable or sharing a
+    /// worker with another is reported and left alone. Returns each miner's outcome by IP
+    pub async fn set_pools_template(&self, templates: &[PoolTemplate], metadata: &HashMap<String, HashMap<String, String>>) -> Vec<(String, Result<(), Error>)> {
+        let miners = self.miners().await;
+        let variables = join_all(miners.iter().map(|m| async move {
+            let mut variables = template::miner_variables(m).await;
+            if let Some(extra) = metadata.get(m.get_ip()) {
+                variables.extend(extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+            (m.get_ip().to_string(), variables)
+        })).await;
+
+        let resolved = template::resolve_all(templates, &variables);
+        join_all(miners.into_iter().zip(resolved).map(|(mut miner, (ip, pools))| async move {
+            let result = match pools {
+                Ok(pools) => miner.set_pools(pools).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                warn!("Not setting pools on {}: {}", ip, e);
+            }
+            (ip, result)
+        })).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_range() {
+        assert_eq!(expand_range("10.0.0.5").unwrap(), vec![Ipv4Addr::new(10, 0, 0, 5)]);
+        assert_eq!(expand_range("10.0.0.5-7").unwrap().len(), 3);
+        assert_eq!(expand_range("10.0.0.250-10.0.1.5").unwrap().len(), 12);
+        let cidr = expand_range("10.0.0.0/24").unwrap();
+        assert_eq!(cidr.len(), 254);
+        assert_eq!(cidr[0], Ipv4Addr::new(10, 0, 0, 1));
+        assert!(expand_range("10.0.0.9-5").is_err());
+        assert!(expand_range("10.0.0.0/33").is_err());
+        assert_eq!(expand_range("10.0.0.0/16").unwrap().len(), 65_534);
+        assert!(expand_range("10.0.0.0/15").is_err());
+        assert!(expand_range("10.0.0.0/8").is_err());
+        assert!(expand_range("10.0.0.0-10.1.0.0").is_err());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "range,vendor,port,username,password\n10.0.0.1-2,Antminer,,root,root\n\n10.0.1.1\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].ip, "10.0.0.2");
+        assert_eq!(entries[1].vendor.as_deref(), Some("Antminer"));
+        assert_eq!(entries[1].credentials.as_ref().unwrap().username, "root");
+        assert_eq!(entries[2].vendor, None);
+        assert_eq!(entries[2].credentials, None);
+        assert!(parse_csv("header\n10.0.0.1,,,root\n").is_err());
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_parse_toml() {
+        let toml = r#"
+            [credentials.bitmain]
+            username = "root"
+            password = "root"
+
+            [[miners]]
+            range = "10.0.0.0/30"
+            vendor = "Antminer"
+            credentials = "bitmain"
+            tags = { rack = "A3", customer = "acme" }
+        "#;
+        let entries = parse_toml(toml).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].ip, "10.0.0.1");
+        assert_eq!(entries[1].tags.get("rack").map(|s| s.as_str()), Some("A3"));
+        assert!(parse_toml("[[miners]]\nrange = \"10.0.0.1\"\ncredentials = \"missing\"").is_err());
+    }
+
+    #[cfg(feature = "vnish")]
+    #[tokio::test]
+    async fn test_entry_credentials_during_detection() {
+        use crate::detection::DetectionCache;
+        use crate::transport::ScriptedHttp;
+        use reqwest::Method;
+
+        const INFO: &str = r#"{"miner":"Antminer S19","model":"s19","fw_name":"Vnish","fw_version":"1.2.0","platform":"xil","install_type":"sd",
+            "build_time":"","system":{"os":"GNU/Linux","file_system_version":"","mem_total":233712,"mem_free":195048,"mem_free_percent":83,
+            "mem_buf":19668,"mem_buf_percent":8,"network_status":{"mac":"4E:9F:85:7B:57:7C","ip":"10.0.0.1","netmask":"255.255.254.0",
+            "gateway":"10.0.0.254","dns":[],"hostname":"Antminer"},"uptime":"9 days,  3:58"}}"#;
+        let script = ScriptedHttp::new()
+            .reply(Method::HEAD, "/", 200, "")
+            .reply(Method::GET, "/", 200, include_str!("../../fixtures/detection/vnish/s19_120.index.html"))
+            .reply(Method::POST, "/api/v1/unlock", 200, r#"{"token":"abc"}"#)
+            .reply(Method::GET, "/api/v1/info", 200, INFO);
+        let client = crate::ClientBuilder::new()
+            .http_transport(script.clone())
+            .detection_cache(DetectionCache::new(chrono::Duration::minutes(1)))
+            .default_credentials("", "client")
+            .build()
+            .unwrap();
+        let entries = parse_csv("ip,vendor,port,username,password\n10.0.0.1,,80,root,entry").unwrap();
+        let fleet = Fleet::new(client.clone(), entries);
+        let miner = fleet.get("10.0.0.1").await.unwrap();
+        assert_eq!(miner.get_type(), "Vnish");
+
+        // Only the entry's password is tried, once, and nothing else is read to fill the cache
+        let unlocks = script.bodies("/api/v1/unlock");
+        assert_eq!(unlocks.len(), 1);
+        assert!(unlocks[0].contains("entry"));
+        assert!(script.bodies("/api/v1/info").is_empty());
+        assert_eq!(client.known_mac("10.0.0.1"), None);
+
+        assert_eq!(miner.get_mac().await.unwrap(), "4E:9F:85:7B:57:7C");
+        assert!(client.observe_mac("10.0.0.1", "4E:9F:85:7B:57:7C"));
+        assert_eq!(client.known_mac("10.0.0.1").as_deref(), Some("4E:9F:85:7B:57:7C"));
+        assert!(!client.observe_mac("10.0.0.1", "11:22:33:44:55:66"));
+    }
+}
@@ -0,0 +1,19 @@
+//! Operations across many miners at once
+//! Miners are passed as SharedMiner handles so a poller can keep reading while these run
+
+mod aggregate;
+mod curtail;
+mod export;
+mod group;
+mod inventory;
+mod template;
+mod wake;
+
+pub use aggregate::{aggregate, snapshots, Aggregate, TEMP_BUCKET_WIDTH};
+pub use curtail::{curtail, CurtailStrategy, CurtailOrder, RampProfile, CurtailAction, CurtailOutcome, CurtailReport};
+pub use export::{export, export_snapshots, ExportFormat};
+pub use group::GroupMiner;
+pub use inventory::{Fleet, FleetEntry, expand_range};
+pub use template::PoolTemplate;
+pub use wake::{wake_ramped, wait_until_hashing, WakeOrder, WakeProgress, WakeOutcome, WakeReport, WAKE_HASHING_TIMEOUT, WAKE_ATTEMPTS};
+pub use crate::credentials::Credentials;
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::error::Error;
+use crate::miner::{Miner, Pool};
+use crate::SecretString;
+
+/// A pool whose fields carry {variables} filled in per miner, e.g. a worker of "{worker_base}.{rack}x{position}"
+/// Every miner has ip, ip1 to ip4 (the octets), mac (lowercase hex without separators) and vendor,
+/// anything else comes from the miner's tags or the metadata passed in alongside, which also overrides these
+#[derive(Debug, Clone)]
+pub struct PoolTemplate {
+    pub url: String,
+    pub username: String,
+    pub password: Option<SecretString>,
+}
+
+impl PoolTemplate {
+    pub fn new(url: &str, username: &str) -> PoolTemplate {
+        PoolTemplate {
+            url: url.to_string(),
+            username: username.to_string(),
+            password: None,
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Names of the variables used, in order of first use
+    pub fn variables(&self) -> Result<Vec<String>, Error> {
+        let mut names = vec![];
+        for field in [&self.url, &self.username] {
+            for name in placeholders(field)? {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// The pool for one miner, InvalidConfig naming the first variable it has no value for
+    pub fn resolve(&self, variables: &HashMap<String, String>) -> Result<Pool, Error> {
+        Ok(Pool {
+            url: render(&self.url, variables)?,
+            username: render(&self.username, variables)?,
+            password: self.password.clone(),
+        })
+    }
+}
+
+/// Variable names in a template with the byte range of their braces, InvalidConfig on an unclosed or empty {}
+/// Whitespace inside the braces is allowed, `{ rack }` is the variable rack
+fn spans(template: &str) -> Result<Vec<(Range<usize>, String)>, Error> {
+    let mut spans = vec![];
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find('{').map(|s| s + offset) {
+        let end = template[start..].find('}').map(|e| e + start)
+            .ok_or_else(|| Error::InvalidConfig(format!("unclosed {{ in {}", template)))?;
+        let name = template[start + 1..end].trim();
+        if name.is_empty() || name.contains('{') {
+            return Err(Error::InvalidConfig(format!("invalid variable in {}", template)));
+        }
+        spans.push((start..end + 1, name.to_string()));
+        offset = end + 1;
+    }
+    Ok(spans)
+}
+
+fn placeholders(template: &str) -> Result<Vec<String>, Error> {
+    Ok(spans(template)?.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Substitutes each span once, so braces in a value are left as they are
+fn render(template: &str, variables: &HashMap<String, String>) -> Result<String, Error> {
+    let mut out = String::with_capacity(template.len());
+    let mut last = 0;
+    for (span, name) in spans(template)? {
+        let value = variables.get(&name)
+            .ok_or_else(|| Error::InvalidConfig(format!("no value for {{{}}} in {}", name, template)))?;
+        out.push_str(&template[last..span.start]);
+        out.push_str(value);
+        last = span.end;
+    }
+    out.push_str(&template[last..]);
+    Ok(out)
+}
+
+/// The variables every miner has and its tags, see PoolTemplate
+pub(crate) async fn miner_variables<M: Miner + ?Sized>(miner: &M) -> HashMap<String, String> {
+    let mut variables: HashMap<String, String> = miner.tags().into_iter().collect();
+    let ip = miner.get_ip().to_string();
+    for (i, octet) in ip.split('.').enumerate().take(4) {
+        variables.insert(format!("ip{}", i + 1), octet.to_string());
+    }
+    variables.insert("ip".to_string(), ip);
+    variables.insert("vendor".to_string(), miner.get_type().to_string());
+    if let Ok(mac) = miner.get_mac().await {
+        let mac = mac.chars().filter(|c| c.is_ascii_hexdigit()).collect::<String>().to_lowercase();
+        variables.insert("mac".to_string(), mac);
+    }
+    variables
+}
+
+/// Each miner's pools from the templates, or why it has none
+/// A worker name given to more than one miner fails all of them, the pool would merge their stats
+pub(crate) fn resolve_all(templates: &[PoolTemplate], variables: &[(String, HashMap<String, String>)]) -> Vec<(String, Result<Vec<Pool>, Error>)> {
+    let mut resolved: Vec<(String, Result<Vec<Pool>, Error>)> = variables.iter()
+        .map(|(ip, vars)| (ip.clone(), templates.iter().map(|t| t.resolve(vars)).collect()))
+        .collect();
+
+    let mut owners: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    for (i, (_, pools)) in resolved.iter().enumerate() {
+        for pool in pools.iter().flatten() {
+            let owner = owners.entry((pool.url.clone(), pool.username.clone())).or_default();
+            if !owner.contains(&i) {
+                owner.push(i);
+            }
+        }
+    }
+    for ((_, worker), miners) in owners.into_iter().filter(|(_, m)| m.len() > 1) {
+        for i in miners {
+            resolved[i].1 = Err(Error::InvalidConfig(format!("worker {} is shared with another miner", worker)));
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let template = PoolTemplate::new("stratum+tcp://btc.pool.example:3333", "{worker_base}.{rack}x{position}");
+        assert_eq!(template.variables().unwrap(), vec!["worker_base", "rack", "position"]);
+        let pool = template.resolve(&vars(&[("worker_base", "acct"), ("rack", "12"), ("position", "4")])).unwrap();
+        assert_eq!(pool.username, "acct.12x4");
+        assert_eq!(pool.url, "stratum+tcp://btc.pool.example:3333");
+        assert!(template.resolve(&vars(&[("worker_base", "acct")])).is_err());
+        assert!(PoolTemplate::new("url", "acct.{rack").variables().is_err());
+        assert!(PoolTemplate::new("url", "acct.{}").variables().is_err());
+    }
+
+    #[test]
+    fn test_resolve_spaced() {
+        let template = PoolTemplate::new("url", "acct.{ rack }x{position }");
+        assert_eq!(template.variables().unwrap(), vec!["rack", "position"]);
+        let pool = template.resolve(&vars(&[("rack", "12"), ("position", "4")])).unwrap();
+        assert_eq!(pool.username, "acct.12x4");
+    }
+
+    #[test]
+    fn test_resolve_once() {
+        // A value that looks like a variable goes out as it is
+        let template = PoolTemplate::new("url", "{worker_base}.{rack}");
+        let pool = template.resolve(&vars(&[("worker_base", "{rack}"), ("rack", "12")])).unwrap();
+        assert_eq!(pool.username, "{rack}.12");
+    }
+
+    #[test]
+    fn test_resolve_all() {
+        let templates = [PoolTemplate::new("stratum+tcp://btc.pool.example:3333", "acct.{rack}")];
+        let resolved = resolve_all(&templates, &[
+            ("10.0.0.1".to_string(), vars(&[("rack", "1")])),
+            ("10.0.0.2".to_string(), vars(&[("rack", "1")])),
+            ("10.0.0.3".to_string(), vars(&[("rack", "2")])),
+            ("10.0.0.4".to_string(), vars(&[])),
+        ]);
+        assert!(resolved[0].1.is_err());
+        assert!(resolved[1].1.is_err());
+        assert_eq!(resolved[2].1.as_ref().unwrap()[0].username, "acct.2");
+        assert!(resolved[3].1.is_err());
+    }
+}
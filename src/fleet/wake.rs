@@ -0,0 +1,193 @@
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::error::Error;
+use crate::miner::{Miner, PowerState, SharedMiner};
+
+/// How long a woken miner gets to start hashing before the wake is sent again
+/// Resuming from sleep takes minutes, the boards are initialized one by one
+pub const WAKE_HASHING_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+/// Wakes sent to a miner before it's reported as failed
+pub const WAKE_ATTEMPTS: usize = 3;
+
+const HASHING_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Which sleeping miners are woken first
+#[derive(Debug, Clone)]
+pub enum WakeOrder {
+    /// Miners in this order of IP, unlisted miners follow in fleet order
+    Priority(Vec<String>),
+    /// Best J/TH first, sleeping miners report their rated efficiency
+    MostEfficient,
+}
+
+/// Reported as each miner finishes, successfully or not
+#[derive(Debug, Clone)]
+pub struct WakeProgress {
+    pub ip: String,
+    pub ok: bool,
+    /// Miners finished so far, including this one
+    pub done: usize,
+    /// Miners being woken
+    pub total: usize,
+}
+
+#[derive(Debug)]
+pub struct WakeOutcome {
+    pub ip: String,
+    /// Wakes sent
+    pub attempts: usize,
+    pub result: Result<(), Error>,
+}
+
+#[derive(Debug)]
+pub struct WakeReport {
+    pub outcomes: Vec<WakeOutcome>,
+    /// Already mining or on their way, left alone
+    pub awake: Vec<String>,
+    /// Miners that couldn't be surveyed
+    pub skipped: Vec<(String, Error)>,
+}
+
+/// Poll until the miner is hashing, VerificationTimeout if it isn't within timeout
+pub async fn wait_until_hashing<M: Miner + Sync + ?Sized>(miner: &M, timeout: Duration) -> Result<(), Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        // Errors are expected while btminer or cgminer restarts
+        if let Ok(PowerState::Mining) = miner.get_power_state().await {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(Error::VerificationTimeout(format!("{} hashing", miner.get_ip())));
+        }
+        sleep(HASHING_POLL_INTERVAL).await;
+    }
+}
+
+/// Time between wakes for a rate, None for no limit
+fn wake_interval(rate_per_minute: usize) -> Option<Duration> {
+    match rate_per_minute {
+        0 => None,
+        rate => Some(Duration::from_secs(60) / rate as u32),
+    }
+}
+
+/// Indexes of (ip, efficiency) in wake order
+fn wake_order(candidates: &[(String, f64)], order: &WakeOrder) -> Vec<usize> {
+    let mut indexes: Vec<usize> = (0..candidates.len()).collect();
+    match order {
+        WakeOrder::Priority(ips) => {
+            indexes.sort_by_key(|&i| (ips.iter().position(|ip| *ip == candidates[i].0).unwrap_or(ips.len()), i));
+        },
+        WakeOrder::MostEfficient => {
+            // Unknown efficiency (0) goes last
+            let key = |i: usize| if candidates[i].1 > 0.0 { candidates[i].1 } else { f64::MAX };
+            indexes.sort_by(|&a, &b| key(a).total_cmp(&key(b)));
+        },
+    }
+    indexes
+}
+
+/// Wake, wait for hashing, and send the wake again if it doesn't come up
+async fn wake_one(mut miner: SharedMiner) -> WakeOutcome {
+    let mut attempts = 0;
+    let result = loop {
+        attempts += 1;
+        let result = match miner.set_sleep(false).await {
+            Ok(()) => wait_until_hashing(&miner, WAKE_HASHING_TIMEOUT).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e) if attempts < WAKE_ATTEMPTS && e.is_retryable() => debug!("Waking {} failed, retrying: {}", miner.get_ip(), e),
+            result => break result,
+        }
+    };
+    WakeOutcome { ip: miner.get_ip().to_string(), attempts, result }
+}
+
+/// Wake sleeping miners at most rate_per_minute at a time, so inrush current is spread out
+/// 0 wakes them all at once. Each miner is retried until hashing, up to WAKE_ATTEMPTS wakes,
+/// unless the error isn't retryable, e.g. Unauthorized
+/// progress is called as each miner finishes
+pub async fn wake_ramped<F>(miners: &[SharedMiner], rate_per_minute: usize, order: &WakeOrder, mut progress: F) -> WakeReport
+    where F: FnMut(&WakeProgress)
+{
+    let states = join_all(miners.iter().map(|m| async move {
+        let state = m.get_power_state().await?;
+        let efficiency = match state {
+            PowerState::Sleeping => m.get_efficiency().await.unwrap_or(0.0),
+            _ => 0.0,
+        };
+        Ok::<_, Error>((state, efficiency))
+    })).await;
+
+    let mut candidates = vec![];
+    let mut awake = vec![];
+    let mut skipped = vec![];
+    for (miner, state) in miners.iter().zip(states) {
+        match state {
+            Ok((PowerState::Sleeping, efficiency)) => candidates.push((miner, efficiency)),
+            Ok(_) => awake.push(miner.get_ip().to_string()),
+            Err(e) => {
+                warn!("Skipping {} for wake: {}", miner.get_ip(), e);
+                skipped.push((miner.get_ip().to_string(), e));
+            },
+        }
+    }
+
+    let keys: Vec<(String, f64)> = candidates.iter().map(|(m, e)| (m.get_ip().to_string(), *e)).collect();
+    let interval = wake_interval(rate_per_minute);
+    let total = candidates.len();
+    debug!("Waking {} miners at {} per minute", total, rate_per_minute);
+
+    let mut pending: FuturesUnordered<_> = wake_order(&keys, order).into_iter()
+        .enumerate()
+        .map(|(n, i)| {
+            let miner = candidates[i].0.clone();
+            let delay = interval.map(|d| d * n as u32).unwrap_or(Duration::ZERO);
+            async move {
+                sleep(delay).await;
+                wake_one(miner).await
+            }
+        })
+        .collect();
+
+    let mut outcomes = vec![];
+    while let Some(outcome) = pending.next().await {
+        progress(&WakeProgress {
+            ip: outcome.ip.clone(),
+            ok: outcome.result.is_ok(),
+            done: outcomes.len() + 1,
+            total,
+        });
+        outcomes.push(outcome);
+    }
+
+    WakeReport { outcomes, awake, skipped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wake_interval() {
+        assert_eq!(wake_interval(0), None);
+        assert_eq!(wake_interval(1), Some(Duration::from_secs(60)));
+        assert_eq!(wake_interval(120), Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_wake_order() {
+        let candidates = vec![
+            ("10.0.0.1".to_string(), 34.0),
+            ("10.0.0.2".to_string(), 0.0),
+            ("10.0.0.3".to_string(), 29.5),
+        ];
+        assert_eq!(wake_order(&candidates, &WakeOrder::Priority(vec!["10.0.0.3".into()])), vec![2, 0, 1]);
+        assert_eq!(wake_order(&candidates, &WakeOrder::MostEfficient), vec![2, 0, 1]);
+        assert_eq!(wake_order(&candidates, &WakeOrder::Priority(vec![])), vec![0, 1, 2]);
+    }
+}
@@ -1,25 +1,77 @@
+// Each vendor's Miner impl lives in a file named after the vendor, e.g. miners::antminer::antminer
+#![allow(clippy::module_inception)]
+
 mod util;
-pub mod miners;
+#[macro_use]
 mod miner;
+pub mod miners;
+pub mod fleet;
+pub mod credentials;
+mod snapshot;
+mod backup;
+mod detection;
+mod model;
+pub mod watch;
+pub mod poll;
+pub mod telemetry;
+pub mod power;
+pub mod deadline;
+pub mod security;
+pub mod stratum;
+pub mod transport;
+#[cfg(feature = "snmp")]
+pub mod snmp;
+#[cfg(feature = "notify")]
+pub mod notify;
+#[cfg(feature = "ssh")]
+pub mod ssh;
 
-pub use miner::{Miner, Pool, Profile, MinerError, ErrorType};
+pub use miner::{Miner, Pool, PoolCounters, PoolCounterDiffer, SystemStats, NetworkStats, CoolingDetail, ChipCount, BoardTemps, TempReport, FanMode, HASHRATE_SETTLE_SECS, stable_hashrate, performance_ratio, Profile, MinerError, ErrorType, Capabilities, PowerState, HealthCondition, PendingChange, TuningStatus, TuningState, LogSource, LogOptions, LogEntry, Resource, Tags, SharedMiner, LockMiner, AsAny};
+pub use snapshot::{MinerSnapshot, DataQuality, Canonical, content_hash};
+pub use miners::dispatch::{MinerEnum, MinerKind};
+pub use deadline::{Deadline, DeadlineMiner};
+pub use security::SecurityIndicator;
+pub use model::{normalize_model, set_model_alias, remove_model_alias};
+pub use util::secret::SecretString;
+#[cfg(feature = "socket-tls")]
+pub use util::stream::SocketTls;
+pub use backup::MinerConfigBackup;
+pub use detection::{DetectionInfo, DetectionMethod, Confidence, Vendor, DetectionCache, DetectionStore, MemoryStore, CachedDetection};
 pub mod error;
 
 use miners::*;
 use error::Error;
-use reqwest;
+use credentials::{CredentialProvider, Credentials, StaticCredentials};
+#[cfg(all(feature = "http", any(feature = "antminer", feature = "avalon", feature = "minerva", feature = "whatsminer")))]
+use detection::Probe;
+use util::framing::{Framing, read_framed};
+use util::resolver::Resolver;
+use transport::{SocketIo, SocketTransport, TcpTransport};
+#[cfg(feature = "http")]
+use transport::{HttpTransport, SendVia};
+pub use util::resolver::AddressPreference;
+pub use util::limiter::ClientStats;
+pub use util::lenient::ParseWarning;
+use util::lenient::ParseLog;
+use util::limiter::{Limiter, SlowAcquireCallback};
+#[cfg(feature = "antminer")]
+use util::write_lock::WriteLocks;
 use serde_json::json;
 use tracing::{debug, instrument};
-use lazy_regex::regex;
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+use tracing::warn;
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+use serde::de::DeserializeOwned;
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use chrono::{DateTime, Utc};
 
 use tokio::{
     self,
     net::TcpStream,
-    io::{AsyncWriteExt, AsyncReadExt},
-    sync::{RwLock, Semaphore},
+    io::AsyncWriteExt,
+    sync::RwLock,
     time::Duration,
 };
 
@@ -28,9 +80,12 @@ use tokio::{
  * Failing this, most miners have an API exposed over HTTP, but these are highly specific
  */
 
+/// btminer answers summary within a second or two, much longer and it isn't going to
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Clone)]
 pub struct CacheItem {
-    pub token: String,
+    pub token: SecretString,
     pub token_expires: DateTime<Utc>,
 }
 
@@ -41,6 +96,38 @@ pub struct ClientBuilder {
     request_timeout: Duration,
     max_connections: usize,
     cache_token: bool,
+    local_address: Option<IpAddr>,
+    dns_ttl: Duration,
+    dns_negative_ttl: Duration,
+    address_preference: AddressPreference,
+    detection_cache: Option<DetectionCache>,
+    enabled_vendors: Option<HashSet<Vendor>>,
+    log_payloads: bool,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    subnet_limits: Vec<(String, usize)>,
+    slow_acquire: Option<(Duration, SlowAcquireCallback)>,
+    #[cfg(feature = "http")]
+    cookie_store: bool,
+    #[cfg(feature = "http")]
+    pool_max_idle_per_host: usize,
+    #[cfg(feature = "http")]
+    pool_idle_timeout: Duration,
+    #[cfg(feature = "http")]
+    tcp_keepalive: Option<Duration>,
+    lenient_parsing: bool,
+    #[cfg(feature = "socket-tls")]
+    socket_tls: Option<SocketTls>,
+    #[cfg(feature = "socket-tls")]
+    socket_tls_targets: Vec<(String, SocketTls)>,
+    socket_transport: Option<Arc<dyn SocketTransport>>,
+    #[cfg(feature = "http")]
+    http_transport: Option<Arc<dyn HttpTransport>>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClientBuilder {
@@ -50,6 +137,32 @@ impl ClientBuilder {
             request_timeout: Duration::from_secs(30),
             max_connections: 0,
             cache_token: false,
+            local_address: None,
+            dns_ttl: Duration::from_secs(60),
+            dns_negative_ttl: Duration::from_secs(5),
+            address_preference: AddressPreference::Ipv4First,
+            detection_cache: None,
+            enabled_vendors: None,
+            log_payloads: false,
+            credentials: None,
+            subnet_limits: Vec::new(),
+            slow_acquire: None,
+            #[cfg(feature = "http")]
+            cookie_store: true,
+            #[cfg(feature = "http")]
+            pool_max_idle_per_host: 0,
+            #[cfg(feature = "http")]
+            pool_idle_timeout: Duration::from_secs(10),
+            #[cfg(feature = "http")]
+            tcp_keepalive: None,
+            lenient_parsing: false,
+            #[cfg(feature = "socket-tls")]
+            socket_tls: None,
+            #[cfg(feature = "socket-tls")]
+            socket_tls_targets: Vec::new(),
+            socket_transport: None,
+            #[cfg(feature = "http")]
+            http_transport: None,
         }
     }
 
@@ -74,78 +187,345 @@ impl ClientBuilder {
         self
     }
 
+    /// Cap the max_connections permits miners in an IPv4 subnet (e.g. "10.1.0.0/16") can hold at once
+    /// So one slow site can't take every permit, overlapping subnets count against the most specific
+    /// Needs max_connections, build fails on an invalid subnet
+    pub fn subnet_limit(mut self, cidr: &str, max: usize) -> Self {
+        self.subnet_limits.push((cidr.to_string(), max));
+        self
+    }
+
+    /// Called with the wait whenever getting a miner waits longer than threshold for a max_connections permit
+    /// For spotting a poll concurrency that's too high, see also Client::stats
+    pub fn on_slow_acquire<F>(mut self, threshold: Duration, callback: F) -> Self
+        where F: Fn(Duration) + Send + Sync + 'static
+    {
+        self.slow_acquire = Some((threshold, Arc::new(callback)));
+        self
+    }
+
     /// Set whether or not to cache the tokens for the client instance
     pub fn cache_token(mut self, cache: bool) -> Self {
         self.cache_token = cache;
         self
     }
 
+    /// Bind outgoing HTTP and socket connections to the interface holding this address
+    /// For multi-homed pollers, e.g. one management VLAN per site
+    /// Hosts are only reached over the address family of the bound address
+    pub fn bind_interface(mut self, addr: IpAddr) -> Self {
+        self.local_address = Some(addr);
+        self
+    }
+
+    /// How long hostname lookups are cached, for both the socket and web APIs
+    /// Default is 60 seconds
+    pub fn dns_ttl(mut self, ttl: Duration) -> Self {
+        self.dns_ttl = ttl;
+        self
+    }
+
+    /// How long a failed hostname lookup is remembered before trying again
+    /// Default is 5 seconds
+    pub fn dns_negative_ttl(mut self, ttl: Duration) -> Self {
+        self.dns_negative_ttl = ttl;
+        self
+    }
+
+    /// Which address family connections try first for hostnames with both
+    /// Default is IPv4 first
+    pub fn address_preference(mut self, preference: AddressPreference) -> Self {
+        self.address_preference = preference;
+        self
+    }
+
+    /// Remember what was detected at each address so get_miner can skip the probe for known devices
+    /// Default is no caching
+    pub fn detection_cache(mut self, cache: DetectionCache) -> Self {
+        self.detection_cache = Some(cache);
+        self
+    }
+
+    /// Only look for these vendors during detection, on top of the compiled in features
+    /// Skips signature checks that can't match in a homogeneous farm, e.g. the Minerva HTTPS probe
+    /// Default is every compiled in vendor
+    pub fn enabled_vendors(mut self, vendors: &[Vendor]) -> Self {
+        self.enabled_vendors = Some(vendors.iter().copied().collect());
+        self
+    }
+
+    /// Include full request and response bodies in debug logs
+    /// Off by default as settings payloads can carry passwords and tokens
+    pub fn log_payloads(mut self, log: bool) -> Self {
+        self.log_payloads = log;
+        self
+    }
+
+    /// Credentials get_miner tries on every miner it returns, so the handle comes back authenticated
+    /// A miner that rejects them is still returned, unauthenticated
+    /// Shorthand for a credential_provider with a single default pair. Default is none
+    pub fn default_credentials(self, username: &str, password: &str) -> Self {
+        self.credential_provider(Arc::new(StaticCredentials::new().default_credentials(Credentials::new(username, password))))
+    }
+
+    /// Where get_miner looks up credentials for each miner it detects
+    /// They're tried during detection, so the detection cache's MAC check and recorded MAC work on
+    /// firmware that only reports it after auth
+    pub fn credential_provider(mut self, provider: Arc<dyn CredentialProvider>) -> Self {
+        self.credentials = Some(provider);
+        self
+    }
+
+    /// Keep cookies miners set, some web UIs track their session with one
+    /// Cookies are held for every host the client talks to, turn this off for large fleets of miners that don't need it
+    /// Default is on
+    #[cfg(feature = "http")]
+    pub fn cookie_store(mut self, enabled: bool) -> Self {
+        self.cookie_store = enabled;
+        self
+    }
+
+    /// Idle HTTP connections kept open per miner for reuse
+    /// Default is 0, a new connection per request, as miner web servers drop idle connections unannounced
+    #[cfg(feature = "http")]
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// How long an idle HTTP connection is kept before it's closed
+    /// Default is 10 seconds
+    #[cfg(feature = "http")]
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Send TCP keepalives on HTTP connections at this interval
+    /// Default is off
+    #[cfg(feature = "http")]
+    pub fn tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Fill in fields a response is missing instead of failing the call with ParseError
+    /// Each fill is logged and kept for Client::parse_warnings
+    pub fn lenient_parsing(mut self, lenient: bool) -> Self {
+        self.lenient_parsing = lenient;
+        self
+    }
+
+    /// Wrap every socket API connection in TLS, for fleets that put port 4028 behind stunnel
+    /// Targets given their own settings with socket_tls_for take those instead
+    #[cfg(feature = "socket-tls")]
+    pub fn socket_tls(mut self, tls: SocketTls) -> Self {
+        self.socket_tls = Some(tls);
+        self
+    }
+
+    /// Wrap socket API connections to one host, address or IPv4 subnet (e.g. "10.1.0.0/16") in TLS,
+    /// for the sites that put port 4028 behind stunnel. An exact host beats the narrowest subnet
+    /// Build fails on an invalid subnet
+    #[cfg(feature = "socket-tls")]
+    pub fn socket_tls_for(mut self, target: &str, tls: SocketTls) -> Self {
+        self.socket_tls_targets.push((target.to_string(), tls));
+        self
+    }
+
+    /// Open socket API connections through this instead of TCP, e.g. a ScriptedSocket in tests
+    /// bind_interface, the DNS settings and socket_tls only apply to the default transport
+    pub fn socket_transport<T: SocketTransport + 'static>(mut self, transport: T) -> Self {
+        self.socket_transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Send web API requests through this instead of reqwest, e.g. a ScriptedHttp in tests
+    /// Requests are still built by reqwest, so the timeouts and connection pool settings only apply to the default transport
+    #[cfg(feature = "http")]
+    pub fn http_transport<T: HttpTransport + 'static>(mut self, transport: T) -> Self {
+        self.http_transport = Some(Arc::new(transport));
+        self
+    }
+
     pub fn build(self) -> Result<Client, Error> {
+        let resolver = Arc::new(Resolver::new(self.dns_ttl, self.dns_negative_ttl, self.address_preference));
+        #[cfg(feature = "http")]
         let client = reqwest::ClientBuilder::new()
             .user_agent("libminer/0.1")
             .connect_timeout(self.connect_timeout)
             .timeout(self.request_timeout)
-            //.tcp_keepalive(None)
+            .tcp_keepalive(self.tcp_keepalive)
             .tcp_nodelay(true) // Disable Nagle's algorithm, which can cause latency issues
             .danger_accept_invalid_certs(true) // Accept self-signed certs
-            .cookie_store(true) // Some miners require a cookie store
-            .pool_max_idle_per_host(0)
-            .pool_idle_timeout(Duration::from_secs(10))
+            .cookie_store(self.cookie_store)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .local_address(self.local_address)
+            .dns_resolver(Arc::new(util::resolver::HttpResolver(resolver.clone())))
             .build()?;
         let lock = {
             if self.max_connections > 0 {
-                Some(Arc::new(Semaphore::new(self.max_connections)))
+                Some(Arc::new(Limiter::new(self.max_connections, &self.subnet_limits, self.slow_acquire)?))
+            } else if !self.subnet_limits.is_empty() {
+                return Err(Error::InvalidConfig("subnet limits need max_connections".into()));
             } else {
                 None
             }
         };
+        #[cfg(feature = "socket-tls")]
+        let socket_tls = util::stream::TlsTargets::build(self.socket_tls.as_ref(), &self.socket_tls_targets)?;
+        let socket = match self.socket_transport {
+            Some(transport) => transport,
+            None => Arc::new(TcpTransport {
+                resolver: resolver.clone(),
+                local_address: self.local_address,
+                #[cfg(feature = "socket-tls")]
+                tls: socket_tls,
+            }),
+        };
         Ok(Client {
+            #[cfg(feature = "http")]
+            http: match self.http_transport {
+                Some(transport) => transport,
+                None => Arc::new(client.clone()),
+            },
+            #[cfg(feature = "http")]
             http_client: client,
+            socket,
             connect_timeout: self.connect_timeout,
             request_timeout: self.request_timeout,
+            local_address: self.local_address,
+            resolver,
             lock,
+            #[cfg(any(feature = "minerva", feature = "vnish", feature = "whatsminer"))]
             tokens: if self.cache_token { Some(Arc::new(RwLock::new(HashMap::new()))) } else { None },
+            detection_cache: self.detection_cache,
+            enabled_vendors: self.enabled_vendors.map(Arc::new),
+            log_payloads: self.log_payloads,
+            credentials: self.credentials,
+            parse_log: if self.lenient_parsing { Some(ParseLog::default()) } else { None },
+            #[cfg(feature = "antminer")]
+            write_locks: WriteLocks::default(),
         })
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Client {
+    /// Builds requests, they're sent through http
+    #[cfg(feature = "http")]
     http_client: reqwest::Client,
+    #[cfg(feature = "http")]
+    http: Arc<dyn HttpTransport>,
+    socket: Arc<dyn SocketTransport>,
     connect_timeout: Duration,
     request_timeout: Duration,
-    lock: Option<Arc<Semaphore>>,
+    local_address: Option<IpAddr>,
+    resolver: Arc<Resolver>,
+    lock: Option<Arc<Limiter>>,
+    #[cfg(any(feature = "minerva", feature = "vnish", feature = "whatsminer"))]
     tokens: Option<Cache>,
+    detection_cache: Option<DetectionCache>,
+    enabled_vendors: Option<Arc<HashSet<Vendor>>>,
+    log_payloads: bool,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    parse_log: Option<ParseLog>,
+    #[cfg(feature = "antminer")]
+    write_locks: WriteLocks,
 }
 
 impl Client {
+    /// Permits in use and waiting under max_connections, empty without a limit
+    pub fn stats(&self) -> ClientStats {
+        self.lock.as_ref().map(|l| l.stats()).unwrap_or_default()
+    }
+
+    /// What lenient parsing filled in since the last call, empty unless it's on
+    pub fn parse_warnings(&self) -> Vec<ParseWarning> {
+        self.parse_log.as_ref().map(|l| l.take()).unwrap_or_default()
+    }
+
+    /// Deserialize a response, through lenient parsing if the client has it on
+    #[cfg(any(feature = "antminer", feature = "whatsminer"))]
+    fn parse<T: DeserializeOwned>(&self, ip: &str, endpoint: &str, body: &str) -> Result<T, Error> {
+        let err = match (serde_json::from_str(body), &self.parse_log) {
+            (Ok(value), _) => return Ok(value),
+            (Err(e), None) => return Err(e.into()),
+            (Err(e), Some(_)) => e,
+        };
+        let (value, patched) = util::lenient::from_str_lenient(body).map_err(|_| err)?;
+        for message in patched {
+            warn!("Lenient parse of {} from {}: {}", endpoint, ip, message);
+            if let Some(log) = &self.parse_log {
+                log.push(ParseWarning { ip: ip.to_string(), endpoint: endpoint.to_string(), message });
+            }
+        }
+        Ok(value)
+    }
+
+    /// Serialize settings writes to a miner across every handle made from this Client or its clones
+    #[cfg(feature = "antminer")]
+    async fn write_lock(&self, ip: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        self.write_locks.lock(ip).await
+    }
+
+    /// A body for debug logs, unless payload logging is off
+    fn payload<'a>(&self, body: &'a str) -> &'a str {
+        if self.log_payloads { body } else { "<payload hidden>" }
+    }
+
+    fn vendor_enabled(&self, vendor: Vendor) -> bool {
+        match &self.enabled_vendors {
+            Some(vendors) => vendors.contains(&vendor),
+            None => true,
+        }
+    }
+
     /// Connect to a given host with the timeout specified
-    async fn connect(&self, ip: &str, port: u16) -> Result<TcpStream, Error> {
+    async fn connect(&self, ip: &str, port: u16) -> Result<Box<dyn SocketIo>, Error> {
         match tokio::time::timeout(
             self.connect_timeout,
-            TcpStream::connect(format!("{}:{}", ip, port))
+            self.socket.connect(ip, port),
         ).await {
-            Ok(Ok(stream)) => Ok(stream),
-            Ok(Err(_)) => Err(Error::NoHostDetected),
+            Ok(result) => result,
             Err(_) => Err(Error::Timeout),
         }
     }
 
+    /// Resolve through the cache, then race the addresses from the bound interface if there is one
+    /// Always TCP, for checks on the host itself rather than its socket API
+    async fn connect_from(&self, ip: &str, port: u16) -> Result<TcpStream, Error> {
+        let addrs = self.resolver.resolve(ip).await?;
+        util::resolver::connect(&addrs, port, self.local_address).await
+    }
+
     /// Connect to a host and send data return data as String, close connection after request
     async fn send_recv<T>(&self, ip: &str, port: u16, data: &T) -> Result<String, Error> 
         where T: ToString + ?Sized
     {
-        let mut stream = self.connect(ip, port).await?;
+        self.send_recv_framed(ip, port, data, Framing::Eof).await
+    }
+
+    /// As send_recv, but stops reading as soon as the framing says the response is complete
+    async fn send_recv_framed<T>(&self, ip: &str, port: u16, data: &T, framing: Framing) -> Result<String, Error> 
+        where T: ToString + ?Sized
+    {
+        let stream = self.connect(ip, port).await?;
+        Self::exchange(stream, data, framing, self.request_timeout).await
+    }
+
+    /// Write a request to a connected stream and read the response, Timeout if it isn't all there within timeout
+    async fn exchange<T>(mut stream: Box<dyn SocketIo>, data: &T, framing: Framing, timeout: Duration) -> Result<String, Error>
+        where T: ToString + ?Sized
+    {
         match tokio::time::timeout(
-            self.request_timeout,
+            timeout,
             async {
-                stream.writable().await?;
                 stream.write_all(data.to_string().as_bytes()).await?;
-                let mut buf = String::new();
-                stream.readable().await?;
-                stream.read_to_string(&mut buf).await?;
-                buf = buf.replace("\0", ""); // Fix for Antminer bug
-                Ok(buf)
+                let (buf, _) = read_framed(&mut stream, framing).await?;
+                let buf = String::from_utf8(buf).map_err(|_| Error::EncodingError)?;
+                Ok(buf.replace("\0", "")) // Fix for Antminer bug
             }
         ).await {
             Ok(result) => result,
@@ -153,7 +533,24 @@ impl Client {
         }
     }
 
+    /// As send_recv_framed, but a connection that's taken and then never answered is ApiDeadlocked
+    /// rather than a Timeout, the host is up and it's the process behind the socket that's wedged
+    /// Failing to connect at all is still a Timeout or IoError
+    async fn send_recv_live<T>(&self, ip: &str, port: u16, data: &T, framing: Framing, timeout: Duration) -> Result<String, Error>
+        where T: ToString + ?Sized
+    {
+        let stream = self.connect(ip, port).await?;
+        match Self::exchange(stream, data, framing, timeout).await {
+            Err(Error::Timeout) => {
+                debug!("{}:{} took the connection but never answered", ip, port);
+                Err(Error::ApiDeadlocked)
+            },
+            result => result,
+        }
+    }
+
     /// Send data over a websocket to a host
+    #[cfg(feature = "avalon")]
     async fn send<T>(&self, ip: &str, port: u16, data: &T) -> Result<(), Error> 
         where T: ToString
     {
@@ -161,7 +558,6 @@ impl Client {
         match tokio::time::timeout(
             self.request_timeout,
             async {
-                stream.writable().await?;
                 stream.write_all(data.to_string().as_bytes()).await?;
                 Ok(())
             }
@@ -173,95 +569,71 @@ impl Client {
 
     /// Attempts to perform miner detection against the cgminer socket API roughly implemented by most miners
     /// NOTES:
-    /// * On Minervas using the Minera interface, the cgminer API can be deadlocked
-    /// * On Whatsminers, the socket API can be responsive but btminer deadlocked, this results in detection successful but every call failing
+    /// * On Minervas using the Minera interface, the cgminer API can be deadlocked, detection then fails
+    ///   with ApiDeadlocked and Miner::recover on a Minera restarts cgminer from the web interface
+    /// * On Whatsminers, the socket API can be responsive but btminer deadlocked, a summary probe after detection
+    ///   turns this into ApiDeadlocked rather than a miner whose every call fails
     async fn socket_detect(&self, ip: &str, port: u16) -> Result<Box<dyn Miner + Send + Sync>, Error> {
         debug!("Trying socket detection...");
         match self.send_recv(ip, port, &json!({"command": "stats"})).await {
             Ok(resp) => {
                 debug!("Received response from socket API...");
-                if let Ok(stats_resp) = serde_json::from_str::<common::StatsResp>(&resp) {
-                    debug!("Received valid cgminer response.");
-                    if stats_resp.status[0].status != common::StatusCode::SUCC {
-                        return Err(Error::ApiCallFailed(stats_resp.status[0].msg.clone()));
+                let (vendor, info) = match detection::socket_signature(&resp) {
+                    Ok(signature) => signature,
+                    Err(e) => {
+                        debug!("No socket signature at {}: {}\n{}", ip, e, self.payload(&resp));
+                        return Err(e);
                     }
-                    if let Some(stats) = stats_resp.stats {
-                        debug!("Checking for type in stats response...");
-                        for stat in stats {
-                            match stat {
-                                #[cfg(feature = "antminer")]
-                                common::Stats::AmVersion(_) => {
-                                    debug!("Found Antminer miner at {}", ip);
-                                    return Ok(Box::new(antminer::Antminer::new(self.clone(), ip.into(), port)));
-                                },
-                                #[cfg(feature = "avalon")]
-                                common::Stats::AvaStats(_) => {
-                                    debug!("Found Avalon miner at {}", ip);
-                                    return Ok(Box::new(avalon::Avalon::new(self.clone(), ip.into(), port)));
-                                },
-                                #[cfg(feature = "minerva")]
-                                common::Stats::Dev(stat) => {
-                                    if let Some(type_) = stat.type_ {
-                                        if type_ == "Minerva" {
-                                            // We need to differentiate between the 2 interfaces
-                                            // easiest thing is to send a GET request to /index.php
-                                            // If we get a 200, we know its running minera
-                                            debug!("Found Minerva, determining interface...");
-                                            let resp2 = self.http_client
-                                                .get(&format!("http://{}/index.php", ip))
-                                                .send()
-                                                .await?;
-                                            return match resp2.status() {
-                                                reqwest::StatusCode::NOT_FOUND => {
-                                                    debug!("Found Minerva (Custom Interface) at {}", ip);
-                                                    Ok(Box::new(minerva::Minerva::new(self.clone(), ip.into(), port)))
-                                                }
-                                                reqwest::StatusCode::OK => {
-                                                    debug!("Found Minerva (Minera Interface) at {}", ip);
-                                                    Ok(Box::new(minerva::Minera::new(self.clone(), ip.into(), port)))
-                                                }
-                                                _ => {
-                                                    debug!("Unable to determine interface for Minerva at {}", ip);
-                                                    Err(Error::UnknownMinerType("Unable to determine interface for Minerva".into()))
-                                                },
-                                            };
-                                        } else {
-                                            debug!("Unsupported miner type: {} at {}", type_, ip);
-                                            return Err(Error::UnknownMinerType(format!("Unsupported miner type: {}", type_)));
-                                        }
-                                    } else {
-                                        debug!("Miner did not include type in response at {}", ip);
-                                        return Err(Error::UnknownMinerType("Miner did not include type in response".into()));
-                                    }
-                                }
-                                _ => {} // We don't care about the other stats
+                };
+                // A device of a vendor left out of enabled_vendors isn't an unknown one
+                if vendor != Vendor::Cgminer && !self.vendor_enabled(vendor) {
+                    debug!("Found {:?} at {}, which isn't enabled", vendor, ip);
+                    return Err(Error::UnknownMinerType(format!("{:?} is not enabled", vendor)));
+                }
+                match vendor {
+                    #[cfg(feature = "minerva")]
+                    Vendor::Minerva => {
+                        // We need to differentiate between the 2 interfaces
+                        // easiest thing is to send a GET request to /index.php
+                        // If we get a 200, we know its running minera
+                        debug!("Found Minerva, determining interface...");
+                        let resp2 = self.http_client
+                            .get(format!("http://{}/index.php", ip))
+                            .send_via(self)
+                            .await?;
+                        match resp2.status() {
+                            reqwest::StatusCode::NOT_FOUND => {
+                                debug!("Found Minerva (Custom Interface) at {}", ip);
+                                let info = info.firmware("Minerva");
+                                Ok(Box::new(minerva::Minerva::new(self.clone(), ip.into(), port).with_cache(self.tokens.clone()).with_detection_info(info)))
+                            }
+                            reqwest::StatusCode::OK => {
+                                debug!("Found Minerva (Minera Interface) at {}", ip);
+                                let info = info.firmware("Minera");
+                                Ok(Box::new(minerva::Minera::new(self.clone(), ip.into(), port).with_detection_info(info)))
                             }
+                            _ => {
+                                debug!("Unable to determine interface for Minerva at {}", ip);
+                                Err(Error::UnknownMinerType("Unable to determine interface for Minerva".into()))
+                            },
                         }
-                        debug!("Stats did not include a section containing type at {}\n{}", ip, resp);
-                        return Err(Error::UnknownMinerType("Stats did not include a section containing type".into()));
-                    } else {
-                        debug!("Unable to parse stats response at {}\n{}", ip, resp);
-                        return Err(Error::UnknownMinerType("Unable to parse stats response".into()));
                     }
-                } else if let Ok(status) = serde_json::from_str::<common::Status>(&resp) {
-                    // Whatsminer returns just the cgminer status error with invalid json and a description containing whatsminer
-                    // {"STATUS":"E","When":"0","Code":23,"Msg":"Invalid JSON","Description":"whatsminer"}
-                    //TODO: Don't hardcode the status code for Invalid Command
-                    #[cfg(feature = "whatsminer")]
-                    if status.status == common::StatusCode::ERROR && status.code == Some(14) {
-                        // lowercase and regex the description for "whatsminer"
-                        if let Some(desc) = status.description {
-                            if desc.to_lowercase().contains("whatsminer") {
-                                debug!("Found Whatsminer at {}", ip);
-                                return Ok(Box::new(whatsminer::Whatsminer::new(self.clone(), ip.into(), port)));
+                    Vendor::Cgminer => self.generic_cgminer(ip, port, info),
+                    vendor => {
+                        match self.build_miner(ip, port, &format!("{:?}", vendor), info.clone()) {
+                            Some(miner) => {
+                                debug!("Found {:?} at {}", vendor, ip);
+                                if vendor == Vendor::Whatsminer && info.firmware.as_deref() != Some(detection::WHATSMINER_CGMINER) {
+                                    // btminer answers the stats probe with its canned error even when deadlocked
+                                    self.liveness(ip, port, &json!({"cmd": "summary"}), Framing::Json).await?;
+                                }
+                                Ok(miner)
                             }
+                            // Whatsminer's error isn't a working cgminer API, its cgminer fallback is
+                            None if vendor == Vendor::Whatsminer && info.firmware.as_deref() != Some(detection::WHATSMINER_CGMINER) => Err(Error::UnknownMinerType("Received error response but not whatsminer".into())),
+                            None => self.generic_cgminer(ip, port, info),
                         }
                     }
-                    debug!("Received error response but not whatsminer at {}\n{}", ip, resp);
-                    return Err(Error::UnknownMinerType("Received error response but not whatsminer".into()));
-                } else {
-                    debug!("Unable to parse response from socket API: {}", resp);
-                    return Err(Error::UnknownMinerType("Unable to parse response from socket API".into()));
                 }
             }
             Err(e) => {
@@ -273,79 +645,116 @@ impl Client {
         }
     }
 
+    /// ApiDeadlocked if the socket takes a cheap command and never answers it, for vendors whose
+    /// detection doesn't already depend on an answer
+    async fn liveness(&self, ip: &str, port: u16, probe: &serde_json::Value, framing: Framing) -> Result<(), Error> {
+        let timeout = LIVENESS_TIMEOUT.min(self.request_timeout);
+        self.send_recv_live(ip, port, probe, framing, timeout).await.map(drop)
+    }
+
+    /// A cgminer API from a vendor we don't know, better partial data than none
+    fn generic_cgminer(&self, ip: &str, port: u16, info: DetectionInfo) -> Result<Box<dyn Miner + Send + Sync>, Error> {
+        if !self.vendor_enabled(Vendor::Cgminer) {
+            return Err(Error::UnknownMinerType("cgminer API from an unknown or disabled vendor".into()));
+        }
+        debug!("Falling back to generic cgminer at {}", ip);
+        Ok(Box::new(cgminer::GenericCgminer::new(self.clone(), ip.into(), port).with_detection_info(info.low_confidence())))
+    }
+
+    #[cfg(feature = "http")]
+    // resp is only read by the Antminer check and port only by the vendor checks
+    #[cfg_attr(not(feature = "antminer"), allow(unused_variables))]
     async fn http_detect(&self, ip: &str, port: u16) -> Result<Box<dyn Miner + Send + Sync>, Error> {
         debug!("Trying HTTP detection...");
         // To reduce traffic and since detection is entirely on status response, we can just send a HEAD request
         // Start with Antminer, if this fails to connect return a timeout
-        match self.http_client.head(&format!("http://{}/", ip)).send().await {
+        match self.http_client.head(format!("http://{}/", ip)).send_via(self).await {
             Ok(resp) => {
                 debug!("Received response from HTTP API...");
                 //TODO: In theory we could probably do this with a single request
                 #[cfg(feature = "antminer")]
-                if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+                if resp.status() == reqwest::StatusCode::UNAUTHORIZED && self.vendor_enabled(Vendor::Antminer) {
                     if let Some(auth) = resp.headers().get("WWW-Authenticate") {
-                        let re = regex!(r"^[Dd]igest");
-                        if re.is_match(auth.to_str()?) {
+                        if detection::http_signature(Probe::Challenge, auth.to_str()?) == Some(Vendor::Antminer) {
                             debug!("Found Antminer at {}", ip);
-                            return Ok(Box::new(antminer::Antminer::new(self.clone(), ip.into(), port)));
+                            let info = DetectionInfo::new(DetectionMethod::Http);
+                            return Ok(Box::new(antminer::Antminer::new(self.clone(), ip.into(), port).with_detection_info(info)));
                         }
                     }
                 }
                 #[cfg(feature = "vnish")]
-                {
+                if self.vendor_enabled(Vendor::Vnish) {
                     debug!("Checking for VNISH...");
-                    if let Ok(resp) = self.http_client.get(&format!("http://{}/", ip)).send().await {
-                        let re = regex!(r"miner-dash-app");
-                        if re.is_match(&resp.text().await?) {
+                    if let Ok(resp) = self.http_client.get(format!("http://{}/", ip)).send_via(self).await {
+                        let text = resp.text().await?;
+                        if detection::http_signature(Probe::Index, &text) == Some(Vendor::Vnish) {
                             debug!("Found VNISH at {}", ip);
-                            return Ok(Box::new(vnish::Vnish::new(self.clone(), ip.into(), port)));
+                            let info = DetectionInfo::new(DetectionMethod::Http).firmware("Vnish").page(&text);
+                            return Ok(Box::new(vnish::Vnish::new(self.clone(), ip.into(), port).with_cache(self.tokens.clone()).with_detection_info(info)));
                         }
                     }
                 }
                 #[cfg(feature = "avalon")]
-                {
-                    let re = regex!(r"<title>Avalon Device</title>");
-                    if let Ok(resp) = self.http_client.get(&format!("http://{}/", ip)).send().await {
-                        if re.is_match(&resp.text().await?) {
+                if self.vendor_enabled(Vendor::Avalon) {
+                    if let Ok(resp) = self.http_client.get(format!("http://{}/", ip)).send_via(self).await {
+                        let text = resp.text().await?;
+                        if detection::http_signature(Probe::Index, &text) == Some(Vendor::Avalon) {
                             debug!("Found Avalon at {}", ip);
-                            return Ok(Box::new(avalon::Avalon::new(self.clone(), ip.into(), port)));
+                            let info = DetectionInfo::new(DetectionMethod::Http).page(&text);
+                            return Ok(Box::new(avalon::Avalon::new(self.clone(), ip.into(), port).with_detection_info(info)));
                         }
                     }
                 }
                 #[cfg(feature = "minerva")]
-                {
-                    // 2 fan minervas have the title Minerva and are based off umi
+                if self.vendor_enabled(Vendor::Minerva) {
                     debug!("Checking for custom Minerva...");
-                    let re = regex!(r"Minerva(.|\n)+umi");
-                    let resp = self.http_client.get(&format!("https://{}", ip)).send().await;
+                    let resp = self.http_client.get(format!("https://{}", ip)).send_via(self).await;
                     if let Ok(resp) = resp {
                         let text = resp.text().await?;
-                        if re.is_match(&text) {
+                        if detection::http_signature(Probe::SecureIndex, &text) == Some(Vendor::Minerva) {
                             debug!("Found Minerva (Custom Interface) at {}", ip);
-                            return Ok(Box::new(minerva::Minerva::new(self.clone(), ip.into(), port)));
+                            let info = DetectionInfo::new(DetectionMethod::Http).firmware("Minerva").page(&text);
+                            return Ok(Box::new(minerva::Minerva::new(self.clone(), ip.into(), port).with_cache(self.tokens.clone()).with_detection_info(info)));
                         }
                     }
 
                     // 4 fan minervas permit a request to /index.php/app/stats even when not logged in
                     debug!("Checking for minera Minerva...");
-                    let resp = self.http_client.head(&format!("http://{}/index.php/app/stats", ip)).send().await?;
+                    let resp = self.http_client.head(format!("http://{}/index.php/app/stats", ip)).send_via(self).await?;
                     if resp.status() == reqwest::StatusCode::OK {
                         debug!("Found Minerva at {}", ip);
-                        return Ok(Box::new(minerva::Minera::new(self.clone(), ip.into(), port)));
+                        // The web interface stays up with cgminer wedged behind it, get_miner_as(ip, port, "Minera")
+                        // still builds one to call recover on
+                        self.liveness(ip, port, &json!({"command": "summary"}), Framing::Eof).await?;
+                        let info = DetectionInfo::new(DetectionMethod::Http).firmware("Minera");
+                        return Ok(Box::new(minerva::Minera::new(self.clone(), ip.into(), port).with_detection_info(info)));
                     }
                 }
 
                 #[cfg(feature = "whatsminer")]
-                {
+                if self.vendor_enabled(Vendor::Whatsminer) {
                     // Lastly check whatsminers, /cgi-bin/luci and look for whatsminer in the body
                     debug!("Checking for Whatsminer...");
-                    let resp = self.http_client.get(&format!("http://{}/cgi-bin/luci", ip)).send().await?;
+                    let resp = self.http_client.get(format!("http://{}/cgi-bin/luci", ip)).send_via(self).await?;
                     if resp.status() == reqwest::StatusCode::FORBIDDEN {
-                        let re = regex!(r"<title>WhatsMiner");
-                        if re.is_match(&resp.text().await?) {
+                        let text = resp.text().await?;
+                        if detection::http_signature(Probe::Luci, &text) == Some(Vendor::Whatsminer) {
                             debug!("Detected Whatsminer at {}:{}", ip, port);
                             //warn!("Socket API did not respond, this miner may not work.");
-                            return Ok(Box::new(whatsminer::Whatsminer::new(self.clone(), ip.to_string(), port).with_cache(self.tokens.clone())));
+                            let info = DetectionInfo::new(DetectionMethod::Http).page(&text);
+                            return Ok(Box::new(whatsminer::Whatsminer::new(self.clone(), ip.to_string(), port).with_cache(self.tokens.clone()).with_detection_info(info)));
+                        }
+                    }
+                }
+
+                // A unit booted into its recovery image only serves an upload page
+                if self.vendor_enabled(Vendor::Antminer) || self.vendor_enabled(Vendor::Whatsminer) {
+                    if let Ok(resp) = self.http_client.get(format!("http://{}/", ip)).send_via(self).await {
+                        let text = resp.text().await?;
+                        if let Some(miner) = recovery::RecoveryMiner::from_page(self.clone(), ip.into(), &text) {
+                            debug!("Found {:?} in recovery mode at {}", miner.vendor(), ip);
+                            let info = DetectionInfo::new(DetectionMethod::Http).firmware("Recovery").page(&text);
+                            return Ok(Box::new(miner.with_detection_info(info)));
                         }
                     }
                 }
@@ -353,62 +762,221 @@ impl Client {
                 debug!("Unable to determine miner type {}", ip);
                 Err(Error::UnknownMinerType("".into()))
             }
+            Err(Error::RequestError(e)) if e.is_timeout() => Err(Error::Timeout),
+            Err(Error::RequestError(e)) if e.is_connect() => Err(Error::NoMinerDetected),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Construct a miner of a known vendor, matched against Miner::get_type
+    /// MinerVa needs the firmware name to pick between its two interfaces
+    fn build_miner(&self, ip: &str, port: u16, vendor: &str, info: DetectionInfo) -> Option<Box<dyn Miner + Send + Sync>> {
+        let miner: Box<dyn Miner + Send + Sync> = match vendor.to_lowercase().as_str() {
+            #[cfg(feature = "antminer")]
+            "antminer" => Box::new(antminer::Antminer::new(self.clone(), ip.into(), port).with_detection_info(info)),
+            #[cfg(feature = "avalon")]
+            "avalon" => Box::new(avalon::Avalon::new(self.clone(), ip.into(), port).with_detection_info(info)),
+            #[cfg(feature = "vnish")]
+            "vnish" => Box::new(vnish::Vnish::new(self.clone(), ip.into(), port).with_cache(self.tokens.clone()).with_detection_info(info)),
+            #[cfg(feature = "whatsminer")]
+            "whatsminer" => Box::new(whatsminer::Whatsminer::new(self.clone(), ip.into(), port).with_cache(self.tokens.clone()).with_detection_info(info)),
+            "cgminer" => Box::new(cgminer::GenericCgminer::new(self.clone(), ip.into(), port).with_detection_info(info)),
+            #[cfg(feature = "minerva")]
+            "minerva" => match info.firmware.as_deref() {
+                Some("Minerva") => Box::new(minerva::Minerva::new(self.clone(), ip.into(), port).with_cache(self.tokens.clone()).with_detection_info(info)),
+                Some("Minera") => Box::new(minerva::Minera::new(self.clone(), ip.into(), port).with_detection_info(info)),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(miner)
+    }
+
+    /// Construct a miner without detection when the vendor is already known, vendor is matched against Miner::get_type
+    /// Unknown vendors, and MinerVa which has two interfaces, fall back to detection
+    pub async fn get_miner_as(&self, ip: &str, port: Option<u16>, vendor: &str) -> Result<Box<dyn Miner + Send + Sync>, Error> {
+        self.open(ip, port.unwrap_or(4028), Some(vendor), None).await
+    }
+
+    /// As get_miner, or get_miner_as with a vendor, but authenticated from provider instead of the
+    /// Client's credentials. Fails if the miner rejects what the provider gives
+    pub async fn get_miner_with(&self, ip: &str, port: Option<u16>, vendor: Option<&str>, provider: &dyn CredentialProvider) -> Result<Box<dyn Miner + Send + Sync>, Error> {
+        self.open(ip, port.unwrap_or(4028), vendor, Some(provider)).await
+    }
+
+    /// MAC recorded for an IP in the detection cache, known without authenticating
+    pub(crate) fn known_mac(&self, ip: &str) -> Option<String> {
+        self.detection_cache.as_ref()?.get(ip)?.mac
+    }
+
+    /// Record a MAC read for an IP, e.g. by MinerSnapshot::take, in the detection cache
+    /// Detection never asks for it, so this is how the cache learns which device has the address
+    /// Returns false if a different device now has it, the next get_miner detects it again
+    pub fn observe_mac(&self, ip: &str, mac: &str) -> bool {
+        match &self.detection_cache {
+            Some(cache) => cache.observe_mac(ip, mac),
+            None => true,
+        }
+    }
+
+    /// Build the miner from the detection cache, if there's an entry for this port
+    fn cached_miner(&self, ip: &str, port: u16) -> Option<Box<dyn Miner + Send + Sync>> {
+        let cache = self.detection_cache.as_ref()?;
+        let entry = cache.get(ip).filter(|entry| entry.port == port)?;
+        let info = entry.info.unwrap_or_else(|| DetectionInfo::new(DetectionMethod::Explicit));
+        let miner = self.build_miner(ip, port, &entry.vendor, info);
+        if miner.is_none() {
+            cache.invalidate(ip);
+        }
+        miner
+    }
+
+    /// Probe HTTP then the socket API
+    #[cfg(feature = "http")]
+    async fn detect(&self, ip: &str, port: u16) -> Result<Box<dyn Miner + Send + Sync>, Error> {
+        match self.http_detect(ip, port).await {
+            Ok(miner) => Ok(miner),
             Err(e) => {
-                if e.is_timeout() {
-                    Err(Error::Timeout)
-                } else if e.is_connect() {
-                    Err(Error::NoMinerDetected)
-                } else {
-                    Err(e.into())
+                match self.socket_detect(ip, port).await {
+                    Ok(miner) => Ok(miner),
+                    Err(e2) => Err(Error::detection_failed(e, e2)),
                 }
             }
         }
     }
 
+    /// Only the socket API can be probed without the http feature
+    #[cfg(not(feature = "http"))]
+    async fn detect(&self, ip: &str, port: u16) -> Result<Box<dyn Miner + Send + Sync>, Error> {
+        self.socket_detect(ip, port).await
+    }
+
+    /// The MAC is left for observe_mac, reading it here would cost a request on every detection
+    fn remember(&self, ip: &str, port: u16, miner: &(dyn Miner + Send + Sync)) {
+        if let Some(cache) = &self.detection_cache {
+            cache.put(ip, CachedDetection {
+                vendor: miner.get_type().to_string(),
+                port,
+                mac: None,
+                info: miner.detection_info(),
+                detected_at: Utc::now(),
+            });
+        }
+    }
+
+    /// Authenticate from provider, or the Client's credentials if it's None
+    /// Only an explicit provider's rejection is an error, the Client's credentials are best effort
+    async fn login(&self, ip: &str, miner: &mut Box<dyn Miner + Send + Sync>, provider: Option<&dyn CredentialProvider>) -> Result<(), Error> {
+        let (provider, strict) = match (provider, &self.credentials) {
+            (Some(provider), _) => (provider, true),
+            (None, Some(provider)) => (provider.as_ref(), false),
+            (None, None) => return Ok(()),
+        };
+        match credentials::authenticate(miner.as_mut(), provider, self.known_mac(ip).as_deref()).await {
+            Ok(_) => Ok(()),
+            Err(e) if !strict => {
+                debug!("Credentials not accepted by {}: {}", ip, e);
+                Ok(())
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Detect, or build from the detection cache, then authenticate
+    /// Credentials are looked up with the MAC the cache knows, if observe_mac has recorded one
+    async fn detect_authenticated(&self, ip: &str, port: u16, provider: Option<&dyn CredentialProvider>) -> Result<Box<dyn Miner + Send + Sync>, Error> {
+        if let Some(mut miner) = self.cached_miner(ip, port) {
+            debug!("Using cached detection for {}", ip);
+            self.login(ip, &mut miner, provider).await?;
+            return Ok(miner);
+        }
+        let mut miner = self.detect(ip, port).await?;
+        self.login(ip, &mut miner, provider).await?;
+        self.remember(ip, port, &*miner);
+        Ok(miner)
+    }
+
+    /// Detect a miner again, e.g. after it was reflashed from stock to Vnish and calls started failing
+    /// If the type changed the new implementation is authenticated with the credentials, or the
+    /// Client's credential provider, and swapped in place, a LockMiner keeps its permit
+    /// Returns true if the miner was swapped
+    /// See Error::suggests_redetect for when this is worth trying
+    pub async fn refresh(&self, miner: &mut Box<dyn Miner + Send + Sync>, port: Option<u16>, credentials: Option<&Credentials>) -> Result<bool, Error> {
+        let port = port.unwrap_or(4028);
+        let ip = miner.get_ip().to_string();
+        if let Some(cache) = &self.detection_cache {
+            cache.invalidate(&ip);
+        }
+        let detected = self.detect(&ip, port).await?;
+        self.remember(&ip, port, &*detected);
+        if detected.get_type() == miner.get_type() {
+            return Ok(false);
+        }
+        debug!("{} changed from {} to {}", ip, miner.get_type(), detected.get_type());
+        #[cfg(feature = "otel")]
+        let detected: Box<dyn Miner + Send + Sync> = Box::new(telemetry::otel::InstrumentedMiner::new(detected));
+        let mut detected = detected;
+        match credentials {
+            Some(credentials) => detected.auth(&credentials.username, credentials.password.expose()).await?,
+            None => self.login(&ip, &mut detected, None).await?,
+        }
+        match (**miner).as_any_mut().downcast_mut::<LockMiner>() {
+            Some(locked) => {
+                locked.replace_inner(detected);
+            },
+            None => *miner = detected,
+        }
+        Ok(true)
+    }
+
     /// Detects the type of miner at the given IP and port
     /// Default port is 4028
     #[instrument]
     pub async fn get_miner(&self, ip: &str, port: Option<u16>) -> Result<Box<dyn Miner + Send + Sync>, Error> {
-        let port = port.unwrap_or(4028);
+        self.open(ip, port.unwrap_or(4028), None, None).await
+    }
+
+    /// Build the miner for vendor, or detect it, authenticated and wrapped for max_connections and otel
+    async fn open(&self, ip: &str, port: u16, vendor: Option<&str>, provider: Option<&dyn CredentialProvider>) -> Result<Box<dyn Miner + Send + Sync>, Error> {
         let permit = {
             if let Some(lock) = &self.lock {
-                Some(lock.clone().acquire_owned().await?)
+                Some(lock.acquire(ip).await?)
             } else {
                 None
             }
         };
-        debug!("Detecting miner at {}:{}", ip, port);
-        let miner = {
-            match self.http_detect(ip, port).await {
-                Ok(miner) => Ok(miner),
-                Err(e) => {
-                    match self.socket_detect(ip, port).await {
-                        Ok(miner) => Ok(miner),
-                        Err(e2) => {
-                            // Handle some special error cases, default to returning the first error
-                            match (e, e2) {
-                                (Error::Timeout, Error::Timeout) => Err(Error::Timeout),
-                                (Error::Timeout, e) => Err(e),
-                                (e, Error::Timeout) => Err(e),
-                                (Error::NoMinerDetected, Error::NoMinerDetected) => Err(Error::NoMinerDetected),
-                                (Error::UnknownMinerType(s), Error::UnknownMinerType(s2)) => Err(Error::UnknownMinerType(format!("{} and {}", s, s2))),
-                                (Error::UnknownMinerType(s), e) => Err(Error::UnknownMinerType(format!("{} and {}", s, e))),
-                                (e, Error::UnknownMinerType(s)) => Err(Error::UnknownMinerType(format!("{} and {}", e, s))),
-                                (Error::NoMinerDetected, e) => Err(Error::UnknownMinerType(format!("No miner detected and {}", e))),
-                                (e, _) => { Err(e) }
-                            }
-                        }
-                    }
-                }
-            }
-        }?;
-        if let Some(permit) = permit {
+        let explicit = vendor.and_then(|vendor| self.build_miner(ip, port, vendor, DetectionInfo::new(DetectionMethod::Explicit)));
+        let miner = match explicit {
+            Some(mut miner) => {
+                self.login(ip, &mut miner, provider).await?;
+                miner
+            },
+            None => {
+                debug!("Detecting miner at {}:{}", ip, port);
+                #[cfg(feature = "otel")]
+                let started = std::time::Instant::now();
+                let miner = self.detect_authenticated(ip, port, provider).await;
+                #[cfg(feature = "otel")]
+                telemetry::otel::record_detection(started.elapsed(), &miner);
+                miner?
+            },
+        };
+        #[cfg(feature = "otel")]
+        let miner: Box<dyn Miner + Send + Sync> = Box::new(telemetry::otel::InstrumentedMiner::new(miner));
+        if let (Some((permit, subnet)), Some(lock)) = (permit, &self.lock) {
+            let in_flight = lock.track(miner.get_type(), subnet);
             Ok(Box::new(miner::LockMiner::new_locked(
                 miner,
                 permit,
-            )) as Box<dyn Miner + Send + Sync>)
+            ).tracked(in_flight)) as Box<dyn Miner + Send + Sync>)
         } else {
             Ok(miner)
         }
     }
+
+    /// As get_miner, but as a MinerEnum for matching on the vendor without downcasting
+    /// Under max_connections, or with otel, the miner is wrapped and comes back as MinerEnum::Other
+    pub async fn get_miner_enum(&self, ip: &str, port: Option<u16>) -> Result<MinerEnum, Error> {
+        self.get_miner(ip, port).await.map(MinerEnum::from)
+    }
 }
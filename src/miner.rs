@@ -1,29 +1,393 @@
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+#[cfg(any(test, feature = "antminer", feature = "avalon", feature = "minerva", feature = "whatsminer"))]
 use lazy_regex::{Regex, Lazy};
 use crate::error::Error;
 use crate::{Client, Cache};
+use crate::util::secret::{self, SecretString};
+use crate::backup::MinerConfigBackup;
+use crate::detection::DetectionInfo;
+use crate::security::SecurityIndicator;
+use crate::util::limiter::InFlight;
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::warn;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Default)]
 pub struct Pool {
     pub url: String,
     #[serde(rename = "user")]
     pub username: String,
-    #[serde(rename = "pass")]
-    pub password: Option<String>,
+    /// None when the vendor doesn't expose the password, see `Capabilities::pool_passwords`
+    /// Serialized in full as pools are written to miners and backups, Debug redacts it
+    #[serde(rename = "pass", serialize_with = "secret::expose_opt")]
+    pub password: Option<SecretString>,
 }
 
-impl Default for Pool {
-    fn default() -> Self {
-        Self {
-            url: String::new(),
-            username: String::new(),
-            password: None,
+
+/// Share counts for one pool as the miner reports them, monotonic until the miner restarts
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PoolCounters {
+    pub url: String,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub stale: u64,
+}
+
+impl PoolCounters {
+    /// Rejected and stale shares as a fraction of all shares, 0 if there were none
+    pub fn reject_rate(&self) -> f64 {
+        let total = self.accepted + self.rejected + self.stale;
+        if total == 0 {
+            0.0
+        } else {
+            (self.rejected + self.stale) as f64 / total as f64
+        }
+    }
+}
+
+/// Control board health, memory running out is a common cause of cgminer deadlocks
+/// Fields are None when the firmware doesn't report them
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SystemStats {
+    /// KiB
+    pub mem_free: Option<u64>,
+    /// KiB
+    pub mem_total: Option<u64>,
+    /// 1 minute load average
+    pub load_avg: Option<f64>,
+    /// Seconds since the control board booted
+    pub uptime: Option<u64>,
+}
+
+/// Health of the control board's ethernet link, a flapping link shows up as stale shares
+/// Fields are None when the firmware doesn't report them
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NetworkStats {
+    /// Mbps
+    pub link_speed: Option<u32>,
+    pub rx_errors: Option<u64>,
+    pub tx_errors: Option<u64>,
+    pub rx_dropped: Option<u64>,
+    pub tx_dropped: Option<u64>,
+    /// Percent of pings to the default gateway lost
+    pub gateway_loss: Option<f64>,
+}
+
+/// Uptime before the short window hashrate is trusted, it spikes or reads zero while chains come up
+pub const HASHRATE_SETTLE_SECS: u64 = 900;
+
+/// The short window rate once the miner has been up past HASHRATE_SETTLE_SECS, otherwise the average since start
+pub fn stable_hashrate(elapsed: u64, short: f64, average: f64) -> f64 {
+    if elapsed < HASHRATE_SETTLE_SECS {
+        average
+    } else {
+        short
+    }
+}
+
+/// Real over ideal hashrate, None without an ideal to compare against
+/// Every vendor's get_performance_ratio goes through this so fleet health means the same thing everywhere
+pub fn performance_ratio(real: f64, ideal: f64) -> Option<f64> {
+    if ideal > 0.0 && real >= 0.0 {
+        Some(real / ideal)
+    } else {
+        None
+    }
+}
+
+/// Keys whose values debug_dump blanks out
+#[cfg(any(feature = "antminer", feature = "whatsminer", feature = "avalon"))]
+const REDACTED_KEYS: [&str; 4] = ["pass", "password", "pw", "token"];
+
+/// Secrets in a body that isn't JSON, e.g. pass=x in a query string or "token":"x" in truncated JSON
+#[cfg(any(feature = "antminer", feature = "whatsminer", feature = "avalon"))]
+static REDACTED_TEXT: Lazy<Regex> = lazy_regex::lazy_regex!(r#"(?i)("?\b(?:pass|password|pw|token)"?\s*[:=]\s*)("[^"]*"|[^\s,&;}"]+)"#);
+
+/// A raw payload for debug_dump, with the error in its place if the endpoint failed
+/// Passwords are redacted so the dump can be pasted into a bug report
+#[cfg(any(feature = "antminer", feature = "whatsminer", feature = "avalon"))]
+pub(crate) fn dump_entry(result: Result<serde_json::Value, Error>) -> serde_json::Value {
+    fn redact(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => {
+                if let std::borrow::Cow::Owned(redacted) = REDACTED_TEXT.replace_all(text, "${1}\"<redacted>\"") {
+                    *text = redacted;
+                }
+            },
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if REDACTED_KEYS.contains(&key.to_lowercase().as_str()) && !value.is_null() {
+                        *value = serde_json::Value::String("<redacted>".into());
+                    } else {
+                        redact(value);
+                    }
+                }
+            },
+            serde_json::Value::Array(values) => values.iter_mut().for_each(redact),
+            _ => {},
+        }
+    }
+    match result {
+        Ok(mut value) => {
+            redact(&mut value);
+            value
+        },
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    }
+}
+
+/// A socket API response as JSON, or as the string it was if it doesn't parse, which is what a bug report needs
+#[cfg(any(feature = "antminer", feature = "whatsminer", feature = "avalon"))]
+pub(crate) fn raw_json(body: &str) -> serde_json::Value {
+    serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string()))
+}
+
+/// Resolvers out of a firmware's DNS field, which separates them with commas, semicolons or spaces
+pub(crate) fn parse_dns_servers(dns: &str) -> Vec<IpAddr> {
+    dns.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter_map(|s| s.parse().ok())
+        .collect()
+}
+
+/// Chips a hashboard should have against the ones it found, a board can hash below spec with some missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChipCount {
+    /// Zero based
+    pub chain: usize,
+    /// None if the firmware doesn't say how many the board should have
+    pub expected: Option<usize>,
+    pub detected: usize,
+}
+
+impl ChipCount {
+    /// Whether chips are known to be missing
+    pub fn degraded(&self) -> bool {
+        self.expected.is_some_and(|expected| self.detected < expected)
+    }
+}
+
+/// Per sensor temperatures of one hashboard in °C, None for a sensor reporting a sentinel value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardTemps {
+    /// Zero based
+    pub chain: usize,
+    pub chip: Vec<Option<f64>>,
+    /// Sensors on the board itself rather than a chip, Antminer PCB then PIC
+    pub pcb: Vec<Option<f64>>,
+    /// Coolest and hottest chip as the firmware works them out, for boards that don't list every chip
+    #[serde(default)]
+    pub chip_min: Option<f64>,
+    #[serde(default)]
+    pub chip_max: Option<f64>,
+}
+
+impl BoardTemps {
+    /// Sensors without a plausible reading, usually disconnected
+    pub fn failed_sensors(&self) -> usize {
+        self.chip.iter().chain(&self.pcb).filter(|t| t.is_none()).count()
+    }
+
+    /// Hottest chip with a working sensor
+    pub fn max_chip(&self) -> Option<f64> {
+        self.chip.iter().flatten().chain(&self.chip_max).copied().reduce(f64::max)
+    }
+
+    /// Coolest chip with a working sensor
+    pub fn min_chip(&self) -> Option<f64> {
+        self.chip.iter().flatten().chain(&self.chip_min).copied().reduce(f64::min)
+    }
+}
+
+/// Temperatures across a whole miner for thermal mapping, °C and None where the firmware doesn't say
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TempReport {
+    /// Over every chip with a working sensor
+    pub chip_min: Option<f64>,
+    pub chip_max: Option<f64>,
+    pub chip_avg: Option<f64>,
+    /// Intake air, or coolant on hydro units
+    pub environment: Option<f64>,
+    pub boards: Vec<BoardTemps>,
+}
+
+impl TempReport {
+    /// Chip figures worked out from the boards' own sensors
+    pub fn from_boards(boards: Vec<BoardTemps>) -> TempReport {
+        let chips: Vec<f64> = boards.iter().flat_map(|b| b.chip.iter().flatten().copied()).collect();
+        TempReport {
+            chip_min: boards.iter().filter_map(BoardTemps::min_chip).reduce(f64::min),
+            chip_max: boards.iter().filter_map(BoardTemps::max_chip).reduce(f64::max),
+            chip_avg: Some(chips.iter().sum::<f64>() / chips.len() as f64).filter(|_| !chips.is_empty()),
+            environment: None,
+            boards,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Hash, Eq)]
+/// How the fans are driven
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FanMode {
+    /// The firmware follows temperature
+    Auto,
+    /// Fixed duty in percent, e.g. for immersion conversions with the fans removed
+    Manual(u8),
+}
+
+/// Cooling beyond fans, for hydro and immersion farms to alert on flow loss
+/// Readings are None when the firmware doesn't report them
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct CoolingDetail {
+    /// Hydro or immersion cooled
+    pub liquid: bool,
+    /// Coolant temperature in °C
+    pub coolant_in: Option<f64>,
+    pub coolant_out: Option<f64>,
+    /// Zero based boards with a flow alarm raised
+    pub flow_alarms: Vec<usize>,
+    pub pump_running: Option<bool>,
+}
+
+/// Turns PoolCounters into deltas since the previous call for the same miner, so callers
+/// can alert on reject rates without keeping the last values themselves
+/// The first reading of a pool is the baseline and yields zeros, a counter going backwards
+/// means the miner restarted and the new value is the delta
+#[derive(Debug, Default)]
+pub struct PoolCounterDiffer {
+    last: Mutex<HashMap<(String, String), PoolCounters>>,
+}
+
+impl PoolCounterDiffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn diff(&self, ip: &str, counters: Vec<PoolCounters>) -> Vec<PoolCounters> {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        counters.into_iter().map(|current| {
+            let key = (ip.to_string(), current.url.clone());
+            let delta = match last.get(&key) {
+                Some(prev) if current.accepted >= prev.accepted && current.rejected >= prev.rejected && current.stale >= prev.stale => PoolCounters {
+                    url: current.url.clone(),
+                    accepted: current.accepted - prev.accepted,
+                    rejected: current.rejected - prev.rejected,
+                    stale: current.stale - prev.stale,
+                },
+                Some(_) => current.clone(),
+                None => PoolCounters { url: current.url.clone(), ..Default::default() },
+            };
+            last.insert(key, current);
+            delta
+        }).collect()
+    }
+
+    /// get_pool_counters as deltas since the last call for this miner
+    pub async fn deltas(&self, miner: &(dyn Miner + Send + Sync)) -> Result<Vec<PoolCounters>, Error> {
+        let counters = miner.get_pool_counters().await?;
+        Ok(self.diff(miner.get_ip(), counters))
+    }
+}
+
+/// Labels the caller attaches to a miner, e.g. rack, row, customer or notes
+/// Carried into snapshots, exports and events so they don't need a map keyed by IP alongside
+pub type Tags = BTreeMap<String, String>;
+
+/// Cached responses prefetch can warm, not every vendor has each
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Resource {
+    /// Hashrate, shares and on most vendors power
+    Summary,
+    /// Per chain detail, temperatures and fans
+    Stats,
+    /// Pools, fan and performance settings
+    Config,
+    /// Model, firmware and network
+    SystemInfo,
+}
+
+/// Which log get_logs_opts reads
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LogSource {
+    /// The mining software's log, what get_logs returns
+    #[default]
+    Miner,
+    Kernel,
+    System,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions {
+    pub source: LogSource,
+    /// Keep only the newest lines
+    pub max_lines: Option<usize>,
+    /// Drop lines timestamped before this, lines without a timestamp go with the line above
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl LogOptions {
+    /// Filter fetched lines by since and max_lines
+    pub fn apply(&self, lines: Vec<String>) -> Vec<String> {
+        let mut lines = match self.since {
+            Some(since) => {
+                let mut keep = true;
+                lines.into_iter().filter(|line| {
+                    if let Some(time) = log_time(line) {
+                        keep = time >= since;
+                    }
+                    keep
+                }).collect()
+            },
+            None => lines,
+        };
+        if let Some(max) = self.max_lines {
+            let skip = lines.len().saturating_sub(max);
+            lines.drain(..skip);
+        }
+        lines
+    }
+}
+
+/// Leading "YYYY-MM-DD HH:MM:SS" of a log line, taken as UTC
+fn log_time(line: &str) -> Option<DateTime<Utc>> {
+    log_naive_time(line).map(|t| t.and_utc())
+}
+
+fn log_naive_time(line: &str) -> Option<NaiveDateTime> {
+    let stamp = line.get(..19)?;
+    NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M:%S").ok()
+}
+
+/// A log line with its timestamp normalized to UTC
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// None for lines without a timestamp, e.g. the rest of a multi line message
+    pub ts: Option<DateTime<Utc>>,
+    /// False if the miner's UTC offset isn't known, ts is then device local time taken as UTC
+    pub offset_known: bool,
+    pub line: String,
+}
+
+impl LogEntry {
+    /// Timestamp the line, shifting device local time by the miner's offset if it's known
+    pub fn parse(line: String, offset: Option<FixedOffset>) -> Self {
+        let ts = log_naive_time(&line).map(|t| match offset {
+            Some(offset) => (t - chrono::Duration::seconds(offset.local_minus_utc() as i64)).and_utc(),
+            None => t.and_utc(),
+        });
+        LogEntry {
+            ts,
+            offset_known: offset.is_some(),
+            line,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Hash, Eq, PartialOrd, Ord, Serialize)]
 pub enum ErrorType {
     ControlBoard,
     HashBoard,
@@ -35,22 +399,36 @@ pub enum ErrorType {
     Other,
 }
 
+/// An entry in a vendor's error table, msg may hold {} for parts of the code or log line
+#[cfg(any(test, feature = "antminer", feature = "minerva", feature = "whatsminer"))]
 #[derive(Debug)]
-pub(crate) struct IntMinerError {
-    pub re: &'static Lazy<Regex>,
-    pub msg: &'static str,
-    pub error_type: ErrorType,
+pub struct IntMinerError {
+    pub(crate) re: &'static Lazy<Regex>,
+    pub(crate) msg: &'static str,
+    pub(crate) error_type: ErrorType,
 }
 
+#[cfg(any(test, feature = "antminer", feature = "minerva", feature = "whatsminer"))]
 impl IntMinerError {
+    pub fn msg(&self) -> &'static str {
+        self.msg
+    }
+
+    pub fn error_type(&self) -> ErrorType {
+        self.error_type
+    }
+
+    /// msg with the placeholders filled from the code
+    pub fn describe(&self, code: u32) -> String {
+        self.get_msg(&code.to_string()).unwrap_or_else(|| self.msg.to_string())
+    }
+
     pub fn get_msg(&self, line: &str) -> Option<String> {
         if let Some(caps) = self.re.captures(line) {
             let caps = caps.iter().skip(1);
             let mut msg = self.msg.to_string();
-            for cap in caps {
-                if let Some(cap) = cap {
-                    msg = msg.replacen("{}", cap.as_str(), 1);
-                }
+            for cap in caps.flatten() {
+                msg = msg.replacen("{}", cap.as_str(), 1);
             }
             Some(msg)
         } else {
@@ -59,33 +437,255 @@ impl IntMinerError {
     }
 
     pub fn get_err(&self, line: &str) -> Option<MinerError> {
-        if let Some(msg) = self.get_msg(line) {
-            Some(MinerError {
-                msg,
-                error_type: self.error_type,
-            })
-        } else {
-            None
-        }
+        self.get_msg(line).map(|msg| MinerError::new(msg, self.error_type))
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize)]
+#[non_exhaustive]
 pub struct MinerError {
     pub msg: String,
     pub error_type: ErrorType,
+    /// The vendor's numeric error code, None for errors read from logs
+    pub code: Option<u32>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl MinerError {
+    pub fn new(msg: impl Into<String>, error_type: ErrorType) -> Self {
+        MinerError { msg: msg.into(), error_type, code: None }
+    }
+
+    pub fn with_code(mut self, code: u32) -> Self {
+        self.code = Some(code);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Profile {
     Default,
     LowPower,
     Preset { name: String, power: f64, ths: f64 },
     Manual { volt: u32, freq: u32, min_freq: u32, max_freq: u32, min_volt: u32, max_volt: u32, def_volt: u32, def_freq: u32 },
+    /// Active on the miner but not one we can describe, e.g. a preset a firmware update removed
+    Unknown { name: String },
+}
+
+/// How often set_profile_verified checks on the miner
+const PROFILE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How often confirm_sleep checks on the miner
+const SLEEP_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Power readings a poll apart that differ by less than this share count as settled
+const POWER_SETTLED_RATIO: f64 = 0.05;
+
+fn power_settled(last: f64, power: f64) -> bool {
+    (power - last).abs() <= last.abs() * POWER_SETTLED_RATIO
+}
+
+/// Put a miner back on a profile, true once it reports it again within timeout
+async fn rollback_profile<M: Miner + Send + Sync + ?Sized>(miner: &mut M, profile: Profile, timeout: std::time::Duration) -> bool {
+    if miner.set_profile(profile.clone()).await.is_err() {
+        return false;
+    }
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        tokio::time::sleep(PROFILE_POLL_INTERVAL.min(deadline.saturating_duration_since(tokio::time::Instant::now()))).await;
+        miner.invalidate().await;
+        if miner.get_profile().await.is_ok_and(|p| p.same_as(&profile)) {
+            return true;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+    }
+}
+
+/// Lowercase with punctuation and spaces stripped, so "Low Power" matches "lowpower"
+fn normalize_name(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+impl Profile {
+    /// Name used when matching profiles across vendors and firmware versions
+    pub fn name(&self) -> String {
+        match self {
+            Profile::Default => "Default".to_string(),
+            Profile::LowPower => "Low Power".to_string(),
+            Profile::Preset { name, .. } | Profile::Unknown { name } => name.clone(),
+            Profile::Manual { volt, freq, .. } => format!("Manual {}mV {}MHz", volt, freq),
+        }
+    }
+
+    /// Same setting, ignoring what the vendor fills in itself such as hashrate estimates and voltage bounds
+    pub fn same_as(&self, other: &Profile) -> bool {
+        match (self, other) {
+            (Profile::Default, Profile::Default) | (Profile::LowPower, Profile::LowPower) => true,
+            (Profile::Manual { volt: v1, freq: f1, .. }, Profile::Manual { volt: v2, freq: f2, .. }) => v1 == v2 && f1 == f2,
+            (Profile::Preset { name: n1, .. }, Profile::Preset { name: n2, .. })
+            | (Profile::Unknown { name: n1 }, Profile::Unknown { name: n2 }) => normalize_name(n1) == normalize_name(n2),
+            _ => false,
+        }
+    }
+
+    /// Whether a Manual profile's volt and freq are inside a Manual entry's bounds
+    fn within(&self, bounds: &Profile) -> bool {
+        match (self, bounds) {
+            (Profile::Manual { volt, freq, .. }, Profile::Manual { min_volt, max_volt, min_freq, max_freq, .. }) => {
+                volt >= min_volt && volt <= max_volt && freq >= min_freq && freq <= max_freq
+            },
+            _ => false,
+        }
+    }
+
+    /// Pick the entry of a miner's get_profiles that this profile asks for
+    /// Manual profiles match a Manual entry whose bounds contain them, anything else tries an exact match,
+    /// then the name ignoring case and punctuation, then for presets the closest power within 5%
+    pub fn resolve<'a>(&self, available: &'a [Profile]) -> Option<&'a Profile> {
+        if let Profile::Manual { .. } = self {
+            return available.iter().find(|p| self.within(p));
+        }
+        if let Some(p) = available.iter().find(|p| self.same_as(p)) {
+            return Some(p);
+        }
+        let name = normalize_name(&self.name());
+        if let Some(p) = available.iter().find(|p| !matches!(p, Profile::Manual { .. }) && normalize_name(&p.name()) == name) {
+            return Some(p);
+        }
+        let power = match self {
+            Profile::Preset { power, .. } if *power > 0.0 => *power,
+            _ => return None,
+        };
+        available.iter()
+            .filter_map(|p| match p {
+                Profile::Preset { power: p_power, .. } if ((p_power - power) / power).abs() <= 0.05 => Some((p, (p_power - power).abs())),
+                _ => None,
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(p, _)| p)
+    }
+
+    /// As resolve, but an error naming the profile if the miner has nothing matching
+    pub fn validate<'a>(&self, available: &'a [Profile]) -> Result<&'a Profile, Error> {
+        self.resolve(available).ok_or_else(|| Error::InvalidProfile(self.name()))
+    }
+}
+
+/// Whether a miner is hashing, asleep, or somewhere in between
+/// Waking can take minutes, issuing another wake in the meantime is at best wasted and at worst restarts it
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PowerState {
+    Mining,
+    Sleeping,
+    /// Going to sleep or waking up
+    Transitioning,
+}
+
+/// Ways a miner can be up and answering but not running as it should, see Miner::get_health
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HealthCondition {
+    /// The vendor's control software is down and a plain cgminer answers in its place
+    /// Readings still work, vendor specific settings don't
+    CgminerFallback,
+}
+
+/// Below this share of target hashrate a miner that has finished tuning counts as underperforming
+const UNDERPERFORMING_RATIO: f64 = 0.9;
+
+/// Where a miner is relative to the hashrate it's tuned for
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TuningState {
+    /// Still tuning or settling, low hashrate is expected
+    Tuning,
+    Tuned,
+    /// Finished tuning but short of target
+    Underperforming,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TuningStatus {
+    pub target_ths: f64,
+    pub actual_ths: f64,
+    pub state: TuningState,
+}
+
+impl TuningStatus {
+    /// Classify a reading, `tuning` is whether the firmware says it is still tuning
+    pub fn new(target_ths: f64, actual_ths: f64, tuning: bool) -> Self {
+        let state = if tuning {
+            TuningState::Tuning
+        } else if actual_ths < target_ths * UNDERPERFORMING_RATIO {
+            TuningState::Underperforming
+        } else {
+            TuningState::Tuned
+        };
+        TuningStatus { target_ths, actual_ths, state }
+    }
+}
+
+/// A written setting that only takes effect once the miner restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PendingChange {
+    Pools,
+    Profile,
+    /// Vendor settings such as fan control, or restored from a backup
+    Settings,
+    /// Hashboards enabled or disabled
+    Boards,
+    /// DNS or addressing
+    Network,
+    /// The firmware says a restart is needed but not for what
+    Unspecified,
+}
+
+/// What a miner will currently accept, so orchestration can skip calls that are bound to fail
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Whether write (control/config) commands are accepted
+    pub write_api: bool,
+    /// Endpoints probed during auth and whether the supplied credentials could access them
+    /// Empty if the miner doesn't have per-endpoint access control
+    pub endpoints: HashMap<String, bool>,
+    /// Whether `get_pools` returns the actual pool passwords
+    /// Antminer and Vnish do, Minerva masks them and Whatsminer omits them
+    pub pool_passwords: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            write_api: true,
+            endpoints: HashMap::new(),
+            pool_passwords: false,
+        }
+    }
+}
+
+/// Lets a `dyn Miner` be downcast back to its concrete type
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Take ownership back out of a Box<dyn Miner>, see MinerEnum
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
 }
 
 #[async_trait]
-pub trait Miner {
+pub trait Miner: AsAny {
     fn new(client: Client, ip: String, port: u16) -> Self
         where Self: Sized;
     
@@ -95,61 +695,785 @@ pub trait Miner {
             self
         }
 
+    /// Attach what detection learnt, the Client calls this right after construction
+    fn with_detection_info(self, _info: DetectionInfo) -> Self
+        where Self: Sized {
+            self
+        }
+
     fn get_type(&self) -> &'static str;
 
+    fn get_ip(&self) -> &str;
+
+    /// What detection learnt about this miner, None for miners built without the Client
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        None
+    }
+
+    /// Tags attached with set_tags, empty for miners that can't hold them
+    fn tags(&self) -> Tags {
+        Tags::new()
+    }
+
+    fn set_tags(&mut self, _tags: Tags) {}
+
+    /// Capabilities as currently known, this may change after calls reveal restrictions
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    // Methods below with a default body are optional, vendors only implement what they support
+
     async fn get_model(&self) -> Result<String, Error>;
 
     async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error>;
 
     async fn reboot(&mut self) -> Result<(), Error>;
 
+    /// Use separate credentials for one endpoint, for firmware where some only accept another user
+    async fn set_endpoint_auth(&mut self, _endpoint: &str, _username: &str, _password: &str) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Restart the mining software if its API has wedged, without rebooting the control board
+    /// Returns whether a restart was needed
+    async fn recover(&mut self) -> Result<bool, Error> {
+        Err(Error::NotSupported)
+    }
+
     async fn get_hashrate(&self) -> Result<f64, Error>;
 
+    /// get_hashrate without the spikes right after a restart, for alerting
+    /// Vendors that report uptime fall back to the average since start until it settles
+    async fn get_hashrate_stable(&self) -> Result<f64, Error> {
+        self.get_hashrate().await
+    }
+
     async fn get_power(&self) -> Result<f64, Error>;
 
-    async fn get_nameplate_power(&self) -> Result<f64, Error>;
+    /// Nameplate rate at the rated efficiency
+    async fn get_nameplate_power(&self) -> Result<f64, Error> {
+        Ok(self.get_nameplate_rate().await? * self.get_rated_efficiency().await?)
+    }
 
-    async fn get_efficiency(&self) -> Result<f64, Error>;
+    /// J/TH from power and hashrate, the rated efficiency while not hashing
+    async fn get_efficiency(&self) -> Result<f64, Error> {
+        if let (Ok(power), Ok(hashrate)) = (self.get_power().await, self.get_hashrate().await) {
+            if hashrate > 0.0 {
+                return Ok(power / hashrate);
+            }
+        }
+        self.get_rated_efficiency().await
+    }
 
     async fn get_nameplate_rate(&self) -> Result<f64, Error>;
 
+    /// Hashrate as a fraction of nameplate, 1.0 is hashing at spec
+    /// Vendors that report an ideal rate per chain use that instead of the nameplate
+    async fn get_performance_ratio(&self) -> Result<f64, Error> {
+        performance_ratio(self.get_hashrate().await?, self.get_nameplate_rate().await?).ok_or(Error::NotSupported)
+    }
+
+    /// Datasheet J/TH for the model
+    async fn get_rated_efficiency(&self) -> Result<f64, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Datasheet max fan RPM for the model
+    async fn get_max_fan_speed(&self) -> Result<u32, Error> {
+        Err(Error::NotSupported)
+    }
+
     async fn get_temperature(&self) -> Result<f64, Error>;
 
     async fn get_fan_speed(&self) -> Result<Vec<u32>, Error>;
 
-    async fn get_fan_pwm(&self) -> Result<f64, Error>;
+    /// Fastest fan against the max fan speed, for miners that don't report a duty cycle
+    async fn get_fan_pwm(&self) -> Result<f64, Error> {
+        let max = self.get_max_fan_speed().await? as f64;
+        self.get_fan_speed().await?.iter()
+            .max()
+            .map(|&s| (s as f64 / max) * 100.0)
+            .ok_or(Error::ApiCallFailed("No fan speed data".to_string()))
+    }
+
+    async fn get_fan_mode(&self) -> Result<FanMode, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn set_fan_mode(&mut self, _mode: FanMode) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Fix the fans at a duty in percent
+    async fn set_fan_pwm(&mut self, pwm: u8) -> Result<(), Error> {
+        self.set_fan_mode(FanMode::Manual(pwm)).await
+    }
 
     async fn get_pools(&self) -> Result<Vec<Pool>, Error>;
 
-    async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error>;
+    async fn set_pools(&mut self, _pools: Vec<Pool>) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// set_pools, refused with NoReachablePool if no pool answers a stratum probe, unless force is set
+    /// Probes run from this host through client, so a pool only the miners' network can reach needs force
+    async fn set_pools_checked(&mut self, client: &Client, pools: Vec<Pool>, force: bool) -> Result<(), Error> {
+        let dead = crate::stratum::dead_pools(client, &pools).await;
+        if !pools.is_empty() && dead.len() == pools.len() {
+            let reasons = dead.iter().map(|(url, e)| format!("{}: {}", url, e)).collect::<Vec<_>>().join(", ");
+            if !force {
+                return Err(Error::NoReachablePool(reasons));
+            }
+            warn!("Setting pools on {} though none answered: {}", self.get_ip(), reasons);
+        }
+        self.set_pools(pools).await
+    }
 
-    async fn get_sleep(&self) -> Result<bool, Error>;
+    async fn get_sleep(&self) -> Result<bool, Error> {
+        Err(Error::NotSupported)
+    }
 
     async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error>;
 
-    async fn get_blink(&self) -> Result<bool, Error>;
+    /// Vendors that can tell a miner is mid-transition override this, otherwise it follows get_sleep
+    async fn get_power_state(&self) -> Result<PowerState, Error> {
+        if self.get_sleep().await? {
+            Ok(PowerState::Sleeping)
+        } else {
+            Ok(PowerState::Mining)
+        }
+    }
+
+    /// Poll get_power_state until the miner has taken a set_sleep, VerificationTimeout if it hasn't within timeout
+    /// A miner still winding down or starting up counts, it has the command. Kept apart from set_sleep so
+    /// a shared miner is only read, not held, while it's checked
+    async fn confirm_sleep(&self, sleep: bool, timeout: std::time::Duration) -> Result<(), Error> where Self: Sync {
+        let opposite = if sleep { PowerState::Mining } else { PowerState::Sleeping };
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            tokio::time::sleep(SLEEP_POLL_INTERVAL.min(deadline.saturating_duration_since(tokio::time::Instant::now()))).await;
+            self.invalidate().await;
+            // The connection may drop while the boards power down, keep polling
+            if self.get_power_state().await.is_ok_and(|state| state != opposite) {
+                return Ok(());
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::VerificationTimeout(if sleep { "sleep" } else { "wake" }.into()));
+            }
+        }
+    }
+
+    async fn get_blink(&self) -> Result<bool, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn set_blink(&mut self, _blink: bool) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// get_logs with a choice of log and filtering, vendors with more than one log override this
+    async fn get_logs_opts(&mut self, opts: LogOptions) -> Result<Vec<String>, Error> {
+        if opts.source != LogSource::Miner {
+            return Err(Error::NotSupported);
+        }
+        Ok(opts.apply(self.get_logs().await?))
+    }
 
-    async fn set_blink(&mut self, blink: bool) -> Result<(), Error>;
+    /// get_logs_opts with timestamps in UTC, since is compared against the normalized time
+    async fn get_log_entries(&mut self, opts: LogOptions) -> Result<Vec<LogEntry>, Error> where Self: Sync {
+        let offset = self.get_utc_offset().await.ok();
+        let lines = self.get_logs_opts(LogOptions { source: opts.source, ..Default::default() }).await?;
+        let mut keep = true;
+        let mut entries: Vec<LogEntry> = lines.into_iter()
+            .map(|line| LogEntry::parse(line, offset))
+            .filter(|entry| {
+                if let (Some(since), Some(ts)) = (opts.since, entry.ts) {
+                    keep = ts >= since;
+                }
+                keep
+            })
+            .collect();
+        if let Some(max) = opts.max_lines {
+            let skip = entries.len().saturating_sub(max);
+            entries.drain(..skip);
+        }
+        Ok(entries)
+    }
 
-    async fn get_logs(&mut self) -> Result<Vec<String>, Error>;
+    /// Offset of the miner's configured timezone, which its logs are written in
+    async fn get_utc_offset(&self) -> Result<FixedOffset, Error> {
+        Err(Error::NotSupported)
+    }
 
     async fn get_mac(&self) -> Result<String, Error>;
 
-    async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error>;
+    async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_dns(&self) -> Result<String, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Every configured DNS server, get_dns only has the first on some firmware
+    async fn get_dns_servers(&self) -> Result<Vec<IpAddr>, Error> {
+        Ok(parse_dns_servers(&self.get_dns().await?))
+    }
+
+    /// Replace the DNS servers, keeping the rest of the network config
+    async fn set_dns(&mut self, _servers: Vec<IpAddr>) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_profile(&self) -> Result<Profile, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn set_profile(&mut self, _profile: Profile) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// set_profile, then wait out any restart until the miner reports the profile and, with a ceiling, hashes under it
+    /// A miner settling above max_power watts is put back on the profile it had, which is returned as PowerCeilingExceeded
+    /// with rolled_back only once the miner reports the old profile again
+    async fn set_profile_verified(&mut self, profile: Profile, timeout: std::time::Duration, max_power: Option<f64>) -> Result<(), Error> where Self: Sync {
+        let previous = self.get_profile().await.ok();
+        self.set_profile(profile.clone()).await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut last_power = None;
+        loop {
+            // A timeout shorter than the interval gets its one check at the deadline
+            tokio::time::sleep(PROFILE_POLL_INTERVAL.min(deadline.saturating_duration_since(tokio::time::Instant::now()))).await;
+            self.invalidate().await;
+            // Errors while it restarts are expected, keep polling
+            if self.get_profile().await.is_ok_and(|p| p.same_as(&profile)) {
+                let ceiling = match max_power {
+                    Some(ceiling) => ceiling,
+                    None => return Ok(()),
+                };
+                // Power climbs while the boards ramp up, only a reading that held since the last poll is judged
+                let power = match self.get_hashrate().await {
+                    Ok(hashrate) if hashrate > 0.0 => self.get_power().await.ok(),
+                    _ => None,
+                };
+                let settled = matches!((power, last_power), (Some(power), Some(last)) if power_settled(last, power));
+                let timed_out = tokio::time::Instant::now() >= deadline;
+                match power {
+                    Some(power) if settled && power <= ceiling => return Ok(()),
+                    // Still over at the deadline is as good as settled over
+                    Some(power) if power > ceiling && (settled || timed_out) => {
+                        let rolled_back = match previous {
+                            Some(previous) => rollback_profile(self, previous, timeout).await,
+                            None => false,
+                        };
+                        return Err(Error::PowerCeilingExceeded { power, ceiling, rolled_back });
+                    },
+                    _ => {},
+                }
+                last_power = power;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(Error::VerificationTimeout(format!("set_profile {}", profile.name())));
+            }
+        }
+    }
+
+    async fn get_hashboard(&mut self) -> Result<String, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Accepted, rejected and stale shares per pool since the miner started, see PoolCounterDiffer for deltas
+    async fn get_pool_counters(&self) -> Result<Vec<PoolCounters>, Error> {
+        Err(Error::NotSupported)
+    }
 
-    async fn get_dns(&self) -> Result<String, Error>;
+    /// Target against actual hashrate, to tell a miner that is still tuning from one that is underperforming
+    async fn get_tuning_status(&self) -> Result<TuningStatus, Error> {
+        Err(Error::NotSupported)
+    }
 
-    async fn get_profile(&self) -> Result<Profile, Error>;
+    /// Memory, load and uptime of the control board
+    async fn get_system_stats(&self) -> Result<SystemStats, Error> {
+        Err(Error::NotSupported)
+    }
 
-    async fn get_profiles(&self) -> Result<Vec<Profile>, Error>;
+    /// Link speed and interface error counters, stock web APIs don't report them, see SshTransport
+    async fn get_network_stats(&self) -> Result<NetworkStats, Error> {
+        Err(Error::NotSupported)
+    }
 
-    async fn set_profile(&mut self, profile: Profile) -> Result<(), Error>;
+    /// Expected and detected chips per chain
+    async fn get_chip_counts(&self) -> Result<Vec<ChipCount>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Every temperature sensor per hashboard, get_temperature is the hottest working chip sensor
+    async fn get_board_temps(&self) -> Result<Vec<BoardTemps>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Chip breakdown and per board sensors, by default from get_board_temps
+    async fn get_temperatures(&self) -> Result<TempReport, Error> {
+        Ok(TempReport::from_boards(self.get_board_temps().await?))
+    }
+
+    /// Fetch these concurrently into the caches, so getters that read them don't each wait on a request in turn
+    /// Resources the vendor doesn't cache are ignored
+    async fn prefetch(&self, _resources: &[Resource]) -> Result<(), Error> {
+        Ok(())
+    }
 
-    async fn get_hashboard(&mut self) -> Result<String, Error>;
+    /// Drop cached responses so the next read asks the miner again
+    async fn invalidate(&self) {}
+
+    /// Raw vendor payloads keyed by endpoint, for bug reports about responses that don't parse
+    /// An endpoint that fails carries its error instead, passwords are redacted
+    async fn debug_dump(&self) -> Result<serde_json::Value, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Conditions the miner is running under that need attention, empty if none
+    async fn get_health(&self) -> Result<Vec<HealthCondition>, Error> {
+        Ok(Vec::new())
+    }
+
+    /// Signs of tampered firmware, empty if nothing matched the IOC lists in `security`
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Coolant temperatures, flow alarms and pump state on liquid cooled miners
+    async fn get_cooling_detail(&self) -> Result<CoolingDetail, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Take a hashboard out of service, or put it back, so a miner can keep running on the rest
+    /// chain is zero based. Stock Antminer firmware has no way to do this
+    async fn set_board_enabled(&mut self, _chain: usize, _enabled: bool) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Settings written since the last reboot that are still waiting on one
+    /// Setters record here what they wrote that the firmware only applies on restart, so orchestration
+    /// can push several changes and then restart once
+    async fn pending_changes(&self) -> Result<Vec<PendingChange>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Whether a reboot is needed for written settings to apply, check this after calling setters
+    async fn restart_required(&self) -> Result<bool, Error> {
+        Ok(!self.pending_changes().await?.is_empty())
+    }
+
+    /// Vendors with a settings blob worth keeping override this to attach it
+    async fn backup_config(&self) -> Result<MinerConfigBackup, Error> {
+        MinerConfigBackup::capture(self).await
+    }
+
+    async fn restore_config(&mut self, backup: MinerConfigBackup) -> Result<(), Error> {
+        backup.apply(self).await
+    }
+}
+
+/// Implements Miner for a wrapper type by forwarding every method to the wrapped miner
+/// `direct` wrappers provide inner()/inner_mut(), `locked` wrappers provide async read()/write()
+/// `instrumented` wrappers are direct but also get record() called with each result
+/// `metered` wrappers are direct but answer get_power and get_efficiency themselves
+/// `deadline` wrappers are direct but run every read against their deadline
+/// `dispatched` is MinerEnum, which matches on its variant through dispatch! instead of wrapping
+/// New trait methods only need adding here to reach every wrapper and MinerEnum
+macro_rules! delegate_miner {
+    (@wrap instrumented $s:ident, "get_hashrate", $e:expr) => {{
+        let r = $e;
+        $s.record("get_hashrate", &r);
+        if let Ok(hashrate) = &r {
+            $s.record_hashrate(*hashrate);
+        }
+        r
+    }};
+    (@wrap instrumented $s:ident, $name:literal, $e:expr) => {{
+        let r = $e;
+        $s.record($name, &r);
+        r
+    }};
+    (@wrap metered $s:ident, "get_power", $e:expr) => { $s.metered_power().await };
+    (@wrap metered $s:ident, "get_efficiency", $e:expr) => { $s.metered_efficiency().await };
+    // A write cut off part way leaves the miner in an unknown state, only reads are bounded
+    (@wrap deadline $s:ident, "auth", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "reboot", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "recover", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_endpoint_auth", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_fan_mode", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_fan_pwm", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_pools", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_pools_checked", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_sleep", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_blink", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_dns", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_profile", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_profile_verified", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "set_board_enabled", $e:expr) => { $e };
+    (@wrap deadline $s:ident, "restore_config", $e:expr) => { $e };
+    (@wrap deadline $s:ident, $name:literal, $e:expr) => { $s.deadline.run(async { $e }).await };
+    (@wrap $mode:ident $s:ident, $name:literal, $e:expr) => { $e };
+    (@call dispatched $s:ident, $_b:tt, $($call:tt)*) => { dispatch!($s, m => m.$($call)*.await) };
+    (@call $mode:ident $s:ident, ref, $($call:tt)*) => { delegate_miner!(@ref $mode $s).$($call)*.await };
+    (@call $mode:ident $s:ident, mut, $($call:tt)*) => { delegate_miner!(@mut $mode $s).$($call)*.await };
+    // Wrappers can't be built from an address, an unknown vendor is assumed to speak cgminer
+    (@new dispatched $c:ident, $i:ident, $p:ident) => { MinerEnum::Cgminer(Box::new(GenericCgminer::new($c, $i, $p))) };
+    (@new $mode:ident $c:ident, $i:ident, $p:ident) => { unimplemented!() };
+    // Wrappers hold a miner that's already built
+    (@with dispatched $s:ident, $f:ident($a:ident)) => { dispatch!(map $s, m => m.$f($a)) };
+    (@with $mode:ident $s:ident, $f:ident($a:ident)) => {{ let _ = $a; $s }};
+    (@ref direct $s:ident) => { $s.inner() };
+    (@mut direct $s:ident) => { $s.inner_mut() };
+    (@ref instrumented $s:ident) => { $s.inner() };
+    (@mut instrumented $s:ident) => { $s.inner_mut() };
+    (@ref metered $s:ident) => { $s.inner() };
+    (@mut metered $s:ident) => { $s.inner_mut() };
+    (@ref deadline $s:ident) => { $s.inner() };
+    (@mut deadline $s:ident) => { $s.inner_mut() };
+    (@ref locked $s:ident) => { $s.read().await };
+    (@mut locked $s:ident) => { $s.write().await };
+    (@type dispatched $s:ident) => { dispatch!($s, m => m.get_type()) };
+    (@type direct $s:ident) => { $s.inner().get_type() };
+    (@type instrumented $s:ident) => { $s.inner().get_type() };
+    (@type metered $s:ident) => { $s.inner().get_type() };
+    (@type deadline $s:ident) => { $s.inner().get_type() };
+    (@type locked $s:ident) => { $s.type_ };
+    (@ip dispatched $s:ident) => { dispatch!($s, m => m.get_ip()) };
+    (@ip direct $s:ident) => { $s.inner().get_ip() };
+    (@ip instrumented $s:ident) => { $s.inner().get_ip() };
+    (@ip metered $s:ident) => { $s.inner().get_ip() };
+    (@ip deadline $s:ident) => { $s.inner().get_ip() };
+    (@ip locked $s:ident) => { &$s.ip };
+    (@caps dispatched $s:ident) => { dispatch!($s, m => m.capabilities()) };
+    (@caps direct $s:ident) => { $s.inner().capabilities() };
+    (@caps instrumented $s:ident) => { $s.inner().capabilities() };
+    (@caps metered $s:ident) => { $s.inner().capabilities() };
+    (@caps deadline $s:ident) => { $s.inner().capabilities() };
+    // Falls back to the defaults if a setter currently holds the miner
+    (@caps locked $s:ident) => { $s.miner.try_read().map(|m| m.capabilities()).unwrap_or_default() };
+    (@info dispatched $s:ident) => { dispatch!($s, m => m.detection_info()) };
+    (@info direct $s:ident) => { $s.inner().detection_info() };
+    (@info instrumented $s:ident) => { $s.inner().detection_info() };
+    (@info metered $s:ident) => { $s.inner().detection_info() };
+    (@info deadline $s:ident) => { $s.inner().detection_info() };
+    (@info locked $s:ident) => { $s.detection.clone() };
+    (@tags dispatched $s:ident) => { dispatch!($s, m => m.tags()) };
+    (@tags direct $s:ident) => { $s.inner().tags() };
+    (@tags instrumented $s:ident) => { $s.inner().tags() };
+    (@tags metered $s:ident) => { $s.inner().tags() };
+    (@tags deadline $s:ident) => { $s.inner().tags() };
+    (@tags locked $s:ident) => { $s.tags.read().unwrap_or_else(|e| e.into_inner()).clone() };
+    (@set_tags dispatched $s:ident, $t:ident) => { dispatch!($s, m => m.set_tags($t)) };
+    (@set_tags direct $s:ident, $t:ident) => { $s.inner_mut().set_tags($t) };
+    (@set_tags instrumented $s:ident, $t:ident) => { $s.inner_mut().set_tags($t) };
+    (@set_tags metered $s:ident, $t:ident) => { $s.inner_mut().set_tags($t) };
+    (@set_tags deadline $s:ident, $t:ident) => { $s.inner_mut().set_tags($t) };
+    // Kept outside the lock so every clone sees them and reads never wait on a setter
+    (@set_tags locked $s:ident, $t:ident) => { *$s.tags.write().unwrap_or_else(|e| e.into_inner()) = $t };
+    ($wrapper:ty, $mode:ident) => {
+        #[async_trait]
+        impl Miner for $wrapper {
+            fn new(_client: $crate::Client, _ip: String, _port: u16) -> Self
+                where Self: Sized {
+                    delegate_miner!(@new $mode _client, _ip, _port)
+                }
+
+            fn with_cache(self, cache: Option<$crate::Cache>) -> Self
+                where Self: Sized {
+                    delegate_miner!(@with $mode self, with_cache(cache))
+                }
+
+            fn with_detection_info(self, info: DetectionInfo) -> Self
+                where Self: Sized {
+                    delegate_miner!(@with $mode self, with_detection_info(info))
+                }
+
+            fn get_type(&self) -> &'static str {
+                delegate_miner!(@type $mode self)
+            }
+
+            fn get_ip(&self) -> &str {
+                delegate_miner!(@ip $mode self)
+            }
+
+            fn capabilities(&self) -> Capabilities {
+                delegate_miner!(@caps $mode self)
+            }
+
+            fn detection_info(&self) -> Option<DetectionInfo> {
+                delegate_miner!(@info $mode self)
+            }
+
+            fn tags(&self) -> Tags {
+                delegate_miner!(@tags $mode self)
+            }
+
+            fn set_tags(&mut self, tags: Tags) {
+                delegate_miner!(@set_tags $mode self, tags)
+            }
+
+            async fn get_model(&self) -> Result<String, Error> {
+                delegate_miner!(@wrap $mode self, "get_model", delegate_miner!(@call $mode self, ref, get_model()))
+            }
+
+            async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "auth", delegate_miner!(@call $mode self, mut, auth(username, password)))
+            }
+
+            async fn reboot(&mut self) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "reboot", delegate_miner!(@call $mode self, mut, reboot()))
+            }
+
+            async fn set_endpoint_auth(&mut self, endpoint: &str, username: &str, password: &str) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_endpoint_auth", delegate_miner!(@call $mode self, mut, set_endpoint_auth(endpoint, username, password)))
+            }
+
+            async fn recover(&mut self) -> Result<bool, Error> {
+                delegate_miner!(@wrap $mode self, "recover", delegate_miner!(@call $mode self, mut, recover()))
+            }
+
+            async fn get_hashrate(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_hashrate", delegate_miner!(@call $mode self, ref, get_hashrate()))
+            }
+
+            async fn get_hashrate_stable(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_hashrate_stable", delegate_miner!(@call $mode self, ref, get_hashrate_stable()))
+            }
+
+            async fn get_power(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_power", delegate_miner!(@call $mode self, ref, get_power()))
+            }
+
+            async fn get_nameplate_power(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_nameplate_power", delegate_miner!(@call $mode self, ref, get_nameplate_power()))
+            }
+
+            async fn get_efficiency(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_efficiency", delegate_miner!(@call $mode self, ref, get_efficiency()))
+            }
+
+            async fn get_nameplate_rate(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_nameplate_rate", delegate_miner!(@call $mode self, ref, get_nameplate_rate()))
+            }
+
+            async fn get_performance_ratio(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_performance_ratio", delegate_miner!(@call $mode self, ref, get_performance_ratio()))
+            }
+
+            async fn get_rated_efficiency(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_rated_efficiency", delegate_miner!(@call $mode self, ref, get_rated_efficiency()))
+            }
+
+            async fn get_max_fan_speed(&self) -> Result<u32, Error> {
+                delegate_miner!(@wrap $mode self, "get_max_fan_speed", delegate_miner!(@call $mode self, ref, get_max_fan_speed()))
+            }
+
+            async fn get_temperature(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_temperature", delegate_miner!(@call $mode self, ref, get_temperature()))
+            }
+
+            async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
+                delegate_miner!(@wrap $mode self, "get_fan_speed", delegate_miner!(@call $mode self, ref, get_fan_speed()))
+            }
+
+            async fn get_fan_pwm(&self) -> Result<f64, Error> {
+                delegate_miner!(@wrap $mode self, "get_fan_pwm", delegate_miner!(@call $mode self, ref, get_fan_pwm()))
+            }
+
+            async fn get_fan_mode(&self) -> Result<FanMode, Error> {
+                delegate_miner!(@wrap $mode self, "get_fan_mode", delegate_miner!(@call $mode self, ref, get_fan_mode()))
+            }
+
+            async fn set_fan_mode(&mut self, mode: FanMode) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_fan_mode", delegate_miner!(@call $mode self, mut, set_fan_mode(mode)))
+            }
+
+            async fn set_fan_pwm(&mut self, pwm: u8) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_fan_pwm", delegate_miner!(@call $mode self, mut, set_fan_pwm(pwm)))
+            }
+
+            async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
+                delegate_miner!(@wrap $mode self, "get_pools", delegate_miner!(@call $mode self, ref, get_pools()))
+            }
+
+            async fn set_pools_checked(&mut self, client: &$crate::Client, pools: Vec<Pool>, force: bool) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_pools_checked", delegate_miner!(@call $mode self, mut, set_pools_checked(client, pools, force)))
+            }
+
+            async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_pools", delegate_miner!(@call $mode self, mut, set_pools(pools)))
+            }
+
+            async fn get_sleep(&self) -> Result<bool, Error> {
+                delegate_miner!(@wrap $mode self, "get_sleep", delegate_miner!(@call $mode self, ref, get_sleep()))
+            }
+
+            async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_sleep", delegate_miner!(@call $mode self, mut, set_sleep(sleep)))
+            }
+
+            async fn get_power_state(&self) -> Result<PowerState, Error> {
+                delegate_miner!(@wrap $mode self, "get_power_state", delegate_miner!(@call $mode self, ref, get_power_state()))
+            }
+
+            async fn confirm_sleep(&self, sleep: bool, timeout: std::time::Duration) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "confirm_sleep", delegate_miner!(@call $mode self, ref, confirm_sleep(sleep, timeout)))
+            }
+
+            async fn get_blink(&self) -> Result<bool, Error> {
+                delegate_miner!(@wrap $mode self, "get_blink", delegate_miner!(@call $mode self, ref, get_blink()))
+            }
+
+            async fn set_blink(&mut self, blink: bool) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_blink", delegate_miner!(@call $mode self, mut, set_blink(blink)))
+            }
+
+            async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
+                delegate_miner!(@wrap $mode self, "get_logs", delegate_miner!(@call $mode self, mut, get_logs()))
+            }
+
+            async fn get_logs_opts(&mut self, opts: LogOptions) -> Result<Vec<String>, Error> {
+                delegate_miner!(@wrap $mode self, "get_logs_opts", delegate_miner!(@call $mode self, mut, get_logs_opts(opts)))
+            }
+
+            async fn get_log_entries(&mut self, opts: LogOptions) -> Result<Vec<LogEntry>, Error> {
+                delegate_miner!(@wrap $mode self, "get_log_entries", delegate_miner!(@call $mode self, mut, get_log_entries(opts)))
+            }
+
+            async fn get_utc_offset(&self) -> Result<FixedOffset, Error> {
+                delegate_miner!(@wrap $mode self, "get_utc_offset", delegate_miner!(@call $mode self, ref, get_utc_offset()))
+            }
+
+            async fn get_mac(&self) -> Result<String, Error> {
+                delegate_miner!(@wrap $mode self, "get_mac", delegate_miner!(@call $mode self, ref, get_mac()))
+            }
+
+            async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
+                delegate_miner!(@wrap $mode self, "get_errors", delegate_miner!(@call $mode self, mut, get_errors()))
+            }
+
+            async fn get_dns(&self) -> Result<String, Error> {
+                delegate_miner!(@wrap $mode self, "get_dns", delegate_miner!(@call $mode self, ref, get_dns()))
+            }
+
+            async fn get_dns_servers(&self) -> Result<Vec<IpAddr>, Error> {
+                delegate_miner!(@wrap $mode self, "get_dns_servers", delegate_miner!(@call $mode self, ref, get_dns_servers()))
+            }
+
+            async fn set_dns(&mut self, servers: Vec<IpAddr>) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_dns", delegate_miner!(@call $mode self, mut, set_dns(servers)))
+            }
+
+            async fn get_profile(&self) -> Result<Profile, Error> {
+                delegate_miner!(@wrap $mode self, "get_profile", delegate_miner!(@call $mode self, ref, get_profile()))
+            }
+
+            async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
+                delegate_miner!(@wrap $mode self, "get_profiles", delegate_miner!(@call $mode self, ref, get_profiles()))
+            }
+
+            async fn set_profile(&mut self, profile: Profile) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_profile", delegate_miner!(@call $mode self, mut, set_profile(profile)))
+            }
+
+            async fn set_profile_verified(&mut self, profile: Profile, timeout: std::time::Duration, max_power: Option<f64>) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_profile_verified", delegate_miner!(@call $mode self, mut, set_profile_verified(profile, timeout, max_power)))
+            }
+
+            async fn get_hashboard(&mut self) -> Result<String, Error> {
+                delegate_miner!(@wrap $mode self, "get_hashboard", delegate_miner!(@call $mode self, mut, get_hashboard()))
+            }
+
+            async fn get_pool_counters(&self) -> Result<Vec<PoolCounters>, Error> {
+                delegate_miner!(@wrap $mode self, "get_pool_counters", delegate_miner!(@call $mode self, ref, get_pool_counters()))
+            }
+
+            async fn get_tuning_status(&self) -> Result<TuningStatus, Error> {
+                delegate_miner!(@wrap $mode self, "get_tuning_status", delegate_miner!(@call $mode self, ref, get_tuning_status()))
+            }
+
+            async fn get_system_stats(&self) -> Result<SystemStats, Error> {
+                delegate_miner!(@wrap $mode self, "get_system_stats", delegate_miner!(@call $mode self, ref, get_system_stats()))
+            }
+
+            async fn get_network_stats(&self) -> Result<NetworkStats, Error> {
+                delegate_miner!(@wrap $mode self, "get_network_stats", delegate_miner!(@call $mode self, ref, get_network_stats()))
+            }
+
+            async fn get_chip_counts(&self) -> Result<Vec<ChipCount>, Error> {
+                delegate_miner!(@wrap $mode self, "get_chip_counts", delegate_miner!(@call $mode self, ref, get_chip_counts()))
+            }
+
+            async fn get_board_temps(&self) -> Result<Vec<BoardTemps>, Error> {
+                delegate_miner!(@wrap $mode self, "get_board_temps", delegate_miner!(@call $mode self, ref, get_board_temps()))
+            }
+
+            async fn get_temperatures(&self) -> Result<TempReport, Error> {
+                delegate_miner!(@wrap $mode self, "get_temperatures", delegate_miner!(@call $mode self, ref, get_temperatures()))
+            }
+
+            async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "prefetch", delegate_miner!(@call $mode self, ref, prefetch(resources)))
+            }
+
+            async fn invalidate(&self) {
+                delegate_miner!(@call $mode self, ref, invalidate())
+            }
+
+            async fn debug_dump(&self) -> Result<serde_json::Value, Error> {
+                delegate_miner!(@wrap $mode self, "debug_dump", delegate_miner!(@call $mode self, ref, debug_dump()))
+            }
+
+            async fn get_health(&self) -> Result<Vec<HealthCondition>, Error> {
+                delegate_miner!(@wrap $mode self, "get_health", delegate_miner!(@call $mode self, ref, get_health()))
+            }
+
+            async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+                delegate_miner!(@wrap $mode self, "security_scan", delegate_miner!(@call $mode self, ref, security_scan()))
+            }
+
+            async fn get_cooling_detail(&self) -> Result<CoolingDetail, Error> {
+                delegate_miner!(@wrap $mode self, "get_cooling_detail", delegate_miner!(@call $mode self, ref, get_cooling_detail()))
+            }
+
+            async fn set_board_enabled(&mut self, chain: usize, enabled: bool) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "set_board_enabled", delegate_miner!(@call $mode self, mut, set_board_enabled(chain, enabled)))
+            }
+
+            async fn pending_changes(&self) -> Result<Vec<PendingChange>, Error> {
+                delegate_miner!(@wrap $mode self, "pending_changes", delegate_miner!(@call $mode self, ref, pending_changes()))
+            }
+
+            async fn restart_required(&self) -> Result<bool, Error> {
+                delegate_miner!(@wrap $mode self, "restart_required", delegate_miner!(@call $mode self, ref, restart_required()))
+            }
+
+            async fn backup_config(&self) -> Result<MinerConfigBackup, Error> {
+                delegate_miner!(@wrap $mode self, "backup_config", delegate_miner!(@call $mode self, ref, backup_config()))
+            }
+
+            async fn restore_config(&mut self, backup: MinerConfigBackup) -> Result<(), Error> {
+                delegate_miner!(@wrap $mode self, "restore_config", delegate_miner!(@call $mode self, mut, restore_config(backup)))
+            }
+        }
+    };
 }
 
 pub struct LockMiner {
     _permit: tokio::sync::OwnedSemaphorePermit,
+    _in_flight: Option<InFlight>,
     miner: Box<dyn Miner + Send + Sync>,
 }
 
@@ -157,119 +1481,213 @@ impl LockMiner {
     pub fn new_locked(miner: Box<dyn Miner + Send + Sync>, permit: tokio::sync::OwnedSemaphorePermit) -> LockMiner {
         LockMiner {
             _permit: permit,
+            _in_flight: None,
             miner,
         }
     }
-}
-
-#[async_trait]
-impl Miner for LockMiner {
-    fn new(_client: Client, _ip: String, _port: u16) -> Self
-        where Self: Sized {
-            unimplemented!();
-        }
 
-    fn get_type(&self) -> &'static str {
-        self.miner.get_type()
+    /// Count the permit against the miner's vendor in Client::stats
+    pub(crate) fn tracked(mut self, in_flight: InFlight) -> LockMiner {
+        self._in_flight = Some(in_flight);
+        self
     }
 
-    async fn get_model(&self) -> Result<String, Error> {
-        self.miner.get_model().await
+    pub fn inner(&self) -> &(dyn Miner + Send + Sync) {
+        &*self.miner
     }
 
-    async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error> {
-        self.miner.auth(username, password).await
+    pub fn inner_mut(&mut self) -> &mut (dyn Miner + Send + Sync) {
+        &mut *self.miner
     }
 
-    async fn reboot(&mut self) -> Result<(), Error> {
-        self.miner.reboot().await
+    /// Release the connection permit and return the wrapped miner
+    pub fn into_inner(self) -> Box<dyn Miner + Send + Sync> {
+        self.miner
     }
 
-    async fn get_hashrate(&self) -> Result<f64, Error> {
-        self.miner.get_hashrate().await
+    /// Swap the wrapped miner, keeping the permit
+    pub(crate) fn replace_inner(&mut self, miner: Box<dyn Miner + Send + Sync>) -> Box<dyn Miner + Send + Sync> {
+        std::mem::replace(&mut self.miner, miner)
     }
 
-    async fn get_power(&self) -> Result<f64, Error> {
-        self.miner.get_power().await
+    /// Downcast the wrapped miner to a concrete vendor type
+    pub fn downcast_ref<T: Miner + 'static>(&self) -> Option<&T> {
+        self.inner().as_any().downcast_ref::<T>()
     }
 
-    async fn get_nameplate_power(&self) -> Result<f64, Error> {
-        self.miner.get_nameplate_power().await
+    pub fn downcast_mut<T: Miner + 'static>(&mut self) -> Option<&mut T> {
+        self.inner_mut().as_any_mut().downcast_mut::<T>()
     }
+}
 
-    async fn get_efficiency(&self) -> Result<f64, Error> {
-        self.miner.get_efficiency().await
-    }
+delegate_miner!(LockMiner, direct);
+
+/// Cloneable handle to a miner for sharing between tasks, e.g. a poller and a control endpoint
+/// Getters share a read lock, setters take the write lock, so each task can call setters on its own clone
+#[derive(Clone)]
+pub struct SharedMiner {
+    type_: &'static str,
+    ip: String,
+    detection: Option<DetectionInfo>,
+    tags: Arc<std::sync::RwLock<Tags>>,
+    miner: Arc<RwLock<Box<dyn Miner + Send + Sync>>>,
+}
 
-    async fn get_nameplate_rate(&self) -> Result<f64, Error> {
-        self.miner.get_nameplate_rate().await
+impl SharedMiner {
+    pub fn new(miner: Box<dyn Miner + Send + Sync>) -> SharedMiner {
+        SharedMiner {
+            type_: miner.get_type(),
+            ip: miner.get_ip().to_string(),
+            detection: miner.detection_info(),
+            tags: Arc::new(std::sync::RwLock::new(miner.tags())),
+            miner: Arc::new(RwLock::new(miner)),
+        }
     }
 
-    async fn get_temperature(&self) -> Result<f64, Error> {
-        self.miner.get_temperature().await
+    /// Lock the inner miner for a sequence of reads
+    pub async fn read(&self) -> RwLockReadGuard<'_, Box<dyn Miner + Send + Sync>> {
+        self.miner.read().await
     }
 
-    async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
-        self.miner.get_fan_speed().await
+    /// Lock the inner miner exclusively, e.g. to batch several setters
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Box<dyn Miner + Send + Sync>> {
+        self.miner.write().await
     }
+}
 
-    async fn get_fan_pwm(&self) -> Result<f64, Error> {
-        self.miner.get_fan_pwm().await
+impl From<Box<dyn Miner + Send + Sync>> for SharedMiner {
+    fn from(miner: Box<dyn Miner + Send + Sync>) -> Self {
+        SharedMiner::new(miner)
     }
+}
 
-    async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
-        self.miner.get_pools().await
-    }
+delegate_miner!(SharedMiner, locked);
 
-    async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
-        self.miner.set_pools(pools).await
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    async fn get_sleep(&self) -> Result<bool, Error> {
-        self.miner.get_sleep().await
+    fn preset(name: &str, power: f64) -> Profile {
+        Profile::Preset { name: name.to_string(), power, ths: 0.0 }
     }
 
-    async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
-        self.miner.set_sleep(sleep).await
+    fn counters(url: &str, accepted: u64, rejected: u64) -> PoolCounters {
+        PoolCounters { url: url.to_string(), accepted, rejected, stale: 0 }
     }
 
-    async fn get_blink(&self) -> Result<bool, Error> {
-        self.miner.get_blink().await
+    #[test]
+    fn test_parse_dns_servers() {
+        let servers: Vec<IpAddr> = vec!["8.8.8.8".parse().unwrap(), "1.1.1.1".parse().unwrap()];
+        assert_eq!(parse_dns_servers("8.8.8.8,1.1.1.1"), servers);
+        assert_eq!(parse_dns_servers("8.8.8.8 1.1.1.1"), servers);
+        assert_eq!(parse_dns_servers(" 8.8.8.8; 1.1.1.1 "), servers);
+        assert!(parse_dns_servers("").is_empty());
     }
 
-    async fn set_blink(&mut self, blink: bool) -> Result<(), Error> {
-        self.miner.set_blink(blink).await
+    #[test]
+    fn test_stable_hashrate() {
+        assert_eq!(stable_hashrate(60, 250.0, 101.0), 101.0);
+        assert_eq!(stable_hashrate(HASHRATE_SETTLE_SECS, 104.5, 101.0), 104.5);
     }
 
-    async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
-        self.miner.get_logs().await
+    #[cfg(any(feature = "antminer", feature = "whatsminer", feature = "avalon"))]
+    #[test]
+    fn test_dump_entry() {
+        let conf = serde_json::json!({"pools": [{"url": "stratum+tcp://pool:3333", "user": "acct.1", "pass": "secret"}], "password": null});
+        let dumped = dump_entry(Ok(conf));
+        assert_eq!(dumped["pools"][0]["pass"], "<redacted>");
+        assert_eq!(dumped["pools"][0]["user"], "acct.1");
+        assert!(dumped["password"].is_null());
+        assert_eq!(dump_entry(Err(Error::Timeout))["error"], "Timeout");
+        assert_eq!(raw_json("{\"a\":1}")["a"], 1);
+        assert_eq!(raw_json("{\"a\":1,}"), serde_json::Value::String("{\"a\":1,}".into()));
+        let truncated = dump_entry(Ok(raw_json(r#"{"user":"acct.1","pass":"secret","url":"#)));
+        assert_eq!(truncated, serde_json::Value::String(r#"{"user":"acct.1","pass":"<redacted>","url":"#.into()));
+        let form = dump_entry(Ok(raw_json("user=root&pw=secret&port=80")));
+        assert_eq!(form, serde_json::Value::String(r#"user=root&pw="<redacted>"&port=80"#.into()));
     }
 
-    async fn get_mac(&self) -> Result<String, Error> {
-        self.miner.get_mac().await
+    #[test]
+    fn test_performance_ratio() {
+        assert_eq!(performance_ratio(95.0, 100.0), Some(0.95));
+        assert_eq!(performance_ratio(0.0, 100.0), Some(0.0));
+        assert_eq!(performance_ratio(95.0, 0.0), None);
     }
 
-    async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
-        self.miner.get_errors().await
+    #[test]
+    fn test_temp_report() {
+        let report = TempReport::from_boards(vec![
+            BoardTemps { chain: 0, chip: vec![Some(60.0), None, Some(70.0)], pcb: vec![Some(45.0)], chip_min: None, chip_max: None },
+            BoardTemps { chain: 1, chip: vec![Some(80.0)], pcb: vec![None], chip_min: None, chip_max: None },
+        ]);
+        assert_eq!(report.chip_min, Some(60.0));
+        assert_eq!(report.chip_max, Some(80.0));
+        assert_eq!(report.chip_avg, Some(70.0));
+        assert_eq!(report.boards[0].failed_sensors(), 1);
+        assert_eq!(report.boards[1].max_chip(), Some(80.0));
+        assert_eq!(TempReport::from_boards(vec![]).chip_avg, None);
     }
 
-    async fn get_dns(&self) -> Result<String, Error> {
-        self.miner.get_dns().await
+    #[test]
+    fn test_log_options() {
+        let lines: Vec<String> = [
+            "2024-03-01 10:00:00 driver-btm-api.c:1200:init_miner_version: miner ID : 8118b4c2",
+            "  continued",
+            "2024-03-01 11:00:00 temperature.c:88:check_temp: chain 1 temp 70",
+            "  continued",
+        ].iter().map(|s| s.to_string()).collect();
+        let since = LogOptions { since: Some("2024-03-01T10:30:00Z".parse().unwrap()), ..Default::default() };
+        assert_eq!(since.apply(lines.clone()), lines[2..].to_vec());
+        let max = LogOptions { max_lines: Some(1), ..Default::default() };
+        assert_eq!(max.apply(lines.clone()), lines[3..].to_vec());
+        assert_eq!(LogOptions::default().apply(lines.clone()), lines);
     }
 
-    async fn get_profile(&self) -> Result<Profile, Error> {
-        self.miner.get_profile().await
+    #[test]
+    fn test_log_entry() {
+        let line = "2024-03-01 10:00:00 temperature.c:88:check_temp: chain 1 temp 70".to_string();
+        let entry = LogEntry::parse(line.clone(), Some(FixedOffset::east_opt(3 * 3600).unwrap()));
+        assert_eq!(entry.ts, Some("2024-03-01T07:00:00Z".parse().unwrap()));
+        assert!(entry.offset_known);
+        let entry = LogEntry::parse(line, None);
+        assert_eq!(entry.ts, Some("2024-03-01T10:00:00Z".parse().unwrap()));
+        assert!(!entry.offset_known);
+        assert_eq!(LogEntry::parse("  continued".to_string(), None).ts, None);
     }
 
-    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
-        self.miner.get_profiles().await
+    #[test]
+    fn test_pool_counter_differ() {
+        let differ = PoolCounterDiffer::new();
+        assert_eq!(differ.diff("10.0.0.1", vec![counters("a", 100, 2)]), vec![counters("a", 0, 0)]);
+        assert_eq!(differ.diff("10.0.0.1", vec![counters("a", 150, 5)]), vec![counters("a", 50, 3)]);
+        // Other miners have their own baseline
+        assert_eq!(differ.diff("10.0.0.2", vec![counters("a", 150, 5)]), vec![counters("a", 0, 0)]);
+        // Restarted
+        assert_eq!(differ.diff("10.0.0.1", vec![counters("a", 10, 1)]), vec![counters("a", 10, 1)]);
+        assert_eq!(counters("a", 90, 10).reject_rate(), 0.1);
     }
 
-    async fn set_profile(&mut self, profile: Profile) -> Result<(), Error> {
-        self.miner.set_profile(profile).await
+    #[test]
+    fn test_tuning_status() {
+        assert_eq!(TuningStatus::new(100.0, 50.0, true).state, TuningState::Tuning);
+        assert_eq!(TuningStatus::new(100.0, 95.0, false).state, TuningState::Tuned);
+        assert_eq!(TuningStatus::new(100.0, 80.0, false).state, TuningState::Underperforming);
     }
 
-    async fn get_hashboard(&mut self) -> Result<String, Error> {
-        self.miner.get_hashboard().await
+    #[test]
+    fn test_profile_resolve() {
+        let manual = Profile::Manual { volt: 1300, freq: 500, min_freq: 300, max_freq: 700, min_volt: 1200, max_volt: 1500, def_volt: 1350, def_freq: 525 };
+        let available = vec![preset("3000", 3000.0), preset("3320", 3320.0), manual.clone(), Profile::Default];
+
+        assert_eq!(preset("3320", 0.0).resolve(&available), Some(&available[1]));
+        assert_eq!(Profile::Unknown { name: "default".to_string() }.resolve(&available), Some(&Profile::Default));
+        assert_eq!(preset("3300W", 3300.0).resolve(&available), Some(&available[1]));
+        assert_eq!(preset("2500", 2500.0).resolve(&available), None);
+        assert_eq!(Profile::LowPower.resolve(&available), None);
+
+        let wanted = Profile::Manual { volt: 1400, freq: 600, min_freq: 0, max_freq: 0, min_volt: 0, max_volt: 0, def_volt: 0, def_freq: 0 };
+        assert_eq!(wanted.resolve(&available), Some(&manual));
+        let too_high = Profile::Manual { volt: 1600, freq: 600, min_freq: 0, max_freq: 0, min_volt: 0, max_volt: 0, def_volt: 0, def_freq: 0 };
+        assert!(matches!(too_high.validate(&available), Err(Error::InvalidProfile(_))));
     }
 }
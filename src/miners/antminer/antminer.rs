@@ -2,16 +2,20 @@ use async_trait::async_trait;
 use lazy_regex::regex;
 use serde_json::json;
 use std::{
-    collections::HashSet,
+    collections::{HashSet, HashMap},
+    net::IpAddr,
 };
 use phf::phf_map;
+use serde::Serialize;
 use tokio::sync::{Mutex, MutexGuard};
 
 use crate::util::digest_auth::WithDigestAuth;
-use crate::miner::{Miner, Pool, Profile, MinerError};
+use crate::miner::{Miner, Pool, PoolCounters, ChipCount, BoardTemps, CoolingDetail, FanMode, Profile, stable_hashrate, performance_ratio, dump_entry, raw_json, MinerError, PendingChange, LogOptions, LogSource, Resource, Tags};
 use crate::miners::antminer::cgi;
+use crate::model::normalize_model;
 use crate::error::Error;
-use crate::{Client, ErrorType};
+use crate::security::{self, SecurityIndicator};
+use crate::{Client, ErrorType, Capabilities, MinerConfigBackup, DetectionInfo, SecretString};
 use crate::miners::antminer::error::ANTMINER_ERRORS;
 
 use super::cgi::SetConf;
@@ -20,6 +24,7 @@ use super::cgi::SetConf;
 /// If more than 1 variant exists, this will be an average of all variants
 /// Antminer rates these @25C
 /// Second number is max fan speed
+/// Keyed by normalize_model
 pub static POWER_MAP: phf::Map<&'static str, (f64, f64)> = phf_map! {
     "t19" => (37.5, 6000.0),
     "s19" => (34.7, 6000.0),
@@ -30,13 +35,55 @@ pub static POWER_MAP: phf::Map<&'static str, (f64, f64)> = phf_map! {
     "s19apro" => (29.5, 6000.0),
     "s19jpro+" => (27.5, 6000.0),
     "s19xp" => (22.0, 6000.0),
+    // Hydro units have no fans of their own
+    "s19pro+hyd" => (27.5, 0.0),
 };
 
+/// Derates offered as profiles on firmware with bitmain-hashrate-percent, any percent can be set directly
+pub const HASHRATE_PERCENT_STEPS: [u8; 5] = [90, 80, 70, 60, 50];
+
+/// Firmware upgrade CGIs, stock firmware has upgrade.cgi or upgrade_clear.cgi or both depending on its age
+const UPGRADE_CGIS: [&str; 2] = ["upgrade.cgi", "upgrade_clear.cgi"];
+
+/// bitmain-work-mode values hydro stock firmware offers besides sleep (1)
+/// With approximate hashrate and power relative to normal mode
+pub const HYD_WORK_MODES: [(u8, &str, f64, f64); 3] = [
+    (0, "Normal", 1.0, 1.0),
+    (2, "High Performance", 1.1, 1.2),
+    (3, "Low Power", 0.75, 0.7),
+];
+
+/// What an Antminer sleep decision was based on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SleepEvidence {
+    /// bitmain-work-mode was set
+    WorkMode,
+    /// Work mode was missing but the miner is hashing
+    Hashrate,
+    /// Work mode was missing and nothing is hashing, decided by whether the fans are still spinning
+    Fans,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SleepStatus {
+    pub sleeping: bool,
+    pub evidence: SleepEvidence,
+}
+
 pub struct Antminer {
     ip: String,
     username: String,
-    password: String,
+    password: SecretString,
     client: Client,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
+    /// Credentials for specific CGIs, some only accept root
+    endpoint_auth: HashMap<String, (String, SecretString)>,
+    /// Whether each CGI accepted our credentials, probed during auth and updated by every request since
+    access: std::sync::Mutex<HashMap<String, bool>>,
+    /// Written since the last reboot, bmminer only reads pools, derating, work mode and fan settings
+    /// from miner_conf at boot, sleep is the one write it applies live
+    pending: Vec<PendingChange>,
 
     sys_info: Mutex<Option<cgi::SystemInfoResponse>>,
     summary: Mutex<Option<cgi::SummaryResponse>>,
@@ -45,82 +92,284 @@ pub struct Antminer {
 }
 
 impl Antminer {
-    async fn sys_info(&self) -> Result<MutexGuard<Option<cgi::SystemInfoResponse>>, Error> {
+    fn credentials(&self, cgi: &str) -> (&str, &str) {
+        match self.endpoint_auth.get(cgi) {
+            Some((username, password)) => (username.as_str(), password.expose()),
+            None => (self.username.as_str(), self.password.expose()),
+        }
+    }
+
+    /// Lines from the first of the CGIs this firmware serves, NotSupported if it serves none
+    /// log.cgi only holds the tail of the miner log, the kernel and system logs reach further back on firmware that has them
+    async fn log_lines(&self, source: LogSource) -> Result<Vec<String>, Error> {
+        let cgis: &[&str] = match source {
+            LogSource::Miner => &["log.cgi"],
+            LogSource::Kernel => &["get_kernel_log.cgi", "kernel_log.cgi"],
+            LogSource::System => &["get_system_log.cgi", "system_log.cgi"],
+        };
+        for cgi in cgis {
+            let resp = self.cgi_get(cgi).await?;
+            match resp.status() {
+                s if s.is_success() => return Ok(resp.text().await?.lines().map(|s| s.to_string()).collect()),
+                reqwest::StatusCode::NOT_FOUND => continue,
+                reqwest::StatusCode::UNAUTHORIZED => return Err(Error::Unauthorized),
+                _ => return Err(Error::HttpRequestFailed),
+            }
+        }
+        Err(Error::NotSupported)
+    }
+
+    /// Anything but a 401 means the credentials for the CGI were accepted
+    fn record_access(&self, cgi: &str, resp: &Result<reqwest::Response, Error>) {
+        if let Ok(resp) = resp {
+            let accepted = resp.status() != reqwest::StatusCode::UNAUTHORIZED;
+            self.access.lock().unwrap_or_else(|e| e.into_inner()).insert(cgi.to_string(), accepted);
+        }
+    }
+
+    async fn cgi_get(&self, cgi: &str) -> Result<reqwest::Response, Error> {
+        let (username, password) = self.credentials(cgi);
+        let resp = self.client.http_client
+            .get(format!("http://{}/cgi-bin/{}", self.ip, cgi))
+            .send_with_digest_auth(&self.client, username, password)
+            .await;
+        self.record_access(cgi, &resp);
+        resp
+    }
+
+    async fn cgi_post<T>(&self, cgi: &str, json: &T) -> Result<reqwest::Response, Error>
+        where T: Serialize + ?Sized
+    {
+        let (username, password) = self.credentials(cgi);
+        let resp = self.client.http_client
+            .post(format!("http://{}/cgi-bin/{}", self.ip, cgi))
+            .json(json)
+            .send_with_digest_auth(&self.client, username, password)
+            .await;
+        self.record_access(cgi, &resp);
+        resp
+    }
+
+    /// Raw JSON from a CGI, for backups that need to carry fields we don't model
+    async fn cgi_json(&self, cgi: &str) -> Result<serde_json::Value, Error> {
+        let resp = self.cgi_get(cgi).await?;
+        if !resp.status().is_success() {
+            if resp.status().as_u16() == 401 {
+                return Err(Error::Unauthorized);
+            }
+            return Err(Error::HttpRequestFailed);
+        }
+        Ok(resp.json().await?)
+    }
+
+    async fn sys_info(&self) -> Result<MutexGuard<'_, Option<cgi::SystemInfoResponse>>, Error> {
         let mut sys_info = self.sys_info.lock().await;
         if sys_info.is_none() {
-            let resp = self.client.http_client
-                .get(&format!("http://{}/cgi-bin/get_system_info.cgi", self.ip))
-                .send_with_digest_auth(&self.username, &self.password)
-                .await?;
+            let resp = self.cgi_get("get_system_info.cgi").await?;
             if !resp.status().is_success() {
                 if resp.status().as_u16() == 401 {
                     return Err(Error::Unauthorized);
                 }
                 return Err(Error::HttpRequestFailed);
             }
-            *sys_info = Some(resp.json().await?);
+            *sys_info = Some(self.client.parse(&self.ip, "get_system_info.cgi", &resp.text().await?)?);
         }
         Ok(sys_info)
     }
 
-    async fn summary(&self) -> Result<MutexGuard<Option<cgi::SummaryResponse>>, Error> {
+    async fn summary(&self) -> Result<MutexGuard<'_, Option<cgi::SummaryResponse>>, Error> {
         let mut summary = self.summary.lock().await;
         if summary.is_none() {
-            let resp = self.client.http_client
-                .get(&format!("http://{}/cgi-bin/summary.cgi", self.ip))
-                .send_with_digest_auth(&self.username, &self.password)
-                .await?;
+            let resp = self.cgi_get("summary.cgi").await?;
             if !resp.status().is_success() {
                 if resp.status().as_u16() == 401 {
                     return Err(Error::Unauthorized);
                 }
                 return Err(Error::HttpRequestFailed);
             }
-            *summary = Some(resp.json().await?);
+            *summary = Some(self.client.parse(&self.ip, "summary.cgi", &resp.text().await?)?);
         }
         Ok(summary)
     }
 
-    async fn miner_conf(&self) -> Result<MutexGuard<Option<cgi::GetConfResponse>>, Error> {
+    async fn miner_conf(&self) -> Result<MutexGuard<'_, Option<cgi::GetConfResponse>>, Error> {
         let mut miner_conf = self.miner_conf.lock().await;
         if miner_conf.is_none() {
-            let resp = self.client.http_client
-                .get(&format!("http://{}/cgi-bin/get_miner_conf.cgi", self.ip))
-                .send_with_digest_auth(&self.username, &self.password)
-                .await?;
+            let resp = self.cgi_get("get_miner_conf.cgi").await?;
             if !resp.status().is_success() {
                 if resp.status().as_u16() == 401 {
                     return Err(Error::Unauthorized);
                 }
                 return Err(Error::HttpRequestFailed);
             }
-            *miner_conf = Some(resp.json().await?);
+            *miner_conf = Some(self.client.parse(&self.ip, "get_miner_conf.cgi", &resp.text().await?)?);
         }
         Ok(miner_conf)
     }
 
-    async fn stats(&self) -> Result<MutexGuard<Option<cgi::StatsResponse>>, Error> {
+    async fn stats(&self) -> Result<MutexGuard<'_, Option<cgi::StatsResponse>>, Error> {
         let mut stats = self.stats.lock().await;
         if stats.is_none() {
-            let resp = self.client.http_client
-                .get(&format!("http://{}/cgi-bin/stats.cgi", self.ip))
-                .send_with_digest_auth(&self.username, &self.password)
-                .await?;
+            let resp = self.cgi_get("stats.cgi").await?;
             if !resp.status().is_success() {
                 if resp.status().as_u16() == 401 {
                     return Err(Error::Unauthorized);
                 }
                 return Err(Error::HttpRequestFailed);
             }
-            *stats = Some(resp.json().await?);
+            *stats = Some(self.client.parse(&self.ip, "stats.cgi", &resp.text().await?)?);
         }
         Ok(stats)
     }
 
-    async fn invalidate(&self) {
-        let _ = self.summary.lock().await.take();
+    /// Sleep state along with what it was decided from
+    /// bitmain-work-mode is trusted when set, otherwise hashrate and then fans are checked
+    pub async fn get_sleep_status(&self) -> Result<SleepStatus, Error> {
+        {
+            let miner_conf = self.miner_conf().await?;
+            let miner_conf = miner_conf.as_ref().unwrap_or_else(|| unreachable!());
+            match miner_conf.bitmain_work_mode.as_str() {
+                "0" | "2" | "3" => return Ok(SleepStatus { sleeping: false, evidence: SleepEvidence::WorkMode }),
+                "1" => return Ok(SleepStatus { sleeping: true, evidence: SleepEvidence::WorkMode }),
+                _ => {},
+            }
+        }
+        // No summary or stats at all is what a sleeping miner answers with
+        match self.get_hashrate().await {
+            Ok(hashrate) if hashrate > 0.0 => return Ok(SleepStatus { sleeping: false, evidence: SleepEvidence::Hashrate }),
+            Ok(_) | Err(Error::ExpectedReturn) => {},
+            Err(e) => return Err(e),
+        }
+        // Fans stop in sleep mode, but keep spinning while the miner initializes
+        let fans = match self.get_fan_speed().await {
+            Ok(fans) => fans,
+            Err(Error::ExpectedReturn) => vec![],
+            Err(e) => return Err(e),
+        };
+        Ok(SleepStatus {
+            sleeping: fans.iter().all(|&f| f == 0),
+            evidence: SleepEvidence::Fans,
+        })
+    }
+
+    /// The cached conf with a change applied, so nothing else is lost when it's written back
+    async fn updated_conf<F: FnOnce(&mut SetConf)>(&self, update: F) -> Result<SetConf, Error> {
+        let (mut json, work_mode) = {
+            let miner_conf = self.miner_conf().await?;
+            let miner_conf = miner_conf.as_ref().unwrap_or_else(|| unreachable!());
+            (SetConf::from(miner_conf), miner_conf.work_mode())
+        };
+        if work_mode.is_none() {
+            json.miner_mode = self.current_work_mode().await?;
+        }
+        update(&mut json);
+        Ok(json)
+    }
+
+    /// Work mode to write back on firmware whose conf doesn't report it, so that a write which
+    /// isn't about sleep leaves a sleeping miner asleep
+    async fn current_work_mode(&self) -> Result<u8, Error> {
+        Ok(self.get_sleep_status().await?.sleeping as u8)
+    }
+
+    /// Post a conf, ConfigRejected with the miner's reason if it refuses it
+    async fn post_conf(&self, json: &SetConf) -> Result<(), Error> {
+        let resp = self.cgi_post("set_miner_conf.cgi", json).await?;
+        if !resp.status().is_success() {
+            if resp.status().as_u16() == 401 {
+                return Err(Error::Unauthorized);
+            }
+            return Err(Error::HttpRequestFailed);
+        }
+        // A 200 still carries "stats": "error" for an invalid pool or a locked conf
+        match cgi::SetConfResp::failure(&resp.text().await?) {
+            Some(reason) => Err(Error::ConfigRejected(reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// Change the conf under the Client's write lock for this miner, re-reading it first as
+    /// another handle may have written since it was cached. verify re-reads it to check it stuck
+    async fn update_conf<F: FnOnce(&mut SetConf)>(&self, update: F, verify: bool) -> Result<(), Error> {
+        let _write = self.client.write_lock(&self.ip).await;
         let _ = self.miner_conf.lock().await.take();
-        let _ = self.stats.lock().await.take();
+        // Built through updated_conf so the conf lock is released before invalidate takes it
+        let json = self.updated_conf(update).await?;
+        if verify {
+            return self.write_conf(&json).await;
+        }
+        self.post_conf(&json).await?;
+        self.invalidate().await;
+        Ok(())
+    }
+
+    /// Write a conf and check it stuck
+    async fn write_conf(&self, json: &SetConf) -> Result<(), Error> {
+        self.post_conf(json).await?;
+        self.invalidate().await;
+
+        let miner_conf = self.miner_conf().await?;
+        match json.mismatch(miner_conf.as_ref().unwrap_or_else(|| unreachable!())) {
+            Some(field) => Err(Error::ConfigNotApplied(field)),
+            None => Ok(()),
+        }
+    }
+
+    /// Stock derating as a percent of nominal hashrate, NotSupported on firmware without it
+    pub async fn get_hashrate_percent(&self) -> Result<u8, Error> {
+        let miner_conf = self.miner_conf().await?;
+        miner_conf.as_ref().unwrap_or_else(|| unreachable!()).hashrate_percent().ok_or(Error::NotSupported)
+    }
+
+    /// Derate to a percent of nominal hashrate, power drops with it after a reboot
+    pub async fn set_hashrate_percent(&mut self, percent: u8) -> Result<(), Error> {
+        if percent == 0 || percent > 100 {
+            return Err(Error::InvalidConfig(format!("Hashrate percent {} outside 1-100", percent)));
+        }
+        self.get_hashrate_percent().await?;
+        self.update_conf(|conf| conf.hashrate_percent = Some(percent.to_string()), true).await?;
+        self.record_pending(PendingChange::Profile);
+        Ok(())
+    }
+
+    /// Record a write that waits on a reboot, see pending_changes
+    fn record_pending(&mut self, change: PendingChange) {
+        if !self.pending.contains(&change) {
+            self.pending.push(change);
+        }
+    }
+
+    /// Hydro stock firmware switches performance with bitmain-work-mode rather than derating
+    async fn is_hyd(&self) -> Result<bool, Error> {
+        Ok(self.get_model().await?.contains("hyd"))
+    }
+
+    /// Preset for a hydro work mode, with nameplate power and hashrate scaled to it
+    async fn work_mode_profile(&self, mode: u8) -> Option<Profile> {
+        let (_, name, rate_scale, power_scale) = HYD_WORK_MODES.iter().find(|(m, ..)| *m == mode)?;
+        Some(Profile::Preset {
+            name: name.to_string(),
+            power: self.get_nameplate_power().await.unwrap_or(0.0) * power_scale,
+            ths: self.get_nameplate_rate().await.unwrap_or(0.0) * rate_scale,
+        })
+    }
+
+    async fn work_mode_profiles(&self) -> Vec<Profile> {
+        let mut profiles = vec![];
+        for (mode, ..) in HYD_WORK_MODES {
+            profiles.extend(self.work_mode_profile(mode).await);
+        }
+        profiles
+    }
+
+    /// Preset for a derate, with nameplate power and hashrate scaled to it
+    async fn derate_profile(&self, percent: u8) -> Profile {
+        let scale = percent as f64 / 100.0;
+        Profile::Preset {
+            name: format!("{}%", percent),
+            power: self.get_nameplate_power().await.unwrap_or(0.0) * scale,
+            ths: self.get_nameplate_rate().await.unwrap_or(0.0) * scale,
+        }
     }
 }
 
@@ -130,8 +379,13 @@ impl Miner for Antminer {
         Antminer {
             ip,
             username: "".to_string(),
-            password: "".to_string(),
+            password: SecretString::default(),
             client,
+            detection: None,
+            tags: Tags::new(),
+            endpoint_auth: HashMap::new(),
+            access: std::sync::Mutex::new(HashMap::new()),
+            pending: Vec::new(),
             sys_info: Mutex::new(None),
             summary: Mutex::new(None),
             miner_conf: Mutex::new(None),
@@ -143,31 +397,79 @@ impl Miner for Antminer {
         "Antminer"
     }
 
+    async fn invalidate(&self) {
+        let _ = self.summary.lock().await.take();
+        let _ = self.miner_conf.lock().await.take();
+        let _ = self.stats.lock().await.take();
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            endpoints: self.access.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            pool_passwords: true,
+            ..Default::default()
+        }
+    }
+
     async fn get_model(&self) -> Result<String, Error> {
         let sys_info = self.sys_info().await?;
         let sys_info = sys_info.as_ref().unwrap_or_else(|| unreachable!());
 
-        Ok(sys_info.minertype.replace("Antminer ", "").replace(" ", "").to_lowercase())
+        // Hydro units end in a period, "Antminer S19 Pro+ Hyd."
+        Ok(sys_info.minertype.replace("Antminer ", "").replace(" ", "").trim_end_matches('.').to_lowercase())
     }
 
+    /// Probes the read CGIs for capabilities(), set_miner_conf.cgi only runs with a conf to write
+    /// so its access is known from the first write on
     async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error> {
         self.username = username.to_string();
-        self.password = password.to_string();
+        self.password = password.into();
+        self.access.lock().unwrap_or_else(|e| e.into_inner()).clear();
         // Test authentication with a simple get request
-        match self.sys_info().await {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e.into()),
-            }
+        drop(self.sys_info().await?);
+        // Probe the remaining read CGIs, cgi_get records which accepted us
+        let _ = self.summary().await;
+        let _ = self.stats().await;
+        let _ = self.miner_conf().await;
+        Ok(())
+    }
+
+    /// Use separate credentials for a single CGI (e.g. "set_miner_conf.cgi"), some only accept root
+    async fn set_endpoint_auth(&mut self, endpoint: &str, username: &str, password: &str) -> Result<(), Error> {
+        self.endpoint_auth.insert(endpoint.to_string(), (username.to_string(), password.into()));
+        self.access.lock().unwrap_or_else(|e| e.into_inner()).remove(endpoint);
+        Ok(())
     }
 
     async fn reboot(&mut self) -> Result<(), Error> {
-        let resp = self.client.http_client
-            .get(&format!("http://{}/cgi-bin/reboot.cgi", self.ip))
-            .send_with_digest_auth(&self.username, &self.password)
-            .await;
+        // Waits for any conf write in flight, a reboot mid write can leave the conf truncated
+        let _write = self.client.write_lock(&self.ip).await;
+        let resp = self.cgi_get("reboot.cgi").await;
         // Miner reboots before a response is returned, so actually we want this to fail
-        if let Err(_) = resp {
+        if resp.is_err() {
             self.invalidate().await;
+            self.pending.clear();
             Ok(())
         } else {
             Err(Error::ApiCallFailed("Reboot failed".to_string()))
@@ -178,46 +480,47 @@ impl Miner for Antminer {
         let summary = self.summary().await?;
         let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
 
-        if let Some(sum) = summary.summary.get(0) {
-            Ok(sum.rate_5s / 1000.0)
-        } else {
-            // Miner can not return a summary if it is not mining
-            Ok(0.0)
-        }
+        // Miner can not return a summary if it is not mining
+        let sum = summary.summary.first().ok_or(Error::ExpectedReturn)?;
+        Ok(sum.rate_5s / 1000.0)
+    }
+
+    async fn get_hashrate_stable(&self) -> Result<f64, Error> {
+        let summary = self.summary().await?;
+        let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = summary.summary.first().ok_or(Error::ExpectedReturn)?;
+        Ok(stable_hashrate(sum.elapsed as u64, sum.rate_5s, sum.rate_avg) / 1000.0)
+    }
+
+    /// Per chain rate_real over rate_ideal, which tracks the frequency the chains actually run at
+    async fn get_performance_ratio(&self) -> Result<f64, Error> {
+        let stats = self.stats().await?;
+        let stat = stats.as_ref().unwrap_or_else(|| unreachable!()).stats.first().ok_or(Error::ExpectedReturn)?;
+        let real = stat.chain.iter().map(|c| c.rate_real).sum::<f64>();
+        let ideal = stat.chain.iter().map(|c| c.rate_ideal).sum::<f64>();
+        performance_ratio(real, ideal).ok_or(Error::NotSupported)
     }
 
     async fn get_power(&self) -> Result<f64, Error> {
         match self.get_hashrate().await {
             Ok(hashrate) => {
                 let model = self.get_model().await?;
-                Ok(hashrate * POWER_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model))?.0)
+                Ok(hashrate * POWER_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model))?.0)
             },
             Err(e) => Err(e),
         }
     }
 
-    async fn get_nameplate_power(&self) -> Result<f64, Error> {
-        let model = self.get_model().await?;
-        let rate = self.get_nameplate_rate().await?;
-
-        Ok(rate * POWER_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model))?.0)
-    }
-
     async fn get_efficiency(&self) -> Result<f64, Error> {
-        let model = self.get_model().await?;
-        Ok(POWER_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model))?.0)
+        self.get_rated_efficiency().await
     }
 
     async fn get_nameplate_rate(&self) -> Result<f64, Error> {
         let stats = self.stats().await?;
         let stats = stats.as_ref().unwrap_or_else(|| unreachable!());
 
-        if let Some(stat) = stats.stats.get(0) {
-            Ok(stat.rate_ideal / 1000.0)
-        } else {
-            //TODO: Decide to return an error or just an empty vector
-            Ok(0.0)
-        }
+        let stat = stats.stats.first().ok_or(Error::ExpectedReturn)?;
+        Ok(stat.rate_ideal / 1000.0)
     }
 
     async fn get_temperature(&self) -> Result<f64, Error> {
@@ -226,98 +529,253 @@ impl Miner for Antminer {
         let stats = self.stats().await?;
         let stats = stats.as_ref().unwrap_or_else(|| unreachable!());
 
-        if let Some(stat) = stats.stats.get(0) {
-            Ok(
-                stat.chain.iter()
-                    .flat_map(|c| c.temp_chip.iter())
-                    .max()
-                    .ok_or(Error::ApiCallFailed("No temperature data".to_string()))?
-                    .clone() as f64
-            )
-        } else {
-            //TODO: Decide to return an error or just an empty vector
-            Ok(0.0)
-        }
+        let stat = stats.stats.first().ok_or(Error::ExpectedReturn)?;
+        // Disconnected sensors read -273 or 0, only working ones count
+        stat.chain.iter()
+            .filter_map(|c| c.max_chip_temp())
+            .reduce(f64::max)
+            .ok_or(Error::ApiCallFailed("No temperature data".to_string()))
     }
 
     async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
         let stats = self.stats().await?;
         let stats = stats.as_ref().unwrap_or_else(|| unreachable!());
 
-        if let Some(stat) = stats.stats.get(0) {
-            //TODO: Gotta be a way to avoid this clone
-            Ok(stat.fan.clone())
-        } else {
-            //TODO: Decide to return an error or just an empty vector
-            Ok(vec![])
-        }
+        let stat = stats.stats.first().ok_or(Error::ExpectedReturn)?;
+        //TODO: Gotta be a way to avoid this clone
+        Ok(stat.fan.clone())
     }
 
     async fn get_fan_pwm(&self) -> Result<f64, Error> {
+        if let Ok(FanMode::Manual(pwm)) = self.get_fan_mode().await {
+            return Ok(pwm as f64);
+        }
         // Antminer doesn't report a single fan pwm, max fan speed varies by model
-        let model = self.get_model().await?;
-        let fan_speed = POWER_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model))?.1;
+        let max = self.get_max_fan_speed().await? as f64;
         self.get_fan_speed().await?.iter()
             .max()
-            .map(|&s| (s as f64 / fan_speed) * 100.0)
+            .map(|&s| (s as f64 / max) * 100.0)
             .ok_or(Error::ApiCallFailed("No fan speed data".to_string()))
     }
 
-    async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
+    async fn get_rated_efficiency(&self) -> Result<f64, Error> {
+        let model = self.get_model().await?;
+        Ok(POWER_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model))?.0)
+    }
+
+    async fn get_max_fan_speed(&self) -> Result<u32, Error> {
+        let model = self.get_model().await?;
+        match POWER_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model))?.1 as u32 {
+            0 => Err(Error::NotSupported),
+            max => Ok(max),
+        }
+    }
+
+    /// Default at 100%, otherwise a preset named after the hashrate percent
+    /// Hydro units report their work mode as a preset instead
+    async fn get_profile(&self) -> Result<Profile, Error> {
+        if self.is_hyd().await? {
+            let mode = {
+                let miner_conf = self.miner_conf().await?;
+                miner_conf.as_ref().unwrap_or_else(|| unreachable!()).bitmain_work_mode.parse::<u8>().unwrap_or(0)
+            };
+            return match self.work_mode_profile(mode).await {
+                Some(profile) => Ok(profile),
+                None if mode == 1 => Ok(Profile::Unknown { name: "Sleep".to_string() }),
+                None => Ok(Profile::Unknown { name: format!("Work mode {}", mode) }),
+            };
+        }
+        match self.get_hashrate_percent().await? {
+            100 => Ok(Profile::Default),
+            percent => Ok(self.derate_profile(percent).await),
+        }
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
+        if self.is_hyd().await? {
+            return Ok(self.work_mode_profiles().await);
+        }
+        self.get_hashrate_percent().await?;
+        let mut profiles = vec![Profile::Default];
+        for percent in HASHRATE_PERCENT_STEPS {
+            profiles.push(self.derate_profile(percent).await);
+        }
+        Ok(profiles)
+    }
+
+    /// Matched against get_profiles, presets named "N%" that aren't listed are still taken so
+    /// derates between the steps work too
+    /// On hydro units Default is normal mode and presets are matched against the work modes
+    async fn set_profile(&mut self, profile: Profile) -> Result<(), Error> {
+        if self.is_hyd().await? {
+            let name = match &profile {
+                Profile::Default => HYD_WORK_MODES[0].1.to_string(),
+                _ => profile.validate(&self.work_mode_profiles().await)?.name(),
+            };
+            let mode = HYD_WORK_MODES.iter().find(|(_, n, ..)| *n == name).map(|(m, ..)| *m)
+                .ok_or(Error::InvalidProfile(name))?;
+            self.update_conf(|conf| conf.miner_mode = mode, true).await?;
+            self.record_pending(PendingChange::Profile);
+            return Ok(());
+        }
+        let percent = |name: &str| name.trim().trim_end_matches('%').parse::<u8>()
+            .map_err(|_| Error::InvalidProfile(profile.name()));
+        let percent = match profile.resolve(&self.get_profiles().await?) {
+            Some(Profile::Default) => 100,
+            Some(Profile::Preset { name, .. }) => percent(name)?,
+            Some(_) => return Err(Error::NotSupported),
+            None => match &profile {
+                Profile::Preset { name, .. } => percent(name)?,
+                _ => return Err(Error::InvalidProfile(profile.name())),
+            },
+        };
+        self.set_hashrate_percent(percent).await
+    }
+
+    async fn get_fan_mode(&self) -> Result<FanMode, Error> {
         let miner_conf = self.miner_conf().await?;
         let miner_conf = miner_conf.as_ref().unwrap_or_else(|| unreachable!());
+        Ok(miner_conf.fan_mode())
+    }
 
-        Ok(miner_conf.pools.clone())
+    /// bitmain-fan-ctrl turns manual control on, bitmain-fan-pwm is the duty
+    async fn set_fan_mode(&mut self, mode: FanMode) -> Result<(), Error> {
+        if let FanMode::Manual(pwm) = mode {
+            if pwm > 100 {
+                return Err(Error::InvalidConfig(format!("Fan PWM {} is over 100%", pwm)));
+            }
+        }
+        self.update_conf(|conf| match mode {
+            FanMode::Auto => conf.bitmain_fan_ctrl = false,
+            FanMode::Manual(pwm) => {
+                conf.bitmain_fan_ctrl = true;
+                conf.bitmain_fan_pwm = pwm.to_string();
+            },
+        }, true).await?;
+        self.record_pending(PendingChange::Settings);
+        Ok(())
     }
 
-    async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
+    async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
         let miner_conf = self.miner_conf().await?;
         let miner_conf = miner_conf.as_ref().unwrap_or_else(|| unreachable!());
 
-        let mut json: SetConf = SetConf::from(miner_conf);
-        json.pools = pools;
+        Ok(miner_conf.pools.clone())
+    }
+
+    async fn get_pool_counters(&self) -> Result<Vec<PoolCounters>, Error> {
+        let pools: cgi::PoolsResponse = serde_json::from_value(self.cgi_json("pools.cgi").await?)?;
+        Ok(pools.pools.into_iter().map(|p| PoolCounters {
+            url: p.url,
+            accepted: p.accepted as u64,
+            rejected: p.rejected as u64,
+            stale: p.stale as u64,
+        }).collect())
+    }
+
+    async fn get_chip_counts(&self) -> Result<Vec<ChipCount>, Error> {
+        let stats = self.stats().await?;
+        let stats = stats.as_ref().unwrap_or_else(|| unreachable!());
+        let stat = stats.stats.first().ok_or(Error::ExpectedReturn)?;
+        Ok(stat.chain.iter().map(|c| ChipCount {
+            chain: c.index,
+            expected: c.expected_chips(),
+            detected: c.detected_chips(),
+        }).collect())
+    }
+
+    async fn get_board_temps(&self) -> Result<Vec<BoardTemps>, Error> {
+        let stats = self.stats().await?;
+        let stats = stats.as_ref().unwrap_or_else(|| unreachable!());
+        let stat = stats.stats.first().ok_or(Error::ExpectedReturn)?;
+        Ok(stat.chain.iter().map(|c| BoardTemps {
+            chain: c.index,
+            chip: c.temp_chip.iter().filter(|t| cgi::sensor_fitted(**t)).map(|t| cgi::sensor_temp(*t)).collect(),
+            pcb: c.temp_pcb.iter().chain(&c.temp_pic).filter(|t| cgi::sensor_fitted(**t)).map(|t| cgi::sensor_temp(*t)).collect(),
+            chip_min: None,
+            chip_max: None,
+        }).collect())
+    }
+
+    async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+        let want = |r: Resource| resources.contains(&r);
+        let (summary, stats, conf, info) = futures::join!(
+            async { if want(Resource::Summary) { self.summary().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::Stats) { self.stats().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::Config) { self.miner_conf().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::SystemInfo) { self.sys_info().await.map(drop) } else { Ok(()) } },
+        );
+        summary.and(stats).and(conf).and(info)
+    }
 
+    async fn debug_dump(&self) -> Result<serde_json::Value, Error> {
+        let cgis = ["get_system_info.cgi", "summary.cgi", "stats.cgi", "pools.cgi", "get_miner_conf.cgi"];
+        // As text, a body that doesn't parse is what the bug report is about
+        let payloads = futures::future::join_all(cgis.iter().map(|cgi| async move {
+            let resp = self.cgi_get(cgi).await?;
+            if !resp.status().is_success() {
+                if resp.status().as_u16() == 401 {
+                    return Err(Error::Unauthorized);
+                }
+                return Err(Error::HttpRequestFailed);
+            }
+            Ok(raw_json(&resp.text().await?))
+        })).await;
+        Ok(serde_json::Value::Object(
+            cgis.iter().zip(payloads).map(|(cgi, payload)| (cgi.to_string(), dump_entry(payload))).collect()
+        ))
+    }
+
+    /// Infections rewrite the web UI and delete the upgrade CGI so they can't be flashed over
+    /// Firmware generations name that CGI differently, it's only blocked if none of them answers
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        let mut found = security::scan_ports(&self.client, &self.ip).await;
         let resp = self.client.http_client
-            .post(&format!("http://{}/cgi-bin/set_miner_conf.cgi", self.ip))
-            .json(&json)
-            .send_with_digest_auth(&self.username, &self.password)
+            .get(format!("http://{}/", self.ip))
+            .send_with_digest_auth(&self.client, &self.username, self.password.expose())
             .await?;
         if resp.status().is_success() {
-            self.invalidate().await;
-            Ok(())
-        } else {
-            Err(Error::HttpRequestFailed)
+            found.extend(security::page_indicators("/", &resp.text().await?));
         }
+        let mut blocked = true;
+        for cgi in UPGRADE_CGIS {
+            if self.cgi_get(cgi).await?.status() != reqwest::StatusCode::NOT_FOUND {
+                blocked = false;
+                break;
+            }
+        }
+        if blocked {
+            found.push(SecurityIndicator::UpgradeBlocked);
+        }
+        Ok(found)
     }
 
-    async fn get_sleep(&self) -> Result<bool, Error> {
-        let miner_conf = self.miner_conf().await?;
-        let miner_conf = miner_conf.as_ref().unwrap_or_else(|| unreachable!());
+    /// Hyd stats don't carry coolant or pump readings, only the model says it's hydro
+    async fn get_cooling_detail(&self) -> Result<CoolingDetail, Error> {
+        Ok(CoolingDetail {
+            liquid: self.get_model().await?.contains("hyd"),
+            ..Default::default()
+        })
+    }
+
+    async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
+        self.update_conf(|conf| conf.pools = pools, false).await?;
+        self.record_pending(PendingChange::Pools);
+        Ok(())
+    }
 
-        Ok(miner_conf.bitmain_work_mode == "1")
+    async fn get_sleep(&self) -> Result<bool, Error> {
+        Ok(self.get_sleep_status().await?.sleeping)
     }
 
+    /// Writes the whole conf back with the new mode, some firmware takes a lone miner-mode
+    /// as a full replace and wipes the pools
     async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
-        let resp = self.client.http_client
-            .post(&format!("http://{}/cgi-bin/set_miner_conf.cgi", self.ip))
-            .json(&json!({
-                "miner-mode": sleep as u8,
-            }))
-            .send_with_digest_auth(&self.username, &self.password)
-            .await?;
-        if resp.status().is_success() {
-            self.invalidate().await;
-            Ok(())
-        } else {
-            Err(Error::HttpRequestFailed)
-        }
+        self.update_conf(|conf| conf.miner_mode = sleep as u8, true).await
     }
 
     async fn get_blink(&self) -> Result<bool, Error> {
-        let resp = self.client.http_client
-            .get(&format!("http://{}/cgi-bin/get_blink_status.cgi", self.ip))
-            .send_with_digest_auth(&self.username, &self.password)
-            .await?;
+        let resp = self.cgi_get("get_blink_status.cgi").await?;
         if resp.status().is_success() {
             let json = resp.json::<serde_json::Value>().await?;
             Ok(json["blink"].as_bool().ok_or(Error::ExpectedReturn)?)
@@ -327,13 +785,10 @@ impl Miner for Antminer {
     }
 
     async fn set_blink(&mut self, blink: bool) -> Result<(), Error> {
-        let resp = self.client.http_client
-            .post(&format!("http://{}/cgi-bin/blink.cgi", self.ip))
-            .json(&json!({
-                "blink": blink,
-            }))
-            .send_with_digest_auth(&self.username, &self.password)
-            .await?;
+        let _write = self.client.write_lock(&self.ip).await;
+        let resp = self.cgi_post("blink.cgi", &json!({
+            "blink": blink,
+        })).await?;
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -342,10 +797,7 @@ impl Miner for Antminer {
     }
 
     async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
-        let resp = self.client.http_client
-            .get(&format!("http://{}/cgi-bin/log.cgi", self.ip))
-            .send_with_digest_auth(&self.username, &self.password)
-            .await?;
+        let resp = self.cgi_get("log.cgi").await?;
         if resp.status().is_success() {
             Ok(resp.text().await?.lines().map(|s| s.to_string()).collect())
         } else {
@@ -353,6 +805,10 @@ impl Miner for Antminer {
         }
     }
 
+    async fn get_logs_opts(&mut self, opts: LogOptions) -> Result<Vec<String>, Error> {
+        Ok(opts.apply(self.log_lines(opts.source).await?))
+    }
+
     async fn get_mac(&self) -> Result<String, Error> {
         let sys_info = self.sys_info().await?;
         let sys_info = sys_info.as_ref().unwrap_or_else(|| unreachable!());
@@ -361,22 +817,41 @@ impl Miner for Antminer {
     }
 
     async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
-        let logs = self.get_logs().await?.join("\n");
         // Only since last boot
         let re = regex!("=capability start=");
-        let start = re.find_iter(&logs).last().map(|m| m.start()).unwrap_or(0);
-        let log = &logs[start..];
+        let mut entries = self.get_log_entries(LogOptions::default()).await?;
+        let start = entries.iter().rposition(|e| re.is_match(&e.line));
+        let boot = start.and_then(|i| entries[i].ts);
+        entries.drain(..start.unwrap_or(0));
+        let mut log = entries.into_iter().map(|e| e.line).collect::<Vec<_>>().join("\n");
+        // Events that scrolled out of log.cgi may still be in the kernel and system logs, if the boot
+        // was logged without a time there's no telling which of their lines came after it
+        if start.is_none() || boot.is_some() {
+            for source in [LogSource::Kernel, LogSource::System] {
+                let Ok(entries) = self.get_log_entries(LogOptions { source, since: boot, ..Default::default() }).await else {
+                    continue;
+                };
+                let start = entries.iter().rposition(|e| re.is_match(&e.line)).unwrap_or(0);
+                for entry in &entries[start..] {
+                    log.push('\n');
+                    log.push_str(&entry.line);
+                }
+            }
+        }
 
         let mut errors = HashSet::new();
         let status = self.stats().await?;
         let status = status.as_ref().unwrap_or_else(|| unreachable!());
-        if let Some(stats) = status.stats.get(0) {
+        if let Some(stats) = status.stats.first() {
             if stats.chain_num < 3 {
-                errors.insert(MinerError { msg: "Missing Board(s)".into(), error_type: ErrorType::HashBoard });
+                errors.insert(MinerError::new("Missing Board(s)", ErrorType::HashBoard));
             }
             for chain in &stats.chain {
                 if chain.rate_real < chain.rate_ideal * 0.9 {
-                    errors.insert(MinerError { msg: format!("Chain {} - Low Hashrate", chain.index), error_type: ErrorType::HashBoard });
+                    errors.insert(MinerError::new(format!("Chain {} - Low Hashrate", chain.index), ErrorType::HashBoard));
+                }
+                if chain.failed_sensors() > 0 {
+                    errors.insert(MinerError::new(format!("Chain {} - {} Temperature Sensor(s) Failed", chain.index, chain.failed_sensors()), ErrorType::Temperature));
                 }
             }
         }
@@ -395,16 +870,24 @@ impl Miner for Antminer {
         Ok(sys_info.dnsservers.clone())
     }
 
-    async fn get_profile(&self) -> Result<Profile, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn set_profile(&mut self, _profile: Profile) -> Result<(), Error> {
-        Err(Error::NotSupported)
+    /// Written through set_network_conf.cgi, the miner restarts its network to apply it
+    /// On DHCP the servers from the lease may still win
+    async fn set_dns(&mut self, servers: Vec<IpAddr>) -> Result<(), Error> {
+        if servers.is_empty() {
+            return Err(Error::InvalidConfig("At least one DNS server is needed".into()));
+        }
+        let _write = self.client.write_lock(&self.ip).await;
+        let _ = self.sys_info.lock().await.take();
+        let json = {
+            let sys_info = self.sys_info().await?;
+            cgi::SetNetworkConf::with_dns(sys_info.as_ref().unwrap_or_else(|| unreachable!()), &servers)
+        };
+        let resp = self.cgi_post("set_network_conf.cgi", &json).await?;
+        if !resp.status().is_success() {
+            return Err(Error::HttpRequestFailed);
+        }
+        let _ = self.sys_info.lock().await.take();
+        Ok(())
     }
 
     async fn get_hashboard(&mut self) -> Result<String, Error> {
@@ -413,4 +896,227 @@ impl Miner for Antminer {
         let hashboard = re.captures(&logs).ok_or(Error::ExpectedReturn)?;
         Ok(hashboard[1].to_string())
     }
+
+    async fn backup_config(&self) -> Result<MinerConfigBackup, Error> {
+        let mut backup = MinerConfigBackup::capture(self).await?;
+        backup.settings = Some(json!({
+            "miner_conf": self.cgi_json("get_miner_conf.cgi").await?,
+            "network": self.cgi_json("get_network_info.cgi").await.ok(),
+        }));
+        Ok(backup)
+    }
+
+    /// Writes the backed up miner_conf (fan mode, frequency level, work mode) with the backup's pools,
+    /// then the backed up network config, after which the miner may answer on another address
+    async fn restore_config(&mut self, backup: MinerConfigBackup) -> Result<(), Error> {
+        let settings = match backup.settings_for(self.get_type()) {
+            Some(settings) if settings.get("miner_conf").is_some() => settings,
+            _ => return backup.apply(self).await,
+        };
+        let miner_conf = serde_json::from_value::<cgi::GetConfResponse>(settings["miner_conf"].clone())?;
+        let network = match settings.get("network").filter(|n| !n.is_null()) {
+            Some(network) => Some(serde_json::from_value::<cgi::NetworkInfoResponse>(network.clone())?),
+            None => None,
+        };
+        let mut json = SetConf::from(&miner_conf);
+        json.pools = backup.pools_with_passwords(&miner_conf.pools);
+        if miner_conf.work_mode().is_none() {
+            json.miner_mode = self.current_work_mode().await?;
+        }
+
+        let _write = self.client.write_lock(&self.ip).await;
+        self.post_conf(&json).await?;
+        self.invalidate().await;
+        self.record_pending(PendingChange::Settings);
+        if let Some(network) = network {
+            let resp = self.cgi_post("set_network_conf.cgi", &cgi::SetNetworkConf::from(&network)).await?;
+            if !resp.status().is_success() {
+                return Err(Error::HttpRequestFailed);
+            }
+        }
+        Ok(())
+    }
+
+    async fn pending_changes(&self) -> Result<Vec<PendingChange>, Error> {
+        Ok(self.pending.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use reqwest::Method;
+    use crate::ClientBuilder;
+    use crate::transport::ScriptedHttp;
+
+    const SYSTEM_INFO: &str = r#"{"minertype":"Antminer S19","nettype":"DHCP","netdevice":"eth0","macaddr":"02:00:00:00:00:01",
+        "hostname":"Antminer","ipaddress":"10.0.0.1","netmask":"255.255.255.0","gateway":"10.0.0.254","dnsservers":"10.0.0.254",
+        "system_mode":"GNU/Linux","system_kernel_version":"4.9.38","system_filesystem_version":"Mon Jan 1 00:00:00 UTC 2024",
+        "firmware_type":"Release"}"#;
+
+    fn conf(percent: u8) -> String {
+        format!(r#"{{"pools":[{{"url":"stratum+tcp://pool.example.com:3333","user":"worker.1","pass":"x"}}],
+            "api-listen":true,"api-network":false,"api-groups":"A:stats:pools:devs:summary:version","api-allow":"A:0/0,W:*",
+            "bitmain-fan-ctrl":false,"bitmain-fan-pwm":"100","bitmain-use-vil":true,"bitmain-freq":"675","bitmain-voltage":"1400",
+            "bitmain-ccdelay":"0","bitmain-pwth":"0","bitmain-work-mode":"0","bitmain-freq-level":"100",
+            "bitmain-hashrate-percent":"{}"}}"#, percent)
+    }
+
+    fn summary(ghs: f64) -> String {
+        format!(r#"{{"STATUS":{{"STATUS":"S","when":1700000000,"Msg":"summary","api_version":"1.0.0"}},
+            "INFO":{{"miner_version":"49.0.1.3","CompileTime":"Mon Jan 1 00:00:00 CST 2024","type":"Antminer S19"}},
+            "SUMMARY":[{{"elapsed":3600,"rate_5s":{ghs},"rate_30m":{ghs},"rate_avg":{ghs},"rate_ideal":95000.0,"rate_unit":"GH/s",
+            "hw_all":0,"bestshare":0,"status":[]}}]}}"#)
+    }
+
+    /// Runs at 100% until the conf is written, then at 80% for good
+    fn derating_miner() -> (Antminer, ScriptedHttp) {
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/cgi-bin/get_system_info.cgi", 200, SYSTEM_INFO)
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf(100))
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf(100))
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf(80))
+            .reply(Method::POST, "/cgi-bin/set_miner_conf.cgi", 200, r#"{"stats":"success","code":"M000","msg":"OK!"}"#)
+            .reply(Method::GET, "/cgi-bin/summary.cgi", 200, &summary(76000.0));
+        let client = ClientBuilder::new().http_transport(script.clone()).build().unwrap();
+        (Antminer::new(client, "10.0.0.1".into(), 80), script)
+    }
+
+    fn preset(name: &str) -> Profile {
+        Profile::Preset { name: name.into(), power: 0.0, ths: 0.0 }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_set_profile_verified() {
+        let (mut miner, _) = derating_miner();
+        let start = tokio::time::Instant::now();
+        // 76 TH/s at 34.7 J/TH, settled from the second poll on
+        miner.set_profile_verified(preset("80%"), Duration::from_secs(60), Some(3000.0)).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(20));
+
+        // No ceiling, so the first check at a deadline under the poll interval settles it
+        let (mut miner, _) = derating_miner();
+        let start = tokio::time::Instant::now();
+        miner.set_profile_verified(preset("80%"), Duration::from_secs(3), None).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_set_profile_verified_rollback() {
+        // The rollback write is taken but the conf keeps reading 80%, so it isn't reported as rolled back
+        let (mut miner, script) = derating_miner();
+        match miner.set_profile_verified(preset("80%"), Duration::from_secs(60), Some(2000.0)).await {
+            Err(Error::PowerCeilingExceeded { ceiling, rolled_back, .. }) => {
+                assert_eq!(ceiling, 2000.0);
+                assert!(!rolled_back);
+            },
+            other => panic!("expected PowerCeilingExceeded, got {:?}", other),
+        }
+        let writes = script.requests().into_iter().filter(|(method, _)| method == Method::POST).count();
+        assert_eq!(writes, 2);
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_access() {
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/cgi-bin/get_system_info.cgi", 200, SYSTEM_INFO)
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf(100))
+            .reply(Method::GET, "/cgi-bin/stats.cgi", 401, "")
+            .reply(Method::POST, "/cgi-bin/set_miner_conf.cgi", 401, "");
+        let client = ClientBuilder::new().http_transport(script).build().unwrap();
+        let mut miner = Antminer::new(client, "10.0.0.1".into(), 80);
+        miner.auth("operator", "secret").await.unwrap();
+        let endpoints = miner.capabilities().endpoints;
+        assert_eq!(endpoints.get("get_miner_conf.cgi"), Some(&true));
+        assert_eq!(endpoints.get("stats.cgi"), Some(&false));
+        assert_eq!(endpoints.get("set_miner_conf.cgi"), None);
+
+        assert!(miner.set_fan_mode(FanMode::Manual(50)).await.is_err());
+        assert_eq!(miner.capabilities().endpoints.get("set_miner_conf.cgi"), Some(&false));
+        // New credentials for it haven't been tried yet
+        miner.set_endpoint_auth("set_miner_conf.cgi", "root", "root").await.unwrap();
+        assert_eq!(miner.capabilities().endpoints.get("set_miner_conf.cgi"), None);
+    }
+
+    #[tokio::test]
+    async fn test_set_pools_keeps_sleep() {
+        // Firmware without bitmain-work-mode, asleep with no hashrate and the fans stopped
+        let conf = conf(100).replace(r#""bitmain-work-mode":"0","#, "");
+        let stats = r#"{"STATUS":{"STATUS":"S","when":1700000000,"Msg":"stats","api_version":"1.0.0"},
+            "INFO":{"miner_version":"49.0.1.3","CompileTime":"Mon Jan 1 00:00:00 CST 2024","type":"Antminer S19"},
+            "STATS":[{"elapsed":0,"rate_5s":0.0,"rate_30m":0.0,"rate_avg":0.0,"rate_ideal":0.0,"rate_unit":"GH/s",
+            "chain_num":0,"fan_num":4,"fan":[0,0,0,0],"hwp_total":0.0,"miner-mode":0,"freq-level":100,"chain":[]}]}"#;
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf)
+            .reply(Method::GET, "/cgi-bin/summary.cgi", 200, &summary(0.0))
+            .reply(Method::GET, "/cgi-bin/stats.cgi", 200, stats)
+            .reply(Method::POST, "/cgi-bin/set_miner_conf.cgi", 200, r#"{"stats":"success","code":"M000","msg":"OK!"}"#);
+        let client = ClientBuilder::new().http_transport(script.clone()).build().unwrap();
+        let mut miner = Antminer::new(client, "10.0.0.1".into(), 80);
+        miner.set_pools(vec![Pool {
+            url: "stratum+tcp://pool.example.com:3333".into(),
+            username: "worker.2".into(),
+            password: None,
+        }]).await.unwrap();
+        let posted = script.bodies("/cgi-bin/set_miner_conf.cgi");
+        let posted: serde_json::Value = serde_json::from_str(&posted[0]).unwrap();
+        assert_eq!(posted["miner-mode"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_pending_changes() {
+        const SUCCESS: &str = r#"{"stats":"success","code":"M000","msg":"OK!"}"#;
+        let fan_conf = conf(100).replace(r#""bitmain-fan-ctrl":false,"bitmain-fan-pwm":"100""#, r#""bitmain-fan-ctrl":true,"bitmain-fan-pwm":"50""#);
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf(100))
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &fan_conf)
+            .reply(Method::POST, "/cgi-bin/set_miner_conf.cgi", 200, SUCCESS);
+        let client = ClientBuilder::new().http_transport(script).build().unwrap();
+        let mut miner = Antminer::new(client, "10.0.0.1".into(), 80);
+        miner.set_fan_mode(FanMode::Manual(50)).await.unwrap();
+        miner.set_pools(vec![Pool {
+            url: "stratum+tcp://pool.example.com:3333".into(),
+            username: "worker.2".into(),
+            password: None,
+        }]).await.unwrap();
+        assert_eq!(miner.pending_changes().await.unwrap(), vec![PendingChange::Settings, PendingChange::Pools]);
+
+        // Derating
+        let (mut miner, _) = derating_miner();
+        miner.set_profile(preset("80%")).await.unwrap();
+        assert_eq!(miner.pending_changes().await.unwrap(), vec![PendingChange::Profile]);
+
+        // Hydro work mode
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/cgi-bin/get_system_info.cgi", 200, &SYSTEM_INFO.replace("Antminer S19", "Antminer S19 Pro+ Hyd."))
+            .reply(Method::GET, "/cgi-bin/get_miner_conf.cgi", 200, &conf(100))
+            .reply(Method::POST, "/cgi-bin/set_miner_conf.cgi", 200, SUCCESS);
+        let client = ClientBuilder::new().http_transport(script).build().unwrap();
+        let mut miner = Antminer::new(client, "10.0.0.1".into(), 80);
+        miner.set_profile(Profile::Default).await.unwrap();
+        assert_eq!(miner.pending_changes().await.unwrap(), vec![PendingChange::Profile]);
+    }
+
+    #[tokio::test]
+    async fn test_get_errors_since_boot() {
+        let chain = |index: u8| format!(r#"{{"index":{index},"freq_avg":525,"rate_ideal":34666.0,"rate_real":34810.2,"asic_num":8,
+            "asic":"oooooooo","temp_pic":[48,62],"temp_pcb":[48,62],"temp_chip":[63,77],"hw":3,"eeprom_loaded":true,"sn":"","hwp":0.0}}"#);
+        let stats = format!(r#"{{"STATUS":{{"STATUS":"S","when":1700000000,"Msg":"stats","api_version":"1.0.0"}},
+            "INFO":{{"miner_version":"49.0.1.3","CompileTime":"Mon Jan 1 00:00:00 CST 2024","type":"Antminer S19"}},
+            "STATS":[{{"elapsed":3600,"rate_5s":104000.0,"rate_30m":104000.0,"rate_avg":104000.0,"rate_ideal":104000.0,"rate_unit":"GH/s",
+            "chain_num":3,"fan_num":4,"fan":[5520,5400,5520,5460],"hwp_total":0.0,"miner-mode":0,"freq-level":100,"chain":[{},{},{}]}}]}}"#, chain(0), chain(1), chain(2));
+        let log = "2024-01-01 09:00:00 driver: ERROR_FAN_LOST\n2024-01-01 10:00:00 =capability start=\n2024-01-01 10:00:01 driver: chains up";
+        let kernel = "2024-01-01 09:30:00 bitmain: ERROR_POWER_LOST\n2024-01-01 10:05:00 bitmain: ERROR_TEMP_TOO_HIGH";
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/cgi-bin/log.cgi", 200, log)
+            .reply(Method::GET, "/cgi-bin/get_kernel_log.cgi", 200, kernel)
+            .reply(Method::GET, "/cgi-bin/stats.cgi", 200, &stats);
+        let client = ClientBuilder::new().http_transport(script).build().unwrap();
+        let mut miner = Antminer::new(client, "10.0.0.1".into(), 80);
+        let errors = miner.get_errors().await.unwrap();
+        let msgs: Vec<_> = errors.iter().map(|e| e.msg.as_str()).collect();
+        // The fan and power errors were logged before the last boot
+        assert_eq!(msgs, vec!["Temperature too high"]);
+    }
 }
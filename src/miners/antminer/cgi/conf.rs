@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use crate::Pool;
+use crate::miner::FanMode;
 
 #[derive(Deserialize, Debug)]
 pub struct GetConfResponse {
@@ -21,6 +22,9 @@ pub struct GetConfResponse {
     pub bitmain_freq: String,
     #[serde(rename = "bitmain-freq-level")]
     pub bitmain_freq_level: String,
+    /// Derating as a percent of nominal hashrate, empty on firmware without it
+    #[serde(rename = "bitmain-hashrate-percent", default)]
+    pub bitmain_hashrate_percent: String,
     #[serde(rename = "bitmain-pwth")]
     pub bitmain_pwth: String,
     #[serde(rename = "bitmain-use-vil")]
@@ -28,11 +32,32 @@ pub struct GetConfResponse {
     #[serde(rename = "bitmain-voltage")]
     pub bitmain_voltage: String,
     /// "0" is normal, "1" is sleep
-    #[serde(rename = "bitmain-work-mode")]
+    /// Some firmware leaves this empty or omits it entirely
+    #[serde(rename = "bitmain-work-mode", default)]
     pub bitmain_work_mode: String,
     pub pools: Vec<Pool>,
 }
 
+impl GetConfResponse {
+    /// None on firmware that can't derate
+    pub fn hashrate_percent(&self) -> Option<u8> {
+        self.bitmain_hashrate_percent.parse().ok()
+    }
+
+    /// None on firmware that leaves bitmain-work-mode empty or out
+    pub fn work_mode(&self) -> Option<u8> {
+        self.bitmain_work_mode.parse().ok()
+    }
+
+    pub fn fan_mode(&self) -> FanMode {
+        if self.bitmain_fan_ctrl {
+            FanMode::Manual(self.bitmain_fan_pwm.parse().unwrap_or(100))
+        } else {
+            FanMode::Auto
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct SetConf {
     #[serde(rename = "bitmain-fan-ctrl")]
@@ -41,6 +66,9 @@ pub struct SetConf {
     pub bitmain_fan_pwm: String,
     #[serde(rename = "freq-level")]
     pub freq_level: String,
+    /// Only sent back if the firmware reported it
+    #[serde(rename = "hashrate-percent", skip_serializing_if = "Option::is_none")]
+    pub hashrate_percent: Option<String>,
     /// 0 is normal, 1 is sleep
     #[serde(rename = "miner-mode")]
     pub miner_mode: u8,
@@ -53,9 +81,147 @@ impl From<&GetConfResponse> for SetConf {
             bitmain_fan_ctrl: conf.bitmain_fan_ctrl,
             bitmain_fan_pwm: conf.bitmain_fan_pwm.clone(),
             freq_level: conf.bitmain_freq_level.clone(),
-            // Antminers sometimes have this empty, default to 0 (normal)
-            miner_mode: conf.bitmain_work_mode.parse().unwrap_or(0),
+            hashrate_percent: Some(conf.bitmain_hashrate_percent.clone()).filter(|p| !p.is_empty()),
+            // Antminers sometimes have this empty, the caller has to work out the mode then
+            miner_mode: conf.work_mode().unwrap_or(0),
             pools: conf.pools.clone(),
         }
     }
 }
+
+impl SetConf {
+    /// What in a re-read conf differs from what was written, None if it all stuck
+    /// The work mode is only compared when the firmware reports one
+    pub fn mismatch(&self, conf: &GetConfResponse) -> Option<String> {
+        // The conf lists blank slots as pools with an empty url
+        let configured = |pools: &[Pool]| -> Vec<(String, String)> {
+            pools.iter().filter(|p| !p.url.is_empty()).map(|p| (p.url.clone(), p.username.clone())).collect()
+        };
+        if configured(&self.pools) != configured(&conf.pools) {
+            return Some("pools".to_string());
+        }
+        if conf.bitmain_fan_ctrl != self.bitmain_fan_ctrl {
+            return Some("bitmain-fan-ctrl".to_string());
+        }
+        if self.bitmain_fan_ctrl && conf.bitmain_fan_pwm != self.bitmain_fan_pwm {
+            return Some("bitmain-fan-pwm".to_string());
+        }
+        if self.hashrate_percent.is_some() && self.hashrate_percent.as_deref() != Some(conf.bitmain_hashrate_percent.as_str()) {
+            return Some("hashrate-percent".to_string());
+        }
+        match conf.work_mode() {
+            Some(mode) if mode != self.miner_mode => Some("miner-mode".to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = r#"{
+        "pools": [{"url": "stratum+tcp://pool.example.com:3333", "user": "worker.1", "pass": "x"}],
+        "api-listen": true,
+        "api-network": false,
+        "api-groups": "A:stats:pools:devs:summary:version",
+        "api-allow": "A:0/0,W:*",
+        "bitmain-fan-ctrl": false,
+        "bitmain-fan-pwm": "100",
+        "bitmain-use-vil": true,
+        "bitmain-freq": "675",
+        "bitmain-voltage": "1400",
+        "bitmain-ccdelay": "0",
+        "bitmain-pwth": "0",
+        "bitmain-work-mode": "1",
+        "bitmain-freq-level": "100"
+    }"#;
+
+    #[test]
+    fn test_work_mode() {
+        let conf: GetConfResponse = serde_json::from_str(CONF).unwrap();
+        assert_eq!(conf.bitmain_work_mode, "1");
+        assert_eq!(SetConf::from(&conf).miner_mode, 1);
+    }
+
+    #[test]
+    fn test_missing_work_mode() {
+        let conf = CONF.replace(r#""bitmain-work-mode": "1","#, "");
+        let conf: GetConfResponse = serde_json::from_str(&conf).unwrap();
+        assert_eq!(conf.bitmain_work_mode, "");
+        assert_eq!(conf.work_mode(), None);
+        assert_eq!(SetConf::from(&conf).miner_mode, 0);
+    }
+
+    #[test]
+    fn test_sleep_keeps_conf() {
+        let conf = CONF.replace(r#""bitmain-work-mode": "1""#, r#""bitmain-work-mode": "0""#);
+        let conf: GetConfResponse = serde_json::from_str(&conf).unwrap();
+        let mut json = SetConf::from(&conf);
+        json.miner_mode = 1;
+        let body = serde_json::to_value(&json).unwrap();
+        assert_eq!(body["miner-mode"], 1);
+        assert_eq!(body["bitmain-fan-pwm"], "100");
+        assert_eq!(body["freq-level"], "100");
+        assert_eq!(body["pools"][0]["url"], "stratum+tcp://pool.example.com:3333");
+        assert_eq!(body["pools"][0]["pass"], "x");
+    }
+
+    #[test]
+    fn test_hashrate_percent() {
+        let conf: GetConfResponse = serde_json::from_str(CONF).unwrap();
+        assert_eq!(conf.hashrate_percent(), None);
+        assert!(serde_json::to_value(SetConf::from(&conf)).unwrap().get("hashrate-percent").is_none());
+
+        let derated = CONF.replace(r#""bitmain-freq-level": "100""#, r#""bitmain-freq-level": "100", "bitmain-hashrate-percent": "80""#);
+        let derated: GetConfResponse = serde_json::from_str(&derated).unwrap();
+        assert_eq!(derated.hashrate_percent(), Some(80));
+        let mut json = SetConf::from(&derated);
+        assert_eq!(serde_json::to_value(&json).unwrap()["hashrate-percent"], "80");
+        json.hashrate_percent = Some("70".to_string());
+        assert_eq!(json.mismatch(&derated).as_deref(), Some("hashrate-percent"));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let conf: GetConfResponse = serde_json::from_str(CONF).unwrap();
+        let json = SetConf::from(&conf);
+        assert_eq!(json.mismatch(&conf), None);
+
+        // Firmware that treats a partial post as a replace comes back with no pools
+        let wiped = CONF.replace(r#"[{"url": "stratum+tcp://pool.example.com:3333", "user": "worker.1", "pass": "x"}]"#, r#"[{"url": "", "user": "", "pass": ""}]"#);
+        let wiped: GetConfResponse = serde_json::from_str(&wiped).unwrap();
+        assert_eq!(json.mismatch(&wiped).as_deref(), Some("pools"));
+
+        let padded = CONF.replace(r#""pass": "x"}]"#, r#""pass": "x"}, {"url": "", "user": "", "pass": ""}]"#);
+        let padded: GetConfResponse = serde_json::from_str(&padded).unwrap();
+        assert_eq!(json.mismatch(&padded), None);
+
+        let awake = CONF.replace(r#""bitmain-work-mode": "1""#, r#""bitmain-work-mode": "0""#);
+        let awake: GetConfResponse = serde_json::from_str(&awake).unwrap();
+        assert_eq!(json.mismatch(&awake).as_deref(), Some("miner-mode"));
+    }
+
+    #[test]
+    fn test_fan_mode() {
+        let conf: GetConfResponse = serde_json::from_str(CONF).unwrap();
+        assert_eq!(conf.fan_mode(), FanMode::Auto);
+        let manual = CONF.replace(r#""bitmain-fan-ctrl": false"#, r#""bitmain-fan-ctrl": true"#).replace(r#""bitmain-fan-pwm": "100""#, r#""bitmain-fan-pwm": "35""#);
+        let manual: GetConfResponse = serde_json::from_str(&manual).unwrap();
+        assert_eq!(manual.fan_mode(), FanMode::Manual(35));
+
+        let mut json = SetConf::from(&conf);
+        json.bitmain_fan_ctrl = true;
+        json.bitmain_fan_pwm = "35".to_string();
+        assert_eq!(json.mismatch(&manual), None);
+        assert_eq!(json.mismatch(&conf).as_deref(), Some("bitmain-fan-ctrl"));
+    }
+
+    #[test]
+    fn test_pool_password() {
+        let conf: GetConfResponse = serde_json::from_str(CONF).unwrap();
+        assert_eq!(conf.pools[0].password.as_ref().map(|p| p.expose()), Some("x"));
+        // Carried over untouched when the conf is written back
+        assert_eq!(SetConf::from(&conf).pools[0].password.as_ref().map(|p| p.expose()), Some("x"));
+    }
+}
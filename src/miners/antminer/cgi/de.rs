@@ -0,0 +1,88 @@
+use std::{fmt, str::FromStr};
+use serde::{Deserialize, Deserializer, de};
+
+// Antminer firmware builds disagree on whether numbers are quoted
+// and whether per-sensor readings are arrays or comma separated strings
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StrOrNum<T> {
+    Num(T),
+    Str(String),
+}
+
+impl<T> StrOrNum<T>
+    where T: FromStr, T::Err: fmt::Display
+{
+    fn into_value<E: de::Error>(self) -> Result<T, E> {
+        match self {
+            StrOrNum::Num(n) => Ok(n),
+            StrOrNum::Str(s) => s.trim().parse().map_err(|e| E::custom(format!("invalid number {:?}: {}", s, e))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VecOrCsv<T> {
+    Vec(Vec<StrOrNum<T>>),
+    Str(String),
+}
+
+/// Accepts 60 or "60"
+pub fn string_or_number<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    T::Err: fmt::Display,
+{
+    StrOrNum::<T>::deserialize(deserializer)?.into_value()
+}
+
+/// Accepts [60, 61], ["60", "61"], "60,61" or "60 61", an empty string is an empty list
+pub fn csv_or_vec<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    T::Err: fmt::Display,
+{
+    match VecOrCsv::<T>::deserialize(deserializer)? {
+        VecOrCsv::Vec(v) => v.into_iter().map(|n| n.into_value()).collect(),
+        VecOrCsv::Str(s) => s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+            .map(|p| StrOrNum::<T>::Str(p.to_string()).into_value())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Debug)]
+    struct Test {
+        #[serde(deserialize_with = "string_or_number")]
+        freq: usize,
+        #[serde(deserialize_with = "string_or_number")]
+        rate: f64,
+        #[serde(deserialize_with = "csv_or_vec")]
+        temps: Vec<usize>,
+    }
+
+    #[test]
+    fn test_numbers() {
+        let t: Test = serde_json::from_str(r#"{"freq":675,"rate":"90.5","temps":[60,61]}"#).unwrap();
+        assert_eq!(t.freq, 675);
+        assert_eq!(t.rate, 90.5);
+        assert_eq!(t.temps, vec![60, 61]);
+        let t: Test = serde_json::from_str(r#"{"freq":"675","rate":90,"temps":"60,61, 62"}"#).unwrap();
+        assert_eq!(t.freq, 675);
+        assert_eq!(t.rate, 90.0);
+        assert_eq!(t.temps, vec![60, 61, 62]);
+        let t: Test = serde_json::from_str(r#"{"freq":" 675 ","rate":"0","temps":["60","61"]}"#).unwrap();
+        assert_eq!(t.temps, vec![60, 61]);
+        let t: Test = serde_json::from_str(r#"{"freq":0,"rate":0,"temps":""}"#).unwrap();
+        assert!(t.temps.is_empty());
+        assert!(serde_json::from_str::<Test>(r#"{"freq":"fast","rate":0,"temps":[]}"#).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use serde::Deserialize;
 pub use crate::miners::common::StatusCode;
 
+mod de;
 mod stats;
 pub use stats::*;
 mod summary;
@@ -34,4 +35,50 @@ pub struct CgiInfo {
 #[derive(Deserialize, Debug)]
 pub struct CgiPostResp {
     pub code: String,
-}
\ No newline at end of file
+}
+
+/// Body of set_miner_conf.cgi, e.g. {"stats":"success","code":"M000","msg":"OK!"}
+#[derive(Deserialize, Debug)]
+pub struct SetConfResp {
+    pub stats: String,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+}
+
+impl SetConfResp {
+    /// The reason the miner gave for refusing the write, None if it took it
+    /// Old firmware answers with an empty or non-JSON body, which is taken as success
+    pub fn failure(body: &str) -> Option<String> {
+        let resp = serde_json::from_str::<SetConfResp>(body).ok()?;
+        if resp.stats.eq_ignore_ascii_case("success") {
+            return None;
+        }
+        let reason = match (resp.code.is_empty(), resp.msg.trim().is_empty()) {
+            (true, false) => resp.msg.trim().to_string(),
+            (false, false) => format!("{} ({})", resp.msg.trim(), resp.code),
+            (false, true) => resp.code,
+            (true, true) => resp.stats,
+        };
+        Some(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_conf_failure() {
+        assert_eq!(SetConfResp::failure(r#"{"stats":"success","code":"M000","msg":"OK!"}"#), None);
+        assert_eq!(SetConfResp::failure(""), None);
+        assert_eq!(SetConfResp::failure("ok"), None);
+        assert_eq!(
+            SetConfResp::failure(r#"{"stats":"error","code":"M001","msg":"Invalid pool url"}"#),
+            Some("Invalid pool url (M001)".to_string()),
+        );
+        assert_eq!(SetConfResp::failure(r#"{"stats":"error","msg":"Config locked"}"#), Some("Config locked".to_string()));
+        assert_eq!(SetConfResp::failure(r#"{"stats":"failure"}"#), Some("failure".to_string()));
+    }
+}
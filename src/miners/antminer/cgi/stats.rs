@@ -1,42 +1,101 @@
 use serde::Deserialize;
 
 use crate::miners::antminer::cgi::{Status, CgiInfo};
+use crate::miners::antminer::cgi::de::{string_or_number, csv_or_vec};
+
+/// Celsius a connected sensor can read, outside it is a sentinel like -273 or 255
+const SENSOR_RANGE: (i32, i32) = (1, 125);
+
+/// A sensor's reading, None for a sentinel from a disconnected or failed sensor
+pub fn sensor_temp(temp: i32) -> Option<f64> {
+    (SENSOR_RANGE.0..=SENSOR_RANGE.1).contains(&temp).then_some(temp as f64)
+}
+
+/// The arrays are padded with 0 for sensor positions the board doesn't have
+pub fn sensor_fitted(temp: i32) -> bool {
+    temp != 0
+}
 
 #[derive(Deserialize, Debug)]
 pub struct Chain {
     pub index: usize,
+    #[serde(deserialize_with = "string_or_number")]
     pub freq_avg: usize,
+    #[serde(deserialize_with = "string_or_number")]
     pub rate_ideal: f64,
+    #[serde(deserialize_with = "string_or_number")]
     pub rate_real: f64,
-    /// Number of detected ASIC chips
+    /// Chips the chain is set up for, chain_acn on the cgminer API
+    #[serde(deserialize_with = "string_or_number")]
     pub asic_num: usize,
-    /// String of detected ASIC chips, o for each successful with space between groups
+    /// Status of each chip, o for one that answers and x for one that doesn't, with spaces between groups
     pub asic: String,
-    pub temp_chip: Vec<usize>,
-    pub temp_pcb: Vec<usize>,
-    pub temp_pic: Vec<usize>,
+    /// Signed, disconnected sensors read -273 and missing ones 0
+    #[serde(deserialize_with = "csv_or_vec")]
+    pub temp_chip: Vec<i32>,
+    #[serde(deserialize_with = "csv_or_vec")]
+    pub temp_pcb: Vec<i32>,
+    #[serde(deserialize_with = "csv_or_vec")]
+    pub temp_pic: Vec<i32>,
+    #[serde(deserialize_with = "string_or_number")]
     pub hw: u16,
     pub eeprom_loaded: bool,
     pub sn: String,
+    #[serde(deserialize_with = "string_or_number")]
     pub hwp: f64,
     //pub tpl: Vec<Vec<usize>>,
 }
 
+impl Chain {
+    pub fn expected_chips(&self) -> Option<usize> {
+        Some(self.asic_num).filter(|n| *n > 0)
+    }
+
+    /// Chips answering in the status map, taken as all of them on firmware that leaves it empty
+    pub fn detected_chips(&self) -> usize {
+        if self.asic.trim().is_empty() {
+            return self.asic_num;
+        }
+        self.asic.chars().filter(|c| *c == 'o').count()
+    }
+
+    /// Hottest chip with a working sensor
+    pub fn max_chip_temp(&self) -> Option<f64> {
+        self.temp_chip.iter().filter_map(|t| sensor_temp(*t)).reduce(f64::max)
+    }
+
+    /// Fitted chip, PCB and PIC sensors reading a sentinel value
+    pub fn failed_sensors(&self) -> usize {
+        self.temp_chip.iter().chain(&self.temp_pcb).chain(&self.temp_pic)
+            .filter(|t| sensor_fitted(**t) && sensor_temp(**t).is_none())
+            .count()
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Stat {
+    #[serde(deserialize_with = "string_or_number")]
     pub elapsed: usize,
+    #[serde(deserialize_with = "string_or_number")]
     pub rate_5s: f64,
+    #[serde(deserialize_with = "string_or_number")]
     pub rate_30m: f64,
+    #[serde(deserialize_with = "string_or_number")]
     pub rate_avg: f64,
+    #[serde(deserialize_with = "string_or_number")]
     pub rate_ideal: f64,
     pub rate_unit: String,
+    #[serde(deserialize_with = "string_or_number")]
     pub chain_num: usize,
+    #[serde(deserialize_with = "string_or_number")]
     pub fan_num: usize,
+    #[serde(deserialize_with = "csv_or_vec")]
     pub fan: Vec<u32>,
+    #[serde(deserialize_with = "string_or_number")]
     pub hwp_total: f64,
-    #[serde(rename = "miner-mode")]
+    #[serde(rename = "miner-mode", deserialize_with = "string_or_number")]
     pub miner_mode: usize,
-    #[serde(rename = "freq-level")]
+    #[serde(rename = "freq-level", deserialize_with = "string_or_number")]
     pub freq_level: usize,
     pub chain: Vec<Chain>,
 }
@@ -47,4 +106,87 @@ pub struct StatsResponse {
     pub info: CgiInfo,
     pub stats: Vec<Stat>,
     pub status: Status,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trimmed from an S19j Pro on 2022 firmware, numbers as numbers
+    const STATS_2022: &str = r#"{
+        "STATUS": {"STATUS": "S", "when": 1671234567, "Msg": "stats", "api_version": "1.0.0"},
+        "INFO": {"miner_version": "49.0.1.3", "CompileTime": "Fri Sep 15 14:39:20 CST 2022", "type": "Antminer S19j Pro"},
+        "STATS": [{
+            "elapsed": 3600, "rate_5s": 104512.3, "rate_30m": 104012.5, "rate_avg": 104100.1, "rate_ideal": 104000.0,
+            "rate_unit": "GH/s", "chain_num": 1, "fan_num": 4, "fan": [5520, 5400, 5520, 5460], "hwp_total": 0.0012,
+            "miner-mode": 0, "freq-level": 100,
+            "chain": [{
+                "index": 0, "freq_avg": 525, "rate_ideal": 34666.0, "rate_real": 34810.2, "asic_num": 126,
+                "asic": "oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooo", "temp_pic": [48, 48, 62, 62], "temp_pcb": [48, 48, 62, 62],
+                "temp_chip": [63, 63, 77, 77], "hw": 3, "eeprom_loaded": true, "sn": "JYZZB2EBDJHBA0123", "hwp": 0.0004
+            }]
+        }]
+    }"#;
+
+    // Same model on a 2023 build, quoted numbers and comma separated sensor readings
+    const STATS_2023: &str = r#"{
+        "STATUS": {"STATUS": "S", "when": 1691234567, "Msg": "stats", "api_version": "1.0.0"},
+        "INFO": {"miner_version": "49.0.1.3", "CompileTime": "Thu Apr 13 16:20:41 CST 2023", "type": "Antminer S19j Pro"},
+        "STATS": [{
+            "elapsed": "3600", "rate_5s": "104512.3", "rate_30m": 104012.5, "rate_avg": "104100.1", "rate_ideal": "104000",
+            "rate_unit": "GH/s", "chain_num": "1", "fan_num": "4", "fan": "5520,5400,5520,5460", "hwp_total": "0.0012",
+            "miner-mode": "1", "freq-level": "100",
+            "chain": [{
+                "index": 0, "freq_avg": "525", "rate_ideal": "34666", "rate_real": 34810.2, "asic_num": "126",
+                "asic": "oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooooooo oooxoooo oooooo", "temp_pic": "48,48,62,62", "temp_pcb": "48 48 62 62",
+                "temp_chip": ["63", "63", "77", "77"], "hw": "3", "eeprom_loaded": true, "sn": "JYZZB2EBDJHBA0123", "hwp": "0.0004"
+            }]
+        }]
+    }"#;
+
+    #[test]
+    fn test_stats_firmware_versions() {
+        for (json, miner_mode) in [(STATS_2022, 0), (STATS_2023, 1)] {
+            let resp: StatsResponse = serde_json::from_str(json).unwrap();
+            let stat = &resp.stats[0];
+            assert_eq!(stat.elapsed, 3600);
+            assert_eq!(stat.rate_5s, 104512.3);
+            assert_eq!(stat.rate_ideal, 104000.0);
+            assert_eq!(stat.fan, vec![5520, 5400, 5520, 5460]);
+            assert_eq!(stat.miner_mode, miner_mode);
+            let chain = &stat.chain[0];
+            assert_eq!(chain.freq_avg, 525);
+            assert_eq!(chain.asic_num, 126);
+            assert_eq!(chain.expected_chips(), Some(126));
+            assert_eq!(chain.temp_pic, vec![48, 48, 62, 62]);
+            assert_eq!(chain.temp_pcb, vec![48, 48, 62, 62]);
+            assert_eq!(chain.temp_chip, vec![63, 63, 77, 77]);
+            assert_eq!(chain.hw, 3);
+            assert_eq!(chain.max_chip_temp(), Some(77.0));
+            assert_eq!(chain.failed_sensors(), 0);
+        }
+    }
+
+    #[test]
+    fn test_chip_counts() {
+        let resp: StatsResponse = serde_json::from_str(STATS_2022).unwrap();
+        assert_eq!(resp.stats[0].chain[0].detected_chips(), 126);
+        // One chip not answering
+        let resp: StatsResponse = serde_json::from_str(STATS_2023).unwrap();
+        assert_eq!(resp.stats[0].chain[0].detected_chips(), 125);
+        let json = STATS_2023.replace(resp.stats[0].chain[0].asic.as_str(), "");
+        let resp: StatsResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(resp.stats[0].chain[0].detected_chips(), 126);
+    }
+
+    #[test]
+    fn test_failed_sensors() {
+        let json = STATS_2023.replace(r#""temp_chip": ["63", "63", "77", "77"]"#, r#""temp_chip": ["63", "-273", "0", "71"]"#);
+        let resp: StatsResponse = serde_json::from_str(&json).unwrap();
+        let chain = &resp.stats[0].chain[0];
+        assert_eq!(chain.temp_chip, vec![63, -273, 0, 71]);
+        assert_eq!(chain.max_chip_temp(), Some(71.0));
+        // The 0 is a position without a sensor, not a failed one
+        assert_eq!(chain.failed_sensors(), 1);
+        assert_eq!(sensor_temp(255), None);
+    }
+}
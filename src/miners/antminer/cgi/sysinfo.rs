@@ -1,4 +1,5 @@
-use serde::Deserialize;
+use std::net::IpAddr;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug)]
 pub struct SystemInfoResponse {
@@ -15,4 +16,83 @@ pub struct SystemInfoResponse {
     pub system_kernel_version: String,
     pub system_filesystem_version: String,
     pub firmware_type: String,
-}
\ No newline at end of file
+}
+
+/// get_network_info.cgi, the conf_ fields are what's configured rather than the current lease
+#[derive(Deserialize, Debug)]
+pub struct NetworkInfoResponse {
+    pub conf_nettype: String,
+    pub conf_hostname: String,
+    #[serde(default)]
+    pub conf_ipaddress: String,
+    #[serde(default)]
+    pub conf_netmask: String,
+    #[serde(default)]
+    pub conf_gateway: String,
+    #[serde(default)]
+    pub conf_dnsservers: String,
+}
+
+/// Body of set_network_conf.cgi, ipPro is 1 for DHCP and 2 for static
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetNetworkConf {
+    pub ip_host: String,
+    pub ip_pro: u8,
+    pub ip_address: String,
+    pub ip_sub: String,
+    pub ip_gateway: String,
+    pub ip_dns: String,
+}
+
+impl SetNetworkConf {
+    /// The current network config with the DNS servers replaced
+    pub fn with_dns(info: &SystemInfoResponse, dns: &[IpAddr]) -> Self {
+        let dhcp = info.nettype.eq_ignore_ascii_case("dhcp");
+        SetNetworkConf {
+            ip_host: info.hostname.clone(),
+            ip_pro: if dhcp { 1 } else { 2 },
+            ip_address: info.ipaddress.clone(),
+            ip_sub: info.netmask.clone(),
+            ip_gateway: info.gateway.clone(),
+            ip_dns: dns.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(","),
+        }
+    }
+}
+
+impl From<&NetworkInfoResponse> for SetNetworkConf {
+    fn from(info: &NetworkInfoResponse) -> Self {
+        SetNetworkConf {
+            ip_host: info.conf_hostname.clone(),
+            ip_pro: if info.conf_nettype.eq_ignore_ascii_case("dhcp") { 1 } else { 2 },
+            ip_address: info.conf_ipaddress.clone(),
+            ip_sub: info.conf_netmask.clone(),
+            ip_gateway: info.conf_gateway.clone(),
+            ip_dns: info.conf_dnsservers.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_network_conf() {
+        let info: SystemInfoResponse = serde_json::from_str(r#"{"minertype":"Antminer S19j Pro","nettype":"Static","netdevice":"eth0","macaddr":"02:0A:8B:44:CE:12","hostname":"Antminer","ipaddress":"10.0.4.21","netmask":"255.255.254.0","gateway":"10.0.5.254","dnsservers":"10.0.5.254","system_mode":"GNU/Linux","system_kernel_version":"Linux 4.6.0-xilinx-gb190a3e #1 SMP PREEMPT","system_filesystem_version":"Thu Apr 13 16:20:41 CST 2023","firmware_type":"Release"}"#).unwrap();
+        let conf = SetNetworkConf::with_dns(&info, &["1.1.1.1".parse().unwrap(), "8.8.8.8".parse().unwrap()]);
+        assert_eq!(conf.ip_pro, 2);
+        assert_eq!(conf.ip_address, "10.0.4.21");
+        assert_eq!(serde_json::to_value(&conf).unwrap()["ipDns"], "1.1.1.1,8.8.8.8");
+    }
+
+    #[test]
+    fn test_restore_network_conf() {
+        let info: NetworkInfoResponse = serde_json::from_str(r#"{"nettype":"Static","netdevice":"eth0","macaddr":"02:0A:8B:44:CE:12","ipaddress":"10.0.4.21","netmask":"255.255.254.0","conf_nettype":"Static","conf_hostname":"Antminer","conf_ipaddress":"10.0.4.21","conf_netmask":"255.255.254.0","conf_gateway":"10.0.5.254","conf_dnsservers":"10.0.5.254"}"#).unwrap();
+        let conf = SetNetworkConf::from(&info);
+        assert_eq!(conf.ip_pro, 2);
+        assert_eq!(conf.ip_gateway, "10.0.5.254");
+        let dhcp: NetworkInfoResponse = serde_json::from_str(r#"{"conf_nettype":"DHCP","conf_hostname":"Antminer"}"#).unwrap();
+        assert_eq!(SetNetworkConf::from(&dhcp).ip_pro, 1);
+    }
+}
@@ -1,4 +1,6 @@
 mod antminer;
-pub use antminer::{Antminer, POWER_MAP};
+pub use antminer::{Antminer, POWER_MAP, SleepEvidence, SleepStatus};
+// CGI responses are declared in full, including fields nothing reads yet
+#[allow(dead_code)]
 mod cgi;
 mod error;
\ No newline at end of file
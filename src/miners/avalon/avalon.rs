@@ -2,94 +2,183 @@ use async_trait::async_trait;
 use serde_json::json;
 use lazy_regex::regex;
 use phf::phf_map;
-use tokio::sync::{Mutex, MutexGuard};
+use tokio::sync::Mutex;
+#[cfg(feature = "http")]
+use tracing::debug;
 
-use crate::miner::{Miner, Pool, Profile, MinerError};
+use crate::miner::{Miner, Pool, PoolCounters, Profile, Resource, dump_entry, raw_json, Tags};
 use crate::miners::avalon::cgminer;
 use crate::error::Error;
-use crate::Client;
+use crate::security::{self, SecurityIndicator};
+use crate::{Client, DetectionInfo, SecretString};
+use crate::model::normalize_model;
+#[cfg(feature = "http")]
+use crate::transport::SendVia;
 
+/// Keyed by normalize_model
 static EFF_MAP: phf::Map<&'static str, f64> = phf_map!{
-    "A1026" => 67.0,
-    "A1066" => 63.0,
-    "A1047" => 62.5,
-    "A1066Pro" => 60.0,
-    "A1146" => 57.0,
-    "A1126Pro" => 53.66,
-    "A1146Pro" => 52.0,
-    "A1166" => 47.0,
-    "A1166Pro" => 45.33,
-    "A1246" => 38.0,
-    "A1266" => 35.0,
-    "A1346" => 30.0,
-    "A1366" => 25.0,
+    "a1026" => 67.0,
+    "a1066" => 63.0,
+    "a1047" => 62.5,
+    "a1066pro" => 60.0,
+    "a1146" => 57.0,
+    "a1126pro" => 53.66,
+    "a1146pro" => 52.0,
+    "a1166" => 47.0,
+    "a1166pro" => 45.33,
+    "a1246" => 38.0,
+    "a1266" => 35.0,
+    "a1346" => 30.0,
+    "a1366" => 25.0,
 };
 
-pub struct Avalon {
-    ip: String,
-    port: u16,
-    username: String,
-    password: String,
-    client: Client,
+/// Per module view of an Avalon controller
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvalonModule {
+    /// MM module id
+    pub id: usize,
+    /// Real time hashrate in TH/s
+    pub hashrate: f64,
+    /// Power draw in watts
+    pub power: f64,
+    /// Max temperature seen by the module
+    pub temperature: f64,
+    /// Per hashboard hashrate in TH/s
+    pub boards: Vec<f64>,
+    /// Per hashboard max temperature
+    pub board_temps: Vec<f64>,
+    /// Fan speeds
+    pub fans: Vec<u32>,
+}
 
-    model: Mutex<Option<String>>,
-    version: Mutex<Option<cgminer::VersionResp>>,
-    estats: Mutex<Option<cgminer::EStats>>,
+impl From<&cgminer::EStats> for AvalonModule {
+    fn from(estats: &cgminer::EStats) -> Self {
+        AvalonModule {
+            id: estats.id,
+            hashrate: estats.ghs_mm / 1000.0,
+            power: estats.ps.power as f64,
+            temperature: estats.tmax as f64,
+            boards: estats.mghs.iter().map(|ghs| ghs / 1000.0).collect(),
+            board_temps: estats.mtmax.iter().map(|t| *t as f64).collect(),
+            fans: vec![estats.fan1, estats.fan2, estats.fan3, estats.fan4],
+        }
+    }
+}
+
+define_miner! {
+    pub struct Avalon {
+        port: u16,
+        username: String,
+        password: SecretString,
+        model: Mutex<Option<String>>,
+    }
+    cached {
+        version: cgminer::VersionResp = fetch_version,
+        estats: cgminer::ModuleStats = fetch_estats,
+    }
 }
 
 impl Avalon {
-    async fn get_version(&self) -> Result<MutexGuard<Option<cgminer::VersionResp>>, Error> {
-        let mut version = self.version.lock().await;
-        if version.is_none() {
-            let resp = self.client.send_recv(&self.ip, self.port, r#"{"command":"version"}"#).await?;
-            let version_resp: cgminer::VersionResp = serde_json::from_str(&resp)?;
-            *version = Some(version_resp);
-        }
-        Ok(version)
+    async fn fetch_version(&self) -> Result<cgminer::VersionResp, Error> {
+        let resp = self.client.send_recv(&self.ip, self.port, r#"{"command":"version"}"#).await?;
+        Ok(serde_json::from_str(&resp)?)
+    }
+
+    async fn fetch_estats(&self) -> Result<cgminer::ModuleStats, Error> {
+        let resp = self.client.send_recv(&self.ip, self.port, r#"{"command":"estats"}"#).await?;
+        let estats_resp: cgminer::StatsResp = serde_json::from_str(&resp)?;
+        cgminer::ModuleStats::try_from(&estats_resp)
     }
 
-    async fn get_estats(&self) -> Result<MutexGuard<Option<cgminer::EStats>>, Error> {
-        let mut estats = self.estats.lock().await;
-        if estats.is_none() {
-            let resp = self.client.send_recv(&self.ip, self.port, r#"{"command":"estats"}"#).await?;
-            let estats_resp: cgminer::StatsResp = serde_json::from_str(&resp)?;
-            let estats_resp = cgminer::EStats::try_from(&estats_resp)?;
-            *estats = Some(estats_resp);
+    /// Stats of every MM module behind this controller
+    pub async fn get_modules(&self) -> Result<Vec<AvalonModule>, Error> {
+        let estats = self.estats().await?;
+        let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
+        Ok(estats.modules.iter().map(AvalonModule::from).collect())
+    }
+
+    async fn led_api(&self) -> cgminer::LedApi {
+        match self.get_model().await {
+            Ok(model) => cgminer::LedApi::for_model(&model),
+            Err(_) => cgminer::LedApi::Legacy,
         }
-        Ok(estats)
     }
 
-    async fn invalidate(&self) {
-        let _ = self.estats.lock().await.take();
+    /// The web interface's LED switch, for firmware that refuses the ascset led command
+    /// Sets the LED first if given a state, then reads it back
+    /// Needs the credentials from auth, Unauthorized if there are none
+    #[cfg(feature = "http")]
+    async fn web_led(&self, set: Option<bool>) -> Result<bool, Error> {
+        if self.username.is_empty() {
+            return Err(Error::Unauthorized);
+        }
+        // The session is kept in a cookie
+        self.client.http_client
+            .post(format!("http://{}/login.cgi", self.ip))
+            .form(&[("username", self.username.as_str()), ("passwd", self.password.expose())])
+            .send_via(&self.client)
+            .await?;
+        let url = format!("http://{}/led.cgi", self.ip);
+        let req = match set {
+            Some(on) => self.client.http_client.post(&url).form(&[("led", if on { "1" } else { "0" })]),
+            None => self.client.http_client.get(&url),
+        };
+        let resp = req.send_via(&self.client).await?;
+        match resp.status() {
+            s if s.is_success() => cgminer::parse_web_led(&resp.text().await?),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => Err(Error::Unauthorized),
+            _ => Err(Error::HttpRequestFailed),
+        }
     }
 }
 
 #[async_trait]
 impl Miner for Avalon {
     fn new(client: Client, ip: String, port: u16) -> Self {
-        Avalon {
-            ip,
-            port,
-            username: "".to_string(),
-            password: "".to_string(),
-            client,
-            model: Mutex::new(None),
-            version: Mutex::new(None),
-            estats: Mutex::new(None),
-        }
+        Avalon { port, ..Avalon::scaffold(client, ip) }
     }
 
     fn get_type(&self) -> &'static str {
         "Avalon"
     }
 
+    /// Nothing past the CGI login page can be read anonymously, so open ports are all there is to check
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        Ok(security::scan_ports(&self.client, &self.ip).await)
+    }
+
+    async fn invalidate(&self) {
+        self.clear_caches().await;
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
     async fn get_model(&self) -> Result<String, Error> {
         let mut model = self.model.lock().await;
         if model.is_none() {
-            let version = self.get_version().await?;
+            let version = self.version().await?;
             let version = version.as_ref().unwrap_or_else(|| unreachable!());
             if let Some(version) = &version.version {
-                if let Some(version) = version.get(0) {
+                if let Some(version) = version.first() {
                     *model = Some(format!("A{}", version.model()?));
                 } else {
                     return Err(Error::ApiCallFailed("version".to_string()));
@@ -103,7 +192,7 @@ impl Miner for Avalon {
 
     async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error> {
         self.username = username.to_string();
-        self.password = password.to_string();
+        self.password = password.into();
         Ok(())
     }
 
@@ -116,9 +205,9 @@ impl Miner for Avalon {
     }
 
     async fn get_hashrate(&self) -> Result<f64, Error> {
-        let estats = self.get_estats().await?;
+        let estats = self.estats().await?;
         let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(estats.ghs_mm / 1000.0)
+        Ok(estats.ghs_mm() / 1000.0)
     }
 
     async fn get_power(&self) -> Result<f64, Error> {
@@ -131,27 +220,27 @@ impl Miner for Avalon {
     async fn get_nameplate_power(&self) -> Result<f64, Error> {
         let nameplate_rate = self.get_nameplate_rate().await?;
         let model = self.get_model().await?;
-        let eff = EFF_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|x| *x)?;
+        let eff = EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).copied()?;
         Ok(nameplate_rate * eff)
     }
 
     async fn get_efficiency(&self) -> Result<f64, Error> {
-        if let Ok(estats) = self.get_estats().await {
+        if let Ok(estats) = self.estats().await {
             let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
-            if estats.ghs_mm > 0.0 {
-                return Ok(estats.ps.power as f64 / (estats.ghs_mm / 1000.0));
+            if estats.ghs_mm() > 0.0 {
+                return Ok(estats.power() as f64 / (estats.ghs_mm() / 1000.0));
             }
         }
         // If we're not hashing return the dataspec efficiency
         let model = self.get_model().await?;
-        EFF_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|x| *x)
+        EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).copied()
     }
 
     async fn get_nameplate_rate(&self) -> Result<f64, Error> {
-        let version = self.get_version().await?;
+        let version = self.version().await?;
         let version = version.as_ref().unwrap_or_else(|| unreachable!());
         if let Some(version) = &version.version {
-            if let Some(version) = version.get(0) {
+            if let Some(version) = version.first() {
                 Ok(version.hashrate_th()?)
             } else {
                 Err(Error::ApiCallFailed("version".to_string()))
@@ -162,26 +251,21 @@ impl Miner for Avalon {
     }
 
     async fn get_temperature(&self) -> Result<f64, Error> {
-        let estats = self.get_estats().await?;
+        let estats = self.estats().await?;
         let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(estats.tmax as f64)
+        Ok(estats.tmax().ok_or(Error::ExpectedReturn)? as f64)
     }
 
     async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
-        let estats = self.get_estats().await?;
+        let estats = self.estats().await?;
         let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(vec![
-            estats.fan1,
-            estats.fan2,
-            estats.fan3,
-            estats.fan4,
-        ])
+        Ok(estats.fans())
     }
 
     async fn get_fan_pwm(&self) -> Result<f64, Error> {
-        let estats = self.get_estats().await?;
+        let estats = self.estats().await?;
         let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(estats.fanr)
+        Ok(estats.fanr())
     }
 
     async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
@@ -196,14 +280,21 @@ impl Miner for Avalon {
         )
     }
 
-    async fn set_pools(&mut self, _pools: Vec<Pool>) -> Result<(), Error> {
-        Err(Error::NotSupported)
+    async fn get_pool_counters(&self) -> Result<Vec<PoolCounters>, Error> {
+        let resp = self.client.send_recv(&self.ip, self.port, r#"{"command":"pools"}"#).await?;
+        Ok(
+            serde_json::from_str::<cgminer::PoolResp>(&resp)?
+                .pools
+                .into_iter()
+                .map(|p| PoolCounters { url: p.url, accepted: p.accepted, rejected: p.rejected, stale: p.stale })
+                .collect()
+        )
     }
 
     async fn get_sleep(&self) -> Result<bool, Error> {
-        let estats = self.get_estats().await?;
+        let estats = self.estats().await?;
         let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(estats.ps.power == 0 && estats.sys_status.work == "In Idle")
+        Ok(estats.idle())
     }
 
     async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
@@ -225,42 +316,69 @@ impl Miner for Avalon {
     }
 
     async fn get_blink(&self) -> Result<bool, Error> {
-        let cmd = r#"{"command":"ascset","parameter":"0,led,1-255"}"#;
-        let resp = self.client.send_recv(&self.ip, self.port, &cmd).await?;
-        let status = serde_json::from_str::<cgminer::StatusResp>(&resp)?;
-        if status.status[0].status == cgminer::StatusCode::INFO {
-            let re = regex!(r#"LED\[(\d)\]"#);
-            let caps = re.captures(&status.status[0].msg).ok_or(Error::InvalidResponse)?;
-            let led = caps.get(1).ok_or(Error::InvalidResponse)?.as_str().parse::<u8>().map_err(|_| Error::InvalidResponse)?;
-            Ok(led > 0)
-        } else {
-            Err(Error::ApiCallFailed(status.status[0].msg.clone()))
+        let led = self.led_api().await;
+        let resp = self.client.send_recv(&self.ip, self.port, led.get_cmd()).await?;
+        match cgminer::parse_led(&resp) {
+            Ok(on) => Ok(on),
+            // Some firmware acks the query without echoing the state, estats still carries it
+            Err(Error::InvalidResponse) => {
+                let estats = self.estats().await?;
+                let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
+                let estats = estats.modules.first().ok_or(Error::InvalidResponse)?;
+                Ok(estats.led > 0)
+            },
+            #[cfg(feature = "http")]
+            Err(Error::ApiCallFailed(msg)) => {
+                debug!("{} refused the LED query ({}), trying the web interface", self.ip, msg);
+                self.web_led(None).await
+            },
+            Err(e) => Err(e),
         }
     }
 
     async fn set_blink(&mut self, blink: bool) -> Result<(), Error> {
-        let cmd = match blink {
-            true => r#"{"command":"ascset","parameter":"0,led,1"}"#,
-            false => r#"{"command":"ascset","parameter":"0,led,0"}"#,
-        };
-        let resp = self.client.send_recv(&self.ip, self.port, &cmd).await?;
-        let status = serde_json::from_str::<cgminer::StatusResp>(&resp)?;
-        if status.status[0].status == cgminer::StatusCode::SUCC {
-            Ok(())
-        } else {
-            Err(Error::ApiCallFailed(status.status[0].msg.clone()))
+        let led = self.led_api().await;
+        let resp = self.client.send_recv(&self.ip, self.port, led.set_cmd(blink)).await?;
+        match cgminer::check_led_set(&resp) {
+            Ok(()) => {},
+            #[cfg(feature = "http")]
+            Err(Error::ApiCallFailed(msg)) => {
+                debug!("{} refused the LED command ({}), trying the web interface", self.ip, msg);
+                if self.web_led(Some(blink)).await? != blink {
+                    return Err(Error::ApiCallFailed("LED did not change".into()));
+                }
+            },
+            Err(e) => return Err(e),
         }
+        self.invalidate().await;
+        Ok(())
     }
 
-    async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
-        Err(Error::NotSupported)
+    async fn debug_dump(&self) -> Result<serde_json::Value, Error> {
+        let commands = ["version", "summary", "estats", "pools"];
+        let payloads = futures::future::join_all(commands.iter().map(|command| async move {
+            let cmd = json!({"command": command});
+            self.client.send_recv(&self.ip, self.port, &cmd).await
+        })).await;
+        Ok(serde_json::Value::Object(
+            commands.iter().zip(payloads).map(|(command, payload)| (command.to_string(), dump_entry(payload.map(|body| raw_json(&body))))).collect()
+        ))
+    }
+
+    async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+        let want = |r: Resource| resources.contains(&r);
+        let (estats, version) = futures::join!(
+            async { if want(Resource::Summary) || want(Resource::Stats) { self.estats().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::SystemInfo) { self.version().await.map(drop) } else { Ok(()) } },
+        );
+        estats.and(version)
     }
 
     async fn get_mac(&self) -> Result<String, Error> {
-        let version = self.get_version().await?;
+        let version = self.version().await?;
         let version = version.as_ref().unwrap_or_else(|| unreachable!());
         if let Some(version) = &version.version {
-            if let Some(version) = version.get(0) {
+            if let Some(version) = version.first() {
                 Ok(version.mac_addr())
             } else {
                 Err(Error::ApiCallFailed("version".to_string()))
@@ -270,18 +388,11 @@ impl Miner for Avalon {
         }
     }
 
-    async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn get_dns(&self) -> Result<String, Error> {
-        Err(Error::NotSupported)
-    }
-
     async fn get_profile(&self) -> Result<Profile, Error> {
-        let estats = self.get_estats().await?;
+        let estats = self.estats().await?;
         let estats = estats.as_ref().unwrap_or_else(|| unreachable!());
 
+        let estats = estats.modules.first().ok_or(Error::InvalidResponse)?;
         match estats.workmode {
             0 => Ok(Profile::LowPower),
             1 => Ok(Profile::Default),
@@ -299,7 +410,8 @@ impl Miner for Avalon {
     async fn set_profile(&mut self, profile: Profile) -> Result<(), Error> {
         // If success response is "ASC 0 set info: WORKMODE[1]"
         let re = regex!(r#"msg=asc 0 set info: workmode\[(\d)\]"#);
-        let workmode = match profile {
+        let profiles = self.get_profiles().await?;
+        let workmode = match profile.validate(&profiles)? {
             Profile::Default => 1,
             Profile::LowPower => 0,
             _ => return Err(Error::NotSupported),
@@ -314,8 +426,34 @@ impl Miner for Avalon {
             Err(Error::ApiCallFailed(resp.to_string()))
         }
     }
+}
 
-    async fn get_hashboard(&mut self) -> Result<String, Error> {
-        Err(Error::NotSupported)
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_web_led_fallback() {
+        use crate::transport::{ScriptedHttp, ScriptedSocket};
+        use reqwest::Method;
+
+        let refused = r#"{"STATUS":[{"STATUS":"E","When":57242,"Code":120,"Msg":"ASC 0 set failed: led is disabled","Description":"cgminer 4.11.1"}],"id":1}"#;
+        let socket = ScriptedSocket::new().reply("led", refused);
+        let http = ScriptedHttp::new()
+            .reply(Method::POST, "/login.cgi", 200, "")
+            .reply(Method::GET, "/led.cgi", 200, r#"{"led":0}"#)
+            .reply(Method::POST, "/led.cgi", 200, r#"{"led":1}"#);
+        let client = crate::ClientBuilder::new().socket_transport(socket).http_transport(http.clone()).build().unwrap();
+        let mut miner = Avalon::new(client, "10.0.0.1".into(), 4028);
+
+        // No credentials, so the web interface isn't tried
+        assert!(matches!(miner.get_blink().await, Err(Error::Unauthorized)));
+        assert!(http.bodies("/login.cgi").is_empty());
+
+        miner.auth("admin", "hunter2").await.unwrap();
+        assert!(!miner.get_blink().await.unwrap());
+        miner.set_blink(true).await.unwrap();
+        assert_eq!(http.bodies("/led.cgi"), vec!["", "led=1"]);
+        assert_eq!(http.bodies("/login.cgi")[0], "username=admin&passwd=hunter2");
     }
 }
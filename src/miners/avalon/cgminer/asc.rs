@@ -17,7 +17,7 @@ pub struct PowerSupplyInfo {
     pub max_power: Option<f32>,
 }
 
-impl<'a> From<Vec<i32>> for PowerSupplyInfo {
+impl From<Vec<i32>> for PowerSupplyInfo {
     fn from(v: Vec<i32>) -> Self {
         PowerSupplyInfo {
             err: v[0],
@@ -31,17 +31,14 @@ impl<'a> From<Vec<i32>> for PowerSupplyInfo {
     }
 }
 
-impl<'a> TryFrom<&'a str> for PowerSupplyInfo {
+impl TryFrom<&str> for PowerSupplyInfo {
     type Error = Error;
 
     fn try_from(input: &str) -> Result<Self, Self::Error> {
         let re = regex!(r"PS\[(\d+) (\d+) (\d+) (\d+) (\d+) (\d+)(?: (\+d))?\]");
-        let caps = re.captures(&input).ok_or(Error::InvalidResponse)?;
+        let caps = re.captures(input).ok_or(Error::InvalidResponse)?;
         let caps = caps.iter().skip(1).filter_map(|c| 
-            match c {
-                Some(c) => Some(c.as_str().parse::<i32>().unwrap()),
-                None => None
-            }
+            c.map(|c| c.as_str().parse::<i32>().unwrap())
         ).collect::<Vec<_>>();
         Ok(Self {
             err: caps[0],
@@ -1,4 +1,4 @@
-use std::{fmt, u8};
+use std::fmt;
 use serde::Deserialize;
 use serde::de::{
     self, DeserializeSeed,
@@ -423,7 +423,7 @@ impl<'de> MsgDeserializer<'de> {
                             let digit = (c - b'0') as u64;
 
                             // We need to be careful with overflow.
-                            if overflow!(significand * 10 + digit, u64::max_value()) {
+                            if overflow!(significand * 10 + digit, u64::MAX) {
                                 return Err(self.error(ErrorCode::Overflow));
                             }
 
@@ -468,7 +468,7 @@ impl<'de> MsgDeserializer<'de> {
         while let c @ b'0'..=b'9' = self.peek_or_null() {
             let digit = (c - b'0') as u64;
 
-            if overflow!(significand * 10 + digit, u64::max_value()) {
+            if overflow!(significand * 10 + digit, u64::MAX) {
                 return Err(self.error(ErrorCode::Overflow));
             }
 
@@ -585,7 +585,7 @@ impl<'de> MsgDeserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut MsgDeserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut MsgDeserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
@@ -0,0 +1,160 @@
+use lazy_regex::regex;
+#[cfg(feature = "http")]
+use serde::Deserialize;
+
+use crate::error::Error;
+use super::{StatusResp, StatusCode};
+
+/// LED command dialect spoken by the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedApi {
+    /// A10/A11 series, JSON `ascset` with `0,led,<0|1>`
+    Legacy,
+    /// A12 series and newer, pipe `ascset|0,led,1-<0|1|255>`
+    Modern,
+}
+
+impl LedApi {
+    /// Pick the dialect from a model name such as "A1246" or "1166Pro"
+    pub fn for_model(model: &str) -> Self {
+        let series = model.trim_start_matches('A')
+            .get(..2)
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or_default();
+        if series >= 12 {
+            LedApi::Modern
+        } else {
+            LedApi::Legacy
+        }
+    }
+
+    /// Command that reads back the LED state
+    pub fn get_cmd(&self) -> &'static str {
+        match self {
+            LedApi::Legacy => r#"{"command":"ascset","parameter":"0,led,1-255"}"#,
+            LedApi::Modern => "ascset|0,led,1-255",
+        }
+    }
+
+    /// Command that turns the LED on or off
+    pub fn set_cmd(&self, on: bool) -> &'static str {
+        match (self, on) {
+            (LedApi::Legacy, true) => r#"{"command":"ascset","parameter":"0,led,1"}"#,
+            (LedApi::Legacy, false) => r#"{"command":"ascset","parameter":"0,led,0"}"#,
+            (LedApi::Modern, true) => "ascset|0,led,1-1",
+            (LedApi::Modern, false) => "ascset|0,led,1-0",
+        }
+    }
+}
+
+/// Status and message of an `ascset` reply, in either JSON or pipe format
+fn parse_status(resp: &str) -> Result<(StatusCode, String), Error> {
+    if resp.trim_start().starts_with('{') {
+        let [status] = serde_json::from_str::<StatusResp>(resp)?.status;
+        return Ok((status.status, status.msg));
+    }
+    let re = regex!(r"STATUS=(\w),(?:.*?,)?Msg=(.*?)(?:,Description=.*)?\|");
+    let caps = re.captures(resp).ok_or(Error::InvalidResponse)?;
+    let status = match &caps[1] {
+        "S" => StatusCode::SUCC,
+        "I" => StatusCode::INFO,
+        "W" => StatusCode::WARN,
+        "E" => StatusCode::ERROR,
+        "F" => StatusCode::FATAL,
+        _ => return Err(Error::InvalidResponse),
+    };
+    Ok((status, caps[2].to_string()))
+}
+
+/// Parse the LED state out of an `ascset ... led` reply
+pub fn parse_led(resp: &str) -> Result<bool, Error> {
+    let (status, msg) = parse_status(resp)?;
+    if matches!(status, StatusCode::ERROR | StatusCode::FATAL) {
+        return Err(Error::ApiCallFailed(msg));
+    }
+    let re = regex!(r"(?i)\bled\[(\d+)\]");
+    let caps = re.captures(&msg).ok_or(Error::InvalidResponse)?;
+    let led = caps[1].parse::<u8>().map_err(|_| Error::InvalidResponse)?;
+    Ok(led > 0)
+}
+
+/// Check that an `ascset ... led` write was accepted
+pub fn check_led_set(resp: &str) -> Result<(), Error> {
+    let (status, msg) = parse_status(resp)?;
+    match status {
+        StatusCode::SUCC | StatusCode::INFO => Ok(()),
+        _ => Err(Error::ApiCallFailed(msg)),
+    }
+}
+
+/// Reply of the web interface's /led.cgi, for firmware whose socket API refuses `ascset ... led`
+#[cfg(feature = "http")]
+#[derive(Debug, Deserialize)]
+pub struct WebLed {
+    pub led: u8,
+}
+
+/// Parse a /led.cgi reply, a session that was refused gets the login page back instead
+#[cfg(feature = "http")]
+pub fn parse_web_led(body: &str) -> Result<bool, Error> {
+    if body.contains("login.cgi") {
+        return Err(Error::Unauthorized);
+    }
+    let led: WebLed = serde_json::from_str(body)?;
+    Ok(led.led > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_model() {
+        assert_eq!(LedApi::for_model("A1066"), LedApi::Legacy);
+        assert_eq!(LedApi::for_model("A1166Pro"), LedApi::Legacy);
+        assert_eq!(LedApi::for_model("A1246"), LedApi::Modern);
+        assert_eq!(LedApi::for_model("1366"), LedApi::Modern);
+        assert_eq!(LedApi::for_model(""), LedApi::Legacy);
+    }
+
+    #[test]
+    fn test_legacy() {
+        let s = r#"{"STATUS":[{"STATUS":"I","When":57242,"Code":118,"Msg":"ASC 0 set info: LED[0]","Description":"cgminer 4.11.1"}],"id":1}"#;
+        assert!(!parse_led(s).unwrap());
+        let s = r#"{"STATUS":[{"STATUS":"S","When":57250,"Code":119,"Msg":"ASC 0 set OK","Description":"cgminer 4.11.1"}],"id":1}"#;
+        assert!(check_led_set(s).is_ok());
+    }
+
+    #[test]
+    fn test_a12() {
+        let s = "STATUS=I,When=1695311405,Code=118,Msg=ASC 0 set info: LED[1],Description=cgminer 4.11.1|\u{0}";
+        assert!(parse_led(s).unwrap());
+        let s = "STATUS=I,When=1695311412,Code=118,Msg=ASC 0 set info: LED[0],Description=cgminer 4.11.1|";
+        assert!(!parse_led(s).unwrap());
+    }
+
+    #[test]
+    fn test_a13() {
+        let s = "STATUS=I,When=1710238817,Code=118,Msg=ASC 0 set info: Led[1] LedUser[1],Description=cgminer 4.11.1|";
+        assert!(parse_led(s).unwrap());
+        let s = "STATUS=I,When=1710238821,Code=118,Msg=ASC 0 set info: Led[0] LedUser[0],Description=cgminer 4.11.1|";
+        assert!(!parse_led(s).unwrap());
+        assert!(check_led_set(s).is_ok());
+    }
+
+    #[test]
+    fn test_error() {
+        let s = "STATUS=E,When=1710238830,Code=120,Msg=ASC 0 set failed: missing led value,Description=cgminer 4.11.1|";
+        assert!(matches!(parse_led(s), Err(Error::ApiCallFailed(_))));
+        assert!(check_led_set(s).is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_web() {
+        assert!(parse_web_led(r#"{"led":1}"#).unwrap());
+        assert!(!parse_web_led(r#"{"led":0}"#).unwrap());
+        let login = include_str!("../../../../fixtures/detection/avalon/a1246.index.html");
+        assert!(matches!(parse_web_led(login), Err(Error::Unauthorized)));
+    }
+}
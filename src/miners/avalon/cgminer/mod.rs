@@ -7,10 +7,12 @@ mod version;
 pub use version::*;
 mod pools;
 pub use pools::*;
+mod led;
+pub use led::*;
 
 pub use de::Error;
 pub use crate::common::{
-    StatsResp, Stats, StatusCode, StatusResp
+    StatsResp, StatusCode, StatusResp
 };
 
 #[cfg(test)]
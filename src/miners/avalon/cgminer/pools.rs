@@ -10,13 +10,19 @@ pub struct Pool {
     #[serde(rename = "URL")]
     pub url: String,
     pub user: String,
+    #[serde(default)]
+    pub accepted: u64,
+    #[serde(default)]
+    pub rejected: u64,
+    #[serde(default)]
+    pub stale: u64,
 }
 
-impl Into<crate::Pool> for Pool {
-    fn into(self) -> crate::Pool {
+impl From<Pool> for crate::Pool {
+    fn from(val: Pool) -> Self {
         crate::Pool {
-            url: self.url,
-            username: self.user,
+            url: val.url,
+            username: val.user,
             password: None,
         }
     }
@@ -30,8 +36,8 @@ pub struct PoolResp {
     pub pools: Vec<Pool>,
 }
 
-impl Into<Vec<crate::Pool>> for PoolResp {
-    fn into(self) -> Vec<crate::Pool> {
-        self.pools.into_iter().map(|p| p.into()).collect()
+impl From<PoolResp> for Vec<crate::Pool> {
+    fn from(val: PoolResp) -> Self {
+        val.pools.into_iter().map(|p| p.into()).collect()
     }
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
+// Mirrors the estats and version replies field for field
+#[allow(dead_code)]
 mod cgminer;
 mod avalon;
-pub use avalon::Avalon;
+pub use avalon::{Avalon, AvalonModule};
 pub use cgminer::Error as DeError;
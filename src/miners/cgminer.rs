@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+
+use crate::{Client, Capabilities, DetectionInfo};
+use crate::miner::{Miner, Pool, PoolCounters, Resource, stable_hashrate, Tags};
+use crate::error::Error;
+use crate::security::{self, SecurityIndicator};
+
+/// Fallback for devices that answer the cgminer socket API but aren't a vendor we know
+/// Only the commands every cgminer fork has are used (summary, pools, devs, version), and
+/// fields are read loosely since forks rename and drop them freely
+pub struct GenericCgminer {
+    ip: String,
+    port: u16,
+    client: Client,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
+
+    /// One slot per command, so fetching one doesn't wait on another
+    responses: std::sync::Mutex<HashMap<&'static str, Arc<Mutex<Option<Value>>>>>,
+}
+
+impl GenericCgminer {
+    /// Whether a socket reply looks like cgminer, a STATUS list whose first entry succeeded
+    pub fn accepts(resp: &str) -> bool {
+        serde_json::from_str::<Value>(resp)
+            .ok()
+            .and_then(|v| v["STATUS"][0]["STATUS"].as_str().map(|s| s == "S"))
+            .unwrap_or(false)
+    }
+
+    fn slot(&self, cmd: &'static str) -> Arc<Mutex<Option<Value>>> {
+        self.responses.lock().unwrap_or_else(|e| e.into_inner())
+            .entry(cmd)
+            .or_default()
+            .clone()
+    }
+
+    async fn command(&self, cmd: &'static str) -> Result<Value, Error> {
+        let slot = self.slot(cmd);
+        let mut cached = slot.lock().await;
+        if let Some(resp) = cached.as_ref() {
+            return Ok(resp.clone());
+        }
+        let resp = self.client.send_recv(&self.ip, self.port, &json!({"command": cmd})).await?;
+        let resp: Value = serde_json::from_str(&resp)?;
+        match resp["STATUS"][0]["STATUS"].as_str() {
+            Some("S") | Some("I") => {},
+            _ => return Err(Error::ApiCallFailed(resp["STATUS"][0]["Msg"].as_str().unwrap_or(cmd).to_string())),
+        }
+        *cached = Some(resp.clone());
+        Ok(resp)
+    }
+}
+
+/// Numbers are sometimes sent as strings, e.g. "GHS 5s":"95123.45"
+fn number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Hashrate in TH/s from a summary reply, whichever unit the fork reports in
+fn hashrate_ths(summary: &Value) -> Option<f64> {
+    let summary = &summary["SUMMARY"][0];
+    [("GHS 5s", 1e3), ("MHS 5s", 1e6), ("GHS av", 1e3), ("MHS av", 1e6)]
+        .iter()
+        .find_map(|(key, div)| number(&summary[*key]).map(|v| v / div))
+}
+
+fn pools(resp: &Value) -> Vec<Pool> {
+    resp["POOLS"].as_array()
+        .map(|pools| pools.iter()
+            .filter_map(|p| Some(Pool {
+                url: p["URL"].as_str()?.to_string(),
+                username: p["User"].as_str().unwrap_or_default().to_string(),
+                password: None,
+            }))
+            .collect())
+        .unwrap_or_default()
+}
+
+fn pool_counters(resp: &Value) -> Vec<PoolCounters> {
+    let count = |p: &Value, key: &str| number(&p[key]).unwrap_or_default() as u64;
+    resp["POOLS"].as_array()
+        .map(|pools| pools.iter()
+            .filter_map(|p| Some(PoolCounters {
+                url: p["URL"].as_str()?.to_string(),
+                accepted: count(p, "Accepted"),
+                rejected: count(p, "Rejected"),
+                stale: count(p, "Stale"),
+            }))
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Hottest device, zero readings are devices without a sensor
+fn max_temperature(devs: &Value) -> Option<f64> {
+    devs["DEVS"].as_array()?
+        .iter()
+        .filter_map(|d| number(&d["Temperature"]))
+        .filter(|t| *t > 0.0)
+        .reduce(f64::max)
+}
+
+fn model(version: &Value) -> Option<String> {
+    let version = &version["VERSION"][0];
+    ["Type", "Model", "Miner"].iter()
+        .find_map(|key| version[*key].as_str().filter(|s| !s.is_empty()))
+        .map(|s| s.to_string())
+}
+
+#[async_trait]
+impl Miner for GenericCgminer {
+    fn new(client: Client, ip: String, port: u16) -> Self {
+        GenericCgminer {
+            ip,
+            port,
+            client,
+            detection: None,
+            tags: Tags::new(),
+            responses: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Cgminer"
+    }
+
+    /// No web UI to check, only the ports
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        Ok(security::scan_ports(&self.client, &self.ip).await)
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
+    /// Read only, write commands differ between forks
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            write_api: false,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn get_model(&self) -> Result<String, Error> {
+        model(&self.command("version").await?).ok_or(Error::ExpectedReturn)
+    }
+
+    /// The socket API has no authentication
+    async fn auth(&mut self, _username: &str, _password: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn reboot(&mut self) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_hashrate(&self) -> Result<f64, Error> {
+        hashrate_ths(&self.command("summary").await?).ok_or(Error::ExpectedReturn)
+    }
+
+    async fn get_hashrate_stable(&self) -> Result<f64, Error> {
+        let summary = self.command("summary").await?;
+        let short = hashrate_ths(&summary).ok_or(Error::ExpectedReturn)?;
+        let sum = &summary["SUMMARY"][0];
+        let average = [("GHS av", 1e3), ("MHS av", 1e6)].iter()
+            .find_map(|(key, div)| number(&sum[*key]).map(|v| v / div));
+        match (number(&sum["Elapsed"]), average) {
+            (Some(elapsed), Some(average)) => Ok(stable_hashrate(elapsed as u64, short, average)),
+            _ => Ok(short),
+        }
+    }
+
+    async fn get_power(&self) -> Result<f64, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_nameplate_rate(&self) -> Result<f64, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_temperature(&self) -> Result<f64, Error> {
+        max_temperature(&self.command("devs").await?).ok_or(Error::ExpectedReturn)
+    }
+
+    async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
+        Ok(pools(&self.command("pools").await?))
+    }
+
+    async fn get_pool_counters(&self) -> Result<Vec<PoolCounters>, Error> {
+        Ok(pool_counters(&self.command("pools").await?))
+    }
+
+    /// Pools stand in for config and version for system info
+    async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+        let cmds = resources.iter().map(|r| match r {
+            Resource::Summary => "summary",
+            Resource::Stats => "devs",
+            Resource::Config => "pools",
+            Resource::SystemInfo => "version",
+        });
+        futures::future::join_all(cmds.map(|cmd| self.command(cmd))).await
+            .into_iter()
+            .try_for_each(|r| r.map(drop))
+    }
+
+    async fn set_sleep(&mut self, _sleep: bool) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_mac(&self) -> Result<String, Error> {
+        Err(Error::NotSupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashrate() {
+        let s: Value = serde_json::from_str(r#"{"STATUS":[{"STATUS":"S","When":1700000000,"Code":11,"Msg":"Summary","Description":"cgminer 4.9.2"}],"SUMMARY":[{"Elapsed":3600,"GHS 5s":"13512.37","GHS av":13498.21}],"id":1}"#).unwrap();
+        assert_eq!(hashrate_ths(&s), Some(13.51237));
+        let s: Value = serde_json::from_str(r#"{"STATUS":[{"STATUS":"S"}],"SUMMARY":[{"MHS av":95000000.0}]}"#).unwrap();
+        assert_eq!(hashrate_ths(&s), Some(95.0));
+        assert_eq!(hashrate_ths(&json!({"SUMMARY":[{}]})), None);
+    }
+
+    #[test]
+    fn test_pools_devs_version() {
+        let p: Value = serde_json::from_str(r#"{"STATUS":[{"STATUS":"S"}],"POOLS":[{"POOL":0,"URL":"stratum+tcp://pool.example.com:3333","User":"worker.1","Status":"Alive"},{"POOL":1,"URL":"stratum+tcp://backup.example.com:3333","Status":"Dead"}]}"#).unwrap();
+        let pools = pools(&p);
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools[0].username, "worker.1");
+        assert_eq!(pools[1].username, "");
+        let p: Value = serde_json::from_str(r#"{"POOLS":[{"URL":"stratum+tcp://pool.example.com:3333","Accepted":1200,"Rejected":"3","Stale":1}]}"#).unwrap();
+        assert_eq!(pool_counters(&p)[0], PoolCounters { url: "stratum+tcp://pool.example.com:3333".to_string(), accepted: 1200, rejected: 3, stale: 1 });
+
+        let d: Value = serde_json::from_str(r#"{"STATUS":[{"STATUS":"S"}],"DEVS":[{"ASC":0,"Temperature":61.5},{"ASC":1,"Temperature":0},{"ASC":2,"Temperature":"64.0"}]}"#).unwrap();
+        assert_eq!(max_temperature(&d), Some(64.0));
+
+        let v: Value = serde_json::from_str(r#"{"STATUS":[{"STATUS":"S"}],"VERSION":[{"CGMiner":"4.10.0","API":"3.7","Type":"Exotic X1"}]}"#).unwrap();
+        assert_eq!(model(&v).as_deref(), Some("Exotic X1"));
+
+        assert!(GenericCgminer::accepts(r#"{"STATUS":[{"STATUS":"S","Msg":"Stats"}],"STATS":[]}"#));
+        assert!(!GenericCgminer::accepts(r#"{"STATUS":"E","Msg":"Invalid JSON"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_scripted_transport() {
+        let script = crate::transport::ScriptedSocket::new()
+            .reply("\"summary\"", r#"{"STATUS":[{"STATUS":"S"}],"SUMMARY":[{"GHS 5s":"13512.37"}]}"#)
+            .reply("\"pools\"", r#"{"STATUS":[{"STATUS":"E","Msg":"No pools"}]}"#);
+        let client = crate::ClientBuilder::new().socket_transport(script.clone()).build().unwrap();
+        let miner = GenericCgminer::new(client, "10.0.0.1".into(), 4028);
+        assert_eq!(miner.get_hashrate().await.unwrap(), 13.51237);
+        assert!(matches!(miner.get_pools().await, Err(Error::ApiCallFailed(msg)) if msg == "No pools"));
+        assert_eq!(script.requests(), vec![r#"{"command":"summary"}"#, r#"{"command":"pools"}"#]);
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_disabled_vendor_not_generic() {
+        let stats = include_str!("../../fixtures/detection/antminer/s19_bmminer.socket.json");
+        let script = crate::transport::ScriptedSocket::new().reply("\"stats\"", stats);
+        let client = crate::ClientBuilder::new()
+            .socket_transport(script.clone())
+            .http_transport(crate::transport::ScriptedHttp::new())
+            .enabled_vendors(&[crate::Vendor::Cgminer])
+            .build()
+            .unwrap();
+        match client.get_miner("10.0.0.1", None).await {
+            Err(Error::DetectionFailed { socket: Some(e), .. }) => assert!(matches!(*e, Error::UnknownMinerType(_))),
+            other => panic!("expected the Antminer to be refused, got {:?}", other.map(|m| m.get_type())),
+        }
+
+        let stats = include_str!("../../fixtures/detection/cgminer/gekkoscience_cgminer_412.socket.json");
+        let script = crate::transport::ScriptedSocket::new().reply("\"stats\"", stats);
+        let client = crate::ClientBuilder::new()
+            .socket_transport(script.clone())
+            .http_transport(crate::transport::ScriptedHttp::new())
+            .enabled_vendors(&[crate::Vendor::Cgminer])
+            .build()
+            .unwrap();
+        assert_eq!(client.get_miner("10.0.0.1", None).await.unwrap().get_type(), "Cgminer");
+    }
+}
@@ -1,6 +1,9 @@
 use serde::Deserialize;
+use serde_json::json;
 
-use crate::miners::common::{Status};
+use crate::Client;
+use crate::error::Error;
+use crate::miners::common::{Status, StatusCode, StatusResp};
 
 #[derive(Deserialize, Debug)]
 pub struct AscIdentify {
@@ -15,3 +18,15 @@ pub struct AscIdentifyResp {
     #[serde(rename = "ASCS")]
     pub ascs: [AscIdentify; 1],
 }
+
+/// ascenable or ascdisable a chain over the socket API, which needs privileged (W:) access
+/// cgminer applies it at once but forgets it on restart
+pub async fn set_asc_enabled(client: &Client, ip: &str, port: u16, chain: usize, enabled: bool) -> Result<(), Error> {
+    let command = if enabled { "ascenable" } else { "ascdisable" };
+    let resp = client.send_recv(ip, port, &json!({"command": command, "parameter": chain.to_string()})).await?;
+    let resp: StatusResp = serde_json::from_str(&resp)?;
+    match resp.status[0].status {
+        StatusCode::SUCC | StatusCode::INFO => Ok(()),
+        _ => Err(Error::ApiCallFailed(resp.status[0].msg.clone())),
+    }
+}
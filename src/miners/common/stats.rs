@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use serde::Deserialize;
 use crate::miners::common::*;
 
@@ -169,6 +170,22 @@ pub struct AvaStats {
     pub shared: StatsShared,
     #[serde(rename = "MM ID0")]
     pub mm_id0: String,
+    /// Remaining keys, including "MM ID1".."MM IDn" on multi-module controllers
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl AvaStats {
+    /// Raw estats string of every MM module, ordered by module id
+    pub fn modules(&self) -> Vec<(usize, &str)> {
+        let mut modules = vec![(0, self.mm_id0.as_str())];
+        modules.extend(self.extra.iter().filter_map(|(k, v)| {
+            let id = k.strip_prefix("MM ID")?.parse::<usize>().ok()?;
+            Some((id, v.as_str()?))
+        }));
+        modules.sort_by_key(|(id, _)| *id);
+        modules
+    }
 }
 
 /// MinerVa Status section
@@ -252,7 +269,7 @@ pub enum Stats {
     AvaStats(AvaStats),
     Dev(DevStats),
     AmVersion(AmVersion),
-    AmStats(AmStats),
+    AmStats(Box<AmStats>),
 }
 
 #[derive(Deserialize, Debug)]
@@ -277,4 +294,17 @@ mod tests {
         assert_eq!(stats.len(), 5);
         assert!(matches!(stats[0], Stats::MvStats(_)));
     }
+
+    #[test]
+    fn test_avastats_modules() {
+        let s = r#"{"STATUS":[{"STATUS":"S","When":1849,"Code":70,"Msg":"CGMiner stats","Description":"cgminer 4.11.1"}],"STATS":[{"STATS":0,"ID":"AVA100","Elapsed":1750,"Calls":0,"Wait":0.000000,"Max":0.000000,"Min":99999999.000000,"MM ID2":"Ver[b]","MM ID0":"Ver[a]","MM ID10":"Ver[c]","MM Count":3,"Smart Speed":1}],"id":1}"#;
+        let stat: StatsResp = from_str(s).unwrap();
+        let stats = stat.stats.unwrap();
+        match &stats[0] {
+            Stats::AvaStats(stats) => {
+                assert_eq!(stats.modules(), vec![(0, "Ver[a]"), (2, "Ver[b]"), (10, "Ver[c]")]);
+            },
+            _ => panic!("expected AvaStats"),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,248 @@
+//! Static dispatch over the miners this build knows, for callers who'd rather match on the
+//! vendor than downcast a `Box<dyn Miner>`, and who poll often enough to care about the vtable
+
+use async_trait::async_trait;
+use chrono::FixedOffset;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+use crate::{Capabilities, DetectionInfo, MinerConfigBackup, SecurityIndicator};
+use crate::error::Error;
+use crate::miner::{
+    Miner, Pool, PoolCounters, FanMode, PowerState, HealthCondition, LogOptions, LogEntry, MinerError, Profile, TuningStatus,
+    SystemStats, NetworkStats, ChipCount, BoardTemps, TempReport, Resource, CoolingDetail, PendingChange, Tags,
+};
+use crate::miners::cgminer::GenericCgminer;
+#[cfg(feature = "http")]
+use crate::miners::recovery::RecoveryMiner;
+#[cfg(feature = "antminer")]
+use crate::miners::antminer::Antminer;
+#[cfg(feature = "avalon")]
+use crate::miners::avalon::Avalon;
+#[cfg(feature = "vnish")]
+use crate::miners::vnish::Vnish;
+#[cfg(feature = "whatsminer")]
+use crate::miners::whatsminer::Whatsminer;
+#[cfg(feature = "minerva")]
+use crate::miners::minerva::{Minerva, Minera};
+
+/// Which MinerEnum variant a miner is, serializable where the miner itself isn't
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MinerKind {
+    Antminer,
+    Avalon,
+    Vnish,
+    Whatsminer,
+    /// MinerVa on its own interface
+    Minerva,
+    /// MinerVa on the Minera interface
+    Minera,
+    Cgminer,
+    Recovery,
+    /// Anything wrapped, e.g. a LockMiner or a miner instrumented by otel
+    Other,
+}
+
+/// One variant per vendor implementation compiled in
+/// Wrappers, which hold permits or record metrics, can't be unwrapped without losing what they
+/// add, so they stay boxed in Other. Vendors are boxed too, so the enum stays small
+pub enum MinerEnum {
+    #[cfg(feature = "antminer")]
+    Antminer(Box<Antminer>),
+    #[cfg(feature = "avalon")]
+    Avalon(Box<Avalon>),
+    #[cfg(feature = "vnish")]
+    Vnish(Box<Vnish>),
+    #[cfg(feature = "whatsminer")]
+    Whatsminer(Box<Whatsminer>),
+    #[cfg(feature = "minerva")]
+    Minerva(Box<Minerva>),
+    #[cfg(feature = "minerva")]
+    Minera(Box<Minera>),
+    Cgminer(Box<GenericCgminer>),
+    #[cfg(feature = "http")]
+    Recovery(Box<RecoveryMiner>),
+    Other(Box<dyn Miner + Send + Sync>),
+}
+
+/// Run an expression against whichever miner the enum holds, bound to $m
+/// `map` takes the miner by value and puts what the expression returns back in the same variant
+macro_rules! dispatch {
+    (map $s:expr, $m:ident => $e:expr) => {
+        match $s {
+            #[cfg(feature = "antminer")]
+            MinerEnum::Antminer($m) => MinerEnum::Antminer(Box::new({ let $m = *$m; $e })),
+            #[cfg(feature = "avalon")]
+            MinerEnum::Avalon($m) => MinerEnum::Avalon(Box::new({ let $m = *$m; $e })),
+            #[cfg(feature = "vnish")]
+            MinerEnum::Vnish($m) => MinerEnum::Vnish(Box::new({ let $m = *$m; $e })),
+            #[cfg(feature = "whatsminer")]
+            MinerEnum::Whatsminer($m) => MinerEnum::Whatsminer(Box::new({ let $m = *$m; $e })),
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minerva($m) => MinerEnum::Minerva(Box::new({ let $m = *$m; $e })),
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minera($m) => MinerEnum::Minera(Box::new({ let $m = *$m; $e })),
+            MinerEnum::Cgminer($m) => MinerEnum::Cgminer(Box::new({ let $m = *$m; $e })),
+            #[cfg(feature = "http")]
+            MinerEnum::Recovery($m) => MinerEnum::Recovery(Box::new({ let $m = *$m; $e })),
+            // Already built, so there's nothing to change
+            MinerEnum::Other($m) => MinerEnum::Other($m),
+        }
+    };
+    ($s:expr, $m:ident => $e:expr) => {
+        match $s {
+            #[cfg(feature = "antminer")]
+            MinerEnum::Antminer($m) => $e,
+            #[cfg(feature = "avalon")]
+            MinerEnum::Avalon($m) => $e,
+            #[cfg(feature = "vnish")]
+            MinerEnum::Vnish($m) => $e,
+            #[cfg(feature = "whatsminer")]
+            MinerEnum::Whatsminer($m) => $e,
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minerva($m) => $e,
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minera($m) => $e,
+            MinerEnum::Cgminer($m) => $e,
+            #[cfg(feature = "http")]
+            MinerEnum::Recovery($m) => $e,
+            MinerEnum::Other($m) => $e,
+        }
+    };
+}
+
+impl MinerEnum {
+    pub fn kind(&self) -> MinerKind {
+        match self {
+            #[cfg(feature = "antminer")]
+            MinerEnum::Antminer(_) => MinerKind::Antminer,
+            #[cfg(feature = "avalon")]
+            MinerEnum::Avalon(_) => MinerKind::Avalon,
+            #[cfg(feature = "vnish")]
+            MinerEnum::Vnish(_) => MinerKind::Vnish,
+            #[cfg(feature = "whatsminer")]
+            MinerEnum::Whatsminer(_) => MinerKind::Whatsminer,
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minerva(_) => MinerKind::Minerva,
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minera(_) => MinerKind::Minera,
+            MinerEnum::Cgminer(_) => MinerKind::Cgminer,
+            #[cfg(feature = "http")]
+            MinerEnum::Recovery(_) => MinerKind::Recovery,
+            MinerEnum::Other(_) => MinerKind::Other,
+        }
+    }
+
+    /// Box the miner back up, e.g. to hand it to a SharedMiner
+    pub fn into_boxed(self) -> Box<dyn Miner + Send + Sync> {
+        match self {
+            #[cfg(feature = "antminer")]
+            MinerEnum::Antminer(m) => m,
+            #[cfg(feature = "avalon")]
+            MinerEnum::Avalon(m) => m,
+            #[cfg(feature = "vnish")]
+            MinerEnum::Vnish(m) => m,
+            #[cfg(feature = "whatsminer")]
+            MinerEnum::Whatsminer(m) => m,
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minerva(m) => m,
+            #[cfg(feature = "minerva")]
+            MinerEnum::Minera(m) => m,
+            MinerEnum::Cgminer(m) => m,
+            #[cfg(feature = "http")]
+            MinerEnum::Recovery(m) => m,
+            MinerEnum::Other(m) => m,
+        }
+    }
+}
+
+/// Moves the miner out of the box if it's the concrete type T, otherwise hands the box back
+fn take<T: Miner + Send + Sync + 'static>(miner: Box<dyn Miner + Send + Sync>) -> Result<T, Box<dyn Miner + Send + Sync>> {
+    if !(*miner).as_any().is::<T>() {
+        return Err(miner);
+    }
+    // Checked above, the downcast can't fail
+    Ok(*miner.into_any().downcast::<T>().unwrap())
+}
+
+impl From<Box<dyn Miner + Send + Sync>> for MinerEnum {
+    fn from(miner: Box<dyn Miner + Send + Sync>) -> Self {
+        #[cfg(feature = "antminer")]
+        let miner = match take::<Antminer>(miner) {
+            Ok(m) => return MinerEnum::Antminer(Box::new(m)),
+            Err(miner) => miner,
+        };
+        #[cfg(feature = "avalon")]
+        let miner = match take::<Avalon>(miner) {
+            Ok(m) => return MinerEnum::Avalon(Box::new(m)),
+            Err(miner) => miner,
+        };
+        #[cfg(feature = "vnish")]
+        let miner = match take::<Vnish>(miner) {
+            Ok(m) => return MinerEnum::Vnish(Box::new(m)),
+            Err(miner) => miner,
+        };
+        #[cfg(feature = "whatsminer")]
+        let miner = match take::<Whatsminer>(miner) {
+            Ok(m) => return MinerEnum::Whatsminer(Box::new(m)),
+            Err(miner) => miner,
+        };
+        #[cfg(feature = "minerva")]
+        let miner = match take::<Minerva>(miner) {
+            Ok(m) => return MinerEnum::Minerva(Box::new(m)),
+            Err(miner) => miner,
+        };
+        #[cfg(feature = "minerva")]
+        let miner = match take::<Minera>(miner) {
+            Ok(m) => return MinerEnum::Minera(Box::new(m)),
+            Err(miner) => miner,
+        };
+        let miner = match take::<GenericCgminer>(miner) {
+            Ok(m) => return MinerEnum::Cgminer(Box::new(m)),
+            Err(miner) => miner,
+        };
+        #[cfg(feature = "http")]
+        let miner = match take::<RecoveryMiner>(miner) {
+            Ok(m) => return MinerEnum::Recovery(Box::new(m)),
+            Err(miner) => miner,
+        };
+        MinerEnum::Other(miner)
+    }
+}
+
+impl From<MinerEnum> for Box<dyn Miner + Send + Sync> {
+    fn from(miner: MinerEnum) -> Self {
+        miner.into_boxed()
+    }
+}
+
+delegate_miner!(MinerEnum, dispatched);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientBuilder, LockMiner};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_from_boxed() {
+        let client = ClientBuilder::new().build().unwrap();
+        let boxed: Box<dyn Miner + Send + Sync> = Box::new(GenericCgminer::new(client.clone(), "10.0.0.1".into(), 4028));
+        let miner = MinerEnum::from(boxed);
+        assert!(matches!(miner, MinerEnum::Cgminer(_)));
+        assert_eq!(miner.get_ip(), "10.0.0.1");
+        assert_eq!(serde_json::to_string(&miner.kind()).unwrap(), "\"Cgminer\"");
+
+        // A wrapper keeps its permit, so it isn't unwrapped
+        let permit = Arc::new(tokio::sync::Semaphore::new(1)).try_acquire_owned().unwrap();
+        let locked = LockMiner::new_locked(miner.into_boxed(), permit);
+        let miner = MinerEnum::from(Box::new(locked) as Box<dyn Miner + Send + Sync>);
+        assert_eq!(miner.kind(), MinerKind::Other);
+        assert_eq!(miner.get_type(), "Cgminer");
+
+        // Built directly, nothing says which vendor it is
+        let miner = MinerEnum::new(client, "10.0.0.2".into(), 4028);
+        assert_eq!(miner.kind(), MinerKind::Cgminer);
+        assert_eq!(miner.get_ip(), "10.0.0.2");
+    }
+}
@@ -1,9 +1,11 @@
 use serde::Deserialize;
 
+use crate::util::secret::SecretString;
+
 #[derive(Deserialize, Debug)]
 pub struct AuthData {
     #[serde(rename = "accessToken")]
-    pub access_token: String,
+    pub access_token: SecretString,
 }
 
 #[derive(Deserialize, Debug)]
@@ -43,8 +43,8 @@ pub struct NotRunningStats {
 #[derive(Deserialize)]
 #[serde(untagged)]
 pub enum StatsResp {
-    Running(RunningStats),
-    NotRunning(NotRunningStats),
+    Running(Box<RunningStats>),
+    NotRunning(Box<NotRunningStats>),
 }
 
 #[cfg(test)]
@@ -7,30 +7,44 @@ use serde_json::json;
 use std::collections::HashSet;
 use scraper::{Html, Selector};
 use tokio::sync::{Mutex, MutexGuard};
-use crate::{Client, ErrorType};
-use crate::miner::{Miner, Pool, Profile, MinerError};
+use std::time::Duration;
+use chrono::{DateTime, TimeZone, Utc};
+use tracing::warn;
+use crate::{Client, ErrorType, Cache, CacheItem, DetectionInfo, SecretString};
+use crate::miner::{Miner, Pool, MinerError, Resource, Tags};
+use crate::security::{self, SecurityIndicator};
 use crate::error::Error;
+use crate::transport::SendVia;
 use crate::miners::minerva::{cgminer, minera};
 use crate::miners::minerva::error::{MINERVA_ERRORS, MINERA_ERRORS};
 use crate::miners::common;
+use crate::util::framing::Framing;
+
+/// cgminer takes about a minute to come back after a restart
+const RESTART_CHECKS: usize = 12;
+const RESTART_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// changePool takes exactly three
+const MAX_POOLS: usize = 3;
 
 /// 4 fan Minervas use this interface
 pub struct Minera {
     ip: String,
     port: u16,
     client: Client,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
 
     stats: Mutex<Option<minera::StatsResp>>,
     cg_stats: Mutex<Option<common::MvStats>>,
 }
 
 impl Minera {
-    async fn get_stats(&self) -> Result<MutexGuard<Option<minera::StatsResp>>, Error> {
+    async fn get_stats(&self) -> Result<MutexGuard<'_, Option<minera::StatsResp>>, Error> {
         let mut stats = self.stats.lock().await;
         if stats.is_none() {
             let resp = self.client.http_client
-                .get(&format!("http://{}/index.php/app/stats", self.ip))
-                .send()
+                .get(format!("http://{}/index.php/app/stats", self.ip))
+                .send_via(&self.client)
                 .await?;
             if resp.status().is_success() {
                 let stat: minera::StatsResp = resp.json().await?;
@@ -42,10 +56,11 @@ impl Minera {
         Ok(stats)
     }
 
-    async fn get_cg_stats(&self) -> Result<MutexGuard<Option<common::MvStats>>, Error> {
+    async fn get_cg_stats(&self) -> Result<MutexGuard<'_, Option<common::MvStats>>, Error> {
         let mut stats = self.cg_stats.lock().await;
         if stats.is_none() {
-            let resp = self.client.send_recv(&self.ip, self.port, &json!({"command":"stats"})).await?;
+            // cgminer can wedge with the socket still taking connections
+            let resp = self.client.send_recv_live(&self.ip, self.port, &json!({"command":"stats"}), Framing::Eof, self.client.request_timeout).await?;
             let cg_stat: common::StatsResp = serde_json::from_str(&resp)?;
             if cg_stat.stats.is_none() {
                 return Err(Error::InvalidResponse);
@@ -63,8 +78,18 @@ impl Minera {
         Ok(stats)
     }
 
-    async fn invalidate_stats(&self) {
-        let _ = self.stats.lock().await.take();
+    /// Restart cgminer from the web interface, the control board stays up
+    pub async fn restart_miner(&self) -> Result<(), Error> {
+        for action in ["stop_miner", "start_miner"] {
+            let resp = self.client.http_client
+                .get(format!("http://{}/index.php/app/{}", self.ip, action))
+                .send_via(&self.client)
+                .await?;
+            if !resp.status().is_success() {
+                return Err(Error::HttpRequestFailed);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -75,6 +100,8 @@ impl Miner for Minera {
             ip,
             port,
             client,
+            detection: None,
+            tags: Tags::new(),
             stats: Mutex::new(None),
             cg_stats: Mutex::new(None),
         }
@@ -84,6 +111,36 @@ impl Miner for Minera {
         "MinerVa"
     }
 
+    async fn invalidate(&self) {
+        let _ = self.stats.lock().await.take();
+        let _ = self.cg_stats.lock().await.take();
+    }
+
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        security::scan(&self.client, &self.ip, &format!("http://{}/", self.ip)).await
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
     async fn get_model(&self) -> Result<String, Error> {
         //The below doesn't respond when the miner is not running
         // let resp = self.client.send_recv(&self.ip, self.port, &json!({"command":"devdetails"})).await?;
@@ -96,9 +153,9 @@ impl Miner for Minera {
         let mut form = HashMap::new();
         form.insert("password", password);
         let resp = self.client.http_client
-            .post(&format!("http://{}/index.php/app/login", self.ip))
+            .post(format!("http://{}/index.php/app/login", self.ip))
             .form(&form)
-            .send()
+            .send_via(&self.client)
             .await?;
         if resp.status().is_success() {
             Ok(())
@@ -107,12 +164,30 @@ impl Miner for Minera {
         }
     }
 
+    async fn recover(&mut self) -> Result<bool, Error> {
+        match self.get_cg_stats().await {
+            Ok(_) => return Ok(false),
+            Err(Error::ApiDeadlocked) => {},
+            Err(e) => return Err(e),
+        }
+        warn!("cgminer on {} is deadlocked, restarting it", self.ip);
+        self.restart_miner().await?;
+        for _ in 0..RESTART_CHECKS {
+            tokio::time::sleep(RESTART_CHECK_INTERVAL).await;
+            let _ = self.cg_stats.lock().await.take();
+            if self.get_cg_stats().await.is_ok() {
+                return Ok(true);
+            }
+        }
+        Err(Error::VerificationTimeout("cgminer restart".into()))
+    }
+
     async fn reboot(&mut self) -> Result<(), Error> {
         //TODO: This always times out as the API reboots before responding
         let resp = self.client.http_client
-            .post(&format!("http://{}/index.php/app/reboot", self.ip))
+            .post(format!("http://{}/index.php/app/reboot", self.ip))
             .query(&[("confirm", "1")])
-            .send()
+            .send_via(&self.client)
             .await?;
         if resp.status().is_success() {
             Ok(())
@@ -164,10 +239,10 @@ impl Miner for Minera {
         let stat = self.get_stats().await?;
         let stat = stat.as_ref().unwrap_or_else(|| unreachable!());
         if let minera::StatsResp::Running(stat) = stat {
-            // Convert to TH/S
             Ok(stat.temp)
         } else {
-            Ok(0.0)
+            // Stopped miners don't report temperature
+            Err(Error::ExpectedReturn)
         }
     }
 
@@ -192,8 +267,8 @@ impl Miner for Minera {
         let pool_user_selector = Selector::parse(r#"input[name="pool_username[]"]"#).unwrap();
         let pool_pass_selector = Selector::parse(r#"input[name="pool_password[]"]"#).unwrap();
         let resp = self.client.http_client
-            .get(&format!("http://{}/index.php/app/settings", self.ip))
-            .send()
+            .get(format!("http://{}/index.php/app/settings", self.ip))
+            .send_via(&self.client)
             .await?;
         let document = Html::parse_document(resp.text().await?.as_str());
         if let Some(pools) = document.select(&pools_selector).next() {
@@ -205,7 +280,8 @@ impl Miner for Minera {
                 pool_list.push(Pool {
                     url,
                     username: user,
-                    password: if pass.is_empty() {None} else {Some(pass)},
+                    // The settings page masks passwords, a mask isn't worth carrying over
+                    password: if pass.is_empty() || pass.chars().all(|c| c == '*') {None} else {Some(pass.into())},
                 });
             }
             Ok(pool_list)
@@ -223,47 +299,35 @@ impl Miner for Minera {
                 .text("pool_url[]", pool.url.clone())
                 .text("pool_username[]", pool.username.clone())
                 .text("pool_password[]", if let Some(ref password) = pool.password {
-                    password.clone()
+                    password.expose().to_string()
                 } else {
                     "".to_string()
                 });
         }
 
         let resp = self.client.http_client
-            .post(&format!("http://{}/index.php/app/settings", self.ip))
+            .post(format!("http://{}/index.php/app/settings", self.ip))
             .multipart(form)
-            .send()
+            .send_via(&self.client)
             .await?;
         if resp.status().is_success() {
-            self.invalidate_stats().await;
+            self.invalidate().await;
             Ok(())
         } else {
             Err(Error::HttpRequestFailed)
         }
     }
 
-    async fn get_sleep(&self) -> Result<bool, Error> {
-        Err(Error::NotSupported)
-    }
-
     async fn set_sleep(&mut self, _sleep: bool) -> Result<(), Error> {
         return Err(Error::NotSupported);
     }
 
-    async fn get_blink(&self) -> Result<bool, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn set_blink(&mut self, _blink: bool) -> Result<(), Error> {
-        Err(Error::NotSupported)
-    }
-
     async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
         // /index.php/app/varLog
         // This returns everything, we're gonna want to subscript it
         let resp = self.client.http_client
-            .get(&format!("http://{}/index.php/app/varLog", self.ip))
-            .send()
+            .get(format!("http://{}/index.php/app/varLog", self.ip))
+            .send_via(&self.client)
             .await?;
         if resp.status().is_success() {
             let text = resp.text().await?;
@@ -273,6 +337,15 @@ impl Miner for Minera {
         }
     }
 
+    async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+        let want = |r: Resource| resources.contains(&r);
+        let (stats, cg_stats) = futures::join!(
+            async { if want(Resource::Summary) || want(Resource::SystemInfo) { self.get_stats().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::Stats) { self.get_cg_stats().await.map(drop) } else { Ok(()) } },
+        );
+        stats.and(cg_stats)
+    }
+
     async fn get_mac(&self) -> Result<String, Error> {
         let stat = self.get_stats().await?;
         let stat = stat.as_ref().unwrap_or_else(|| unreachable!());
@@ -295,16 +368,13 @@ impl Miner for Minera {
         let mut errors = HashSet::new();
         let stats = self.get_stats().await?;
         let stats = stats.as_ref().unwrap_or_else(|| unreachable!());
-        match stats {
-            minera::StatsResp::Running(stat) => {
-                if let None = stat.devices.board_2 {
-                    errors.insert(MinerError { msg: "Missing Board(s)".into(), error_type: ErrorType::HashBoard });
-                }
-                if let None = stat.devices.board_3 {
-                    errors.insert(MinerError { msg: "Missing Board(s)".into(), error_type: ErrorType::HashBoard });
-                }
+        if let minera::StatsResp::Running(stat) = stats {
+            if stat.devices.board_2.is_none() {
+                errors.insert(MinerError::new("Missing Board(s)", ErrorType::HashBoard));
+            }
+            if stat.devices.board_3.is_none() {
+                errors.insert(MinerError::new("Missing Board(s)", ErrorType::HashBoard));
             }
-            _ => {}
         }
         for err in MINERA_ERRORS.iter() {
             if let Some(msg) = err.get_err(&log) {
@@ -323,29 +393,118 @@ impl Miner for Minera {
         }
     }
 
-    async fn get_profile(&self) -> Result<Profile, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn set_profile(&mut self, _profile: Profile) -> Result<(), Error> {
-        Err(Error::NotSupported)
+    /// Lasts until cgminer restarts
+    async fn set_board_enabled(&mut self, chain: usize, enabled: bool) -> Result<(), Error> {
+        common::set_asc_enabled(&self.client, &self.ip, self.port, chain, enabled).await?;
+        self.invalidate().await;
+        Ok(())
     }
+}
 
-    async fn get_hashboard(&mut self) -> Result<String, Error> {
-        Err(Error::NotSupported)
-    }
+/// A logged in API session
+struct Session {
+    token: SecretString,
+    expires: DateTime<Utc>,
 }
 
 /// 2 fan Minervas use this interface
 pub struct Minerva {
     ip: String,
-    _port: u16,
+    port: u16,
     client: Client,
-    token: String,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
+    username: String,
+    password: SecretString,
+    cache: Option<Cache>,
+
+    session: Mutex<Option<Session>>,
+}
+
+impl Minerva {
+    /// Read the expiry out of the JWT, falling back to 30 minutes if it can't be decoded
+    fn token_expiry(token: &str) -> DateTime<Utc> {
+        token.split('.').nth(1)
+            .and_then(|payload| base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok())
+            .and_then(|payload| serde_json::from_slice::<serde_json::Value>(&payload).ok())
+            .and_then(|claims| claims["exp"].as_i64())
+            .and_then(|exp| Utc.timestamp_opt(exp, 0).single())
+            .unwrap_or_else(|| Utc::now() + chrono::Duration::minutes(30))
+    }
+
+    /// Log in with the stored credentials, returning the new token
+    async fn login(&self) -> Result<SecretString, Error> {
+        let resp = self.client.http_client
+            .post(format!("https://{}/api/v1/auth/login", self.ip))
+            .json(&json!({
+                "username": self.username,
+                "password": self.password.expose(),
+            }))
+            .send_via(&self.client)
+            .await?;
+        if resp.status().is_success() {
+            let text = resp.text().await?;
+            if let Ok(js) = serde_json::from_str::<cgminer::AuthResp>(&text) {
+                let token = js.data.access_token;
+                let expires = Self::token_expiry(token.expose());
+                if let Some(cache) = &self.cache {
+                    cache.write().await.insert(
+                        self.ip.clone(),
+                        CacheItem {
+                            token: token.clone(),
+                            token_expires: expires,
+                        },
+                    );
+                }
+                *self.session.lock().await = Some(Session {
+                    token: token.clone(),
+                    expires,
+                });
+                Ok(token)
+            } else if serde_json::from_str::<cgminer::ApiResp>(&text).is_ok() {
+                //TODO: Check returned status code and return appropriate error
+                Err(Error::Unauthorized)
+            } else {
+                Err(Error::UnknownMinerType("Not a Minerva".into()))
+            }
+        } else {
+            Err(Error::HttpRequestFailed)
+        }
+    }
+
+    /// Get a valid token, from our session, the cache or by logging in again
+    async fn token(&self) -> Result<SecretString, Error> {
+        if let Some(session) = self.session.lock().await.as_ref() {
+            if session.expires > Utc::now() {
+                return Ok(session.token.clone());
+            }
+        }
+        if let Some(cache) = &self.cache {
+            if let Some(item) = cache.read().await.get(&self.ip) {
+                if item.token_expires > Utc::now() {
+                    *self.session.lock().await = Some(Session {
+                        token: item.token.clone(),
+                        expires: item.token_expires,
+                    });
+                    return Ok(item.token.clone());
+                }
+            }
+        }
+        self.login().await
+    }
+
+    /// Send an authenticated request, logging in again and retrying once if the token was rejected
+    async fn send<F>(&self, build: F) -> Result<reqwest::Response, Error>
+        where F: Fn(&str) -> reqwest::RequestBuilder
+    {
+        let token = self.token().await?;
+        let resp = build(token.expose()).send_via(&self.client).await?;
+        if resp.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(resp);
+        }
+        let token = self.login().await?;
+        build(token.expose()).send_via(&self.client).await
+    }
 }
 
 #[async_trait]
@@ -353,16 +512,52 @@ impl Miner for Minerva {
     fn new(client: Client, ip: String, port: u16) -> Self {
         Minerva {
             ip,
-            _port: port,
+            port,
             client,
-            token: "".to_string(),
+            detection: None,
+            tags: Tags::new(),
+            username: "".to_string(),
+            password: SecretString::default(),
+            cache: None,
+            session: Mutex::new(None),
         }
     }
 
+    fn with_cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
     fn get_type(&self) -> &'static str {
         "MinerVa"
     }
 
+    /// The web UI is an SPA behind a login, only the ports are checked
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        Ok(security::scan_ports(&self.client, &self.ip).await)
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
     async fn get_model(&self) -> Result<String, Error> {
         // let resp = self.client.send_recv(&self.ip, self.port, &json!({"command":"devdetails"})).await?;
         // let js = serde_json::from_str::<common::DevDetailsResp>(&resp)?;
@@ -371,54 +566,36 @@ impl Miner for Minerva {
     }
 
     async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error> {
-        let resp = self.client.http_client
-            .post(&format!("https://{}/api/v1/auth/login", self.ip))
-            .json(&json!({
-                "username": username,
-                "password": password,
-            }))
-            .send()
-            .await?;
-        if resp.status().is_success() {
-            let text = resp.text().await?;
-            if let Ok(js) = serde_json::from_str::<cgminer::AuthResp>(&text) {
-                self.token = js.data.access_token.clone();
-                Ok(())
-            } else if let Ok(_) = serde_json::from_str::<cgminer::ApiResp>(&text) {
-                //TODO: Check returned status code and return appropriate error
-                Err(Error::Unauthorized)
-            } else {
-                Err(Error::UnknownMinerType("Not a Minerva".into()))
-            }
-        } else {
-            Err(Error::HttpRequestFailed)
-        }
+        self.username = username.to_string();
+        self.password = password.into();
+        // Drop any previous session so we verify the new credentials
+        *self.session.lock().await = None;
+        self.login().await?;
+        Ok(())
     }
 
     async fn reboot(&mut self) -> Result<(), Error> {
         //TODO: This always times out as the API reboots before responding
-        let _ = self.client.http_client
-            .post(&format!("https://{}:/api/v1/cgminer/reboot", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await;
+        let _ = self.send(|token| self.client.http_client
+            .post(format!("https://{}:/api/v1/cgminer/reboot", self.ip))
+            .bearer_auth(token)
+        ).await;
         Ok(())
     }
 
     async fn get_hashrate(&self) -> Result<f64, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/cgminer/summary", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/cgminer/summary", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let text = resp.text().await?;
             if let Ok(summary) = serde_json::from_str::<cgminer::SummaryResp>(&text) {
                 // Convert to TH/s
                 Ok(summary.data[0].mhs_5s / 1000000.0)
-            } else if let Ok(_) = serde_json::from_str::<cgminer::ApiResp>(&text) {
-                // The miners up but didn't give us a great response, so just return 0
-                Ok(0.0)
+            } else if serde_json::from_str::<cgminer::ApiResp>(&text).is_ok() {
+                // The miners up but didn't give us a hashrate
+                Err(Error::ExpectedReturn)
             } else {
                 Err(Error::ApiCallFailed("Unknown error".to_string()))
             }
@@ -430,8 +607,8 @@ impl Miner for Minerva {
     async fn get_power(&self) -> Result<f64, Error> {
         // Turns out 2 fans report power consumption at port 9999
         let resp = self.client.http_client
-            .get(&format!("http://{}:9999/", self.ip))
-            .send()
+            .get(format!("http://{}:9999/", self.ip))
+            .send_via(&self.client)
             .await?;
 
         if resp.status().is_success() {
@@ -467,11 +644,10 @@ impl Miner for Minerva {
     }
 
     async fn get_temperature(&self) -> Result<f64, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/systemInfo/tempAndSpeed", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/systemInfo/tempAndSpeed", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let temp = resp.json::<cgminer::TempAndSpeedResp>().await?;
             Ok(temp.data.temperature)
@@ -481,11 +657,10 @@ impl Miner for Minerva {
     }
 
     async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/systemInfo/tempAndSpeed", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/systemInfo/tempAndSpeed", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let temp = resp.json::<cgminer::TempAndSpeedResp>().await?;
             Ok(vec![temp.data.fan_speed1, temp.data.fan_speed2])
@@ -497,8 +672,8 @@ impl Miner for Minerva {
     async fn get_fan_pwm(&self) -> Result<f64, Error> {
         // Turnes out minervas report pwm at port 9999
         let resp = self.client.http_client
-            .get(&format!("http://{}:9999/", self.ip))
-            .send()
+            .get(format!("http://{}:9999/", self.ip))
+            .send_via(&self.client)
             .await?;
 
         if resp.status().is_success() {
@@ -515,52 +690,58 @@ impl Miner for Minerva {
     }
 
     async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/cgminer/poolsInSetting", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/cgminer/poolsInSetting", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let pools = resp.json::<cgminer::GetPoolsResp>().await?;
-            let mut ret = Vec::new();
-            ret.push(Pool {
-                url: pools.data.pool1url,
-                username: pools.data.pool1user,
-                password: None,
-            });
-            ret.push(Pool {
-                url: pools.data.pool2url,
-                username: pools.data.pool2user,
-                password: None,
-            });
-            ret.push(Pool {
-                url: pools.data.pool3url,
-                username: pools.data.pool3user,
-                password: None,
-            });
-            Ok(ret)
+            Ok(vec![
+                Pool {
+                    url: pools.data.pool1url,
+                    username: pools.data.pool1user,
+                    password: None,
+                },
+                Pool {
+                    url: pools.data.pool2url,
+                    username: pools.data.pool2user,
+                    password: None,
+                },
+                Pool {
+                    url: pools.data.pool3url,
+                    username: pools.data.pool3user,
+                    password: None,
+                },
+            ])
         } else {
             Err(Error::HttpRequestFailed)
         }
     }
 
     async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
-        let resp = self.client.http_client
-            .post(&format!("https://{}/api/v1/cgminer/changePool", self.ip))
-            .bearer_auth(&self.token)
-            .json(&cgminer::SetPoolRequest {
-                pool1url: &pools[0].url,
-                pool1user: &pools[0].username,
-                pool1pwd: if let Some(pwd) = &pools[0].password {&pwd} else {""},
-                pool2url: &pools[1].url,
-                pool2user: &pools[1].username,
-                pool2pwd: if let Some(pwd) = &pools[1].password {&pwd} else {""},
-                pool3url: &pools[2].url,
-                pool3user: &pools[2].username,
-                pool3pwd: if let Some(pwd) = &pools[2].password {&pwd} else {""},
-            })
-            .send()
-            .await?;
+        if pools.len() > MAX_POOLS {
+            return Err(Error::TooManyPools { given: pools.len(), max: MAX_POOLS });
+        }
+        // Unused slots are sent empty
+        let empty = Pool::default();
+        let pool = |i: usize| pools.get(i).unwrap_or(&empty);
+        let pwd = |i: usize| pool(i).password.as_ref().map(|p| p.expose()).unwrap_or_default();
+        let req = cgminer::SetPoolRequest {
+            pool1url: &pool(0).url,
+            pool1user: &pool(0).username,
+            pool1pwd: pwd(0),
+            pool2url: &pool(1).url,
+            pool2user: &pool(1).username,
+            pool2pwd: pwd(1),
+            pool3url: &pool(2).url,
+            pool3user: &pool(2).username,
+            pool3pwd: pwd(2),
+        };
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("https://{}/api/v1/cgminer/changePool", self.ip))
+            .bearer_auth(token)
+            .json(&req)
+        ).await?;
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -569,11 +750,10 @@ impl Miner for Minerva {
     }
 
     async fn get_sleep(&self) -> Result<bool, Error> {
-        let resp1 = self.client.http_client
-            .get(&format!("https://{}/api/v1/cgminer/workMode", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp1 = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/cgminer/workMode", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp1.status().is_success() {
             let js = resp1.json::<serde_json::Value>().await?;
             if let Some(mask) = js["data"]["mask"].as_str() {
@@ -587,11 +767,10 @@ impl Miner for Minerva {
     }
 
     async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
-        let resp1 = self.client.http_client
-            .get(&format!("https://{}/api/v1/cgminer/workMode", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp1 = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/cgminer/workMode", self.ip))
+            .bearer_auth(token)
+        ).await?;
         //println!("{}", resp1.text().await.unwrap());
         let js = resp1.json::<serde_json::Value>().await?;
         let mut hash = js.as_object().ok_or(Error::ExpectedReturn)?.clone();
@@ -602,12 +781,11 @@ impl Miner for Minerva {
         data.remove("mask");
         data.insert("mask".to_string(), serde_json::Value::from(if sleep { "0x0" } else { "0xf" }));
         //println!("{:?}", data);
-        let resp = self.client.http_client
-            .post(&format!("https://{}/api/v1/cgminer/setWorkMode", self.ip))
-            .bearer_auth(&self.token)
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("https://{}/api/v1/cgminer/setWorkMode", self.ip))
+            .bearer_auth(token)
             .json(&data)
-            .send()
-            .await?;
+        ).await?;
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -616,11 +794,10 @@ impl Miner for Minerva {
     }
 
     async fn get_blink(&self) -> Result<bool, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/systemInfo/redLedStatus", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/systemInfo/redLedStatus", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let led = resp.json::<cgminer::LedResp>().await?;
             Ok(led.data.status == "1")
@@ -633,12 +810,11 @@ impl Miner for Minerva {
         let status = cgminer::LedStatus {
             status: (if blink { "1" } else { "0" }).to_string(),
         };
-        let resp = self.client.http_client
-            .post(&format!("https://{}/api/v1/systemInfo/setRedLedStatus", self.ip))
-            .bearer_auth(&self.token)
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("https://{}/api/v1/systemInfo/setRedLedStatus", self.ip))
+            .bearer_auth(token)
             .json(&status)
-            .send()
-            .await?;
+        ).await?;
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -647,11 +823,10 @@ impl Miner for Minerva {
     }
 
     async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/cgminer/log", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/cgminer/log", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let logs = resp.json::<cgminer::LogResp>().await?;
             Ok(logs.data)
@@ -661,11 +836,10 @@ impl Miner for Minerva {
     }
 
     async fn get_mac(&self) -> Result<String, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/systemInfo/network", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/systemInfo/network", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let network = resp.json::<cgminer::NetworkResponse>().await?;
             Ok(network.data.hardware_address)
@@ -675,18 +849,17 @@ impl Miner for Minerva {
     }
 
     async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
-        let r = self.client.http_client
-            .get(&format!("https://{}/api/v1/systemInfo/hashBoards", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let r = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/systemInfo/hashBoards", self.ip))
+            .bearer_auth(token)
+        ).await?;
         let boards = r.json::<cgminer::HashBoardsResp>().await?;
 
         let log = self.get_logs().await?.join("\n");
         let mut errors = HashSet::new();
         if let Some(boards) = boards.data {
             if boards.len() < 3 {
-                errors.insert(MinerError { msg: "Missing Board(s)".into(), error_type: ErrorType::HashBoard });
+                errors.insert(MinerError::new("Missing Board(s)", ErrorType::HashBoard));
             }
         }
         for err in MINERVA_ERRORS.iter() {
@@ -698,11 +871,10 @@ impl Miner for Minerva {
     }
 
     async fn get_dns(&self) -> Result<String, Error> {
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/systemInfo/network", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/systemInfo/network", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let network = resp.json::<cgminer::NetworkResponse>().await?;
             Ok(network.data.dns.clone())
@@ -711,26 +883,13 @@ impl Miner for Minerva {
         }
     }
 
-    async fn get_profile(&self) -> Result<Profile, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
-        Err(Error::NotSupported)
-    }
-
-    async fn set_profile(&mut self, _profile: Profile) -> Result<(), Error> {
-        Err(Error::NotSupported)
-    }
-
     async fn get_hashboard(&mut self) -> Result<String, Error> {
         // Reach back into historical logs for this
         let re = regex!(r#"type code:([\w\d]+)"#);
-        let resp = self.client.http_client
-            .get(&format!("https://{}/api/v1/cgminer/historyLog", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("https://{}/api/v1/cgminer/historyLog", self.ip))
+            .bearer_auth(token)
+        ).await?;
         if resp.status().is_success() {
             let text = resp.text().await?;
             if let Some(caps) = re.captures(&text) {
@@ -742,4 +901,79 @@ impl Miner for Minerva {
             Err(Error::HttpRequestFailed)
         }
     }
+
+    /// Through the cgminer socket API, lasts until cgminer restarts
+    async fn set_board_enabled(&mut self, chain: usize, enabled: bool) -> Result<(), Error> {
+        common::set_asc_enabled(&self.client, &self.ip, self.port, chain, enabled).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{ScriptedHttp, ScriptedSocket};
+
+    const CG_STATS: &str = include_str!("../../../fixtures/detection/minerva/mv7_cgminer_410.socket.json");
+
+    fn scripted(socket: ScriptedSocket, http: ScriptedHttp) -> Minera {
+        let client = crate::ClientBuilder::new()
+            .socket_transport(socket)
+            .http_transport(http)
+            .build()
+            .unwrap();
+        Minera::new(client, "127.0.0.1".into(), 4028)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_recover_healthy() {
+        let http = ScriptedHttp::new();
+        let mut miner = scripted(ScriptedSocket::new().reply("\"stats\"", CG_STATS), http.clone());
+        assert!(!miner.recover().await.unwrap());
+        assert!(http.requests().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_recover_wedged() {
+        let http = ScriptedHttp::new()
+            .reply(reqwest::Method::GET, "/index.php/app/stop_miner", 200, "")
+            .reply(reqwest::Method::GET, "/index.php/app/start_miner", 200, "");
+        let mut miner = scripted(ScriptedSocket::new().hang("\"stats\""), http.clone());
+        assert!(matches!(miner.get_cg_stats().await, Err(Error::ApiDeadlocked)));
+        // Still wedged after the restart
+        assert!(matches!(miner.recover().await, Err(Error::VerificationTimeout(_))));
+        let paths: Vec<_> = http.requests().into_iter().map(|(_, p)| p).collect();
+        assert_eq!(paths, ["/index.php/app/stop_miner", "/index.php/app/start_miner"]);
+    }
+
+    #[tokio::test]
+    async fn test_login_keeps_secrets() {
+        let http = ScriptedHttp::new()
+            .reply(reqwest::Method::POST, "/api/v1/auth/login", 200, r#"{"code":200,"data":{"accessToken":"abc.def.ghi"},"message":"ok"}"#);
+        let client = crate::ClientBuilder::new().http_transport(http.clone()).build().unwrap();
+        let mut miner = Minerva::new(client, "127.0.0.1".into(), 4028);
+        miner.auth("admin", "hunter2").await.unwrap();
+        // Sent as is, but kept out of Debug
+        assert!(http.bodies("/api/v1/auth/login")[0].contains(r#""password":"hunter2""#));
+        let session = miner.session.lock().await;
+        assert!(!format!("{:?}", session.as_ref().unwrap().token).contains("abc"));
+    }
+
+    #[tokio::test]
+    async fn test_set_pools_pads() {
+        let http = ScriptedHttp::new()
+            .reply(reqwest::Method::POST, "/api/v1/auth/login", 200, r#"{"code":200,"data":{"accessToken":"abc.def.ghi"},"message":"ok"}"#)
+            .reply(reqwest::Method::POST, "/api/v1/cgminer/changePool", 200, r#"{"code":200,"message":"ok"}"#);
+        let client = crate::ClientBuilder::new().http_transport(http.clone()).build().unwrap();
+        let mut miner = Minerva::new(client, "127.0.0.1".into(), 4028);
+        miner.auth("admin", "hunter2").await.unwrap();
+
+        let pool = Pool { url: "stratum+tcp://pool:3333".into(), username: "acct.1".into(), password: Some("x".into()) };
+        miner.set_pools(vec![pool.clone()]).await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&http.bodies("/api/v1/cgminer/changePool")[0]).unwrap();
+        assert_eq!(body["pool1user"], "acct.1");
+        assert_eq!(body["pool1pwd"], "x");
+        assert_eq!(body["pool3url"], "");
+
+        assert!(matches!(miner.set_pools(vec![pool; 4]).await, Err(Error::TooManyPools { given: 4, max: 3 })));
+    }
 }
@@ -1,4 +1,7 @@
+// Both web APIs are declared as documented, most of each reply goes unread
+#[allow(dead_code)]
 mod minera;
+#[allow(dead_code)]
 mod cgminer;
 
 mod minerva;
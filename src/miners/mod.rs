@@ -1,4 +1,10 @@
+#[macro_use]
+mod scaffold;
 pub mod common;
+pub mod cgminer;
+pub mod dispatch;
+#[cfg(feature = "http")]
+pub mod recovery;
 
 #[cfg(feature = "avalon")]
 pub mod avalon;
@@ -0,0 +1,214 @@
+use async_trait::async_trait;
+use lazy_regex::regex;
+use reqwest::multipart::{Form, Part};
+use scraper::{Html, Selector};
+
+use crate::{Client, DetectionInfo, Miner, Pool};
+use crate::miner::Tags;
+use crate::detection::web_title;
+use crate::transport::SendVia;
+use crate::error::Error;
+
+/// Firmware a miner in recovery mode will take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryVendor {
+    Antminer,
+    Whatsminer,
+}
+
+/// Which vendor's recovery page this is, if it is one
+/// Recovery and rescue images serve a bare upload page titled as such
+pub fn recovery_vendor(body: &str) -> Option<RecoveryVendor> {
+    let title = web_title(body)?;
+    if !regex!(r"(?i)recovery|rescue").is_match(&title) {
+        return None;
+    }
+    if regex!(r"(?i)antminer|bitmain").is_match(body) {
+        Some(RecoveryVendor::Antminer)
+    } else if regex!(r"(?i)whatsminer|microbt").is_match(body) {
+        Some(RecoveryVendor::Whatsminer)
+    } else {
+        None
+    }
+}
+
+/// Action and file field of the page's upload form
+fn upload_form(body: &str) -> Option<(String, String)> {
+    let html = Html::parse_document(body);
+    let form_selector = Selector::parse(r#"form[enctype="multipart/form-data"]"#).unwrap();
+    let file_selector = Selector::parse(r#"input[type="file"]"#).unwrap();
+    let form = html.select(&form_selector).next()?;
+    let action = form.value().attr("action").unwrap_or("/").to_string();
+    let field = form.select(&file_selector).next()?.value().attr("name")?.to_string();
+    Some((action, field))
+}
+
+/// A miner booted into its recovery image, it can only be reflashed or rebooted
+/// Recovery pages don't ask for credentials
+pub struct RecoveryMiner {
+    ip: String,
+    client: Client,
+    vendor: Option<RecoveryVendor>,
+    upload: Option<(String, String)>,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
+}
+
+impl RecoveryMiner {
+    /// None if the page isn't a recovery page
+    pub fn from_page(client: Client, ip: String, body: &str) -> Option<Self> {
+        let vendor = recovery_vendor(body)?;
+        Some(RecoveryMiner {
+            ip,
+            client,
+            vendor: Some(vendor),
+            upload: upload_form(body),
+            detection: None,
+            tags: Tags::new(),
+        })
+    }
+
+    pub fn vendor(&self) -> Option<RecoveryVendor> {
+        self.vendor
+    }
+
+    /// Upload a firmware image through the recovery page, the miner reboots into it once written
+    pub async fn reflash(&mut self, filename: &str, image: Vec<u8>) -> Result<(), Error> {
+        let (action, field) = match &self.upload {
+            Some(upload) => upload.clone(),
+            None => match self.vendor {
+                Some(RecoveryVendor::Antminer) => ("/cgi-bin/upgrade.cgi".to_string(), "datafile".to_string()),
+                _ => return Err(Error::NotSupported),
+            },
+        };
+        let url = match action.strip_prefix('/') {
+            Some(path) => format!("http://{}/{}", self.ip, path),
+            None if action.starts_with("http") => action,
+            None => format!("http://{}/{}", self.ip, action),
+        };
+        let form = Form::new().part(field, Part::bytes(image).file_name(filename.to_string()));
+        let resp = self.client.http_client.post(&url).multipart(form).send_via(&self.client).await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::HttpRequestFailed)
+        }
+    }
+}
+
+#[async_trait]
+impl Miner for RecoveryMiner {
+    fn new(client: Client, ip: String, _port: u16) -> Self {
+        RecoveryMiner {
+            ip,
+            client,
+            vendor: None,
+            upload: None,
+            detection: None,
+            tags: Tags::new(),
+        }
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn get_type(&self) -> &'static str {
+        "Recovery"
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
+    async fn get_model(&self) -> Result<String, Error> {
+        match self.vendor {
+            Some(RecoveryVendor::Antminer) => Ok("Antminer (recovery)".to_string()),
+            Some(RecoveryVendor::Whatsminer) => Ok("Whatsminer (recovery)".to_string()),
+            None => Err(Error::NotSupported),
+        }
+    }
+
+    async fn auth(&mut self, _username: &str, _password: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Reboot out of recovery, back into the installed firmware if it's intact
+    async fn reboot(&mut self) -> Result<(), Error> {
+        let path = match self.vendor {
+            Some(RecoveryVendor::Antminer) => "cgi-bin/reboot.cgi",
+            Some(RecoveryVendor::Whatsminer) => "cgi-bin/luci/admin/system/reboot",
+            None => return Err(Error::NotSupported),
+        };
+        // Like the normal firmware the recovery image often reboots before answering
+        match self.client.http_client.post(format!("http://{}/{}", self.ip, path)).send_via(&self.client).await {
+            Ok(resp) if !resp.status().is_success() => Err(Error::HttpRequestFailed),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_hashrate(&self) -> Result<f64, Error> {
+        Ok(0.0)
+    }
+
+    async fn get_power(&self) -> Result<f64, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_nameplate_rate(&self) -> Result<f64, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_temperature(&self) -> Result<f64, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn set_sleep(&mut self, _sleep: bool) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    async fn get_mac(&self) -> Result<String, Error> {
+        Err(Error::NotSupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_page() {
+        let page = r#"<html><head><title>Recovery Mode</title></head><body>
+            <h1>Antminer firmware recovery</h1>
+            <form action="/cgi-bin/upgrade.cgi" method="post" enctype="multipart/form-data">
+                <input type="file" name="firmware"><input type="submit" value="Upgrade">
+            </form></body></html>"#;
+        assert_eq!(recovery_vendor(page), Some(RecoveryVendor::Antminer));
+        assert_eq!(upload_form(page), Some(("/cgi-bin/upgrade.cgi".to_string(), "firmware".to_string())));
+
+        assert_eq!(recovery_vendor("<title>WhatsMiner Rescue</title> MicroBT"), Some(RecoveryVendor::Whatsminer));
+        assert_eq!(recovery_vendor("<title>WhatsMiner - LuCI</title>"), None);
+        assert_eq!(upload_form("<title>Recovery</title>"), None);
+    }
+}
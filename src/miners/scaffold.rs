@@ -0,0 +1,172 @@
+//! Starting point for a new vendor module
+//!
+//! `define_miner!` writes the struct, its cached endpoints and `clear_caches`, leaving only the
+//! fetches and the `Miner` impl to the vendor, see Avalon for one built on it:
+//!
+//! ```ignore
+//! define_miner! {
+//!     pub struct Acme {
+//!         port: u16,
+//!     }
+//!     cached {
+//!         summary: api::Summary = fetch_summary,
+//!         stats: api::Stats = fetch_stats,
+//!     }
+//!     errors = ACME_ERRORS;
+//! }
+//!
+//! impl Acme {
+//!     async fn fetch_summary(&self) -> Result<api::Summary, Error> { ... }
+//!     async fn fetch_stats(&self) -> Result<api::Stats, Error> { ... }
+//! }
+//!
+//! #[async_trait]
+//! impl Miner for Acme {
+//!     fn new(client: Client, ip: String, port: u16) -> Self {
+//!         Self { port, ..Self::scaffold(client, ip) }
+//!     }
+//!
+//!     fn get_type(&self) -> &'static str { "Acme" }
+//!     fn get_ip(&self) -> &str { &self.ip }
+//!     fn tags(&self) -> Tags { self.tags.clone() }
+//!     fn set_tags(&mut self, tags: Tags) { self.tags = tags; }
+//!     async fn invalidate(&self) { self.clear_caches().await; }
+//!
+//!     // get_model, auth, reboot, get_hashrate, get_power, get_nameplate_rate, get_temperature,
+//!     // get_fan_speed, get_pools, set_sleep and get_mac have no default, read them off the caches:
+//!     async fn get_hashrate(&self) -> Result<f64, Error> {
+//!         let summary = self.summary().await?;
+//!         let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+//!         Ok(summary.ths)
+//!     }
+//!
+//!     async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
+//!         Ok(Self::errors_in(&self.get_logs().await?.join("\n")))
+//!     }
+//!
+//!     // Anything the firmware can't do is left out, the trait answers Error::NotSupported.
+//!     // Datasheet numbers go in get_rated_efficiency and get_max_fan_speed, which get_efficiency,
+//!     // get_nameplate_power and get_fan_pwm are worked out from.
+//! }
+//! ```
+//!
+//! Setters should call `self.invalidate().await` after a successful write so the next read refetches.
+
+/// Vendor struct with ip, client, detection and a Mutex<Option<T>> per cached endpoint
+/// Each cache gets an accessor of the same name that calls its fetch on a miss
+#[allow(unused_macros)]
+macro_rules! define_miner {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$fmeta:meta])* $field:ident: $fty:ty),* $(,)?
+        }
+        cached {
+            $($cache:ident: $cty:ty = $fetch:ident),* $(,)?
+        }
+        $(errors = $errors:path;)?
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            ip: String,
+            client: $crate::Client,
+            detection: Option<$crate::DetectionInfo>,
+            tags: $crate::Tags,
+            $($(#[$fmeta])* $field: $fty,)*
+            $($cache: tokio::sync::Mutex<Option<$cty>>,)*
+        }
+
+        #[allow(dead_code)]
+        impl $name {
+            /// Everything but ip and client at its default, for `Miner::new` to fill in the rest
+            fn scaffold(client: $crate::Client, ip: String) -> Self {
+                Self {
+                    ip,
+                    client,
+                    detection: None,
+                    tags: $crate::Tags::new(),
+                    $($field: Default::default(),)*
+                    $($cache: tokio::sync::Mutex::new(None),)*
+                }
+            }
+
+            $(
+                async fn $cache(&self) -> Result<tokio::sync::MutexGuard<'_, Option<$cty>>, $crate::error::Error> {
+                    let mut cached = self.$cache.lock().await;
+                    if cached.is_none() {
+                        *cached = Some(self.$fetch().await?);
+                    }
+                    Ok(cached)
+                }
+            )*
+
+            /// Drop every cached response, what the vendor's Miner::invalidate does
+            async fn clear_caches(&self) {
+                $(let _ = self.$cache.lock().await.take();)*
+            }
+
+            $(
+                /// Every entry of the error table found in a log
+                fn errors_in(log: &str) -> Vec<$crate::MinerError> {
+                    let errors: std::collections::HashSet<_> = $errors.iter()
+                        .filter_map(|e| e.get_err(log))
+                        .collect();
+                    errors.into_iter().collect()
+                }
+            )?
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use lazy_regex::regex;
+    use crate::error::Error;
+    use crate::miner::{IntMinerError, ErrorType};
+
+    static TEST_ERRORS: [IntMinerError; 2] = [
+        IntMinerError {
+            re: regex!(r"fan (\d) lost"),
+            msg: "Fan {} lost",
+            error_type: ErrorType::Fan,
+        },
+        IntMinerError {
+            re: regex!(r"psu fault"),
+            msg: "Power supply fault",
+            error_type: ErrorType::Power,
+        },
+    ];
+
+    define_miner! {
+        struct TestMiner {
+            port: u16,
+        }
+        cached {
+            summary: String = fetch_summary,
+        }
+        errors = TEST_ERRORS;
+    }
+
+    impl TestMiner {
+        async fn fetch_summary(&self) -> Result<String, Error> {
+            Ok(format!("{}:{}", self.ip, self.port))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_define_miner() {
+        let miner = TestMiner { port: 4028, ..TestMiner::scaffold(crate::ClientBuilder::new().build().unwrap(), "10.0.0.1".into()) };
+        assert_eq!(miner.port, 4028);
+        assert!(miner.summary.try_lock().unwrap().is_none());
+
+        assert_eq!(miner.summary().await.unwrap().as_deref(), Some("10.0.0.1:4028"));
+        assert!(miner.summary.try_lock().unwrap().is_some());
+        miner.clear_caches().await;
+        assert!(miner.summary.try_lock().unwrap().is_none());
+
+        let errors = TestMiner::errors_in("chain 1 ok\nfan 2 lost\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].msg, "Fan 2 lost");
+        assert_eq!(errors[0].error_type, ErrorType::Fan);
+    }
+}
@@ -20,9 +20,31 @@ pub struct System {
     pub mem_buf: usize,
     pub mem_buf_percent: u8,
     pub network_status: Network,
+    /// As printed by uptime, e.g. "9 days,  3:58"
     pub uptime: String,
 }
 
+impl System {
+    pub fn uptime_secs(&self) -> Option<u64> {
+        parse_uptime(&self.uptime)
+    }
+}
+
+fn parse_uptime(uptime: &str) -> Option<u64> {
+    let mut secs = 0;
+    for part in uptime.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        secs += match part.split_once(' ') {
+            Some((n, unit)) if unit.starts_with("day") => n.parse::<u64>().ok()? * 86400,
+            Some((n, unit)) if unit.starts_with("min") => n.parse::<u64>().ok()? * 60,
+            _ => {
+                let (h, m) = part.split_once(':')?;
+                h.parse::<u64>().ok()? * 3600 + m.parse::<u64>().ok()? * 60
+            },
+        };
+    }
+    Some(secs)
+}
+
 #[derive(Deserialize)]
 pub struct Info {
     pub miner: String,
@@ -33,4 +55,18 @@ pub struct Info {
     pub install_type: String,
     pub build_time: String,
     pub system: System,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uptime_secs() {
+        let system: System = serde_json::from_str(r#"{"os":"GNU/Linux","file_system_version":"","mem_total":233712,"mem_free":195048,"mem_free_percent":83,"mem_buf":19668,"mem_buf_percent":8,"network_status":{"mac":"4E:9F:85:7B:57:7C","ip":"10.138.11.63","netmask":"255.255.254.0","gateway":"10.138.11.254","dns":[],"hostname":"Antminer"},"uptime":"9 days,  3:58"}"#).unwrap();
+        assert_eq!(system.uptime_secs(), Some(9 * 86400 + 3 * 3600 + 58 * 60));
+        assert_eq!(parse_uptime("1 day, 12 min"), Some(86400 + 720));
+        assert_eq!(parse_uptime("45 min"), Some(2700));
+        assert_eq!(parse_uptime("bogus"), None);
+    }
+}
@@ -4,10 +4,14 @@ mod status;
 mod summary;
 mod presets;
 mod ui;
+mod patch;
+mod version;
 
 pub use info::*;
 pub use settings::*;
 pub use status::*;
 pub use summary::*;
 pub use presets::*;
-pub use ui::*;
\ No newline at end of file
+pub use ui::*;
+pub use patch::*;
+pub use version::*;
@@ -0,0 +1,226 @@
+use serde::Serialize;
+
+use crate::Pool;
+use crate::error::Error;
+use super::{ChainSettings, CoolingMode, CoolingSettings, GlobalOverclockSettings, NetworkSettings, VPool, Consts};
+
+/// Partial update for /api/v1/settings, only what was set gets sent
+#[derive(Serialize, Default)]
+pub struct SettingsPatch {
+    #[serde(skip_serializing_if = "MinerPatch::is_empty")]
+    miner: MinerPatch,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network: Option<NetworkSettings>,
+}
+
+#[derive(Serialize, Default)]
+struct MinerPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cooling: Option<CoolingSettings>,
+    #[serde(skip_serializing_if = "OverclockPatch::is_empty")]
+    overclock: OverclockPatch,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pools: Option<Vec<VPool>>,
+    #[serde(skip_serializing_if = "MiscPatch::is_empty")]
+    misc: MiscPatch,
+}
+
+impl MinerPatch {
+    fn is_empty(&self) -> bool {
+        self.cooling.is_none() && self.overclock.is_empty() && self.pools.is_none() && self.misc.is_empty()
+    }
+}
+
+#[derive(Serialize, Default)]
+struct OverclockPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    globals: Option<GlobalOverclockSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chains: Option<Vec<ChainSettings>>,
+}
+
+impl OverclockPatch {
+    fn is_empty(&self) -> bool {
+        self.preset.is_none() && self.globals.is_none() && self.chains.is_none()
+    }
+}
+
+#[derive(Serialize, Default)]
+struct MiscPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asic_boost: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart_temp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_restart_attempts: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disable_restart_unbalanced: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    disable_chain_break_protection: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quick_start: Option<bool>,
+}
+
+impl MiscPatch {
+    fn is_empty(&self) -> bool {
+        self.asic_boost.is_none()
+            && self.restart_temp.is_none()
+            && self.max_restart_attempts.is_none()
+            && self.disable_restart_unbalanced.is_none()
+            && self.disable_chain_break_protection.is_none()
+            && self.quick_start.is_none()
+    }
+}
+
+impl SettingsPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.miner.is_empty() && self.network.is_none()
+    }
+
+    pub fn cooling(mut self, mode: CoolingMode) -> Self {
+        self.miner.cooling = Some(CoolingSettings { mode });
+        self
+    }
+
+    /// Name of a preset from /api/v1/autotune/presets, or "disabled" for the globals
+    pub fn preset(mut self, name: &str) -> Self {
+        self.miner.overclock.preset = Some(name.to_string());
+        self
+    }
+
+    /// Voltage in mV and frequency in MHz, used while the preset is "disabled"
+    pub fn globals(mut self, volt: u32, freq: u32) -> Self {
+        self.miner.overclock.globals = Some(GlobalOverclockSettings { freq, volt });
+        self
+    }
+
+    /// Vnish replaces the whole list, so every chain has to be given
+    pub fn chains(mut self, chains: Vec<ChainSettings>) -> Self {
+        self.miner.overclock.chains = Some(chains);
+        self
+    }
+
+    /// In priority order
+    pub fn pools(mut self, pools: &[Pool]) -> Self {
+        self.miner.pools = Some(pools.iter().enumerate().map(|(i, p)| VPool {
+            url: p.url.clone(),
+            user: p.username.clone(),
+            pass: p.password.clone().unwrap_or_default(),
+            order: i,
+        }).collect());
+        self
+    }
+
+    pub fn asic_boost(mut self, enabled: bool) -> Self {
+        self.miner.misc.asic_boost = Some(enabled);
+        self
+    }
+
+    /// Chip temperature in C the miner restarts at
+    pub fn restart_temp(mut self, temp: i64) -> Self {
+        self.miner.misc.restart_temp = Some(temp);
+        self
+    }
+
+    /// 0 for unlimited
+    pub fn max_restart_attempts(mut self, attempts: usize) -> Self {
+        self.miner.misc.max_restart_attempts = Some(attempts);
+        self
+    }
+
+    pub fn disable_restart_unbalanced(mut self, disable: bool) -> Self {
+        self.miner.misc.disable_restart_unbalanced = Some(disable);
+        self
+    }
+
+    pub fn disable_chain_break_protection(mut self, disable: bool) -> Self {
+        self.miner.misc.disable_chain_break_protection = Some(disable);
+        self
+    }
+
+    pub fn quick_start(mut self, enabled: bool) -> Self {
+        self.miner.misc.quick_start = Some(enabled);
+        self
+    }
+
+    /// The whole network section, see Settings::network for the current one
+    pub fn network(mut self, network: NetworkSettings) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Check against the limits the miner's UI enforces
+    pub fn validate(&self, consts: &Consts) -> Result<(), Error> {
+        if let Some(cooling) = &self.miner.cooling {
+            match cooling.mode {
+                CoolingMode::Manual(pwm) if (pwm as f64) < consts.cooling.min_fan_pwm || pwm > 100 => {
+                    return Err(Error::InvalidConfig(format!("Fan pwm {} outside {}-100", pwm, consts.cooling.min_fan_pwm)));
+                },
+                CoolingMode::Auto(temp) if (temp as f64) < consts.cooling.min_target_temp || (temp as f64) > consts.cooling.max_target_temp => {
+                    return Err(Error::InvalidConfig(format!("Target temp {} outside {}-{}", temp, consts.cooling.min_target_temp, consts.cooling.max_target_temp)));
+                },
+                _ => {},
+            }
+        }
+        if let Some(globals) = &self.miner.overclock.globals {
+            let oc = &consts.overclock;
+            if globals.volt < oc.min_voltage || globals.volt > oc.max_voltage {
+                return Err(Error::InvalidConfig(format!("Voltage {} outside {}-{}", globals.volt, oc.min_voltage, oc.max_voltage)));
+            }
+            if globals.freq < oc.min_freq || globals.freq > oc.max_freq {
+                return Err(Error::InvalidConfig(format!("Frequency {} outside {}-{}", globals.freq, oc.min_freq, oc.max_freq)));
+            }
+        }
+        if self.miner.pools.as_ref().is_some_and(|p| p.is_empty()) {
+            return Err(Error::InvalidConfig("At least one pool is needed".into()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn consts() -> Consts {
+        serde_json::from_value(json!({
+            "cooling": {"min_fan_pwm": 10, "min_target_temp": 20, "max_target_temp": 100},
+            "overclock": {"max_voltage": 1535, "min_voltage": 1200, "default_voltage": 1340, "max_freq": 1000,
+                "min_freq": 50, "default_freq": 600, "warn_freq": 750, "max_voltage_stock_psu": 1500},
+            "timezones": [],
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_settings_patch() {
+        assert!(SettingsPatch::new().is_empty());
+        assert_eq!(serde_json::to_value(SettingsPatch::new()).unwrap(), json!({}));
+
+        let patch = SettingsPatch::new().preset("disabled").globals(1400, 610).quick_start(true);
+        assert_eq!(serde_json::to_value(&patch).unwrap(), json!({
+            "miner": {
+                "overclock": {"preset": "disabled", "globals": {"freq": 610, "volt": 1400}},
+                "misc": {"quick_start": true},
+            },
+        }));
+        assert!(patch.validate(&consts()).is_ok());
+
+        let patch = SettingsPatch::new().cooling(CoolingMode::Manual(5));
+        assert_eq!(serde_json::to_value(&patch).unwrap(), json!({"miner": {"cooling": {"mode": {"name": "manual", "param": 5}}}}));
+        assert!(matches!(patch.validate(&consts()), Err(Error::InvalidConfig(_))));
+        assert!(SettingsPatch::new().globals(1600, 610).validate(&consts()).is_err());
+
+        // The password goes to the miner in full
+        let pool = Pool { url: "stratum+tcp://pool:3333".into(), username: "acct.1".into(), password: Some("x123".into()) };
+        assert_eq!(serde_json::to_value(SettingsPatch::new().pools(&[pool])).unwrap(), json!({
+            "miner": {"pools": [{"url": "stratum+tcp://pool:3333", "user": "acct.1", "pass": "x123", "order": 0}]},
+        }));
+    }
+}
@@ -7,22 +7,31 @@ use crate::miner::Profile;
 pub struct Preset {
     pub name: String,
     pub pretty: String,
+    /// 1.1 and later
+    #[serde(default)]
     pub status: String,
+    #[serde(default)]
     pub modded_psu_required: bool,
 }
 
-impl Into<Profile> for Preset {
-    fn into(self) -> Profile {
-        match self.name.as_str() {
+impl From<Preset> for Profile {
+    fn from(val: Preset) -> Self {
+        match val.name.as_str() {
             "disabled" => Profile::Default,
             _ => {
-                // 3800 watt ~ 106 Th
-                let re = regex!(r"(\d+) Th");
-                let caps = re.captures(&self.pretty).unwrap();
-                Profile::Preset {
-                    power: self.name.parse::<f64>().unwrap(),
-                    name: self.name,
-                    ths: caps.get(1).unwrap().as_str().parse::<f64>().unwrap(),
+                // 3800 watt ~ 106 Th, the name is usually the wattage but newer firmware adds suffixes
+                let number = |re: &lazy_regex::Regex, s: &str| re.captures(s).and_then(|caps| caps[1].parse::<f64>().ok());
+                let power = number(regex!(r"(\d+)\s*watt"), &val.pretty)
+                    .or_else(|| number(regex!(r"^(\d+)"), &val.name));
+                let ths = number(regex!(r"(\d+) Th"), &val.pretty).unwrap_or_default();
+                match power {
+                    Some(power) => Profile::Preset {
+                        power,
+                        name: val.name,
+                        ths,
+                    },
+                    // Still settable by name, but there's no power to match or rank it by
+                    None => Profile::Unknown { name: val.name },
                 }
             },
         }
@@ -70,4 +79,14 @@ mod tests {
             _ => unreachable!(),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_preset_names() {
+        let preset = |name: &str, pretty: &str| -> Profile {
+            Preset { name: name.into(), pretty: pretty.into(), status: String::new(), modded_psu_required: false }.into()
+        };
+        assert_eq!(preset("3800_lc", "3800 watt ~ 110 Th LC"), Profile::Preset { name: "3800_lc".into(), power: 3800.0, ths: 110.0 });
+        assert_eq!(preset("3460", "Liquid cooled"), Profile::Preset { name: "3460".into(), power: 3460.0, ths: 0.0 });
+        assert_eq!(preset("eco", "Eco"), Profile::Unknown { name: "eco".into() });
+    }
+}
@@ -1,13 +1,16 @@
+use chrono::FixedOffset;
 use serde::{Deserialize, de::Deserializer, Serialize, ser::Serializer};
 
 use crate::Pool;
+use crate::util::secret::{self, SecretString};
 use super::UI;
 
 #[derive(Serialize)]
 pub struct VPool {
     pub url: String,
     pub user: String,
-    pub pass: String,
+    #[serde(serialize_with = "secret::expose")]
+    pub pass: SecretString,
     pub order: usize,
 }
 
@@ -82,7 +85,9 @@ pub struct MiscSettings {
     pub bitmain_disable_volt_comp: bool,
     pub quick_start: bool,
     pub higher_volt_offset: usize,
-    pub tuner_bad_chip_hr_threshold: usize,
+    /// Added with autotune in 1.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tuner_bad_chip_hr_threshold: Option<usize>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -95,6 +100,8 @@ pub struct GlobalOverclockSettings {
 pub struct ChainSettings {
     pub freq: usize,
     pub chips: Vec<usize>,
+    #[serde(default)]
+    pub disabled: bool,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -132,20 +139,30 @@ pub struct TzSettings {
     pub current: String,
 }
 
+impl TzSettings {
+    /// "GMT", "GMT+3" or "GMT-11", as labelled in the UI
+    pub fn utc_offset(&self) -> Option<FixedOffset> {
+        let hours = match self.current.strip_prefix("GMT")? {
+            "" => 0,
+            hours => hours.parse::<i32>().ok()?,
+        };
+        FixedOffset::east_opt(hours * 3600)
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct RegionalSettings {
     pub timezone: TzSettings,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct NetworkSettings {
-    pub mac: String,
-    pub ip: String,
-    pub netmask: String,
-    pub gateway: String,
-    pub dns: Vec<String>,
     pub hostname: String,
     pub dhcp: bool,
+    pub ipaddress: String,
+    pub netmask: String,
+    pub gateway: String,
+    pub dnsservers: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -165,23 +182,61 @@ pub struct Settings {
     pub miner: MinerSettings,
     // ui - UI shit we don't care about
     pub regional: RegionalSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSettings>,
     pub ssh: SshSettings,
     pub password: Option<PasswordSettings>,
     #[serde(skip_serializing)]
     pub ui: UI,
 }
 
+/// Reply to a settings write
+#[derive(Deserialize)]
+struct SettingsResult {
+    restart_required: Option<bool>,
+    reboot_required: Option<bool>,
+}
+
+/// Whether a settings write waits on a restart, taken as yes when the reply doesn't say
+pub fn restart_needed(body: &str) -> bool {
+    match serde_json::from_str::<SettingsResult>(body) {
+        Ok(SettingsResult { restart_required: None, reboot_required: None }) | Err(_) => true,
+        Ok(result) => result.restart_required.unwrap_or(false) || result.reboot_required.unwrap_or(false),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::from_str;
 
+    #[test]
+    fn test_utc_offset() {
+        let tz = |current: &str| TzSettings { current: current.to_string() }.utc_offset();
+        assert_eq!(tz("GMT+3"), FixedOffset::east_opt(3 * 3600));
+        assert_eq!(tz("GMT-11"), FixedOffset::east_opt(-11 * 3600));
+        assert_eq!(tz("Europe/Berlin"), None);
+    }
+
     #[test]
     fn test() {
-        let s = r#"{"miner":{"cooling":{"mode":{"name":"auto","param":60}},"devfee":{"region":"auto"},"misc":{"asic_boost":false,"restart_hashrate":0,"restart_temp":85,"disable_restart_unbalanced":false,"disable_chain_break_protection":false,"max_restart_attempts":0,"bitmain_disable_volt_comp":false,"quick_start":false,"higher_volt_offset":100,"tuner_bad_chip_hr_threshold":50},"overclock":{"modded_psu":false,"preset":"3486","globals":{"volt":1400,"freq":610},"chains":[{"freq":0,"chips":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"disabled":false},{"freq":0,"chips":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"disabled":false},{"freq":0,"chips":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"disabled":false}]},"pools":[{"url":"btc.foundryusapool.com:3333","user":"pct19.47.4x243","pass":""},{"url":"btc.foundryusapool.com:443","user":"pct19.47.4x243","pass":""},{"url":"btc.foundryusapool.com:25","user":"pct19.47.4x243","pass":""}],"hotel_fee":{"enable":false,"pool":{"url":"stratum.slushpool.com:3333","worker":"ahx.hotelfee","percent":1.0}}},"ui":{"theme":"auto","dark_side_pane":false,"disable_animation":false,"locale":"en","timezone":"GMT","consts":{"cooling":{"min_fan_pwm":10,"min_target_temp":20,"max_target_temp":100},"overclock":{"max_voltage":1535,"min_voltage":1200,"default_voltage":1340,"max_freq":1000,"min_freq":50,"default_freq":600,"warn_freq":750,"max_voltage_stock_psu":1500},"timezones":[["GMT-11","GMT-11"],["GMT-10","GMT-10"],["GMT-9","GMT-09"],["GMT-8","GMT-08"],["GMT-7","GMT-07"],["GMT-6","GMT-06"],["GMT-5","GMT-05"],["GMT-4","GMT-04"],["GMT-3","GMT-03"],["GMT-2","GMT-02"],["GMT-1","GMT-01"],["GMT","GMT"],["GMT+1","GMT+01"],["GMT+2","GMT+02"],["GMT+3","GMT+03"],["GMT+4","GMT+04"],["GMT+5","GMT+05"],["GMT+6","GMT+06"],["GMT+7","GMT+07"],["GMT+8","GMT+08"],["GMT+9","GMT+09"],["GMT+10","GMT+10"],["GMT+11","GMT+11"],["GMT+12","GMT+12"]]}},"regional":{"timezone":{"current":"GMT"}},"network":{"hostname":"Antminer","dhcp":true,"ipaddress":"192.168.15.44","netmask":"255.255.255.0","gateway":"192.168.15.1","dnsservers":["192.168.15.1","1.1.1.1"]},"ssh":{"enabled":true,"port":22},"password":null,"layout":null,"boot":null}"#;
+        let s = include_str!("../../../../fixtures/vnish/settings.json");
         let settings: Settings = from_str(s).unwrap();
         assert_eq!(settings.miner.pools.len(), 3);
         assert_eq!(settings.miner.pools[0].url, "btc.foundryusapool.com:3333");
         assert_eq!(settings.miner.pools[0].username, "pct19.47.4x243");
+        assert_eq!(settings.miner.overclock.chains.len(), 3);
+        assert!(!settings.miner.overclock.chains[0].disabled);
+        assert_eq!(settings.regional.timezone.utc_offset(), FixedOffset::east_opt(0));
+        assert_eq!(settings.network.unwrap().dnsservers, vec!["192.168.15.1", "1.1.1.1"]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_restart_needed() {
+        assert!(!restart_needed(r#"{"restart_required":false,"reboot_required":false}"#));
+        assert!(restart_needed(r#"{"restart_required":true,"reboot_required":false}"#));
+        assert!(restart_needed(r#"{"reboot_required":true}"#));
+        assert!(restart_needed("{}"));
+        assert!(restart_needed(""));
+    }
+}
@@ -0,0 +1,94 @@
+use lazy_regex::regex;
+use serde::Deserialize;
+
+use super::Presets;
+
+/// Vnish API revisions with differing endpoints or fields, ordered oldest first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    /// Presets at /api/v1/presets without status, no autotune settings
+    V1_0,
+    /// Presets moved under /api/v1/autotune
+    V1_1,
+    /// Presets wrapped in an object alongside the tuner state
+    V1_2,
+}
+
+/// 1.2 wraps the list alongside the tuner state, some 1.1 builds already do
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PresetsBody {
+    Plain(Presets),
+    Wrapped { presets: Presets },
+}
+
+impl ApiVersion {
+    /// From Info::fw_version, e.g. "1.2.0-rc5"
+    /// Anything newer than we know or unparseable is taken as the latest
+    pub fn from_fw_version(fw_version: &str) -> Self {
+        let re = regex!(r"(\d+)\.(\d+)");
+        let version = re.captures(fw_version)
+            .and_then(|caps| Some((caps[1].parse::<u32>().ok()?, caps[2].parse::<u32>().ok()?)));
+        match version {
+            Some((1, 0)) | Some((0, _)) => ApiVersion::V1_0,
+            Some((1, 1)) => ApiVersion::V1_1,
+            _ => ApiVersion::V1_2,
+        }
+    }
+
+    pub fn presets_path(&self) -> &'static str {
+        match self {
+            ApiVersion::V1_0 => "api/v1/presets",
+            ApiVersion::V1_1 | ApiVersion::V1_2 => "api/v1/autotune/presets",
+        }
+    }
+
+    /// Either shape is accepted whatever the version, the fw_version string doesn't always
+    /// match the API the build serves
+    pub fn parse_presets(&self, body: &str) -> Result<Presets, serde_json::Error> {
+        match serde_json::from_str::<PresetsBody>(body)? {
+            PresetsBody::Plain(presets) | PresetsBody::Wrapped { presets } => Ok(presets),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miners::vnish::api::Settings;
+
+    const SETTINGS_V1_0: &str = r#"{"miner":{"cooling":{"mode":{"name":"auto","param":60}},"devfee":{"region":"auto"},"misc":{"asic_boost":false,"restart_hashrate":0,"restart_temp":85,"disable_restart_unbalanced":false,"disable_chain_break_protection":false,"max_restart_attempts":0,"bitmain_disable_volt_comp":false,"quick_start":false,"higher_volt_offset":100},"overclock":{"preset":"disabled","globals":{"volt":1340,"freq":600},"chains":[]},"pools":[],"hotel_fee":{"enable":false,"pool":{"url":"","worker":"","percent":0.0}}},"ui":{"theme":"auto","dark_side_pane":false,"disable_animation":false,"locale":"en","timezone":"GMT","consts":{"cooling":{"min_fan_pwm":10,"min_target_temp":20,"max_target_temp":100},"overclock":{"max_voltage":1535,"min_voltage":1200,"default_voltage":1340,"max_freq":1000,"min_freq":50,"default_freq":600,"warn_freq":750,"max_voltage_stock_psu":1500},"timezones":[]}},"regional":{"timezone":{"current":"GMT"}},"network":{"hostname":"Antminer","dhcp":true,"ipaddress":"192.168.15.44","netmask":"255.255.255.0","gateway":"192.168.15.1","dnsservers":[]},"ssh":{"enabled":true,"port":22},"password":null,"layout":null,"boot":null}"#;
+    const PRESETS_V1_0: &str = include_str!("../../../../fixtures/vnish/presets_1.0.json");
+    const PRESETS_V1_1: &str = include_str!("../../../../fixtures/vnish/presets_1.1.json");
+    const PRESETS_V1_2: &str = include_str!("../../../../fixtures/vnish/presets_1.2.json");
+
+    #[test]
+    fn test_from_fw_version() {
+        assert_eq!(ApiVersion::from_fw_version("1.0.3"), ApiVersion::V1_0);
+        assert_eq!(ApiVersion::from_fw_version("1.1.0-rc2"), ApiVersion::V1_1);
+        assert_eq!(ApiVersion::from_fw_version("1.2.0"), ApiVersion::V1_2);
+        assert_eq!(ApiVersion::from_fw_version("1.4.1"), ApiVersion::V1_2);
+        assert_eq!(ApiVersion::from_fw_version("unknown"), ApiVersion::V1_2);
+    }
+
+    #[test]
+    fn test_presets_by_version() {
+        for (version, body) in [(ApiVersion::V1_0, PRESETS_V1_0), (ApiVersion::V1_1, PRESETS_V1_1), (ApiVersion::V1_2, PRESETS_V1_2)] {
+            let presets = version.parse_presets(body).unwrap();
+            assert_eq!(presets.len(), 4, "{:?}", version);
+            assert_eq!(presets[1].name, "2710");
+        }
+        // A build reporting one version while serving the other shape
+        assert_eq!(ApiVersion::V1_2.parse_presets(PRESETS_V1_1).unwrap().len(), 4);
+        assert_eq!(ApiVersion::V1_1.parse_presets(PRESETS_V1_2).unwrap().len(), 4);
+        assert!(ApiVersion::V1_2.parse_presets(r#"{"current":"2710"}"#).is_err());
+    }
+
+    #[test]
+    fn test_settings_v1_0() {
+        let settings: Settings = serde_json::from_str(SETTINGS_V1_0).unwrap();
+        assert_eq!(settings.miner.misc.tuner_bad_chip_hr_threshold, None);
+        // Not written back to firmware that doesn't know it
+        assert!(!serde_json::to_string(&settings.miner.misc).unwrap().contains("tuner_bad_chip_hr_threshold"));
+    }
+}
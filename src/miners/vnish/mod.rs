@@ -1,46 +1,69 @@
 use async_trait::async_trait;
 use lazy_regex::regex;
 use serde_json::json;
-use crate::{Client, Miner, error::Error, Pool, miner::Profile};
+use crate::{Client, Miner, error::Error, Pool, miner::Profile, Cache, CacheItem, SecretString, Capabilities, MinerConfigBackup, DetectionInfo};
 use tokio::sync::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-
+use std::net::IpAddr;
+use chrono::FixedOffset;
+use std::time::Duration;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+// The whole settings and status schema, not just the fields used here
+#[allow(dead_code)]
 mod api;
 mod error;
 
+pub use api::{SettingsPatch, CoolingMode, ChainSettings, NetworkSettings};
+
 use error::VNISH_ERRORS;
 
 use crate::miners::antminer::POWER_MAP;
-use crate::miner::MinerError;
+use crate::model::normalize_model;
+use crate::transport::SendVia;
+use crate::security::{self, SecurityIndicator};
+use crate::miner::{MinerError, PowerState, PendingChange, TuningStatus, SystemStats, CoolingDetail, ChipCount, Resource, stable_hashrate, performance_ratio, dump_entry, raw_json, Tags};
+use crate::util::ttl::{Expiring, TtlCell};
+
+/// Vnish doesn't tell us when a token expires, so only trust cached tokens for this long
+const TOKEN_TTL_MINUTES: i64 = 60;
+
+/// Status carries find_miner and the mining state, which change without us writing anything
+const STATUS_TTL: Duration = Duration::from_secs(5);
 
 pub struct Vnish {
     ip: String,
     _port: u16,
     client: Client,
-    token: String,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
+    password: SecretString,
+    token: Mutex<SecretString>,
+    cache: Option<Cache>,
 
-    status: Mutex<Option<api::MinerStatus>>,
+    status: TtlCell<api::MinerStatus>,
     settings: Mutex<Option<api::Settings>>,
     info: Mutex<Option<api::Info>>,
     summary: Mutex<Option<api::Summary>>,
     presets: Mutex<Option<Vec<Profile>>>,
+    /// Kept across invalidate, firmware only changes with an upgrade and a restart
+    version: Mutex<Option<api::ApiVersion>>,
+    /// Written since the last restart, the firmware only tells us that something is pending
+    pending: Vec<PendingChange>,
 }
 
 impl Vnish {
-    async fn get_status(&self) -> Result<MutexGuard<'_, Option<api::MinerStatus>>, Error> {
+    async fn get_status(&self) -> Result<MutexGuard<'_, Option<Expiring<api::MinerStatus>>>, Error> {
         let mut status = self.status.lock().await;
 
         if status.is_none() {
-            *status = Some(
-                self.client.http_client
-                    .get(&format!("http://{}/api/v1/status", self.ip))
-                    .bearer_auth(&self.token)
-                    .send()
-                    .await?
-                    .json::<api::MinerStatus>()
-                    .await?
-            );
+            let body = self.send(|token| self.client.http_client
+                .get(format!("http://{}/api/v1/status", self.ip))
+                .bearer_auth(token)
+            ).await?.text().await?;
+            *status = Some(Expiring::new(self.client.parse(&self.ip, "status", &body)?));
 
         }
 
@@ -51,15 +74,11 @@ impl Vnish {
         let mut settings = self.settings.lock().await;
 
         if settings.is_none() {
-            *settings = Some(
-                self.client.http_client
-                    .get(&format!("http://{}/api/v1/settings", self.ip))
-                    .bearer_auth(&self.token)
-                    .send()
-                    .await?
-                    .json::<api::Settings>()
-                    .await?
-            );
+            let body = self.send(|token| self.client.http_client
+                .get(format!("http://{}/api/v1/settings", self.ip))
+                .bearer_auth(token)
+            ).await?.text().await?;
+            *settings = Some(self.client.parse(&self.ip, "settings", &body)?);
         }
 
         Ok(settings)
@@ -69,15 +88,11 @@ impl Vnish {
         let mut info = self.info.lock().await;
 
         if info.is_none() {
-            *info = Some(
-                self.client.http_client
-                    .get(&format!("http://{}/api/v1/info", self.ip))
-                    .bearer_auth(&self.token)
-                    .send()
-                    .await?
-                    .json::<api::Info>()
-                    .await?
-            );
+            let body = self.send(|token| self.client.http_client
+                .get(format!("http://{}/api/v1/info", self.ip))
+                .bearer_auth(token)
+            ).await?.text().await?;
+            *info = Some(self.client.parse(&self.ip, "info", &body)?);
 
         }
 
@@ -88,27 +103,125 @@ impl Vnish {
         let mut summary = self.summary.lock().await;
 
         if summary.is_none() {
-            *summary = Some(
-                self.client.http_client
-                    .get(&format!("http://{}/api/v1/summary", self.ip))
-                    .bearer_auth(&self.token)
-                    .send()
-                    .await?
-                    .json::<api::Summary>()
-                    .await?
-            );
+            let body = self.send(|token| self.client.http_client
+                .get(format!("http://{}/api/v1/summary", self.ip))
+                .bearer_auth(token)
+            ).await?.text().await?;
+            *summary = Some(self.client.parse(&self.ip, "summary", &body)?);
 
         }
 
         Ok(summary)
     }
 
-    async fn invalidate(&self) -> Result<(), Error> {
-        *self.status.lock().await = None;
-        *self.settings.lock().await = None;
-        *self.info.lock().await = None;
-        *self.summary.lock().await = None;
+    /// Cache entries are keyed by the password as well as the IP, so a wrong password isn't let
+    /// in on a token someone else unlocked
+    fn cache_key(&self) -> String {
+        let digest = Sha256::digest(self.password.expose().as_bytes());
+        let mut fingerprint = [0u8; 8];
+        fingerprint.copy_from_slice(&digest[..8]);
+        format!("{}#{:016x}", self.ip, u64::from_be_bytes(fingerprint))
+    }
+
+    /// Unlock with the stored password, returning the new token
+    async fn unlock(&self) -> Result<SecretString, Error> {
+        #[derive(Deserialize)]
+        struct TokenResp {
+            pub token: SecretString,
+        }
+
+        #[derive(Serialize)]
+        struct UnlockReq<'a> {
+            pub pw: &'a str,
+        }
+
+        let resp = self.client.http_client
+            .post(format!("http://{}/api/v1/unlock", self.ip))
+            .json(&UnlockReq {
+                pw: self.password.expose(),
+            })
+            .send_via(&self.client)
+            .await?;
+
+        match resp.status() {
+            reqwest::StatusCode::OK => {},
+            reqwest::StatusCode::FORBIDDEN => return Err(Error::Unauthorized),
+            _ => return Err(Error::ApiCallFailed(format!("auth/unlock {:?}", resp.status()))),
+        }
+
+        let token = resp.json::<TokenResp>().await?.token;
+        if let Some(cache) = &self.cache {
+            cache.write().await.insert(
+                self.cache_key(),
+                CacheItem {
+                    token: token.clone(),
+                    token_expires: chrono::Utc::now() + chrono::Duration::minutes(TOKEN_TTL_MINUTES),
+                },
+            );
+        }
+        *self.token.lock().await = token.clone();
+        Ok(token)
+    }
+
+    /// Send an authenticated request, unlocking again and retrying once if the token was rejected,
+    /// as it is after the firmware restarts or the cached token has been revoked
+    async fn send<F>(&self, build: F) -> Result<reqwest::Response, Error>
+        where F: Fn(&str) -> reqwest::RequestBuilder
+    {
+        let token = self.token.lock().await.clone();
+        let resp = build(token.expose()).send_via(&self.client).await?;
+        if resp.status() != reqwest::StatusCode::UNAUTHORIZED || self.password.expose().is_empty() {
+            return Ok(resp);
+        }
+        let token = self.unlock().await?;
+        build(token.expose()).send_via(&self.client).await
+    }
+
+    /// API revision from the firmware version in /api/v1/info, which reads the same on every release
+    async fn api_version(&self) -> Result<api::ApiVersion, Error> {
+        let mut version = self.version.lock().await;
+        if version.is_none() {
+            let info = self.get_info().await?;
+            let fw_version = &info.as_ref().unwrap_or_else(|| unreachable!()).fw_version;
+            let detected = api::ApiVersion::from_fw_version(fw_version);
+            debug!("Vnish {} on {} uses API {:?}", fw_version, self.ip, detected);
+            *version = Some(detected);
+        }
+        Ok(version.unwrap_or_else(|| unreachable!()))
+    }
+
+    /// Write a settings patch, checked against the limits the miner reports first
+    /// True if the miner needs a restart for it to take effect, as recorded in pending_changes by the setters
+    pub async fn apply_settings(&mut self, patch: &SettingsPatch) -> Result<bool, Error> {
+        if patch.is_empty() {
+            return Ok(false);
+        }
+        {
+            let settings = self.get_settings().await?;
+            patch.validate(&settings.as_ref().unwrap_or_else(|| unreachable!()).ui.consts)?;
+        }
 
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("http://{}/api/v1/settings", self.ip))
+            .bearer_auth(token)
+            .json(patch)
+        ).await?;
+
+        if resp.status().is_success() {
+            let body = resp.text().await?;
+            debug!("Settings response from {}: {}", self.ip, self.client.payload(&body));
+            self.invalidate().await;
+            Ok(api::restart_needed(&body))
+        } else {
+            Err(Error::ApiCallFailed("settings".into()))
+        }
+    }
+
+    /// Apply a patch and record change in pending_changes if the firmware needs a restart for it
+    async fn apply_pending(&mut self, patch: &SettingsPatch, change: PendingChange) -> Result<(), Error> {
+        if self.apply_settings(patch).await? && !self.pending.contains(&change) {
+            self.pending.push(change);
+        }
         Ok(())
     }
 }
@@ -120,66 +233,103 @@ impl Miner for Vnish {
             ip,
             _port: port,
             client,
+            detection: None,
+            tags: Tags::new(),
 
-            token: String::new(),
-            status: Mutex::new(None),
+            password: SecretString::default(),
+            token: Mutex::new(SecretString::default()),
+            cache: None,
+            status: TtlCell::new(STATUS_TTL),
             settings: Mutex::new(None),
             info: Mutex::new(None),
             summary: Mutex::new(None),
             presets: Mutex::new(None),
+            version: Mutex::new(None),
+            pending: Vec::new(),
         }
     }
 
+    fn with_cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
     fn get_type(&self) -> &'static str {
         "Vnish"
     }
 
+    async fn invalidate(&self) {
+        self.status.invalidate().await;
+        *self.settings.lock().await = None;
+        *self.info.lock().await = None;
+        *self.summary.lock().await = None;
+    }
+
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        security::scan(&self.client, &self.ip, &format!("http://{}/", self.ip)).await
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            pool_passwords: true,
+            ..Default::default()
+        }
+    }
+
     async fn get_model(&self) -> Result<String, Error> {
         let info = self.get_info().await?;
         let info = info.as_ref().unwrap_or_else(|| unreachable!());
         Ok(info.model.clone())
     }
 
+    /// A cached token is only reused for the password it was unlocked with
     async fn auth(&mut self, _username: &str, password: &str) -> Result<(), Error> {
-        #[derive(Deserialize)]
-        struct TokenResp {
-            pub token: String,
-        }
-
-        #[derive(Serialize)]
-        struct UnlockReq<'a> {
-            pub pw: &'a str,
-        }
-
-        let resp = self.client.http_client
-            .post(&format!("http://{}/api/v1/unlock", self.ip))
-            .json(&UnlockReq {
-                pw: password,
-            })
-            .send()
-            .await?;
-
-        match resp.status() {
-            reqwest::StatusCode::OK => {},
-            reqwest::StatusCode::FORBIDDEN => return Err(Error::Unauthorized),
-            _ => return Err(Error::ApiCallFailed(format!("auth/unlock {:?}", resp.status()))),
+        self.password = password.into();
+        if let Some(cache) = &self.cache {
+            if let Some(item) = cache.read().await.get(&self.cache_key()) {
+                if item.token_expires > chrono::Utc::now() {
+                    *self.token.lock().await = item.token.clone();
+                    return Ok(());
+                }
+            }
         }
-
-        self.token = resp.json::<TokenResp>().await?.token;
-        Ok(())
+        self.unlock().await.map(drop)
     }
 
     async fn reboot(&mut self) -> Result<(), Error> {
-        let resp = self.client.http_client
-            .post(&format!("http://{}/api/v1/mining/restart", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("http://{}/api/v1/mining/restart", self.ip))
+            .bearer_auth(token)
+        ).await?;
 
-        self.invalidate().await?;
+        self.invalidate().await;
 
         match resp.status() {
-            reqwest::StatusCode::OK => Ok(()),
+            reqwest::StatusCode::OK => {
+                self.pending.clear();
+                Ok(())
+            },
             reqwest::StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
             _ => Err(Error::ApiCallFailed("mining/restart".into())),
         }
@@ -191,6 +341,14 @@ impl Miner for Vnish {
         Ok(summary.miner.instant_hashrate)
     }
 
+    /// Time in the current state stands in for uptime, Vnish restarts mining without a reboot
+    async fn get_hashrate_stable(&self) -> Result<f64, Error> {
+        let summary = self.get_summary().await?;
+        let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+        let miner = &summary.miner;
+        Ok(stable_hashrate(miner.miner_status.miner_state_time as u64, miner.instant_hashrate, miner.average_hashrate))
+    }
+
     async fn get_power(&self) -> Result<f64, Error> {
         let summary = self.get_summary().await?;
         let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
@@ -205,10 +363,9 @@ impl Miner for Vnish {
                 Ok(power)
             }
             _ => {
-                let model = self.get_model().await?;
-                // Map s19-88 to s19
-                let model = model.split('-').next().unwrap_or_else(|| unreachable!());
-                let eff = POWER_MAP.get(model).ok_or(Error::ApiCallFailed("Invalid model".into()))?;
+                // Maps s19-88 to s19
+                let model = normalize_model(&self.get_model().await?);
+                let eff = POWER_MAP.get(model.as_str()).ok_or(Error::ApiCallFailed("Invalid model".into()))?;
                 Ok(eff.0 * self.get_nameplate_rate().await?)
             },
         }
@@ -227,6 +384,15 @@ impl Miner for Vnish {
         Ok(summary.miner.chains.iter().map(|c| c.hashrate_ideal).sum::<f64>() / 1000.0)
     }
 
+    /// Chain hashrate_rt over hashrate_ideal, the sum behind the UI's per chain hashrate_percentage
+    async fn get_performance_ratio(&self) -> Result<f64, Error> {
+        let summary = self.get_summary().await?;
+        let chains = &summary.as_ref().unwrap_or_else(|| unreachable!()).miner.chains;
+        let real = chains.iter().map(|c| c.hashrate_rt).sum::<f64>();
+        let ideal = chains.iter().map(|c| c.hashrate_ideal).sum::<f64>();
+        performance_ratio(real, ideal).ok_or(Error::NotSupported)
+    }
+
     async fn get_temperature(&self) -> Result<f64, Error> {
         let summary = self.get_summary().await?;
         let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
@@ -252,30 +418,7 @@ impl Miner for Vnish {
     }
 
     async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
-        let js = json!({
-            "miner": {
-                "pools": pools.into_iter().enumerate().map(|(i, p)| api::VPool {
-                    url: p.url,
-                    user: p.username,
-                    pass: p.password.unwrap_or("".into()),
-                    order: i,
-                }).collect::<Vec<_>>(),
-            }
-        });
-
-        let resp = self.client.http_client
-            .post(&format!("http://{}/api/v1/settings", self.ip))
-            .bearer_auth(&self.token)
-            .json(&js)
-            .send()
-            .await?;
-
-        if resp.status().is_success() {
-            self.invalidate().await?;
-            Ok(())
-        } else {
-            Err(Error::ApiCallFailed("settings".into()))
-        }
+        self.apply_pending(&SettingsPatch::new().pools(&pools), PendingChange::Pools).await
     }
 
     async fn get_sleep(&self) -> Result<bool, Error> {
@@ -299,11 +442,10 @@ impl Miner for Vnish {
                 let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
                 if (summary.miner.chip_temp.max - summary.miner.chip_temp.min) < 5 ||
                     (summary.miner.miner_status.miner_state == api::StatusCode::Stopped && summary.miner.miner_status.miner_state_time >= 120) {
-                        let resp = self.client.http_client
-                            .post(&format!("http://{}/api/v1/mining/start", self.ip))
-                            .bearer_auth(&self.token)
-                            .send()
-                            .await?;
+                        let resp = self.send(|token| self.client.http_client
+                            .post(format!("http://{}/api/v1/mining/start", self.ip))
+                            .bearer_auth(token)
+                        ).await?;
                         return if resp.status().is_success() {
                             Ok(())
                         } else {
@@ -314,11 +456,10 @@ impl Miner for Vnish {
                     }
             }
             true => {
-                let resp = self.client.http_client
-                    .post(&format!("http://{}/api/v1/mining/stop", self.ip))
-                    .bearer_auth(&self.token)
-                    .send()
-                    .await?;
+                let resp = self.send(|token| self.client.http_client
+                    .post(format!("http://{}/api/v1/mining/stop", self.ip))
+                    .bearer_auth(token)
+                ).await?;
                 return if resp.status().is_success() {
                     Ok(())
                 } else {
@@ -326,30 +467,44 @@ impl Miner for Vnish {
                 }
             }
         };
-        let _ = self.invalidate().await;
+        self.invalidate().await;
         r
     }
 
+    async fn get_power_state(&self) -> Result<PowerState, Error> {
+        let summary = self.get_summary().await?;
+        let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+        Ok(match summary.miner.miner_status.miner_state {
+            api::StatusCode::Stopped => PowerState::Sleeping,
+            api::StatusCode::Initializing |
+            api::StatusCode::Restarting |
+            api::StatusCode::ShuttingDown => PowerState::Transitioning,
+            // A failed miner isn't asleep, waking it won't help
+            api::StatusCode::Running |
+            api::StatusCode::AutoTuning |
+            api::StatusCode::Failure => PowerState::Mining,
+        })
+    }
+
     async fn get_blink(&self) -> Result<bool, Error> {
-        let status: MutexGuard<Option<api::MinerStatus>> = self.get_status().await?;
+        let status: MutexGuard<Option<Expiring<api::MinerStatus>>> = self.get_status().await?;
         let status = status.as_ref().unwrap_or_else(|| unreachable!());
         Ok(status.find_miner)
     }
 
     async fn set_blink(&mut self, blink: bool) -> Result<(), Error> {
-        let status: MutexGuard<Option<api::MinerStatus>> = self.get_status().await?;
-        let status = status.as_ref().unwrap_or_else(|| unreachable!());
-        if status.find_miner == blink {
+        if self.get_blink().await? == blink {
             return Ok(());
         }
 
-        let resp = self.client.http_client
-            .post(&format!("http://{}/api/v1/mining/find_miner", self.ip))
-            .bearer_auth(&self.token)
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("http://{}/api/v1/mining/find_miner", self.ip))
+            .bearer_auth(token)
             .json(&serde_json::json!({"on": blink}))
-            .send()
-            .await?;
+        ).await?;
         
+        // Otherwise the next toggle would compare against the state from before this one
+        self.status.invalidate().await;
         if resp.status().is_success() {
             Ok(())
         } else {
@@ -358,11 +513,10 @@ impl Miner for Vnish {
     }
 
     async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
-        let resp = self.client.http_client
-            .get(&format!("http://{}/api/v1/logs/miner", self.ip))
-            .bearer_auth(&self.token)
-            .send()
-            .await?;
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("http://{}/api/v1/logs/miner", self.ip))
+            .bearer_auth(token)
+        ).await?;
 
         if resp.status().is_success() {
             let logs = resp.text().await?;
@@ -372,6 +526,33 @@ impl Miner for Vnish {
         }
     }
 
+    async fn debug_dump(&self) -> Result<serde_json::Value, Error> {
+        let presets = self.api_version().await.map(|v| v.presets_path()).unwrap_or("api/v1/autotune/presets");
+        let paths = ["api/v1/info", "api/v1/summary", "api/v1/status", "api/v1/settings", presets];
+        let payloads = futures::future::join_all(paths.iter().map(|path| async move {
+            let body = self.send(|token| self.client.http_client
+                .get(format!("http://{}/{}", self.ip, path))
+                .bearer_auth(token)
+            ).await?.text().await?;
+            Ok(raw_json(&body))
+        })).await;
+        Ok(serde_json::Value::Object(
+            paths.iter().zip(payloads).map(|(path, payload)| (path.to_string(), dump_entry(payload))).collect()
+        ))
+    }
+
+    async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+        let want = |r: Resource| resources.contains(&r);
+        // The summary endpoint doesn't carry the miner state, that's in status
+        let (summary, status, settings, info) = futures::join!(
+            async { if want(Resource::Summary) || want(Resource::Stats) { self.get_summary().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::Summary) { self.get_status().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::Config) { self.get_settings().await.map(drop) } else { Ok(()) } },
+            async { if want(Resource::SystemInfo) { self.get_info().await.map(drop) } else { Ok(()) } },
+        );
+        summary.and(status).and(settings).and(info)
+    }
+
     async fn get_mac(&self) -> Result<String, Error> {
         let info = self.get_info().await?;
         let info = info.as_ref().unwrap_or_else(|| unreachable!());
@@ -388,8 +569,8 @@ impl Miner for Vnish {
         let mut errors = HashSet::new();
         for err in VNISH_ERRORS.iter() {
             let mut logs = logs;
-            while let Some(msg) = err.get_err(&logs) {
-                let end = err.re.find(&logs).unwrap().end();
+            while let Some(msg) = err.get_err(logs) {
+                let end = err.re.find(logs).unwrap().end();
                 logs = &logs[end..];
                 errors.insert(msg);
             }
@@ -400,9 +581,39 @@ impl Miner for Vnish {
     async fn get_dns(&self) -> Result<String, Error> {
         let info = self.get_info().await?;
         let info = info.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(info.system.network_status.dns.get(0).ok_or(Error::ApiCallFailed("No DNS servers found".into()))?.clone())
+        Ok(info.system.network_status.dns.first().ok_or(Error::ApiCallFailed("No DNS servers found".into()))?.clone())
+    }
+
+    /// The regional timezone is a GMT offset in whole hours
+    async fn get_utc_offset(&self) -> Result<FixedOffset, Error> {
+        let settings = self.get_settings().await?;
+        let settings = settings.as_ref().unwrap_or_else(|| unreachable!());
+        let timezone = &settings.regional.timezone;
+        timezone.utc_offset().ok_or_else(|| Error::InvalidConfig(format!("Unknown timezone {}", timezone.current)))
+    }
+
+    async fn get_dns_servers(&self) -> Result<Vec<IpAddr>, Error> {
+        let info = self.get_info().await?;
+        let info = info.as_ref().unwrap_or_else(|| unreachable!());
+        Ok(info.system.network_status.dns.iter().filter_map(|s| s.parse().ok()).collect())
+    }
+
+    /// Only used while the miner has a static address, on DHCP the lease's servers are used
+    async fn set_dns(&mut self, servers: Vec<IpAddr>) -> Result<(), Error> {
+        if servers.is_empty() {
+            return Err(Error::InvalidConfig("At least one DNS server is needed".into()));
+        }
+        let network = {
+            let settings = self.get_settings().await?;
+            let settings = settings.as_ref().unwrap_or_else(|| unreachable!());
+            let mut network = settings.network.clone().ok_or(Error::NotSupported)?;
+            network.dnsservers = servers.iter().map(|ip| ip.to_string()).collect();
+            network
+        };
+        self.apply_pending(&SettingsPatch::new().network(network), PendingChange::Network).await
     }
 
+    /// Profile::Unknown if the active preset isn't in the preset list, e.g. after a firmware update removed it
     async fn get_profile(&self) -> Result<Profile, Error> {
         let presets = self.get_profiles().await?;
         let settings = self.get_settings().await?;
@@ -421,25 +632,27 @@ impl Miner for Vnish {
                         && settings.miner.overclock.globals.freq == settings.ui.consts.overclock.default_freq
                     },
                     Profile::Preset { name, .. } => name == &settings.miner.overclock.preset,
-                    Profile::LowPower => false,
+                    Profile::LowPower | Profile::Unknown { .. } => false,
                 }
-            }).unwrap_or_else(|| unreachable!()).clone()
+            })
+                .cloned()
+                .unwrap_or_else(|| Profile::Unknown { name: settings.miner.overclock.preset.clone() })
         )
     }
 
     async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
         let mut profiles = self.presets.lock().await;
         if profiles.is_none() {
-            let resp = self.client.http_client
-                .get(&format!("http://{}/api/v1/autotune/presets", self.ip))
-                .bearer_auth(&self.token)
-                .send()
-                .await?;
+            let version = self.api_version().await?;
+            let resp = self.send(|token| self.client.http_client
+                .get(format!("http://{}/{}", self.ip, version.presets_path()))
+                .bearer_auth(token)
+            ).await?;
 
             if !resp.status().is_success() {
                 return Err(Error::ApiCallFailed("presets".into()));
             }
-            let presets = resp.json::<api::Presets>().await?;
+            let presets = version.parse_presets(&resp.text().await?)?;
 
             let settings = self.get_settings().await?;
             let settings = settings.as_ref().unwrap_or_else(|| unreachable!());
@@ -463,81 +676,217 @@ impl Miner for Vnish {
 
     async fn set_profile(&mut self, profile: Profile) -> Result<(), Error> {
         let presets = self.get_profiles().await?;
-        let preset = presets.iter().find(|p| match (*p, &profile) {
-            (Profile::Default, Profile::Default) => true,
-            (Profile::Preset { name: n1, .. }, Profile::Preset { name: n2, .. }) => n1 == n2,
-            (Profile::Manual { volt: _, freq: _, max_freq, min_freq, min_volt, max_volt, .. }, Profile::Manual { volt: v2, freq: f2, .. }) => {
-                v2 >= min_volt && v2 <= max_volt && f2 >= min_freq && f2 <= max_freq
+        // The Manual entry only carries the bounds, write the requested volt and freq
+        let preset = match profile.validate(&presets)? {
+            Profile::Manual { .. } => &profile,
+            preset => preset,
+        };
+
+        let patch = match preset {
+            Profile::Default => {
+                let settings = self.get_settings().await?;
+                let overclock = &settings.as_ref().unwrap_or_else(|| unreachable!()).ui.consts.overclock;
+                SettingsPatch::new().preset("disabled").globals(overclock.default_voltage, overclock.default_freq)
             },
-            _ => false,
-        })
-            .ok_or(Error::ApiCallFailed("Invalid profile".into()))?;
+            // A preset listed without a wattage comes back as Unknown, it's still a preset name
+            Profile::Preset { name, .. } | Profile::Unknown { name } => SettingsPatch::new().preset(name),
+            Profile::Manual { volt, freq, .. } => SettingsPatch::new().preset("disabled").globals(*volt, *freq),
+            Profile::LowPower => return Err(Error::NotSupported),
+        };
+
+        self.apply_settings(&patch).await?;
+        self.reboot().await
+    }
+
+    /// Still tuning while the miner is auto-tuning or any chain is still initializing
+    async fn get_tuning_status(&self) -> Result<TuningStatus, Error> {
+        let summary = self.get_summary().await?;
+        let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+        let tuning = summary.miner.miner_status.miner_state == api::StatusCode::AutoTuning
+            || summary.miner.chains.iter().any(|c| c.status.state == api::ChainState::Initializing);
+        // Convert from GH/s to TH/s
+        let target = summary.miner.chains.iter().map(|c| c.hashrate_ideal).sum::<f64>() / 1000.0;
+        Ok(TuningStatus::new(target, summary.miner.instant_hashrate, tuning))
+    }
 
-        let js = {
+    /// Writes every chain back as a partial list would replace the others
+    async fn set_board_enabled(&mut self, chain: usize, enabled: bool) -> Result<(), Error> {
+        let chains = {
             let settings = self.get_settings().await?;
             let settings = settings.as_ref().unwrap_or_else(|| unreachable!());
+            let mut chains = settings.miner.overclock.chains.clone();
+            let count = chains.len();
+            chains.get_mut(chain)
+                .ok_or_else(|| Error::InvalidConfig(format!("No chain {}, the miner has {}", chain, count)))?
+                .disabled = !enabled;
+            chains
+        };
 
-            let _chains = settings.miner.overclock.chains.iter().cloned()
-                .map(|mut c| {
-                    // Set to global freq
-                    c.freq = 0;
-                    // Set to chain freq
-                    c.chips = vec![0; c.chips.len()];
-                    c
-                });
-    
-            match preset {
-                Profile::Default => json!({
-                    "miner": {
-                        "overclock": {
-                            "preset": "disabled",
-                            "globals": {
-                                "volt": settings.ui.consts.overclock.default_voltage,
-                                "freq": settings.ui.consts.overclock.default_freq,
-                            },
-                        },
-                    },
-                }),
-                Profile::Preset { name, .. } => json!({
-                    "miner": {
-                        "overclock": {
-                            "preset": name,
-                        },
-                    },
-                }),
-                Profile::Manual { volt, freq, .. } => json!({
-                    "miner": {
-                        "overclock": {
-                            "preset": "disabled",
-                            "globals": {
-                                "volt": volt,
-                                "freq": freq,
-                            },
-                        },
-                    },
-                }),
-                Profile::LowPower => return Err(Error::NotSupported),
-            }
+        self.apply_pending(&SettingsPatch::new().chains(chains), PendingChange::Boards).await
+    }
+
+    /// Expected from the per chip overclock settings, detected from the chips with a status
+    async fn get_chip_counts(&self) -> Result<Vec<ChipCount>, Error> {
+        let expected: Vec<usize> = {
+            let settings = self.get_settings().await?;
+            let settings = settings.as_ref().unwrap_or_else(|| unreachable!());
+            settings.miner.overclock.chains.iter().map(|c| c.chips.len()).collect()
         };
-    
-        let resp = self.client.http_client
-            .post(&format!("http://{}/api/v1/settings", self.ip))
-            .bearer_auth(&self.token)
-            .json(&js)
-            .send()
-            .await?;
+        let summary = self.get_summary().await?;
+        let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+        // grey counts every chip found, red and orange are the ones among them misbehaving
+        Ok(summary.miner.chains.iter().enumerate().map(|(i, c)| ChipCount {
+            chain: i,
+            expected: expected.get(i).copied().filter(|n| *n > 0),
+            detected: c.chip_statuses.grey.max(0) as usize,
+        }).collect())
+    }
+
+    /// Vnish only knows whether it's set up for immersion
+    async fn get_cooling_detail(&self) -> Result<CoolingDetail, Error> {
+        let summary = self.get_summary().await?;
+        let summary = summary.as_ref().unwrap_or_else(|| unreachable!());
+        Ok(CoolingDetail {
+            liquid: matches!(summary.miner.cooling.settings.mode, api::CoolingMode::Immersion),
+            ..Default::default()
+        })
+    }
+
+    /// Vnish doesn't report load
+    async fn get_system_stats(&self) -> Result<SystemStats, Error> {
+        let info = self.get_info().await?;
+        let info = info.as_ref().unwrap_or_else(|| unreachable!());
+        Ok(SystemStats {
+            mem_free: Some(info.system.mem_free as u64),
+            mem_total: Some(info.system.mem_total as u64),
+            load_avg: None,
+            uptime: info.system.uptime_secs(),
+        })
+    }
+
+    /// restart_required from the firmware is authoritative, what we wrote only says which settings are waiting
+    async fn pending_changes(&self) -> Result<Vec<PendingChange>, Error> {
+        let status = self.get_status().await?;
+        let status = status.as_ref().unwrap_or_else(|| unreachable!());
+        if !status.restart_required {
+            Ok(vec![])
+        } else if self.pending.is_empty() {
+            Ok(vec![PendingChange::Unspecified])
+        } else {
+            Ok(self.pending.clone())
+        }
+    }
+
+    async fn backup_config(&self) -> Result<MinerConfigBackup, Error> {
+        let mut backup = MinerConfigBackup::capture(self).await?;
+        // Raw JSON so fields we don't model survive the round trip
+        let resp = self.send(|token| self.client.http_client
+            .get(format!("http://{}/api/v1/settings", self.ip))
+            .bearer_auth(token)
+        ).await?;
+        if !resp.status().is_success() {
+            return Err(Error::ApiCallFailed("settings".into()));
+        }
+        backup.settings = Some(resp.json::<serde_json::Value>().await?);
+        Ok(backup)
+    }
+
+    /// Writes back the miner section (pools, cooling, overclock, misc) and the network section,
+    /// then reboots to apply them, after which the miner may answer on another address
+    async fn restore_config(&mut self, backup: MinerConfigBackup) -> Result<(), Error> {
+        let settings = match backup.settings_for(self.get_type()) {
+            Some(settings) if settings["miner"].is_object() => settings,
+            _ => return backup.apply(self).await,
+        };
+        let mut miner = settings["miner"].clone();
+        let known: Vec<Pool> = serde_json::from_value(miner["pools"].clone()).unwrap_or_default();
+        miner["pools"] = json!(backup.pools_with_passwords(&known).into_iter().enumerate().map(|(i, p)| api::VPool {
+            url: p.url,
+            user: p.username,
+            pass: p.password.unwrap_or_default(),
+            order: i,
+        }).collect::<Vec<_>>());
+
+        let resp = self.send(|token| self.client.http_client
+            .post(format!("http://{}/api/v1/settings", self.ip))
+            .bearer_auth(token)
+            .json(&match settings.get("network").filter(|n| n.is_object()) {
+                Some(network) => json!({ "miner": miner, "network": network }),
+                None => json!({ "miner": miner }),
+            })
+        ).await?;
 
         if resp.status().is_success() {
-            println!("{:?}", resp.text().await?);
             self.reboot().await?;
-            self.invalidate().await?;
+            self.invalidate().await;
             Ok(())
         } else {
             Err(Error::ApiCallFailed("settings".into()))
         }
     }
-
-    async fn get_hashboard(&mut self) -> Result<String, Error> {
-        Err(Error::NotSupported)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+    use crate::ClientBuilder;
+    use crate::transport::ScriptedHttp;
+
+    const INFO: &str = r#"{"miner":"Antminer S19","model":"s19","fw_name":"Vnish","fw_version":"1.2.0","platform":"xil","install_type":"sd",
+        "build_time":"","system":{"os":"GNU/Linux","file_system_version":"","mem_total":233712,"mem_free":195048,"mem_free_percent":83,
+        "mem_buf":19668,"mem_buf_percent":8,"network_status":{"mac":"4E:9F:85:7B:57:7C","ip":"10.0.0.1","netmask":"255.255.254.0",
+        "gateway":"10.0.0.254","dns":[],"hostname":"Antminer"},"uptime":"9 days,  3:58"}}"#;
+
+    fn vnish(script: &ScriptedHttp, cache: &Cache) -> Vnish {
+        let client = ClientBuilder::new().http_transport(script.clone()).build().unwrap();
+        Vnish::new(client, "10.0.0.1".into(), 80).with_cache(Some(cache.clone()))
+    }
+
+    #[tokio::test]
+    async fn test_cached_token_needs_password() {
+        let script = ScriptedHttp::new()
+            .reply(Method::POST, "/api/v1/unlock", 200, r#"{"token":"abc"}"#)
+            .reply(Method::POST, "/api/v1/unlock", 403, "");
+        let cache = Cache::default();
+        vnish(&script, &cache).auth("", "admin").await.unwrap();
+        vnish(&script, &cache).auth("", "admin").await.unwrap();
+        assert!(matches!(vnish(&script, &cache).auth("", "wrong").await, Err(Error::Unauthorized)));
+        // The second login was a cache hit
+        assert_eq!(script.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reauth_on_unauthorized() {
+        let script = ScriptedHttp::new()
+            .reply(Method::POST, "/api/v1/unlock", 200, r#"{"token":"abc"}"#)
+            .reply(Method::GET, "/api/v1/info", 401, "")
+            .reply(Method::GET, "/api/v1/info", 200, INFO);
+        let cache = Cache::default();
+        let mut miner = vnish(&script, &cache);
+        miner.auth("", "admin").await.unwrap();
+        assert_eq!(miner.get_model().await.unwrap(), "s19");
+        let unlocks = script.requests().into_iter().filter(|(_, path)| path == "/api/v1/unlock").count();
+        assert_eq!(unlocks, 2);
+    }
+
+    #[tokio::test]
+    async fn test_pending_changes() {
+        let status = |restart: bool| format!(r#"{{"restart_required":{},"miner_state":"mining","miner_state_time":60,
+            "find_miner":false,"unlocked":true,"warranty":false}}"#, restart);
+        let script = ScriptedHttp::new()
+            .reply(Method::GET, "/api/v1/settings", 200, include_str!("../../../fixtures/vnish/settings.json"))
+            .reply(Method::POST, "/api/v1/settings", 200, r#"{"restart_required":false,"reboot_required":false}"#)
+            .reply(Method::POST, "/api/v1/settings", 200, r#"{"restart_required":true,"reboot_required":false}"#)
+            .reply(Method::GET, "/api/v1/status", 200, &status(true));
+        let mut miner = vnish(&script, &Cache::default());
+        miner.set_pools(vec![Pool {
+            url: "stratum+tcp://pool.example.com:3333".into(),
+            username: "worker.1".into(),
+            password: None,
+        }]).await.unwrap();
+        miner.set_dns(vec!["1.1.1.1".parse().unwrap()]).await.unwrap();
+        miner.set_board_enabled(1, false).await.unwrap();
+        // Taken live, so the pools aren't waiting on the restart
+        assert_eq!(miner.pending_changes().await.unwrap(), vec![PendingChange::Network, PendingChange::Boards]);
     }
-}
\ No newline at end of file
+}
@@ -1,4 +1,7 @@
 mod whatsminer;
 pub use whatsminer::Whatsminer;
+// Replies keep the STATUS header and every documented field, read or not
+#[allow(dead_code)]
 mod wmapi;
 mod error;
+pub use error::{ErrorSpec, decode_error_code};
@@ -1,37 +1,122 @@
 use async_trait::async_trait;
 use serde::Deserialize;
-use serde_json::json;
-use tokio::{net::TcpStream, io::{AsyncWriteExt, AsyncReadExt}, sync::{Mutex, MutexGuard}};
+use serde_json::{json, Value};
+use tokio::{io::{AsyncWriteExt, AsyncReadExt}, sync::{Mutex, MutexGuard}};
 use lazy_regex::regex;
 use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use phf::phf_map;
 
-use crate::{Client, Miner, miner::MinerError, error::Error, Pool, miners::common, miners::whatsminer::wmapi, Cache, CacheItem, miner::Profile};
-use super::{error::WHATSMINER_ERRORS, wmapi::StatusCode};
+use crate::{Client, Miner, miner::MinerError, miner::HealthCondition, miner::stable_hashrate, PoolCounters, SystemStats, CoolingDetail, ChipCount, BoardTemps, TempReport, SecretString, error::Error, Pool, miners::common, miners::whatsminer::wmapi, Cache, CacheItem, Capabilities, PowerState, DetectionInfo, DetectionMethod, TuningStatus, Resource, Profile};
+use super::{error::code_error, wmapi::StatusCode, wmapi::v3};
+use crate::util::framing::{Framing, read_framed};
+use crate::model::normalize_model;
+use crate::security::{self, SecurityIndicator};
+use crate::transport::SendVia;
+use crate::miners::cgminer::GenericCgminer;
+use crate::detection::WHATSMINER_CGMINER;
+use crate::miner::{dump_entry, raw_json, Tags};
 
-// (J/TH, Datasheet TH)
+// (J/TH, Datasheet TH), keyed by normalize_model
 static EFF_MAP: phf::Map<&'static str, (f64, f64)> = phf_map! {
-    "M20S" => (48.0, 68.0),
-    "M31S" => (46.0, 72.0),
-    "M31S+" => (42.0, 80.0),
-    "M30S" => (38.0, 88.0),
-    "M30S+" => (34.0, 100.0),
-    "M33S+" => (34.0, 210.0),
-    "M30S++" => (31.0, 108.0),
-    "M33S++" => (31.0, 230.0),
-    "M50" => (29.0, 115.0),
-    "M53" => (29.0, 235.0),
-    "M50S" => (26.0, 125.0),
-    "M53S" => (26.0, 235.0),
-    "M50S+" => (24.0, 138.0),
+    "m20s" => (48.0, 68.0),
+    "m31s" => (46.0, 72.0),
+    "m31s+" => (42.0, 80.0),
+    "m30s" => (38.0, 88.0),
+    "m30s+" => (34.0, 100.0),
+    "m33s+" => (34.0, 210.0),
+    "m30s++" => (31.0, 108.0),
+    "m33s++" => (31.0, 230.0),
+    "m50" => (29.0, 115.0),
+    "m53" => (29.0, 235.0),
+    "m50s" => (26.0, 125.0),
+    "m53s" => (26.0, 235.0),
+    "m50s+" => (24.0, 138.0),
 };
 
+/// net_config with the miner's static config and new DNS servers
+fn net_config_cmd(info: &wmapi::MinerInfo, dns: &[IpAddr]) -> Result<Value, Error> {
+    if info.proto.eq_ignore_ascii_case("dhcp") {
+        return Err(Error::InvalidConfig("DNS comes from the DHCP lease, set a static address first".into()));
+    }
+    Ok(json!({
+        "cmd": "net_config",
+        "ip": info.ip,
+        "mask": info.netmask,
+        "gate": info.gateway,
+        "dns": dns.iter().map(|ip| ip.to_string()).collect::<Vec<_>>().join(" "),
+        "host": info.hostname,
+    }))
+}
+
+/// Effective Chips of each board in edevs
+fn chip_counts(resp: &Value) -> Result<Vec<ChipCount>, Error> {
+    let devs = resp["DEVS"].as_array().ok_or(Error::InvalidResponse)?;
+    Ok(devs.iter().enumerate().filter_map(|(i, dev)| Some(ChipCount {
+        chain: dev["Slot"].as_u64().map(|s| s as usize).unwrap_or(i),
+        expected: None,
+        detected: dev["Effective Chips"].as_u64()? as usize,
+    })).collect())
+}
+
+/// btminer sends 0 for a sensor it couldn't read
+fn sensor_temp(temp: f64) -> Option<f64> {
+    Some(temp).filter(|t| *t > 0.0)
+}
+
+/// Slot temperature and the coolest and hottest chip of each board in edevs
+/// btminer doesn't list chips one by one
+fn slot_temps(resp: &Value) -> Result<Vec<BoardTemps>, Error> {
+    let devs = resp["DEVS"].as_array().ok_or(Error::InvalidResponse)?;
+    let reading = |dev: &Value, key: &str| dev[key].as_f64().and_then(sensor_temp);
+    Ok(devs.iter().enumerate().map(|(i, dev)| BoardTemps {
+        chain: dev["Slot"].as_u64().map(|s| s as usize).unwrap_or(i),
+        chip: vec![],
+        pcb: dev["Temperature"].as_f64().map(sensor_temp).into_iter().collect(),
+        chip_min: reading(dev, "Chip Temp Min"),
+        chip_max: reading(dev, "Chip Temp Max"),
+    }).collect())
+}
+
+/// Boards with error 507x, water velocity abnormal
+fn flow_alarms(codes: &[String]) -> Vec<usize> {
+    let mut boards: Vec<usize> = codes.iter()
+        .filter_map(|code| code.strip_prefix("507"))
+        .filter_map(|board| board.parse().ok())
+        .filter(|board| *board <= 2)
+        .collect();
+    boards.sort_unstable();
+    boards.dedup();
+    boards
+}
+
+/// btminer has three pool slots
+const MAX_POOLS: usize = 3;
+
+/// update_pools with every slot filled, unused slots are blank as the API expects
+fn update_pools_cmd(pools: &[Pool]) -> Result<Value, Error> {
+    if pools.len() > MAX_POOLS {
+        return Err(Error::TooManyPools { given: pools.len(), max: MAX_POOLS });
+    }
+    let mut js = json!({"cmd": "update_pools"});
+    for i in 0..MAX_POOLS {
+        let pool = pools.get(i);
+        js[format!("pool{}", i + 1)] = json!(pool.map(|p| p.url.as_str()).unwrap_or_default());
+        js[format!("worker{}", i + 1)] = json!(pool.map(|p| p.username.as_str()).unwrap_or_default());
+        js[format!("passwd{}", i + 1)] = json!(pool.and_then(|p| p.password.as_ref()).map(|p| p.expose()).unwrap_or_default());
+    }
+    Ok(js)
+}
+
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 pub struct LogLen {
     pub logfilelen: String,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 pub struct LogsResponse {
     #[serde(rename = "STATUS")]
     pub status: common::StatusCode,
@@ -45,23 +130,134 @@ pub struct LogsResponse {
     pub description: String,
 }
 
+/// Which socket API the firmware speaks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Protocol {
+    /// get_token and AES on port 4028
+    Legacy,
+    /// JSON v3 on port 4433, M6X/M7X
+    V3,
+    /// btminer has died and stock cgminer answers on 4028 instead
+    Cgminer,
+}
+
+/// How legacy firmware takes write commands, settled at auth
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum WriteMode {
+    /// Token inside an AES envelope
+    Encrypted,
+    /// Token in plain JSON, firmware that hands out tokens but can't decrypt
+    PlainToken,
+    /// Early M20 firmware without get_token, writes are open
+    Plain,
+}
+
+/// Whether a plaintext reply is the firmware not knowing the command
+fn invalid_cmd(resp: &str) -> bool {
+    serde_json::from_str::<wmapi::Status>(resp).map(|s| s.is_invalid_cmd()).unwrap_or(false)
+}
+
 pub struct Whatsminer {
     ip: String,
     port: u16,
-    password: Option<String>,
+    password: Option<SecretString>,
     token: Option<wmapi::WhatsminerToken>,
+    write_mode: WriteMode,
     client: Client,
+    detection: Option<DetectionInfo>,
+    tags: Tags,
     cache: Option<Cache>,
+    /// Cleared once the miner tells us the write API is disabled
+    write_api: AtomicBool,
+
+    protocol: Mutex<Option<Protocol>>,
+    v3: v3::V3Api,
+    cgminer: GenericCgminer,
 
     model: Mutex<Option<String>>,
     summary: Mutex<Option<wmapi::SummaryResp>>,
+    v3_summary: Mutex<Option<v3::Summary>>,
 }
 
 impl Whatsminer {
+    /// Legacy firmware answers get_version on its own port, v3 firmware has that port closed
+    async fn protocol(&self) -> Result<Protocol, Error> {
+        let mut protocol = self.protocol.lock().await;
+        if let Some(protocol) = *protocol {
+            return Ok(protocol);
+        }
+        let negotiated = if self.port == v3::V3_PORT {
+            Protocol::V3
+        } else {
+            match self.send_recv(&json!({"cmd": "get_version"})).await {
+                // cgminer wraps its STATUS in a list, btminer doesn't
+                Ok(resp) if serde_json::from_str::<Value>(&resp).map(|v| v["STATUS"].is_array()).unwrap_or(false) => Protocol::Cgminer,
+                Ok(_) => Protocol::Legacy,
+                Err(e) => match self.v3.call::<Value>("get.device.info", Some(json!("miner"))).await {
+                    Ok(_) => Protocol::V3,
+                    Err(_) => return Err(e),
+                },
+            }
+        };
+        *protocol = Some(negotiated);
+        Ok(negotiated)
+    }
+
+    async fn is_v3(&self) -> Result<bool, Error> {
+        Ok(self.protocol().await? == Protocol::V3)
+    }
+
+    /// Running on the cgminer fallback, calls go through the generic cgminer path
+    pub async fn is_cgminer(&self) -> Result<bool, Error> {
+        Ok(self.protocol().await? == Protocol::Cgminer)
+    }
+
+    async fn v3_summary(&self) -> Result<MutexGuard<'_, Option<v3::Summary>>, Error> {
+        let mut summary = self.v3_summary.lock().await;
+        if summary.is_none() {
+            let msg: v3::SummaryMsg = self.v3.call("get.miner.status", Some(json!("summary"))).await?;
+            *summary = Some(msg.summary);
+        }
+        Ok(summary)
+    }
+
+    async fn v3_device(&self) -> Result<Value, Error> {
+        self.v3.call("get.device.info", None).await
+    }
+
+    /// Codes of the errors currently raised
+    async fn error_codes(&self) -> Result<Vec<String>, Error> {
+        if self.is_v3().await? {
+            let msg: Value = self.v3.call("get.device.errorcode", None).await?;
+            return Ok(v3::error_codes(&msg));
+        }
+        let resp = self.send_recv(&json!({"cmd":"get_error_code"})).await?;
+        // Whatsminer again returning invalid JSON
+        //{"error_code":["111":"2022-10-20 09:18:54","110":"2022-10-20 09:18:54","2010":"1970-01-02 08:00:04"]}
+        //TODO: it might be cheaper to regex this
+        let resp = resp.replace("[", "{").replace("]", "}");
+        let resp = serde_json::from_str::<wmapi::ErrorResp>(&resp)?;
+        // Our response is a hashmap of error_code : datetime, only the codes matter
+        Ok(resp.msg.error_code.keys().map(|s| s.to_string()).collect())
+    }
+
+    /// btminer's own view of whether mining is switched off, None if it doesn't say
+    async fn get_mineroff(&self) -> Option<bool> {
+        if self.is_v3().await.ok()? {
+            return None;
+        }
+        let resp = self.send_recv(&json!({"cmd":"status"})).await.ok()?;
+        match serde_json::from_str::<wmapi::BtStatusResp>(&resp).ok()?.msg {
+            wmapi::BtStatus::V2(status) => Some(status.mineroff),
+            wmapi::BtStatus::V1(status) => Some(status.btmineroff),
+        }
+    }
+
     async fn send_recv<T>(&self, data: &T) -> Result<String, Error>
         where T: ToString
     {
-        let mut resp = self.client.send_recv(&self.ip, self.port, data).await?;
+        // Some firmware keeps the connection open after responding
+        let mut resp = self.client.send_recv_framed(&self.ip, self.port, data, Framing::Json).await?;
         // Whatsminer can return non-compliant JSON
         resp = resp.replace("inf", "\"inf\"");
         resp = resp.replace("nan", "\"nan\"");
@@ -69,14 +265,30 @@ impl Whatsminer {
         Ok(resp)
     }
 
+    /// Check a plaintext response for the write API being disabled, remembering it for capabilities()
+    fn check_api_disabled(&self, resp: &str) -> Result<(), Error> {
+        if let Ok(status) = serde_json::from_str::<wmapi::Status>(resp) {
+            if status.is_api_disabled() {
+                self.write_api.store(false, Ordering::Relaxed);
+                return Err(Error::ApiDisabled);
+            }
+        }
+        Ok(())
+    }
+
     async fn refresh_token(&mut self) -> Result<(), Error> {
         if let Some(passwd) = &self.password {
             let resp = self.send_recv(&json!({"cmd": "get_token"})).await?;
+            self.check_api_disabled(&resp)?;
+            if invalid_cmd(&resp) {
+                self.write_mode = WriteMode::Plain;
+                return Ok(());
+            }
             match serde_json::from_str::<wmapi::TokenResponse>(&resp) {
                 Ok(token_resp) => {
                     self.token = Some(
                         token_resp
-                            .make_token(passwd)
+                            .make_token(passwd.expose())
                             .map_err(|_| Error::ApiCallFailed("Failed to make token".into()))?
                     );
                     if let Some(cache) = &self.cache {
@@ -85,7 +297,7 @@ impl Whatsminer {
                             cache.insert(
                                 self.ip.clone(),
                                 CacheItem {
-                                    token: serde_json::to_string(token)?,
+                                    token: serde_json::to_string(token)?.into(),
                                     token_expires: token.expires,
                                 },
                             );
@@ -107,7 +319,7 @@ impl Whatsminer {
                 let cache = cache.read().await;
                 if let Some(token) = cache.get(&self.ip) {
                     if token.token_expires > chrono::Utc::now() {
-                        self.token = serde_json::from_str(&token.token)?;
+                        self.token = serde_json::from_str(token.token.expose())?;
                         return Ok(());
                     }
                 }
@@ -117,58 +329,89 @@ impl Whatsminer {
         self.refresh_token().await
     }
 
-    async fn send_recv_enc(&mut self, mut data: serde_json::Value) -> Result<String, Error> {
-        if let Some(token) = &self.token {
+    /// Wrap a write command the way the firmware expects it
+    fn write_request(&self, mut data: Value) -> Result<Value, Error> {
+        if self.write_mode == WriteMode::Plain {
+            return Ok(data);
+        }
+        let token = self.token.as_ref().ok_or(Error::Unauthorized)?;
+        // Stuff our token into the JSON
+        data.as_object_mut().unwrap().insert("token".to_string(), Value::String(token.get_token().into()));
+        match self.write_mode {
+            WriteMode::Encrypted => token.encrypt(&data),
+            _ => Ok(data),
+        }
+    }
+
+    /// Firmware that hands out tokens but can't decrypt takes an AES envelope for an unknown command
+    async fn negotiate_write_mode(&mut self) -> Result<(), Error> {
+        if self.write_mode != WriteMode::Encrypted {
+            return Ok(());
+        }
+        let resp = self.send_recv(&self.write_request(json!({"cmd": "get_version"}))?).await?;
+        self.check_api_disabled(&resp)?;
+        if invalid_cmd(&resp) {
+            self.write_mode = WriteMode::PlainToken;
+        }
+        Ok(())
+    }
+
+    async fn send_recv_enc(&mut self, data: serde_json::Value) -> Result<String, Error> {
+        if self.write_mode != WriteMode::Plain {
             // Refresh our token if its expired
-            if token.is_expired() {
-                self.refresh_token().await?;
+            match self.token.as_ref().map(|t| t.is_expired()) {
+                Some(true) => self.refresh_token().await?,
+                Some(false) => {},
+                None => return Err(Error::Unauthorized),
             }
-            // We need to reborrow the token due to the possibility of it being mutated by the refresh
-            // Should never panic so unwrap() is fine
-            let token = self.token.as_ref().unwrap();
-            // Stuff our token into the JSON
-            data.as_object_mut().unwrap().insert("token".to_string(), serde_json::Value::String(token.get_token().into()));
-            let enc_data = token.encrypt(&data)?;
-            let resp = self.send_recv(&enc_data).await?;
-            let js = serde_json::from_str(&resp).map_err(|_| Error::ApiCallFailed("Failed to parse JSON".into()))?;
-            let dec_data = token.decrypt(&js)?;
-            Ok(dec_data.to_string())
-        } else {
-            Err(Error::Unauthorized)
         }
+        let resp = self.send_recv(&self.write_request(data)?).await?;
+        // Errors such as a disabled API come back unencrypted
+        self.check_api_disabled(&resp)?;
+        self.write_api.store(true, Ordering::Relaxed);
+        if self.write_mode != WriteMode::Encrypted {
+            return Ok(resp);
+        }
+        let js = serde_json::from_str(&resp).map_err(|_| Error::ApiCallFailed("Failed to parse JSON".into()))?;
+        let dec_data = self.token.as_ref().ok_or(Error::Unauthorized)?.decrypt(&js)?;
+        Ok(dec_data.to_string())
     }
 
-    async fn get_summary(&self) -> Result<MutexGuard<Option<wmapi::SummaryResp>>, Error> {
+    async fn get_summary(&self) -> Result<MutexGuard<'_, Option<wmapi::SummaryResp>>, Error> {
         let mut summary = self.summary.lock().await;
         if summary.is_none() {
             let resp = self.send_recv(&json!({"cmd": "summary"})).await?;
             if let Ok(s) = serde_json::from_str::<wmapi::Status>(&resp) {
-                println!("Summary API call failed: {}", s.msg);
                 return Err(Error::ApiCallFailed(s.msg));
             } else {
-                let resp = serde_json::from_str::<wmapi::SummaryResp>(&resp)?;
-                *summary = Some(resp);
+                *summary = Some(self.client.parse(&self.ip, "summary", &resp)?);
             }
         }
         Ok(summary)
     }
-
-    async fn invalidate(&self) {
-        let _ = self.summary.lock().await.take();
-    }
 }
 
 #[async_trait]
 impl Miner for Whatsminer {
     fn new(client: Client, ip: String, port: u16) -> Self {
+        let v3 = v3::V3Api::new(client.clone(), ip.clone(), v3::V3_PORT);
+        let cgminer = GenericCgminer::new(client.clone(), ip.clone(), port);
         Self {
             ip: ip.clone(),
             port,
             password: None,
             token: None,
+            write_mode: WriteMode::Encrypted,
             client,
+            detection: None,
+            tags: Tags::new(),
             cache: None,
+            write_api: AtomicBool::new(true),
+            protocol: Mutex::new(None),
+            v3,
+            cgminer,
             summary: Mutex::new(None),
+            v3_summary: Mutex::new(None),
             model: Mutex::new(None),
         }
     }
@@ -182,13 +425,66 @@ impl Miner for Whatsminer {
         "Whatsminer"
     }
 
+    /// LuCI hides the config files behind the admin login, so this only looks for open ports
+    async fn security_scan(&self) -> Result<Vec<SecurityIndicator>, Error> {
+        Ok(security::scan_ports(&self.client, &self.ip).await)
+    }
+
+    async fn invalidate(&self) {
+        let _ = self.summary.lock().await.take();
+        let _ = self.v3_summary.lock().await.take();
+    }
+
+    fn get_ip(&self) -> &str {
+        &self.ip
+    }
+
+    fn with_detection_info(mut self, info: DetectionInfo) -> Self {
+        // Only legacy firmware answers the socket probe, or cgminer once btminer has fallen back to it
+        if info.firmware.as_deref() == Some(WHATSMINER_CGMINER) {
+            self.protocol = Mutex::new(Some(Protocol::Cgminer));
+        } else if info.method == DetectionMethod::Socket {
+            self.protocol = Mutex::new(Some(Protocol::Legacy));
+        }
+        self.detection = Some(info);
+        self
+    }
+
+    fn detection_info(&self) -> Option<DetectionInfo> {
+        self.detection.clone()
+    }
+
+    fn tags(&self) -> Tags {
+        self.tags.clone()
+    }
+
+    fn set_tags(&mut self, tags: Tags) {
+        self.tags = tags;
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            write_api: self.write_api.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+
     async fn get_model(&self) -> Result<String, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_model().await;
+        }
         let mut model = self.model.lock().await;
 
+        if model.is_none() && self.is_v3().await? {
+            let device = self.v3_device().await?;
+            let name = device["miner"]["type"].as_str().ok_or(Error::ExpectedReturn)?;
+            // e.g. M60S_VK30
+            *model = name.split('_').next().map(|s| s.to_string());
+        }
         if model.is_none() {
             let resp = self.client.http_client
                 .get(format!("https://{}/cgi-bin/luci/admin/status/overview", self.ip))
-                .send()
+                .send_via(&self.client)
                 .await?
                 .text()
                 .await?;
@@ -204,21 +500,43 @@ impl Miner for Whatsminer {
         Ok(model.as_ref().unwrap_or_else(|| unreachable!()).clone())
     }
 
+    /// v3 credentials are the API account, e.g. super, only checked when a set command is signed
+    /// Legacy firmware without get_token is written to in plain JSON, firmware that can't decrypt
+    /// gets its token in plain JSON
     async fn auth(&mut self, username: &str, password: &str) -> Result<(), Error> {
-        self.password = Some(password.to_string());
+        self.password = Some(password.into());
+        if self.is_v3().await? {
+            self.v3.set_credentials(username, password);
+            self.v3.salt().await?;
+            return Ok(());
+        }
         let r = self.client.http_client
             .post(format!("https://{}/cgi-bin/luci", self.ip))
             .form(&[("luci_username", username), ("luci_password", password)])
-            .send()
+            .send_via(&self.client)
             .await?;
         if r.status() != 200 {
             return Err(Error::Unauthorized);
         }
-        self.token_cached().await?;
-        Ok(())
+        self.write_mode = WriteMode::Encrypted;
+        let negotiated = match self.token_cached().await {
+            Ok(()) => self.negotiate_write_mode().await,
+            Err(e) => Err(e),
+        };
+        match negotiated {
+            // Reads still work without the write API, capabilities() reports the restriction
+            Ok(()) | Err(Error::ApiDisabled) => Ok(()),
+            Err(e) => Err(e),
+        }
     }
 
     async fn reboot(&mut self) -> Result<(), Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.reboot().await;
+        }
+        if self.is_v3().await? {
+            return self.v3.call_auth::<Value>("set.system.reboot", None).await.map(|_| ());
+        }
         let js = json!({
             "command": "reboot",
         });
@@ -227,72 +545,147 @@ impl Miner for Whatsminer {
     }
 
     async fn get_hashrate(&self) -> Result<f64, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_hashrate().await;
+        }
+        if self.is_v3().await? {
+            let sum = self.v3_summary().await?;
+            return sum.as_ref().and_then(|s| s.hash_realtime).ok_or(Error::ExpectedReturn);
+        }
         let sum = self.get_summary().await?;
         let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
-        Ok(sum.summary[0].hashrate_ths())
+        Ok(sum.summary.first().ok_or(Error::ExpectedReturn)?.hashrate_ths())
+    }
+
+    async fn get_hashrate_stable(&self) -> Result<f64, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_hashrate_stable().await;
+        }
+        if self.is_v3().await? {
+            let sum = self.v3_summary().await?;
+            let sum = sum.as_ref().ok_or(Error::ExpectedReturn)?;
+            return sum.hash_average.or(sum.hash_realtime).ok_or(Error::ExpectedReturn);
+        }
+        let sum = self.get_summary().await?;
+        let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = sum.summary.first().ok_or(Error::InvalidResponse)?;
+        Ok(stable_hashrate(sum.elapsed as u64, sum.hashrate_ths(), sum.mhs_av / 1000000.0))
     }
 
     async fn get_power(&self) -> Result<f64, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_power().await;
+        }
+        if self.is_v3().await? {
+            let sum = self.v3_summary().await?;
+            return sum.as_ref().and_then(|s| s.power_realtime).ok_or(Error::ExpectedReturn);
+        }
         let sum = self.get_summary().await?;
         let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
 
-        Ok(sum.summary[0].power as f64)
+        Ok(sum.summary.first().ok_or(Error::ExpectedReturn)?.power as f64)
     }
 
     async fn get_nameplate_power(&self) -> Result<f64, Error> {
         let model = self.get_model().await?;
 
-        EFF_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(jth, watts)| jth * watts)
+        EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(jth, watts)| jth * watts)
     }
 
     async fn get_efficiency(&self) -> Result<f64, Error> {
-        if let Ok(sum) = self.get_summary().await {
+        if self.is_v3().await? {
+            if let (Ok(power), Ok(hashrate)) = (self.get_power().await, self.get_hashrate().await) {
+                if hashrate > 0.0 {
+                    return Ok(power / hashrate);
+                }
+            }
+        } else if let Ok(sum) = self.get_summary().await {
             let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
-            if sum.summary[0].hashrate_ths() > 0.0 {
-                return Ok(sum.summary[0].power as f64 / (sum.summary[0].hashrate_ths()));
+            if let Some(sum) = sum.summary.first().filter(|s| s.hashrate_ths() > 0.0) {
+                return Ok(sum.power as f64 / sum.hashrate_ths());
             }
         }
         // If we're not hashing return the dataspec efficiency
         let model = self.get_model().await?;
-        EFF_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(x, _)| *x)
+        EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(x, _)| *x)
     }
 
     async fn get_nameplate_rate(&self) -> Result<f64, Error> {
-        if let Ok(sum) = self.get_summary().await {
-            let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
-    
-            Ok(sum.summary[0].factory_ghs as f64 / 1000.0)
+        if self.is_v3().await? {
+            let factory = self.v3_summary().await.ok().and_then(|s| s.as_ref().and_then(|s| s.factory_hash));
+            if let Some(factory) = factory {
+                return Ok(factory);
+            }
+            let model = self.get_model().await?;
+            return EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(_, x)| *x);
+        }
+        let factory = self.get_summary().await.ok()
+            .and_then(|sum| sum.as_ref().and_then(|s| s.summary.first()).map(|s| s.factory_ghs as f64 / 1000.0));
+        if let Some(factory) = factory {
+            Ok(factory)
         } else {
             // If we're not hashing return the dataspec efficiency
             // Cause whatsminer .-.
             let model = self.get_model().await?;
-            EFF_MAP.get(model.as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(_, x)| *x)
+            EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(_, x)| *x)
         }
     }
 
     async fn get_temperature(&self) -> Result<f64, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_temperature().await;
+        }
+        if self.is_v3().await? {
+            let sum = self.v3_summary().await?;
+            return sum.as_ref().and_then(|s| s.chip_temp_max).ok_or(Error::ExpectedReturn);
+        }
         let sum = self.get_summary().await?;
         let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
 
-        Ok(sum.summary[0].temperature)
+        Ok(sum.summary.first().ok_or(Error::ExpectedReturn)?.temperature)
     }
 
     async fn get_fan_speed(&self) -> Result<Vec<u32>, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_fan_speed().await;
+        }
+        if self.is_v3().await? {
+            let sum = self.v3_summary().await?;
+            let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+            return Ok(vec![
+                sum.fan_speed_in.ok_or(Error::ExpectedReturn)?,
+                sum.fan_speed_out.ok_or(Error::ExpectedReturn)?,
+            ]);
+        }
         let sum = self.get_summary().await?;
         let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = sum.summary.first().ok_or(Error::ExpectedReturn)?;
 
-        Ok(vec![sum.summary[0].fan_speed_in, sum.summary[0].fan_speed_out])
+        Ok(vec![sum.fan_speed_in, sum.fan_speed_out])
     }
 
-    async fn get_fan_pwm(&self) -> Result<f64, Error> {
-        // Whatsminers don't have fan pwm, max fan speed is 7000 RPM
-        self.get_fan_speed().await?.iter()
-            .max()
-            .map(|x| (*x as f64 / 7000.0) * 100.0)
-            .ok_or(Error::ApiCallFailed("No fan speed".to_string()))
+    /// Whatsminers don't report fan pwm, get_fan_pwm goes from this
+    async fn get_max_fan_speed(&self) -> Result<u32, Error> {
+        Ok(7000)
+    }
+
+    async fn get_rated_efficiency(&self) -> Result<f64, Error> {
+        let model = self.get_model().await?;
+        EFF_MAP.get(normalize_model(&model).as_str()).ok_or(Error::UnknownModel(model.to_string())).map(|(jth, _)| *jth)
     }
 
     async fn get_pools(&self) -> Result<Vec<Pool>, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_pools().await;
+        }
+        if self.is_v3().await? {
+            let msg: v3::PoolsMsg = self.v3.call("get.miner.status", Some(json!("pools"))).await?;
+            return Ok(msg.pools.into_iter().map(|p| Pool {
+                url: p.url,
+                username: p.account,
+                password: None,
+            }).collect());
+        }
         let resp = self.send_recv(&json!({"cmd":"pools"})).await?;
         let pools: common::PoolsResp = serde_json::from_str(&resp)?;
         Ok(pools.pools.iter().map(|p| Pool {
@@ -302,30 +695,69 @@ impl Miner for Whatsminer {
         }).collect())
     }
 
+    async fn get_pool_counters(&self) -> Result<Vec<PoolCounters>, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_pool_counters().await;
+        }
+        if self.is_v3().await? {
+            let msg: v3::PoolsMsg = self.v3.call("get.miner.status", Some(json!("pools"))).await?;
+            return Ok(msg.pools.into_iter().map(|p| PoolCounters {
+                url: p.url,
+                accepted: p.accepted,
+                rejected: p.rejected,
+                stale: p.stale,
+            }).collect());
+        }
+        let resp = self.send_recv(&json!({"cmd":"pools"})).await?;
+        let pools: common::PoolsResp = serde_json::from_str(&resp)?;
+        Ok(pools.pools.into_iter().map(|p| PoolCounters {
+            url: p.url,
+            accepted: p.accepted as u64,
+            rejected: p.rejected as u64,
+            stale: p.stale as u64,
+        }).collect())
+    }
+
     async fn set_pools(&mut self, pools: Vec<Pool>) -> Result<(), Error> {
-        //TODO: this can panic
-        let js = json!({
-            "cmd": "update_pools",
-            "pool1": pools[0].url,
-            "worker1": pools[0].username,
-            "passwd1": pools[0].password,
-            "pool2": pools[1].url,
-            "worker2": pools[1].username,
-            "passwd2": pools[1].password,
-            "pool3": pools[2].url,
-            "worker3": pools[2].username,
-            "passwd3": pools[2].password,
-        });
+        if self.is_cgminer().await? {
+            return self.cgminer.set_pools(pools).await;
+        }
+        if pools.len() > MAX_POOLS {
+            return Err(Error::TooManyPools { given: pools.len(), max: MAX_POOLS });
+        }
+        if self.is_v3().await? {
+            let pools: Vec<Value> = pools.iter().map(|p| json!({
+                "pool": p.url,
+                "worker": p.username,
+                "passwd": p.password.as_ref().map(|p| p.expose()).unwrap_or_default(),
+            })).collect();
+            self.v3.call_auth::<Value>("set.miner.pools", Some(json!(pools))).await?;
+            self.invalidate().await;
+            return Ok(());
+        }
+        let js = update_pools_cmd(&pools)?;
         let _ = self.send_recv_enc(js).await?;
         self.invalidate().await;
         Ok(())
     }
 
     async fn get_sleep(&self) -> Result<bool, Error> {
+        // Only btminer can power off the boards, with it down the miner can't be asleep
+        if self.is_cgminer().await? {
+            return Ok(false);
+        }
         // Grrrrrr, cg/btminer isn't always present in the process listing...
         if self.get_hashrate().await? > 0.0 {
             return Ok(false);
         }
+        if self.is_v3().await? {
+            let working = &self.v3_device().await?["miner"]["working"];
+            return match working {
+                Value::Bool(working) => Ok(!working),
+                Value::String(working) => Ok(working != "true"),
+                _ => Err(Error::ExpectedReturn),
+            };
+        }
         //This doesn't work for miners running cgminer
         let resp = self.send_recv(&json!({"cmd":"status"})).await;
         let sleep_stat = match resp {
@@ -350,8 +782,8 @@ impl Miner for Whatsminer {
         // Double check that cgminer isn't running
         // Scrape the web API yet again
         if let Ok(r) = self.client.http_client
-            .get(&format!("https://{}/cgi-bin/luci/admin/status/processes", self.ip))
-            .send()
+            .get(format!("https://{}/cgi-bin/luci/admin/status/processes", self.ip))
+            .send_via(&self.client)
             .await {
                 if let Ok(r) = r.text().await {
                     let re = regex!(r#".COMMAND" value="(cg|bt)miner" />"#);
@@ -362,7 +794,36 @@ impl Miner for Whatsminer {
         return Ok(sleep_stat)
     }
 
+    async fn get_power_state(&self) -> Result<PowerState, Error> {
+        if self.is_cgminer().await? {
+            return Ok(PowerState::Mining);
+        }
+        let hashing = self.get_hashrate().await? > 0.0;
+        if hashing {
+            // power_off is accepted before the boards actually stop
+            return match self.get_mineroff().await {
+                Some(true) => Ok(PowerState::Transitioning),
+                _ => Ok(PowerState::Mining),
+            };
+        }
+        // btminer comes back well before the boards start hashing, resuming takes minutes
+        if self.get_sleep().await? {
+            Ok(PowerState::Sleeping)
+        } else {
+            Ok(PowerState::Transitioning)
+        }
+    }
+
     async fn set_sleep(&mut self, sleep: bool) -> Result<(), Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.set_sleep(sleep).await;
+        }
+        if self.is_v3().await? {
+            let service = if sleep { "stop" } else { "start" };
+            self.v3.call_auth::<Value>("set.miner.service", Some(json!(service))).await?;
+            self.invalidate().await;
+            return Ok(());
+        }
         let js = match sleep {
             true => json!({
                 "cmd": "power_off",
@@ -374,30 +835,31 @@ impl Miner for Whatsminer {
         };
         let resp = self.send_recv_enc(js).await;
         match (sleep, resp) {
-            (true, Err(e)) => {
-                // If the error was a timeout assume we're sleeping
-                if let Error::Timeout = e {
-                    Ok(())
-                } else {
-                    Err(e)
-                }
-            },
+            // respbefore isn't always honoured, the connection can drop as the boards power down
+            // confirm_sleep tells whether it was acted on
+            (true, Err(Error::Timeout)) => {},
             (_, Ok(resp)) => {
                 let stat = serde_json::from_str::<wmapi::Status>(&resp)?;
-                if stat.status == StatusCode::SUCC {
-                    self.invalidate().await;
-                    Ok(())
-                } else {
-                    Err(Error::ApiCallFailed(stat.msg))
+                if stat.status != StatusCode::SUCC {
+                    return Err(Error::ApiCallFailed(stat.msg));
                 }
             },
-            (_, Err(e)) => Err(e),
+            (_, Err(e)) => return Err(e),
         }
+        self.invalidate().await;
+        Ok(())
     }
 
     async fn get_blink(&self) -> Result<bool, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_blink().await;
+        }
+        if self.is_v3().await? {
+            let device = self.v3_device().await?;
+            return device["system"]["ledstatus"].as_str().map(|s| s != "auto").ok_or(Error::ExpectedReturn);
+        }
         let resp = self.send_recv(&json!({"cmd":"get_miner_info"})).await?;
-        if let Ok(_) = serde_json::from_str::<wmapi::Status>(&resp) {
+        if serde_json::from_str::<wmapi::Status>(&resp).is_ok() {
             // We could error or assume not hashing
             // Err(Error::ApiCallFailed(status.msg))
             Ok(false)
@@ -408,6 +870,16 @@ impl Miner for Whatsminer {
     }
 
     async fn set_blink(&mut self, blink: bool) -> Result<(), Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.set_blink(blink).await;
+        }
+        if self.is_v3().await? {
+            let param = match blink {
+                true => json!({"color": "red", "period": 1000, "duration": 500, "start": 0}),
+                false => json!("auto"),
+            };
+            return self.v3.call_auth::<Value>("set.system.led", Some(param)).await.map(|_| ());
+        }
         let js = match blink {
             true => json!({
                 "command": "set_led",
@@ -427,27 +899,26 @@ impl Miner for Whatsminer {
     }
 
     async fn get_logs(&mut self) -> Result<Vec<String>, Error> {
-        if let Some(token) = &self.token {
-            let js = token.encrypt(&json!({
-                "command": "download_logs",
-                "token": token.get_token(),
-            }))?;
+        if self.is_cgminer().await? {
+            return self.cgminer.get_logs().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        if self.token.is_some() || self.write_mode == WriteMode::Plain {
+            let js = self.write_request(json!({"command": "download_logs"}))?;
             // This responds in 2 parts, the first part is a status response for the command
             // the second part is the logs sent 10ms after the first part.
-            let mut stream = TcpStream::connect(format!("{}:{}", &self.ip, self.port)).await?;
-            stream.writable().await?;
+            let mut stream = self.client.connect(&self.ip, self.port).await?;
             stream.write_all(js.to_string().as_bytes()).await?;
-            let mut resp = String::new();
-            stream.readable().await?;
-            stream.read_to_string(&mut resp).await?;
-            resp = resp.replace("\0", "");
-            
-            let status: LogsResponse = serde_json::from_str(&resp)?;
+            // Only read the status part here, the logs may already be behind it in the buffer
+            let (status, mut logs) = read_framed(&mut stream, Framing::Json).await?;
+            let status = String::from_utf8_lossy(&status).replace("\0", "");
+
+            let status: LogsResponse = serde_json::from_str(&status)?;
             if status.status == common::StatusCode::SUCC {
-                let mut resp = String::new();
-                stream.readable().await?;
-                stream.read_to_string(&mut resp).await?;
-                resp = resp.replace("\0", "");
+                stream.read_to_end(&mut logs).await?;
+                let resp = String::from_utf8_lossy(&logs).replace("\0", "");
                 Ok(resp.split('\n').map(|s| s.to_string()).collect())
             } else {
                 //println!("Failed to get logs");
@@ -458,13 +929,61 @@ impl Miner for Whatsminer {
         }
     }
 
+    async fn debug_dump(&self) -> Result<Value, Error> {
+        let mut dump = serde_json::Map::new();
+        if self.is_v3().await? {
+            let calls = [("get.miner.status", Some(json!("summary"))), ("get.miner.status", Some(json!("pools"))), ("get.miner.status", Some(json!("edevs"))), ("get.device.info", None)];
+            let payloads = futures::future::join_all(calls.iter().map(|(cmd, param)| self.v3.call_raw(cmd, param.clone()))).await;
+            for ((cmd, param), payload) in calls.iter().zip(payloads) {
+                let key = match param {
+                    Some(param) => format!("{} {}", cmd, param.as_str().unwrap_or_default()),
+                    None => cmd.to_string(),
+                };
+                dump.insert(key, dump_entry(payload.map(|body| raw_json(&body))));
+            }
+        } else {
+            let cmds = ["summary", "pools", "edevs", "devdetails", "get_version", "get_miner_info", "status"];
+            // Straight off the socket, send_recv patches up the JSON btminer gets wrong
+            let payloads = futures::future::join_all(cmds.iter().map(|cmd| async move {
+                let cmd = json!({"cmd": cmd});
+                self.client.send_recv_framed(&self.ip, self.port, &cmd, Framing::Json).await
+            })).await;
+            for (cmd, payload) in cmds.iter().zip(payloads) {
+                dump.insert(cmd.to_string(), dump_entry(payload.map(|body| raw_json(&body))));
+            }
+        }
+        Ok(Value::Object(dump))
+    }
+
+    /// Only the summary is cached, it also carries the stats and system fields btminer reports
+    async fn prefetch(&self, resources: &[Resource]) -> Result<(), Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.prefetch(resources).await;
+        }
+        if resources.is_empty() {
+            return Ok(());
+        }
+        if self.is_v3().await? {
+            self.v3_summary().await.map(drop)
+        } else {
+            self.get_summary().await.map(drop)
+        }
+    }
+
     async fn get_mac(&self) -> Result<String, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_mac().await;
+        }
+        if self.is_v3().await? {
+            let device = self.v3_device().await?;
+            return device["network"]["mac"].as_str().map(|s| s.to_string()).ok_or(Error::ExpectedReturn);
+        }
         let resp = self.send_recv(&json!({"cmd":"get_miner_info"})).await?;
-        if let Ok(_) = serde_json::from_str::<wmapi::Status>(&resp) {
+        if serde_json::from_str::<wmapi::Status>(&resp).is_ok() {
             // Older API version
             let sum = self.get_summary().await?;
             let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
-            sum.summary[0].mac.clone().ok_or(Error::ApiCallFailed("Failed to get MAC".to_string()))
+            sum.summary.first().ok_or(Error::ExpectedReturn)?.mac.clone().ok_or(Error::ApiCallFailed("Failed to get MAC".to_string()))
         } else {
             let resp: wmapi::MinerInfoResponse = serde_json::from_str(&resp)?;
             Ok(resp.msg.mac.clone())
@@ -472,30 +991,81 @@ impl Miner for Whatsminer {
     }
 
     async fn get_errors(&mut self) -> Result<Vec<MinerError>, Error> {
-        let resp = self.send_recv(&json!({"cmd":"get_error_code"})).await?;
-        // Whatsminer again returning invalid JSON
-        //{"error_code":["111":"2022-10-20 09:18:54","110":"2022-10-20 09:18:54","2010":"1970-01-02 08:00:04"]}
-        //TODO: it might be cheaper to regex this
-        let resp = resp.replace("[", "{").replace("]", "}");
-        let resp = serde_json::from_str::<wmapi::ErrorResp>(&resp)?;
-        // Our response is a hashmap of error_code : datetime
-        // I only care about the error codes, throw them into a single string to regex against
-        let log = resp.msg.error_code.keys()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
-        let mut errors = HashSet::new();
-        for err in WHATSMINER_ERRORS.iter() {
-            if let Some(msg) = err.get_err(&log) {
-                errors.insert(msg);
-            }
+        if self.is_cgminer().await? {
+            return self.cgminer.get_errors().await;
         }
+        let errors: HashSet<MinerError> = self.error_codes().await?.iter()
+            .filter_map(|c| c.trim().parse::<u32>().ok())
+            .map(code_error)
+            .collect();
         Ok(errors.into_iter().collect())
     }
 
+    /// Running on the cgminer fallback is reported here rather than as a miner error
+    async fn get_health(&self) -> Result<Vec<HealthCondition>, Error> {
+        if self.is_cgminer().await? {
+            return Ok(vec![HealthCondition::CgminerFallback]);
+        }
+        Ok(Vec::new())
+    }
+
+    /// btminer's power mode, Normal is the Default profile
+    async fn get_profile(&self) -> Result<Profile, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_profile().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        let sum = self.get_summary().await?;
+        let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let mode = &sum.summary.first().ok_or(Error::InvalidResponse)?.power_mode;
+        Ok(match mode.as_str() {
+            "Normal" => Profile::Default,
+            "Low" => Profile::LowPower,
+            name => Profile::Unknown { name: name.to_string() },
+        })
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_profiles().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        Ok(vec![Profile::Default, Profile::LowPower])
+    }
+
+    /// btminer restarts into the new power mode
+    async fn set_profile(&mut self, profile: Profile) -> Result<(), Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.set_profile(profile).await;
+        }
+        let cmd = match profile.validate(&self.get_profiles().await?)? {
+            Profile::Default => "set_normal_power",
+            Profile::LowPower => "set_low_power",
+            _ => return Err(Error::NotSupported),
+        };
+        let resp = self.send_recv_enc(json!({"cmd": cmd})).await?;
+        let stat = serde_json::from_str::<wmapi::Status>(&resp)?;
+        if stat.status != StatusCode::SUCC {
+            return Err(Error::ApiCallFailed(stat.msg));
+        }
+        self.invalidate().await;
+        Ok(())
+    }
+
     async fn get_dns(&self) -> Result<String, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_dns().await;
+        }
+        if self.is_v3().await? {
+            let device = self.v3_device().await?;
+            return device["network"]["dns"].as_str().map(|s| s.to_string()).ok_or(Error::ExpectedReturn);
+        }
         let resp = self.send_recv(&json!({"cmd":"get_miner_info"})).await?;
-        if let Ok(_) = serde_json::from_str::<wmapi::Status>(&resp) {
+        if serde_json::from_str::<wmapi::Status>(&resp).is_ok() {
             Err(Error::NotSupported)
         } else {
             let resp: wmapi::MinerInfoResponse = serde_json::from_str(&resp)?;
@@ -503,19 +1073,314 @@ impl Miner for Whatsminer {
         }
     }
 
-    async fn get_profile(&self) -> Result<Profile, Error> {
-        Err(Error::NotSupported)
+    /// net_config takes the whole static config, so a DHCP miner can't have its DNS changed alone
+    async fn set_dns(&mut self, servers: Vec<IpAddr>) -> Result<(), Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.set_dns(servers).await;
+        }
+        if servers.is_empty() {
+            return Err(Error::InvalidConfig("At least one DNS server is needed".into()));
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        let resp = self.send_recv(&json!({"cmd":"get_miner_info"})).await?;
+        if serde_json::from_str::<wmapi::Status>(&resp).is_ok() {
+            return Err(Error::NotSupported);
+        }
+        let info = serde_json::from_str::<wmapi::MinerInfoResponse>(&resp)?.msg;
+        let js = net_config_cmd(&info, &servers)?;
+        let _ = self.send_recv_enc(js).await?;
+        self.invalidate().await;
+        Ok(())
     }
 
-    async fn get_profiles(&self) -> Result<Vec<Profile>, Error> {
-        Err(Error::NotSupported)
+    /// Target MHS is what the current power mode is tuned for, Hash Stable is false until it settles there
+    async fn get_tuning_status(&self) -> Result<TuningStatus, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_tuning_status().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        let sum = self.get_summary().await?;
+        let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = sum.summary.first().ok_or(Error::InvalidResponse)?;
+        Ok(TuningStatus::new(sum.target_mhs / 1000000.0, sum.hashrate_ths(), !sum.hash_stable))
+    }
+
+    /// btminer doesn't report how many chips a board should have
+    async fn get_chip_counts(&self) -> Result<Vec<ChipCount>, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_chip_counts().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        let resp: Value = serde_json::from_str(&self.send_recv(&json!({"cmd":"edevs"})).await?)?;
+        chip_counts(&resp)
+    }
+
+    async fn get_board_temps(&self) -> Result<Vec<BoardTemps>, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_board_temps().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        let resp: Value = serde_json::from_str(&self.send_recv(&json!({"cmd":"edevs"})).await?)?;
+        slot_temps(&resp)
+    }
+
+    /// Chip min, max and average are the firmware's own over every chip, v3 only reports the max
+    async fn get_temperatures(&self) -> Result<TempReport, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_temperatures().await;
+        }
+        if self.is_v3().await? {
+            let sum = self.v3_summary().await?;
+            let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+            return Ok(TempReport {
+                chip_max: sum.chip_temp_max,
+                environment: sum.environment_temperature,
+                ..Default::default()
+            });
+        }
+        let boards = self.get_board_temps().await?;
+        let sum = self.get_summary().await?;
+        let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = sum.summary.first().ok_or(Error::ExpectedReturn)?;
+        Ok(TempReport {
+            chip_min: sensor_temp(sum.chip_temp_min),
+            chip_max: sensor_temp(sum.chip_temp_max),
+            chip_avg: sensor_temp(sum.chip_temp_avg),
+            environment: sum.env_temp,
+            boards,
+        })
+    }
+
+    /// Hydro units measure Env Temp at the coolant inlet, 507x is a board's water velocity alarm
+    async fn get_cooling_detail(&self) -> Result<CoolingDetail, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_cooling_detail().await;
+        }
+        let flow_alarms = flow_alarms(&self.error_codes().await?);
+        if self.is_v3().await? {
+            return Ok(CoolingDetail { flow_alarms, ..Default::default() });
+        }
+        let sum = self.get_summary().await?;
+        let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = sum.summary.first().ok_or(Error::InvalidResponse)?;
+        let liquid = sum.liquid_cooling.unwrap_or(false);
+        Ok(CoolingDetail {
+            liquid,
+            coolant_in: if liquid { sum.env_temp } else { None },
+            flow_alarms,
+            ..Default::default()
+        })
     }
 
-    async fn set_profile(&mut self, _profile: Profile) -> Result<(), Error> {
-        Err(Error::NotSupported)
+    async fn get_system_stats(&self) -> Result<SystemStats, Error> {
+        if self.is_cgminer().await? {
+            return self.cgminer.get_system_stats().await;
+        }
+        if self.is_v3().await? {
+            return Err(Error::NotSupported);
+        }
+        let sum = self.get_summary().await?;
+        let sum = sum.as_ref().unwrap_or_else(|| unreachable!());
+        let sum = sum.summary.first().ok_or(Error::InvalidResponse)?;
+        Ok(SystemStats {
+            mem_free: sum.free_memory,
+            uptime: Some(sum.uptime as u64),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_update_pools_cmd() {
+        let pool = Pool {
+            url: "stratum+tcp://pool.example.com:3333".to_string(),
+            username: "worker.1".to_string(),
+            password: Some("x".into()),
+        };
+        let js = update_pools_cmd(&[pool.clone()]).unwrap();
+        assert_eq!(js["pool1"], "stratum+tcp://pool.example.com:3333");
+        assert_eq!(js["passwd1"], "x");
+        assert_eq!(js["pool2"], "");
+        assert_eq!(js["worker3"], "");
+        assert_eq!(js["passwd3"], "");
+        assert!(matches!(update_pools_cmd(&vec![pool; 4]), Err(Error::TooManyPools { given: 4, max: 3 })));
+    }
+
+    #[test]
+    fn test_net_config_cmd() {
+        let mut info: wmapi::MinerInfo = serde_json::from_value(json!({"ip":"10.0.4.22","proto":"static","netmask":"255.255.254.0",
+            "dns":"10.0.5.254","mac":"C4:11:04:01:2A:3B","ledstat":"auto","gateway":"10.0.5.254","hostname":"WhatsMiner"})).unwrap();
+        let js = net_config_cmd(&info, &["1.1.1.1".parse().unwrap(), "8.8.8.8".parse().unwrap()]).unwrap();
+        assert_eq!(js["dns"], "1.1.1.1 8.8.8.8");
+        assert_eq!(js["ip"], "10.0.4.22");
+        assert_eq!(js["host"], "WhatsMiner");
+
+        info.proto = "dhcp".to_string();
+        assert!(matches!(net_config_cmd(&info, &["1.1.1.1".parse().unwrap()]), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_chip_counts() {
+        let resp = json!({"STATUS":[{"STATUS":"S","Msg":"EDevs"}],"DEVS":[
+            {"ASC":0,"Slot":0,"Enabled":"Y","Status":"Alive","Effective Chips":156},
+            {"ASC":1,"Slot":1,"Enabled":"Y","Status":"Alive","Effective Chips":148},
+            {"ASC":2,"Slot":2,"Enabled":"Y","Status":"Alive","Effective Chips":156}
+        ]});
+        let counts = chip_counts(&resp).unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[1], ChipCount { chain: 1, expected: None, detected: 148 });
+        assert!(!counts[1].degraded());
+    }
+
+    #[test]
+    fn test_slot_temps() {
+        let resp = json!({"STATUS":[{"STATUS":"S","Msg":"EDevs"}],"DEVS":[
+            {"ASC":0,"Slot":0,"Temperature":78.5,"Chip Temp Min":69.19,"Chip Temp Max":97.58,"Chip Temp Avg":86.19},
+            {"ASC":1,"Slot":1,"Temperature":0.0,"Chip Temp Min":0.0,"Chip Temp Max":0.0,"Chip Temp Avg":0.0},
+            {"ASC":2,"Slot":2,"Temperature":77.0}
+        ]});
+        let temps = slot_temps(&resp).unwrap();
+        assert_eq!(temps[0], BoardTemps { chain: 0, chip: vec![], pcb: vec![Some(78.5)], chip_min: Some(69.19), chip_max: Some(97.58) });
+        assert_eq!(temps[1].failed_sensors(), 1);
+        assert_eq!(temps[1].max_chip(), None);
+        assert_eq!(temps[2].chip_min, None);
+        let report = TempReport::from_boards(temps);
+        assert_eq!(report.chip_min, Some(69.19));
+        assert_eq!(report.chip_max, Some(97.58));
+        // Nothing to average without the chips themselves
+        assert_eq!(report.chip_avg, None);
+    }
+
+    #[test]
+    fn test_flow_alarms() {
+        let codes: Vec<String> = ["5072", "110", "5070", "5072", "50710"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(flow_alarms(&codes), vec![0, 2]);
+    }
+
+    const BTMINER_VERSION: &str = r#"{"STATUS":"S","When":1700000000,"Code":131,"Msg":{"api_ver":"2.0.5","fw_ver":"20230911.22.Rel"},"Description":""}"#;
+    const BTMINER_SUMMARY: &str = r#"{"STATUS":[{"STATUS":"S","Msg":"Summary"}],"SUMMARY":[{"Elapsed":10256,"MHS av":86344408.19,"MHS 5s":104558122.51,"MHS 1m":87932837.07,"MHS 5m":86351357.73,"MHS 15m":86295510.58,"HS RT":86351357.73,"Accepted":786,"Rejected":2,"Total MH":885555462530.0000,"Temperature":77.75,"freq_avg":650,"Fan Speed In":2880,"Fan Speed Out":2850,"Power":3431,"Power Rate":39.73,"Pool Rejected%":0.2611,"Pool Stale%":0.0000,"Uptime":10974,"Security Mode":0,"Hash Stable":true,"Hash Stable Cost Seconds":426,"Hash Deviation%":0.0559,"Target Freq":637,"Target MHS":85788612,"Env Temp":13.25,"Power Mode":"Normal","Factory GHS":86022,"Power Limit":3600,"Chip Temp Min":69.19,"Chip Temp Max":97.58,"Chip Temp Avg":86.19,"Debug":"","Btminer Fast Boot":"disable"}],"id":1}"#;
+
+    fn scripted(script: &crate::transport::ScriptedSocket) -> Whatsminer {
+        let client = crate::ClientBuilder::new().socket_transport(script.clone()).build().unwrap();
+        Whatsminer::new(client, "10.0.0.1".into(), 4028)
+    }
+
+    #[tokio::test]
+    async fn test_scripted_transport() {
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"summary""#, BTMINER_SUMMARY);
+        let miner = scripted(&script);
+        assert_eq!(miner.get_hashrate().await.unwrap(), 86.35135773);
+        assert_eq!(miner.get_power().await.unwrap(), 3431.0);
+        assert!(miner.get_health().await.unwrap().is_empty());
+        // The summary is cached, the protocol is only negotiated once
+        assert_eq!(script.requests(), vec![r#"{"cmd":"get_version"}"#, r#"{"cmd":"summary"}"#]);
+    }
+
+    #[tokio::test]
+    async fn test_cgminer_fallback() {
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""cmd":"get_version""#, r#"{"STATUS":[{"STATUS":"E","Msg":"Invalid command"}]}"#)
+            .reply(r#""command":"summary""#, r#"{"STATUS":[{"STATUS":"S"}],"SUMMARY":[{"GHS 5s":"13512.37"}]}"#);
+        let mut miner = scripted(&script);
+        assert_eq!(miner.get_health().await.unwrap(), vec![HealthCondition::CgminerFallback]);
+        assert_eq!(miner.get_hashrate().await.unwrap(), 13.51237);
+        assert_eq!(script.requests().last().map(String::as_str), Some(r#"{"command":"summary"}"#));
+
+        // cgminer has no equivalent, nothing btminer only is sent to it
+        let sent = script.requests().len();
+        assert!(matches!(miner.get_tuning_status().await, Err(Error::NotSupported)));
+        assert!(matches!(miner.get_logs().await, Err(Error::NotSupported)));
+        assert!(matches!(miner.set_dns(vec!["1.1.1.1".parse().unwrap()]).await, Err(Error::NotSupported)));
+        assert_eq!(script.requests().len(), sent);
+    }
+
+    #[tokio::test]
+    async fn test_write_mode() {
+        const TOKEN: &str = r#"{"STATUS":"S","When":1700000000,"Code":134,"Msg":{"time":"8433","salt":"BQ5hoXV9","newsalt":"jbzkfQls"},"Description":""}"#;
+        const INVALID: &str = r#"{"STATUS":"E","When":1700000000,"Code":14,"Msg":"invalid cmd","Description":""}"#;
+        let authed = |socket: &crate::transport::ScriptedSocket| {
+            let http = crate::transport::ScriptedHttp::new().reply(reqwest::Method::POST, "/cgi-bin/luci", 200, "");
+            let client = crate::ClientBuilder::new().socket_transport(socket.clone()).http_transport(http).build().unwrap();
+            Whatsminer::new(client, "10.0.0.1".into(), 4028)
+        };
+
+        // Firmware that can't decrypt the probe envelope
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""enc""#, INVALID)
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"get_token""#, TOKEN)
+            .reply(r#""command":"reboot""#, r#"{"STATUS":"S","When":1700000000,"Code":131,"Msg":"","Description":""}"#);
+        let mut miner = authed(&script);
+        miner.auth("admin", "admin").await.unwrap();
+        assert_eq!(miner.write_mode, WriteMode::PlainToken);
+        miner.reboot().await.unwrap();
+        let reboot = script.requests().pop().unwrap();
+        assert!(reboot.contains(r#""token""#) && !reboot.contains(r#""enc""#));
+
+        // Decrypting firmware answers the probe in its own envelope
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""enc""#, r#"{"enc":"AAAAAAAAAAAAAAAAAAAAAA=="}"#)
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"get_token""#, TOKEN);
+        let mut miner = authed(&script);
+        miner.auth("admin", "admin").await.unwrap();
+        assert_eq!(miner.write_mode, WriteMode::Encrypted);
+
+        // Early firmware without get_token
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"get_token""#, INVALID);
+        let mut miner = authed(&script);
+        miner.auth("admin", "admin").await.unwrap();
+        assert_eq!(miner.write_mode, WriteMode::Plain);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_confirm_sleep() {
+        let status = |mineroff: &str| format!(r#"{{"STATUS":"S","When":1700000000,"Code":131,"Msg":{{"mineroff":"{}","FirmwareVersion":"20230911.22.Rel","power_mode":"Normal","hash_percent":"100"}},"Description":""}}"#, mineroff);
+        // Still hashing with mining off is a power_off on its way
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"summary""#, BTMINER_SUMMARY)
+            .reply(r#""cmd":"status""#, &status("true"));
+        assert!(scripted(&script).confirm_sleep(true, Duration::from_secs(12)).await.is_ok());
+
+        // A power_off whose reply was lost and never acted on
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"summary""#, BTMINER_SUMMARY)
+            .reply(r#""cmd":"status""#, &status("false"));
+        let miner = scripted(&script);
+        assert!(matches!(miner.confirm_sleep(true, Duration::from_secs(12)).await, Err(Error::VerificationTimeout(_))));
+        assert!(miner.confirm_sleep(false, Duration::from_secs(12)).await.is_ok());
     }
 
-    async fn get_hashboard(&mut self) -> Result<String, Error> {
-        Err(Error::NotSupported)
+    #[tokio::test]
+    async fn test_profiles() {
+        let script = crate::transport::ScriptedSocket::new()
+            .reply(r#""cmd":"get_version""#, BTMINER_VERSION)
+            .reply(r#""cmd":"summary""#, BTMINER_SUMMARY)
+            .reply(r#""cmd":"set_low_power""#, r#"{"STATUS":"S","When":1700000000,"Code":131,"Msg":"","Description":""}"#);
+        let mut miner = scripted(&script);
+        miner.write_mode = WriteMode::Plain;
+        assert_eq!(miner.get_profile().await.unwrap(), Profile::Default);
+        assert!(matches!(miner.set_profile(Profile::Preset { name: "High".into(), power: 3800.0, ths: 100.0 }).await, Err(Error::InvalidProfile(_))));
+        miner.set_profile(Profile::LowPower).await.unwrap();
+        assert_eq!(script.requests().last().map(String::as_str), Some(r#"{"cmd":"set_low_power"}"#));
     }
 }
@@ -11,6 +11,8 @@ pub struct MinerInfo {
     pub mac: String,
     pub ledstat: String,
     pub gateway: String,
+    #[serde(default)]
+    pub hostname: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -16,6 +16,23 @@ pub struct Status {
     pub description: Option<String>,
 }
 
+/// btminer answers with this code when the API has been switched off by an admin
+pub const API_DISABLED_CODE: usize = 45;
+
+/// cgminer's invalid command code, early firmware sends it for get_token and encrypted writes
+pub const INVALID_CMD_CODE: usize = 14;
+
+impl Status {
+    pub fn is_api_disabled(&self) -> bool {
+        self.status == StatusCode::ERROR && self.code == Some(API_DISABLED_CODE)
+    }
+
+    pub fn is_invalid_cmd(&self) -> bool {
+        self.status == StatusCode::ERROR
+            && (self.code == Some(INVALID_CMD_CODE) || self.msg.eq_ignore_ascii_case("invalid cmd"))
+    }
+}
+
 fn deserialize_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: de::Deserializer<'de>,
@@ -96,6 +113,27 @@ mod tests {
         assert_eq!(status.firmware_version(), "1.0.0");
     }
 
+    #[test]
+    fn test_api_disabled() {
+        let json = r#"{"STATUS":"E","When":1669118585,"Code":45,"Msg":"Permission denied","Description":""}"#;
+        let status: Status = serde_json::from_str(json).unwrap();
+        assert!(status.is_api_disabled());
+        let json = r#"{"STATUS":"E","When":1669118585,"Code":14,"Msg":"invalid cmd","Description":""}"#;
+        let status: Status = serde_json::from_str(json).unwrap();
+        assert!(!status.is_api_disabled());
+        assert!(status.is_invalid_cmd());
+    }
+
+    #[test]
+    fn test_invalid_cmd() {
+        let json = r#"{"STATUS":"E","Msg":"Invalid Cmd"}"#;
+        let status: Status = serde_json::from_str(json).unwrap();
+        assert!(status.is_invalid_cmd());
+        let json = r#"{"STATUS":"S","When":1669118585,"Code":131,"Msg":"API command OK","Description":""}"#;
+        let status: Status = serde_json::from_str(json).unwrap();
+        assert!(!status.is_invalid_cmd());
+    }
+
     #[test]
     fn test_bt_statusv2() {
         let json = r#"{"mineroff":"true","FirmwareVersion":"1.0.0","power_mode":"","hash_percent":""}"#;
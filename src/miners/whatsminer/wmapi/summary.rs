@@ -81,6 +81,9 @@ pub struct Summary {
     pub last_getwork: Option<usize>,
     #[serde(rename = "Uptime")]
     pub uptime: usize,
+    /// KiB, only on newer firmware
+    #[serde(rename = "Free Memory")]
+    pub free_memory: Option<u64>,
     // #[serde(rename = "Power Current")]
     // pub power_current: Option<f64>,
     #[serde(rename = "Power Fanspeed")]
@@ -1,8 +1,10 @@
+use std::fmt;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sha2::{Sha256, Digest};
 use openssl::symm::{Cipher, Crypter, Mode};
 use base64;
+use zeroize::Zeroize;
 use chrono::{Duration, Utc, DateTime};
 
 use crate::error::Error;
@@ -43,7 +45,7 @@ impl TokenResponse {
             let hashkey = do_md5_crypt(password.as_bytes(), &token.salt)?;
             let key = hashkey.split('$').nth(3).unwrap();
             let mut sha256 = Sha256::new();
-            sha256.update(&key);
+            sha256.update(key);
             let aeskey = sha256.finalize_reset().to_vec();
             
             let hashkey2 = do_md5_crypt((key.to_string() + &token.time).as_bytes(), &token.newsalt)?;
@@ -59,13 +61,31 @@ impl TokenResponse {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize)]
 pub struct WhatsminerToken {
     token: String,
     pub expires: DateTime<Utc>,
     cipher: Vec<u8>,
 }
 
+impl Drop for WhatsminerToken {
+    fn drop(&mut self) {
+        self.token.zeroize();
+        self.cipher.zeroize();
+    }
+}
+
+/// The session token and AES key stay out of logs, like SecretString
+impl fmt::Debug for WhatsminerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WhatsminerToken")
+            .field("token", &"***")
+            .field("expires", &self.expires)
+            .field("cipher", &"***")
+            .finish()
+    }
+}
+
 impl WhatsminerToken {
     #[inline]
     pub fn is_expired(&self) -> bool {
@@ -121,3 +141,21 @@ impl WhatsminerToken {
         &self.token
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_redacted() {
+        let token = WhatsminerToken {
+            token: "s3cret".into(),
+            expires: Utc::now(),
+            cipher: vec![0xab; 32],
+        };
+        let debug = format!("{:?}", token);
+        assert!(!debug.contains("s3cret"));
+        assert!(!debug.contains("171"));
+        assert!(debug.contains("expires"));
+    }
+}
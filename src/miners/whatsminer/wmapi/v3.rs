@@ -0,0 +1,262 @@
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use sha2::{Sha256, Digest};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::{Client, SecretString};
+use crate::error::Error;
+use crate::util::framing::{Framing, read_framed};
+
+/// Port the v3 API listens on, M6X/M7X firmware no longer opens 4028
+pub const V3_PORT: u16 = 4433;
+
+/// Request as sent on the wire, a little endian length then the JSON
+pub fn frame(req: &Value) -> Vec<u8> {
+    let body = req.to_string().into_bytes();
+    let mut buf = (body.len() as u32).to_le_bytes().to_vec();
+    buf.extend(body);
+    buf
+}
+
+/// Per request token for set.* commands
+/// First 8 characters of base64(sha256(cmd + password + salt + ts))
+pub fn token(cmd: &str, password: &str, salt: &str, ts: i64) -> String {
+    let digest = Sha256::digest(format!("{}{}{}{}", cmd, password, salt, ts).as_bytes());
+    base64::encode(digest).chars().take(8).collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub code: i64,
+    #[serde(default)]
+    pub when: i64,
+    #[serde(default)]
+    pub msg: Value,
+    #[serde(default)]
+    pub desc: String,
+}
+
+impl Response {
+    pub fn into_result<T: DeserializeOwned>(self) -> Result<T, Error> {
+        match self.code {
+            0 => Ok(serde_json::from_value(self.msg)?),
+            // Permission denied, a bad account, password or token
+            -4 => Err(Error::Unauthorized),
+            _ => Err(Error::ApiCallFailed(match self.msg.as_str() {
+                Some(msg) => msg.to_string(),
+                None => self.desc,
+            })),
+        }
+    }
+}
+
+/// msg of get.miner.status with param summary, every field is optional as
+/// they vary between firmware releases
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Summary {
+    /// TH/s
+    #[serde(rename = "hash-realtime")]
+    pub hash_realtime: Option<f64>,
+    #[serde(rename = "hash-average")]
+    pub hash_average: Option<f64>,
+    #[serde(rename = "factory-hash")]
+    pub factory_hash: Option<f64>,
+    #[serde(rename = "power-realtime")]
+    pub power_realtime: Option<f64>,
+    #[serde(rename = "chip-temp-max")]
+    pub chip_temp_max: Option<f64>,
+    #[serde(rename = "environment-temperature")]
+    pub environment_temperature: Option<f64>,
+    #[serde(rename = "fan-speed-in")]
+    pub fan_speed_in: Option<u32>,
+    #[serde(rename = "fan-speed-out")]
+    pub fan_speed_out: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SummaryMsg {
+    pub summary: Summary,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Pool {
+    pub url: String,
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub accepted: u64,
+    #[serde(default)]
+    pub rejected: u64,
+    #[serde(default)]
+    pub stale: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PoolsMsg {
+    #[serde(default)]
+    pub pools: Vec<Pool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaltMsg {
+    pub salt: String,
+}
+
+/// Error codes from get.device.errorcode, which has been seen as a list of
+/// {"code": time} objects, a list of {"code": .., "time": ..} objects and a bare map
+pub fn error_codes(msg: &Value) -> Vec<String> {
+    let list = msg.get("error-code").unwrap_or(msg);
+    let entries = match list {
+        Value::Array(entries) => entries.iter().collect(),
+        entry => vec![entry],
+    };
+    entries.into_iter()
+        .filter_map(|e| e.as_object())
+        .flat_map(|e| match e.get("code") {
+            Some(Value::Number(n)) => vec![n.to_string()],
+            Some(Value::String(s)) => vec![s.clone()],
+            _ => e.keys().cloned().collect(),
+        })
+        .collect()
+}
+
+/// Connection to the v3 JSON API
+/// Reads need no credentials, set.* commands are signed with a token made from the account's password
+pub struct V3Api {
+    client: Client,
+    ip: String,
+    port: u16,
+    credentials: Option<(String, SecretString)>,
+    salt: Mutex<Option<String>>,
+}
+
+impl V3Api {
+    pub fn new(client: Client, ip: String, port: u16) -> Self {
+        V3Api {
+            client,
+            ip,
+            port,
+            credentials: None,
+            salt: Mutex::new(None),
+        }
+    }
+
+    pub fn set_credentials(&mut self, account: &str, password: &str) {
+        self.credentials = Some((account.to_string(), password.into()));
+    }
+
+    /// The response body without its length prefix
+    async fn send_raw(&self, req: &Value) -> Result<Vec<u8>, Error> {
+        let mut stream = self.client.connect(&self.ip, self.port).await?;
+        match tokio::time::timeout(
+            self.client.request_timeout,
+            async {
+                stream.write_all(&frame(req)).await?;
+                let (mut buf, _) = read_framed(&mut stream, Framing::LengthPrefixed).await?;
+                if buf.len() < 4 {
+                    return Err(Error::InvalidResponse);
+                }
+                Ok(buf.split_off(4))
+            }
+        ).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    async fn send(&self, req: &Value) -> Result<Response, Error> {
+        Ok(serde_json::from_slice(&self.send_raw(req).await?)?)
+    }
+
+    /// Unauthenticated command, returning the body as the miner sent it
+    pub async fn call_raw(&self, cmd: &str, param: Option<Value>) -> Result<String, Error> {
+        let mut req = json!({"cmd": cmd});
+        if let Some(param) = param {
+            req["param"] = param;
+        }
+        Ok(String::from_utf8_lossy(&self.send_raw(&req).await?).into_owned())
+    }
+
+    /// Unauthenticated command, get.*
+    pub async fn call<T: DeserializeOwned>(&self, cmd: &str, param: Option<Value>) -> Result<T, Error> {
+        let mut req = json!({"cmd": cmd});
+        if let Some(param) = param {
+            req["param"] = param;
+        }
+        self.send(&req).await?.into_result()
+    }
+
+    /// Salt for signing, fetched once per connection
+    pub async fn salt(&self) -> Result<String, Error> {
+        let mut salt = self.salt.lock().await;
+        if salt.is_none() {
+            let msg: SaltMsg = self.call("get.device.info", Some(json!("salt"))).await?;
+            *salt = Some(msg.salt);
+        }
+        Ok(salt.clone().unwrap_or_else(|| unreachable!()))
+    }
+
+    /// Signed command, set.*
+    pub async fn call_auth<T: DeserializeOwned>(&self, cmd: &str, param: Option<Value>) -> Result<T, Error> {
+        let (account, password) = self.credentials.as_ref().ok_or(Error::Unauthorized)?;
+        let salt = self.salt().await?;
+        let ts = chrono::Utc::now().timestamp();
+        let mut req = json!({
+            "cmd": cmd,
+            "ts": ts,
+            "account": account,
+            "token": token(cmd, password.expose(), &salt, ts),
+        });
+        if let Some(param) = param {
+            req["param"] = param;
+        }
+        let resp = self.send(&req).await?;
+        if resp.code == -4 {
+            // The salt changes when the password does
+            let _ = self.salt.lock().await.take();
+        }
+        resp.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame() {
+        let buf = frame(&json!({"cmd": "get.device.info"}));
+        assert_eq!(&buf[..4], &[25, 0, 0, 0]);
+        assert_eq!(&buf[4..], br#"{"cmd":"get.device.info"}"#);
+    }
+
+    #[test]
+    fn test_token() {
+        let t = token("set.system.reboot", "super", "BQ5hoXV9", 1700000000);
+        assert_eq!(t.len(), 8);
+        assert_eq!(t, token("set.system.reboot", "super", "BQ5hoXV9", 1700000000));
+        assert_ne!(t, token("set.system.reboot", "super", "BQ5hoXV9", 1700000001));
+    }
+
+    #[test]
+    fn test_response() {
+        let resp: Response = serde_json::from_str(r#"{"code":0,"when":1700000000,"msg":{"summary":{"hash-realtime":172.5,"power-realtime":3380,"fan-speed-in":4200,"fan-speed-out":4150}},"desc":"get.miner.status"}"#).unwrap();
+        let msg: SummaryMsg = resp.into_result().unwrap();
+        assert_eq!(msg.summary.hash_realtime, Some(172.5));
+        assert_eq!(msg.summary.power_realtime, Some(3380.0));
+        assert_eq!(msg.summary.chip_temp_max, None);
+
+        let resp: Response = serde_json::from_str(r#"{"code":-4,"when":1700000000,"msg":"permission denied","desc":"set.system.reboot"}"#).unwrap();
+        assert!(matches!(resp.into_result::<Value>(), Err(Error::Unauthorized)));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(error_codes(&json!({"error-code":[{"203":"2024-01-01 00:00:00"},{"2010":"2024-01-01 00:00:01"}]})), vec!["203", "2010"]);
+        assert_eq!(error_codes(&json!({"error-code":[{"code":530,"time":"2024-01-01 00:00:00"}]})), vec!["530"]);
+        assert_eq!(error_codes(&json!({"error-code":[]})), Vec::<String>::new());
+    }
+}
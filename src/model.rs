@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+/// Spellings that still differ once canonicalized, mapped to the one the spec tables use
+static BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("s19jproplus", "s19jpro+"),
+    ("s19proplushyd", "s19pro+hyd"),
+    ("m30splus", "m30s+"),
+    ("m30splusplus", "m30s++"),
+    ("m31splus", "m31s+"),
+    ("m50splus", "m50s+"),
+];
+
+fn aliases() -> &'static RwLock<HashMap<String, String>> {
+    static ALIASES: OnceLock<RwLock<HashMap<String, String>>> = OnceLock::new();
+    ALIASES.get_or_init(|| RwLock::new(
+        BUILTIN_ALIASES.iter().map(|(alias, model)| (alias.to_string(), model.to_string())).collect()
+    ))
+}
+
+/// Lowercase without vendor prefix, spaces, firmware variant or trailing period
+/// "Antminer S19 Pro+ Hyd." is "s19pro+hyd", "M60S_VK30" is "m60s", "s19-88" is "s19"
+fn canonicalize(model: &str) -> String {
+    let model = model.trim().to_lowercase();
+    let model = ["antminer", "whatsminer", "avalonminer", "avalon"].iter()
+        .find_map(|prefix| model.strip_prefix(prefix))
+        .unwrap_or(&model);
+    model.split(['_', '-']).next().unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// The canonical name for a model as any vendor or firmware spells it, used for every spec lookup
+/// "S19J Pro+", "s19jpro+" and "Antminer S19j Pro+" all give "s19jpro+"
+pub fn normalize_model(model: &str) -> String {
+    let model = canonicalize(model);
+    aliases().read().unwrap_or_else(|e| e.into_inner())
+        .get(&model)
+        .cloned()
+        .unwrap_or(model)
+}
+
+/// Resolve alias to model from now on, replacing any built in alias for it
+/// Both sides are canonicalized, so the alias covers every spelling of it
+pub fn set_model_alias(alias: &str, model: &str) {
+    aliases().write().unwrap_or_else(|e| e.into_inner())
+        .insert(canonicalize(alias), canonicalize(model));
+}
+
+/// Stop resolving an alias, built in or not
+pub fn remove_model_alias(alias: &str) {
+    aliases().write().unwrap_or_else(|e| e.into_inner())
+        .remove(&canonicalize(alias));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_model() {
+        assert_eq!(normalize_model("S19J Pro+"), "s19jpro+");
+        assert_eq!(normalize_model("s19jpro+"), "s19jpro+");
+        assert_eq!(normalize_model("S19j Pro Plus"), "s19jpro+");
+        assert_eq!(normalize_model("Antminer S19 Pro+ Hyd."), "s19pro+hyd");
+        // A different machine from the Pro+ Hyd, it mustn't pick up its power figures
+        assert_eq!(normalize_model("Antminer S19 Pro Hyd."), "s19prohyd");
+        assert_eq!(normalize_model("M30S++"), "m30s++");
+        assert_eq!(normalize_model("WhatsMiner M60S_VK30"), "m60s");
+        assert_eq!(normalize_model("s19-88"), "s19");
+        assert_eq!(normalize_model("A1246"), "a1246");
+    }
+
+    #[test]
+    fn test_set_model_alias() {
+        assert_eq!(normalize_model("Test Miner X"), "testminerx");
+        set_model_alias("Test Miner X", "S19J Pro");
+        assert_eq!(normalize_model("testminerx"), "s19jpro");
+        remove_model_alias("TEST MINER X");
+        assert_eq!(normalize_model("Test Miner X"), "testminerx");
+    }
+}
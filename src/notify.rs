@@ -0,0 +1,83 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::error::Error;
+use crate::watch::{EventSink, MinerEvent};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// `{"events": [...]}` with one object per event
+    Json,
+    /// `{"text": "..."}` with one line per event, also accepted by Mattermost and Discord's /slack endpoint
+    Slack,
+}
+
+/// Posts events to an HTTP webhook
+pub struct Notifier {
+    http_client: reqwest::Client,
+    url: String,
+    format: WebhookFormat,
+    timeout: Duration,
+}
+
+impl Notifier {
+    pub fn new(url: &str, format: WebhookFormat) -> Notifier {
+        Notifier {
+            http_client: reqwest::Client::new(),
+            url: url.to_string(),
+            format,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// How long to wait for the webhook before giving up on a batch, default is 10 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn payload(&self, events: &[MinerEvent]) -> serde_json::Value {
+        match self.format {
+            WebhookFormat::Json => json!({ "events": events }),
+            WebhookFormat::Slack => json!({
+                "text": events.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for Notifier {
+    async fn send(&self, events: &[MinerEvent]) -> Result<(), Error> {
+        let resp = self.http_client
+            .post(&self.url)
+            .json(&self.payload(events))
+            .timeout(self.timeout)
+            .send()
+            .await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::ApiCallFailed(format!("webhook returned {}", resp.status())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let events = vec![
+            MinerEvent::Recovered { ip: "10.0.0.1".into(), tags: Default::default() },
+            MinerEvent::Overheating { ip: "10.0.0.2".into(), temperature: 91.0, tags: Default::default() },
+        ];
+        let slack = Notifier::new("http://localhost", WebhookFormat::Slack).payload(&events);
+        assert_eq!(slack["text"], "10.0.0.1 is reachable again\n10.0.0.2 is overheating at 91.0C");
+        let js = Notifier::new("http://localhost", WebhookFormat::Json).payload(&events);
+        assert_eq!(js["events"][0]["event"], "Recovered");
+        assert_eq!(js["events"][1]["temperature"], 91.0);
+    }
+}
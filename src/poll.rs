@@ -0,0 +1,108 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::miner::{Miner, SharedMiner};
+use crate::snapshot::MinerSnapshot;
+
+/// Somewhere to deliver the snapshots a poller takes
+#[async_trait]
+pub trait SnapshotSink {
+    async fn send(&self, snap: MinerSnapshot);
+}
+
+/// A full channel holds the poller back rather than dropping snapshots
+#[async_trait]
+impl SnapshotSink for mpsc::Sender<MinerSnapshot> {
+    async fn send(&self, snap: MinerSnapshot) {
+        if mpsc::Sender::send(self, snap).await.is_err() {
+            debug!("Snapshot receiver dropped");
+        }
+    }
+}
+
+/// A running poller, dropping it stops the polling
+pub struct PollerHandle {
+    ip: String,
+    task: JoinHandle<()>,
+}
+
+impl PollerHandle {
+    pub fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    /// Same as dropping the handle
+    pub fn stop(self) {}
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for PollerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Uniform in [0, max), from the per process random keys of the std hasher
+fn random_delay(max: Duration) -> Duration {
+    let max = max.as_nanos().min(u64::MAX as u128) as u64;
+    if max == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_nanos(RandomState::new().build_hasher().finish() % max)
+}
+
+/// Snapshot the miner every interval and hand each snapshot to the sink
+/// Each poll lands somewhere in the first jitter of its interval, so a fleet of pollers with
+/// jitter equal to the interval spreads evenly instead of bursting together. A snapshot gets
+/// at most the interval to complete, a poll that still overruns pushes the schedule back
+/// Needs a tokio runtime
+pub fn spawn_poller(miner: SharedMiner, interval: Duration, jitter: Duration, sink: Arc<dyn SnapshotSink + Send + Sync>) -> PollerHandle {
+    let ip = miner.get_ip().to_string();
+    let jitter = jitter.min(interval);
+    let task = tokio::spawn(async move {
+        let mut start = Instant::now();
+        loop {
+            tokio::time::sleep_until(start + random_delay(jitter)).await;
+            let snap = MinerSnapshot::take_within(&miner, interval).await;
+            sink.send(snap).await;
+            start += interval;
+            let now = Instant::now();
+            if start < now {
+                start = now;
+            }
+        }
+    });
+    PollerHandle { ip, task }
+}
+
+/// spawn_poller for each miner, all delivering to the same sink
+pub fn spawn_pollers(miners: &[SharedMiner], interval: Duration, jitter: Duration, sink: Arc<dyn SnapshotSink + Send + Sync>) -> Vec<PollerHandle> {
+    miners.iter()
+        .map(|miner| spawn_poller(miner.clone(), interval, jitter, sink.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_delay() {
+        assert_eq!(random_delay(Duration::ZERO), Duration::ZERO);
+        let max = Duration::from_secs(60);
+        let delays = (0..100).map(|_| random_delay(max)).collect::<Vec<_>>();
+        assert!(delays.iter().all(|d| *d < max));
+        // Not every poller in the same spot
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+}
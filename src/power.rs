@@ -0,0 +1,216 @@
+use std::net::IpAddr;
+use chrono::FixedOffset;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::time::Duration;
+
+use crate::error::Error;
+use crate::miner::{Miner, MinerError, Pool, Profile, PowerState, HealthCondition, Capabilities, PendingChange, TuningStatus, PoolCounters, LogOptions, LogEntry, SystemStats, NetworkStats, CoolingDetail, FanMode, ChipCount, BoardTemps, TempReport, Resource, Tags};
+use crate::security::SecurityIndicator;
+use crate::backup::MinerConfigBackup;
+use crate::detection::DetectionInfo;
+#[cfg(feature = "http")]
+use crate::{Client, SecretString};
+
+/// How long an outlet stays off during a power cycle, long enough for the PSU to drain
+pub const DEFAULT_OFF_TIME: Duration = Duration::from_secs(10);
+
+/// An external switch for a miner's power, e.g. a smart PDU outlet
+/// Implement this for the PDU in use, HttpOutlet covers PDUs with URL based control
+#[async_trait]
+pub trait PowerControl: Send + Sync {
+    async fn power_off(&self) -> Result<(), Error>;
+
+    async fn power_on(&self) -> Result<(), Error>;
+
+    /// Off, wait, then on again
+    async fn power_cycle(&self, off_for: Duration) -> Result<(), Error> {
+        self.power_off().await?;
+        tokio::time::sleep(off_for).await;
+        self.power_on().await
+    }
+}
+
+/// PDU outlet switched by requesting a URL, e.g. `http://pdu/outlet?3=OFF`
+#[cfg(feature = "http")]
+pub struct HttpOutlet {
+    http_client: reqwest::Client,
+    on_url: String,
+    off_url: String,
+    credentials: Option<(String, SecretString)>,
+}
+
+#[cfg(feature = "http")]
+impl HttpOutlet {
+    pub fn new(client: &Client, on_url: &str, off_url: &str) -> Self {
+        HttpOutlet {
+            http_client: client.http_client.clone(),
+            on_url: on_url.to_string(),
+            off_url: off_url.to_string(),
+            credentials: None,
+        }
+    }
+
+    /// Send HTTP basic auth with each request
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.into()));
+        self
+    }
+
+    async fn request(&self, url: &str) -> Result<(), Error> {
+        let mut req = self.http_client.get(url);
+        if let Some((username, password)) = &self.credentials {
+            req = req.basic_auth(username, Some(password.expose()));
+        }
+        let resp = req.send().await?;
+        match resp.status() {
+            s if s.is_success() => Ok(()),
+            reqwest::StatusCode::UNAUTHORIZED => Err(Error::Unauthorized),
+            s => Err(Error::ApiCallFailed(format!("outlet {}", s))),
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl PowerControl for HttpOutlet {
+    async fn power_off(&self) -> Result<(), Error> {
+        self.request(&self.off_url).await
+    }
+
+    async fn power_on(&self) -> Result<(), Error> {
+        self.request(&self.on_url).await
+    }
+}
+
+/// Errors that mean the miner didn't answer at all
+fn unreachable(e: &Error) -> bool {
+    match e {
+        Error::Timeout | Error::NoHostDetected | Error::NoMinerDetected | Error::ConnectionRefused | Error::IoError(_) => true,
+        #[cfg(feature = "http")]
+        Error::RequestError(_) => true,
+        _ => false,
+    }
+}
+
+/// How a restart was carried out
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RestartKind {
+    /// The miner rebooted itself
+    Warm,
+    /// The outlet was power cycled
+    Cold,
+}
+
+/// A miner paired with the outlet feeding it
+pub struct PoweredMiner {
+    miner: Box<dyn Miner + Send + Sync>,
+    outlet: Arc<dyn PowerControl>,
+    off_time: Duration,
+}
+
+impl PoweredMiner {
+    pub fn new(miner: Box<dyn Miner + Send + Sync>, outlet: Arc<dyn PowerControl>) -> PoweredMiner {
+        PoweredMiner {
+            miner,
+            outlet,
+            off_time: DEFAULT_OFF_TIME,
+        }
+    }
+
+    /// How long the outlet stays off during a power cycle
+    /// Default is 10 seconds
+    pub fn off_time(mut self, off_time: Duration) -> Self {
+        self.off_time = off_time;
+        self
+    }
+
+    pub fn inner(&self) -> &(dyn Miner + Send + Sync) {
+        &*self.miner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut (dyn Miner + Send + Sync) {
+        &mut *self.miner
+    }
+
+    pub fn into_inner(self) -> Box<dyn Miner + Send + Sync> {
+        self.miner
+    }
+
+    pub fn outlet(&self) -> &Arc<dyn PowerControl> {
+        &self.outlet
+    }
+
+    /// Cut power to the miner and restore it, without asking the miner
+    pub async fn hard_power_cycle(&self) -> Result<(), Error> {
+        self.outlet.power_cycle(self.off_time).await
+    }
+
+    /// Reboot through the miner's API, power cycling instead if it can't be reached
+    /// API errors such as Unauthorized are returned rather than power cycling a miner that is answering
+    pub async fn restart(&mut self) -> Result<RestartKind, Error> {
+        match self.miner.reboot().await {
+            Ok(()) => Ok(RestartKind::Warm),
+            Err(e) if unreachable(&e) => {
+                self.hard_power_cycle().await?;
+                Ok(RestartKind::Cold)
+            },
+            Err(e) => Err(e),
+        }
+    }
+}
+
+delegate_miner!(PoweredMiner, direct);
+
+/// An external reading of a miner's power draw, e.g. a metered PDU outlet
+#[async_trait]
+pub trait PowerMeter: Send + Sync {
+    /// Watts drawn at the wall
+    async fn read_watts(&self) -> Result<f64, Error>;
+}
+
+/// A miner whose get_power comes from a meter instead of the firmware, for billing where
+/// firmware wattage isn't trusted
+/// get_efficiency follows, using the metered power over the firmware hashrate
+pub struct MeteredMiner {
+    miner: Box<dyn Miner + Send + Sync>,
+    meter: Arc<dyn PowerMeter>,
+}
+
+impl MeteredMiner {
+    pub fn new(miner: Box<dyn Miner + Send + Sync>, meter: Arc<dyn PowerMeter>) -> MeteredMiner {
+        MeteredMiner { miner, meter }
+    }
+
+    pub fn inner(&self) -> &(dyn Miner + Send + Sync) {
+        &*self.miner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut (dyn Miner + Send + Sync) {
+        &mut *self.miner
+    }
+
+    pub fn into_inner(self) -> Box<dyn Miner + Send + Sync> {
+        self.miner
+    }
+
+    /// What the firmware reports, to compare against the meter
+    pub async fn firmware_power(&self) -> Result<f64, Error> {
+        self.miner.get_power().await
+    }
+
+    async fn metered_power(&self) -> Result<f64, Error> {
+        self.meter.read_watts().await
+    }
+
+    async fn metered_efficiency(&self) -> Result<f64, Error> {
+        let hashrate = self.miner.get_hashrate().await?;
+        if hashrate > 0.0 {
+            Ok(self.metered_power().await? / hashrate)
+        } else {
+            Err(Error::ExpectedReturn)
+        }
+    }
+}
+
+delegate_miner!(MeteredMiner, metered);
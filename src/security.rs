@@ -0,0 +1,80 @@
+use futures::future::join_all;
+use serde::Serialize;
+
+use crate::Client;
+#[cfg(any(feature = "minerva", feature = "vnish"))]
+use crate::error::Error;
+#[cfg(any(feature = "minerva", feature = "vnish"))]
+use crate::transport::SendVia;
+
+/// Strings found in the web UI of infected miners, with what they point to
+/// From public reports, the first match per page is enough to flag it
+pub static PAGE_IOCS: &[(&str, &str)] = &[
+    ("antbuild", "antbuild infection"),
+    ("minerlink.com", "Antbleed remote shutdown"),
+];
+
+/// Ports stock firmware keeps closed, with what they are known to be opened for
+pub static PORT_IOCS: &[(u16, &str)] = &[
+    (23, "telnet backdoor"),
+    (1080, "SOCKS proxy"),
+    (2222, "second ssh daemon"),
+    (3333, "local stratum proxy diverting hashrate"),
+];
+
+/// Something a miner does that stock firmware doesn't, each one worth a reflash
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum SecurityIndicator {
+    OpenPort { port: u16, note: &'static str },
+    /// A page served by the miner contains a known IOC string
+    PageSignature { page: String, note: &'static str },
+    /// The firmware upgrade endpoint was removed or refuses uploads
+    UpgradeBlocked,
+}
+
+/// Check a page body against PAGE_IOCS
+pub fn page_indicators(page: &str, body: &str) -> Vec<SecurityIndicator> {
+    let body = body.to_lowercase();
+    PAGE_IOCS.iter()
+        .filter(|(ioc, _)| body.contains(ioc))
+        .map(|(_, note)| SecurityIndicator::PageSignature { page: page.to_string(), note })
+        .collect()
+}
+
+/// Try every port in PORT_IOCS at once, anything that accepts a connection is flagged
+pub async fn scan_ports(client: &Client, ip: &str) -> Vec<SecurityIndicator> {
+    let probes: Vec<_> = PORT_IOCS.iter().map(|(port, _)| async move {
+        tokio::time::timeout(client.connect_timeout, client.connect_from(ip, *port)).await
+            .is_ok_and(|r| r.is_ok())
+    }).collect();
+    let open = join_all(probes).await;
+    PORT_IOCS.iter()
+        .zip(open)
+        .filter(|(_, open)| *open)
+        .map(|((port, note), _)| SecurityIndicator::OpenPort { port: *port, note })
+        .collect()
+}
+
+/// Open ports and the front page of a web UI that needs no login to read
+#[cfg(any(feature = "minerva", feature = "vnish"))]
+pub(crate) async fn scan(client: &Client, ip: &str, web_root: &str) -> Result<Vec<SecurityIndicator>, Error> {
+    let mut found = scan_ports(client, ip).await;
+    let resp = client.http_client.get(web_root).send_via(client).await?;
+    if resp.status().is_success() {
+        found.extend(page_indicators("/", &resp.text().await?));
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_indicators() {
+        assert!(page_indicators("/", "<title>Antminer</title>").is_empty());
+        assert_eq!(page_indicators("/", "<script src=\"/js/AntBuild.js\"></script>"), vec![
+            SecurityIndicator::PageSignature { page: "/".into(), note: "antbuild infection" },
+        ]);
+    }
+}
@@ -0,0 +1,312 @@
+use std::future::Future;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::time::Duration;
+
+use crate::deadline::Deadline;
+use crate::error::Error;
+use crate::miner::{Miner, PowerState, Tags, MinerError, BoardTemps, ChipCount};
+
+/// Plausible range for SHA256 J/TH, anything outside is a misread rather than a real miner
+const EFFICIENCY_RANGE: (f64, f64) = (10.0, 100.0);
+/// Celsius, boards shut down well before this
+const MAX_TEMPERATURE: f64 = 120.0;
+/// Multiple of nameplate power no PSU can deliver
+const MAX_POWER_RATIO: f64 = 2.0;
+
+/// Whether a snapshot's readings can be believed
+/// Firmware often reports corrupt values after a partial deadlock instead of failing
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub enum DataQuality {
+    #[default]
+    Ok,
+    /// Readings outside what the hardware can do, with what was wrong
+    Suspect(Vec<String>),
+}
+
+impl DataQuality {
+    pub fn is_suspect(&self) -> bool {
+        matches!(self, DataQuality::Suspect(_))
+    }
+}
+
+/// "Ok", or "Suspect: " and what was wrong, for exports that flatten it to one value
+impl std::fmt::Display for DataQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataQuality::Ok => write!(f, "Ok"),
+            DataQuality::Suspect(problems) => write!(f, "Suspect: {}", problems.join("; ")),
+        }
+    }
+}
+
+/// Point in time readings from a single miner
+/// Fields the miner couldn't provide are None, so one failing endpoint doesn't lose the rest
+#[derive(Debug, Clone, Serialize)]
+pub struct MinerSnapshot {
+    pub ip: String,
+    pub vendor: String,
+    pub model: Option<String>,
+    pub mac: Option<String>,
+    pub power_state: Option<PowerState>,
+    /// TH/s
+    pub hashrate: Option<f64>,
+    /// Watts
+    pub power: Option<f64>,
+    /// J/TH
+    pub efficiency: Option<f64>,
+    /// Hashrate over ideal, see Miner::get_performance_ratio
+    pub performance_ratio: Option<f64>,
+    /// Celsius
+    pub temperature: Option<f64>,
+    pub fan_speed: Option<Vec<u32>>,
+    /// The miner's tags when the snapshot was taken
+    pub tags: Tags,
+    pub taken_at: DateTime<Utc>,
+    /// First failure if the miner didn't answer at all
+    pub error: Option<String>,
+    pub quality: DataQuality,
+}
+
+impl MinerSnapshot {
+    pub async fn take<M: Miner + Sync + ?Sized>(miner: &M) -> MinerSnapshot {
+        Self::take_by(miner, None).await
+    }
+
+    /// As take, but every reading has to come in within budget, readings still pending then are None
+    pub async fn take_within<M: Miner + Sync + ?Sized>(miner: &M, budget: Duration) -> MinerSnapshot {
+        Self::take_by(miner, Some(Deadline::after(budget))).await
+    }
+
+    async fn take_by<M: Miner + Sync + ?Sized>(miner: &M, deadline: Option<Deadline>) -> MinerSnapshot {
+        async fn by<T>(deadline: Option<Deadline>, call: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+            match deadline {
+                Some(deadline) => deadline.run(call).await,
+                None => call.await,
+            }
+        }
+
+        let taken_at = Utc::now();
+        let (power_state, hashrate) = futures::join!(
+            by(deadline, miner.get_power_state()),
+            by(deadline, miner.get_hashrate()),
+        );
+        let error = match (&power_state, &hashrate) {
+            (Err(e), Err(_)) => Some(e.to_string()),
+            _ => None,
+        };
+        // Nothing else will answer either, don't wait on every timeout
+        if error.is_some() {
+            return MinerSnapshot {
+                ip: miner.get_ip().to_string(),
+                vendor: miner.get_type().to_string(),
+                model: None,
+                mac: None,
+                power_state: None,
+                hashrate: None,
+                power: None,
+                efficiency: None,
+                performance_ratio: None,
+                temperature: None,
+                fan_speed: None,
+                tags: miner.tags(),
+                taken_at,
+                error,
+                quality: DataQuality::Ok,
+            };
+        }
+
+        // Each cache has its own lock, so readings from different endpoints are fetched side by side
+        // and readings from the same one wait for the first fetch instead of repeating it
+        let (model, mac, power, efficiency, performance_ratio, temperature, fan_speed, nameplate_power) = futures::join!(
+            by(deadline, miner.get_model()),
+            by(deadline, miner.get_mac()),
+            by(deadline, miner.get_power()),
+            by(deadline, miner.get_efficiency()),
+            by(deadline, miner.get_performance_ratio()),
+            by(deadline, miner.get_temperature()),
+            by(deadline, miner.get_fan_speed()),
+            by(deadline, miner.get_nameplate_power()),
+        );
+        let mut snap = MinerSnapshot {
+            ip: miner.get_ip().to_string(),
+            vendor: miner.get_type().to_string(),
+            model: model.ok(),
+            mac: mac.ok(),
+            power_state: power_state.ok(),
+            hashrate: hashrate.ok(),
+            power: power.ok(),
+            efficiency: efficiency.ok(),
+            performance_ratio: performance_ratio.ok(),
+            temperature: temperature.ok(),
+            fan_speed: fan_speed.ok(),
+            tags: miner.tags(),
+            taken_at,
+            error,
+            quality: DataQuality::Ok,
+        };
+        snap.quality = snap.validate(nameplate_power.ok());
+        snap
+    }
+
+    /// Check readings against what the hardware can physically do
+    pub(crate) fn validate(&self, nameplate_power: Option<f64>) -> DataQuality {
+        let mut problems = Vec::new();
+        // Idle miners report their rated efficiency, only a hashing one's can be off
+        if let (Some(eff), Some(hashrate)) = (self.efficiency, self.hashrate) {
+            if hashrate > 0.0 && !(EFFICIENCY_RANGE.0..=EFFICIENCY_RANGE.1).contains(&eff) {
+                problems.push(format!("Efficiency {:.1} J/TH", eff));
+            }
+        }
+        if let Some(temp) = self.temperature {
+            if temp > MAX_TEMPERATURE {
+                problems.push(format!("Temperature {:.1}C", temp));
+            }
+        }
+        if let (Some(power), Some(nameplate)) = (self.power, nameplate_power) {
+            if nameplate > 0.0 && power > nameplate * MAX_POWER_RATIO {
+                problems.push(format!("Power {:.0}W against {:.0}W nameplate", power, nameplate));
+            }
+        }
+        if problems.is_empty() {
+            DataQuality::Ok
+        } else {
+            DataQuality::Suspect(problems)
+        }
+    }
+
+    pub fn reachable(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Hash of the readings, leaving out taken_at, so two polls that saw the same thing hash the same
+    pub fn content_hash(&self) -> u64 {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("taken_at");
+        }
+        content_hash(&value)
+    }
+}
+
+/// Hash of a value's serialized form for change detection, e.g. content_hash(&miner.get_pools().await?)
+/// Keyed the same in every process so hashes can be stored and compared later, unlike std's Hash
+/// Put collections in canonical order first, pools are hashed in priority order as that's part of the config
+pub fn content_hash<T: Serialize + ?Sized>(value: &T) -> u64 {
+    // Map keys serialize sorted, so field order doesn't depend on how the value was built
+    let json = serde_json::to_value(value).map(|v| v.to_string()).unwrap_or_default();
+    let digest = Sha256::digest(json.as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// Sort into a fixed order, so the same readings compare and hash equal whatever order firmware reported them in
+pub trait Canonical {
+    fn canonicalize(&mut self);
+}
+
+/// Snapshots of a fleet by address
+impl Canonical for Vec<MinerSnapshot> {
+    fn canonicalize(&mut self) {
+        self.sort_by(|a, b| a.ip.cmp(&b.ip));
+    }
+}
+
+/// Errors by type, code then message, with repeats of the same error dropped
+impl Canonical for Vec<MinerError> {
+    fn canonicalize(&mut self) {
+        self.sort_by(|a, b| (a.error_type, a.code, &a.msg).cmp(&(b.error_type, b.code, &b.msg)));
+        self.dedup();
+    }
+}
+
+impl Canonical for Vec<BoardTemps> {
+    fn canonicalize(&mut self) {
+        self.sort_by_key(|b| b.chain);
+    }
+}
+
+impl Canonical for Vec<ChipCount> {
+    fn canonicalize(&mut self) {
+        self.sort_by_key(|c| c.chain);
+    }
+}
+
+/// A mining S19 with every reading in range, tests override the fields they care about
+#[cfg(test)]
+impl MinerSnapshot {
+    pub(crate) fn test() -> MinerSnapshot {
+        MinerSnapshot {
+            ip: "10.0.0.1".into(),
+            vendor: "Antminer".into(),
+            model: Some("s19".into()),
+            mac: None,
+            power_state: Some(PowerState::Mining),
+            hashrate: Some(95.0),
+            power: Some(3250.0),
+            efficiency: Some(34.2),
+            performance_ratio: None,
+            temperature: Some(68.0),
+            fan_speed: None,
+            tags: Default::default(),
+            taken_at: Utc::now(),
+            error: None,
+            quality: DataQuality::Ok,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::ErrorType;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(MinerSnapshot::test().validate(Some(3250.0)), DataQuality::Ok);
+
+        let mut snap = MinerSnapshot::test();
+        snap.efficiency = Some(3250.0 / 0.4);
+        snap.temperature = Some(655.0);
+        assert_eq!(snap.validate(None), DataQuality::Suspect(vec!["Efficiency 8125.0 J/TH".into(), "Temperature 655.0C".into()]));
+
+        let mut snap = MinerSnapshot::test();
+        snap.power = Some(65535.0);
+        assert!(snap.validate(Some(3250.0)).is_suspect());
+        assert_eq!(snap.validate(None), DataQuality::Ok);
+
+        // Sleeping miners report rated efficiency with no hashrate
+        let mut snap = MinerSnapshot::test();
+        snap.hashrate = Some(0.0);
+        snap.efficiency = Some(0.0);
+        assert_eq!(snap.validate(None), DataQuality::Ok);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let a = MinerSnapshot::test();
+        let mut b = MinerSnapshot::test();
+        b.taken_at = a.taken_at + chrono::Duration::seconds(30);
+        assert_eq!(a.content_hash(), b.content_hash());
+        b.hashrate = Some(94.0);
+        assert_ne!(a.content_hash(), b.content_hash());
+
+        let mut snapshots = vec![MinerSnapshot::test(), MinerSnapshot::test()];
+        snapshots[0].ip = "10.0.0.2".into();
+        snapshots.canonicalize();
+        assert_eq!(snapshots[0].ip, "10.0.0.1");
+    }
+
+    #[test]
+    fn test_canonical_errors() {
+        let error = |msg: &str, error_type| MinerError::new(msg, error_type);
+        let mut a = vec![error("Fan 2 lost", ErrorType::Fan), error("Chain 1 missing", ErrorType::HashBoard), error("Fan 2 lost", ErrorType::Fan)];
+        let mut b = vec![error("Chain 1 missing", ErrorType::HashBoard), error("Fan 2 lost", ErrorType::Fan)];
+        a.canonicalize();
+        b.canonicalize();
+        assert_eq!(a, b);
+        assert_eq!(content_hash(&a), content_hash(&b));
+    }
+}
@@ -0,0 +1,315 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicI32, Ordering};
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+
+use crate::error::Error;
+use crate::power::PowerMeter;
+use crate::Client;
+
+pub const SNMP_PORT: u16 = 161;
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_COUNTER64: u8 = 0x46;
+const TAG_GET_REQUEST: u8 = 0xa0;
+const TAG_RESPONSE: u8 = 0xa2;
+
+/// SNMP version field for v2c
+const VERSION_2C: i64 = 1;
+
+static REQUEST_ID: AtomicI32 = AtomicI32::new(1);
+
+/// A value returned by an SNMP GET
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnmpValue {
+    Integer(i64),
+    Counter(u64),
+    Gauge(u64),
+    TimeTicks(u64),
+    String(Vec<u8>),
+    Oid(String),
+    Null,
+}
+
+impl SnmpValue {
+    /// Numeric value, strings are parsed since some PDUs report readings as text
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            SnmpValue::Integer(v) => Some(*v as f64),
+            SnmpValue::Counter(v) | SnmpValue::Gauge(v) | SnmpValue::TimeTicks(v) => Some(*v as f64),
+            SnmpValue::String(s) => std::str::from_utf8(s).ok()?.trim().parse().ok(),
+            SnmpValue::Oid(_) | SnmpValue::Null => None,
+        }
+    }
+}
+
+fn push_len(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let bytes = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1)..];
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    push_len(&mut out, content.len());
+    out.extend_from_slice(content);
+    out
+}
+
+fn encode_int(v: i64) -> Vec<u8> {
+    let bytes = v.to_be_bytes();
+    let mut start = 0;
+    // Drop leading bytes that only repeat the sign
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    tlv(TAG_INTEGER, &bytes[start..])
+}
+
+fn encode_oid(oid: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::InvalidConfig(format!("invalid OID {}", oid));
+    let parts = oid.trim_start_matches('.')
+        .split('.')
+        .map(|p| p.parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if parts.len() < 2 || parts[0] > 2 || (parts[0] < 2 && parts[1] >= 40) {
+        return Err(invalid());
+    }
+    let mut content = Vec::new();
+    for part in std::iter::once(parts[0] * 40 + parts[1]).chain(parts[2..].iter().copied()) {
+        let mut chunk = vec![(part & 0x7f) as u8];
+        let mut rest = part >> 7;
+        while rest > 0 {
+            chunk.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(chunk.iter().rev());
+    }
+    Ok(tlv(TAG_OID, &content))
+}
+
+/// SNMPv2c GetRequest for a single OID
+pub(crate) fn get_request(community: &str, oid: &str, request_id: i32) -> Result<Vec<u8>, Error> {
+    let varbind = [encode_oid(oid)?, tlv(TAG_NULL, &[])].concat();
+    let varbinds = tlv(TAG_SEQUENCE, &tlv(TAG_SEQUENCE, &varbind));
+    let pdu = tlv(TAG_GET_REQUEST, &[encode_int(request_id as i64), encode_int(0), encode_int(0), varbinds].concat());
+    let message = [encode_int(VERSION_2C), tlv(TAG_OCTET_STRING, community.as_bytes()), pdu].concat();
+    Ok(tlv(TAG_SEQUENCE, &message))
+}
+
+/// Split the next TLV off a buffer, returning its tag, content and what follows
+fn read_tlv(buf: &[u8]) -> Result<(u8, &[u8], &[u8]), Error> {
+    let (&tag, buf) = buf.split_first().ok_or(Error::InvalidResponse)?;
+    let (&first, buf) = buf.split_first().ok_or(Error::InvalidResponse)?;
+    let (len, buf) = if first < 0x80 {
+        (first as usize, buf)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 || buf.len() < n {
+            return Err(Error::InvalidResponse);
+        }
+        (buf[..n].iter().fold(0usize, |len, b| (len << 8) | *b as usize), &buf[n..])
+    };
+    if buf.len() < len {
+        return Err(Error::InvalidResponse);
+    }
+    Ok((tag, &buf[..len], &buf[len..]))
+}
+
+fn expect(buf: &[u8], tag: u8) -> Result<(&[u8], &[u8]), Error> {
+    match read_tlv(buf)? {
+        (t, content, rest) if t == tag => Ok((content, rest)),
+        _ => Err(Error::InvalidResponse),
+    }
+}
+
+fn decode_int(content: &[u8]) -> Result<i64, Error> {
+    if content.is_empty() || content.len() > 8 {
+        return Err(Error::InvalidResponse);
+    }
+    let init = if content[0] & 0x80 != 0 { -1 } else { 0 };
+    Ok(content.iter().fold(init, |v, b| (v << 8) | *b as i64))
+}
+
+fn decode_uint(content: &[u8]) -> Result<u64, Error> {
+    // A leading zero keeps the top bit clear, so 64 bit counters can take 9 bytes
+    if content.is_empty() || content.len() > 9 || (content.len() == 9 && content[0] != 0) {
+        return Err(Error::InvalidResponse);
+    }
+    Ok(content.iter().fold(0u64, |v, b| (v << 8) | *b as u64))
+}
+
+fn decode_oid(content: &[u8]) -> Result<String, Error> {
+    let mut parts = Vec::new();
+    let mut part: u64 = 0;
+    for b in content {
+        part = (part << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (part / 40).min(2);
+                parts.push(first);
+                parts.push(part - first * 40);
+            } else {
+                parts.push(part);
+            }
+            part = 0;
+        }
+    }
+    if parts.is_empty() {
+        return Err(Error::InvalidResponse);
+    }
+    Ok(parts.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("."))
+}
+
+/// Value of the first varbind in a GetResponse
+pub(crate) fn parse_response(buf: &[u8], request_id: i32) -> Result<SnmpValue, Error> {
+    let (message, _) = expect(buf, TAG_SEQUENCE)?;
+    let (_, rest) = expect(message, TAG_INTEGER)?;
+    let (_, rest) = expect(rest, TAG_OCTET_STRING)?;
+    let (pdu, _) = expect(rest, TAG_RESPONSE)?;
+    let (id, rest) = expect(pdu, TAG_INTEGER)?;
+    if decode_int(id)? != request_id as i64 {
+        return Err(Error::InvalidResponse);
+    }
+    let (status, rest) = expect(rest, TAG_INTEGER)?;
+    let status = decode_int(status)?;
+    if status != 0 {
+        return Err(Error::ApiCallFailed(format!("snmp error status {}", status)));
+    }
+    let (_, rest) = expect(rest, TAG_INTEGER)?;
+    let (varbinds, _) = expect(rest, TAG_SEQUENCE)?;
+    let (varbind, _) = expect(varbinds, TAG_SEQUENCE)?;
+    let (_, value) = expect(varbind, TAG_OID)?;
+    let (tag, content, _) = read_tlv(value)?;
+    Ok(match tag {
+        TAG_INTEGER => SnmpValue::Integer(decode_int(content)?),
+        TAG_OCTET_STRING => SnmpValue::String(content.to_vec()),
+        TAG_NULL => SnmpValue::Null,
+        TAG_OID => SnmpValue::Oid(decode_oid(content)?),
+        TAG_COUNTER32 | TAG_COUNTER64 => SnmpValue::Counter(decode_uint(content)?),
+        TAG_GAUGE32 => SnmpValue::Gauge(decode_uint(content)?),
+        TAG_TIMETICKS => SnmpValue::TimeTicks(decode_uint(content)?),
+        // noSuchObject, noSuchInstance, endOfMibView
+        0x80..=0x82 => return Err(Error::NotSupported),
+        _ => return Err(Error::InvalidResponse),
+    })
+}
+
+/// SNMPv2c GET of a single OID, e.g. a PDU outlet's power reading
+/// Uses the Client's resolver, bound interface and request timeout
+pub async fn get(client: &Client, host: &str, community: &str, oid: &str) -> Result<SnmpValue, Error> {
+    let request_id = REQUEST_ID.fetch_add(1, Ordering::Relaxed) & i32::MAX;
+    let request = get_request(community, oid, request_id)?;
+    let addr = *client.resolver.resolve(host).await?.first().ok_or(Error::NoHostDetected)?;
+    let local = client.local_address.unwrap_or(match addr {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0)).await
+        .map_err(|e| Error::InvalidConfig(format!("cannot bind {}: {}", local, e)))?;
+    socket.connect(SocketAddr::new(addr, SNMP_PORT)).await?;
+    socket.send(&request).await?;
+    let mut buf = vec![0u8; 65535];
+    match tokio::time::timeout(client.request_timeout, async {
+        loop {
+            let n = socket.recv(&mut buf).await?;
+            // Late replies to an earlier request on a reused port are skipped
+            match parse_response(&buf[..n], request_id) {
+                Err(Error::InvalidResponse) => continue,
+                result => return result,
+            }
+        }
+    }).await {
+        Ok(result) => result,
+        Err(_) => Err(Error::Timeout),
+    }
+}
+
+/// At-the-wall power from an SNMP OID, for use with MeteredMiner
+pub struct SnmpMeter {
+    client: Client,
+    host: String,
+    community: String,
+    oid: String,
+    scale: f64,
+}
+
+impl SnmpMeter {
+    pub fn new(client: Client, host: &str, community: &str, oid: &str) -> Self {
+        SnmpMeter {
+            client,
+            host: host.to_string(),
+            community: community.to_string(),
+            oid: oid.to_string(),
+            scale: 1.0,
+        }
+    }
+
+    /// Multiply readings by this to get watts, e.g. 0.1 for a PDU reporting tenths of a watt
+    /// Default is 1
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+#[async_trait]
+impl PowerMeter for SnmpMeter {
+    async fn read_watts(&self) -> Result<f64, Error> {
+        let value = get(&self.client, &self.host, &self.community, &self.oid).await?;
+        Ok(value.as_f64().ok_or(Error::InvalidResponse)? * self.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_request() {
+        let req = get_request("public", "1.3.6.1.2.1.1.1.0", 1).unwrap();
+        let mut expected = vec![0x30, 0x26, 0x02, 0x01, 0x01, 0x04, 0x06];
+        expected.extend_from_slice(b"public");
+        expected.extend_from_slice(&[
+            0xa0, 0x19, 0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+            0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+        ]);
+        assert_eq!(req, expected);
+        assert!(get_request("public", "1.3.x", 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let mut resp = vec![0x30, 0x28, 0x02, 0x01, 0x01, 0x04, 0x06];
+        resp.extend_from_slice(b"public");
+        resp.extend_from_slice(&[
+            0xa2, 0x1b, 0x02, 0x01, 0x07, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+            0x30, 0x10, 0x30, 0x0e, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x42, 0x02, 0x05, 0xdc,
+        ]);
+        assert_eq!(parse_response(&resp, 7).unwrap(), SnmpValue::Gauge(1500));
+        assert!(matches!(parse_response(&resp, 8), Err(Error::InvalidResponse)));
+        assert_eq!(decode_oid(&[0x2b, 0x06, 0x01, 0x04, 0x01, 0x83, 0x74]).unwrap(), "1.3.6.1.4.1.500");
+    }
+
+    #[test]
+    fn test_encode_int() {
+        assert_eq!(encode_int(0), vec![0x02, 0x01, 0x00]);
+        assert_eq!(encode_int(128), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(encode_int(-1), vec![0x02, 0x01, 0xff]);
+        assert_eq!(decode_int(&[0xff, 0x7f]).unwrap(), -129);
+    }
+}
@@ -0,0 +1,219 @@
+use async_ssh2_tokio::client::{AuthMethod, Client as SshClient, ServerCheckMethod};
+use lazy_regex::regex;
+
+use crate::error::Error;
+use crate::{Client, NetworkStats, SecretString};
+
+pub const SSH_PORT: u16 = 22;
+
+/// Interface counters, link speed and a ping of the default gateway, in sections split by markers
+const NETWORK_STATS: &str = "cat /proc/net/dev; echo '--speed'; cat /sys/class/net/eth0/speed 2>/dev/null; \
+    echo '--ping'; gw=$(ip route 2>/dev/null | awk '/^default/ {print $3; exit}'); \
+    if [ -n \"$gw\" ]; then ping -c 5 -q $gw 2>&1; fi; true";
+
+/// Recovery actions for a miner whose HTTP and socket APIs are wedged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// Restart bmminer, cgminer or btminer, whichever the firmware runs
+    RestartMiner,
+    /// Tail of the system log
+    FetchLogs,
+    /// Restart networking with the saved config, for a control board that lost its lease
+    RestartNetwork,
+}
+
+impl Recovery {
+    /// Shell command for the action, written for the busybox ash on stock firmware
+    pub fn command(&self) -> &'static str {
+        match self {
+            Recovery::RestartMiner => "for s in /etc/init.d/bmminer.sh /etc/init.d/cgminer.sh /etc/init.d/btminer; do \
+                if [ -x $s ]; then $s restart; exit $?; fi; done; exit 127",
+            Recovery::FetchLogs => "tail -n 500 /var/log/messages 2>/dev/null || dmesg",
+            // Detached and delayed, the restart takes down the link this session runs over
+            Recovery::RestartNetwork => "for s in /etc/init.d/network.sh /etc/init.d/network; do \
+                if [ -x $s ]; then (trap '' HUP; sleep 1; $s restart) </dev/null >/dev/null 2>&1 & exit 0; fi; done; exit 127",
+        }
+    }
+}
+
+/// How the miner's host key is verified
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum HostKeyCheck {
+    /// ~/.ssh/known_hosts
+    #[default]
+    KnownHosts,
+    /// A known_hosts file at this path
+    KnownHostsFile(String),
+    /// The base64 public key the miner must present
+    Pinned(String),
+    /// Accept any key, miners regenerate theirs on every reflash so a fleet that's reflashed
+    /// often may have nothing to check against
+    Insecure,
+}
+
+impl HostKeyCheck {
+    fn method(&self) -> ServerCheckMethod {
+        match self {
+            HostKeyCheck::KnownHosts => ServerCheckMethod::DefaultKnownHostsFile,
+            HostKeyCheck::KnownHostsFile(path) => ServerCheckMethod::KnownHostsFile(path.clone()),
+            HostKeyCheck::Pinned(key) => ServerCheckMethod::PublicKey(key.clone()),
+            HostKeyCheck::Insecure => ServerCheckMethod::NoCheck,
+        }
+    }
+}
+
+/// Last resort access over SSH, for when sshd still answers but the miner's APIs don't
+pub struct SshTransport {
+    client: Client,
+    ip: String,
+    port: u16,
+    username: String,
+    password: SecretString,
+    host_key: HostKeyCheck,
+}
+
+impl SshTransport {
+    pub fn new(client: Client, ip: &str, username: &str, password: &str) -> Self {
+        SshTransport {
+            client,
+            ip: ip.to_string(),
+            port: SSH_PORT,
+            username: username.to_string(),
+            password: password.into(),
+            host_key: HostKeyCheck::default(),
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn host_key(mut self, check: HostKeyCheck) -> Self {
+        self.host_key = check;
+        self
+    }
+
+    async fn connect(&self) -> Result<SshClient, Error> {
+        let auth = AuthMethod::with_password(self.password.expose());
+        match tokio::time::timeout(
+            self.client.connect_timeout,
+            SshClient::connect((self.ip.as_str(), self.port), &self.username, auth, self.host_key.method()),
+        ).await {
+            Ok(Ok(ssh)) => Ok(ssh),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(Error::Timeout),
+        }
+    }
+
+    /// Run a command, returning its stdout
+    /// A non zero exit is an ApiCallFailed with stderr
+    pub async fn exec(&self, command: &str) -> Result<String, Error> {
+        let ssh = self.connect().await?;
+        let result = match tokio::time::timeout(self.client.request_timeout, ssh.execute(command)).await {
+            Ok(result) => result?,
+            Err(_) => return Err(Error::Timeout),
+        };
+        if result.exit_status == 0 {
+            Ok(result.stdout)
+        } else {
+            Err(Error::ApiCallFailed(format!("exit {}: {}", result.exit_status, result.stderr.trim())))
+        }
+    }
+
+    pub async fn run(&self, recovery: Recovery) -> Result<String, Error> {
+        self.exec(recovery.command()).await
+    }
+
+    /// Restart the mining process without rebooting the control board
+    pub async fn restart_miner(&self) -> Result<(), Error> {
+        self.run(Recovery::RestartMiner).await.map(|_| ())
+    }
+
+    pub async fn get_logs(&self) -> Result<Vec<String>, Error> {
+        Ok(self.run(Recovery::FetchLogs).await?.lines().map(|s| s.to_string()).collect())
+    }
+
+    pub async fn restart_network(&self) -> Result<(), Error> {
+        self.run(Recovery::RestartNetwork).await.map(|_| ())
+    }
+
+    /// Counters of eth0 and packet loss to the gateway, for diagnosing a flapping link
+    pub async fn get_network_stats(&self) -> Result<NetworkStats, Error> {
+        parse_network_stats(&self.exec(NETWORK_STATS).await?, "eth0")
+    }
+}
+
+/// Output of NETWORK_STATS for an interface
+fn parse_network_stats(output: &str, iface: &str) -> Result<NetworkStats, Error> {
+    let (dev, rest) = output.split_once("--speed").ok_or(Error::InvalidResponse)?;
+    let (speed, ping) = rest.split_once("--ping").unwrap_or((rest, ""));
+
+    // rx bytes packets errs drop fifo frame compressed multicast, then tx bytes packets errs drop ...
+    let counters: Vec<u64> = dev.lines()
+        .find_map(|line| line.trim().strip_prefix(iface)?.strip_prefix(':').map(|c| c.to_string()))
+        .ok_or_else(|| Error::ApiCallFailed(format!("No interface {}", iface)))?
+        .split_whitespace()
+        .filter_map(|c| c.parse().ok())
+        .collect();
+    let counter = |i: usize| counters.get(i).copied();
+
+    Ok(NetworkStats {
+        // Reads -1 with the cable out
+        link_speed: speed.trim().parse::<i64>().ok().and_then(|s| u32::try_from(s).ok()),
+        rx_errors: counter(2),
+        tx_errors: counter(10),
+        rx_dropped: counter(3),
+        tx_dropped: counter(11),
+        gateway_loss: regex!(r"([\d.]+)% packet loss").captures(ping).and_then(|caps| caps[1].parse().ok()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_network_stats() {
+        let output = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:   10240      80    0    0    0     0          0         0    10240      80    0    0    0     0       0          0
+  eth0: 918273645 1203344   12    3    0    12          0      4411 51234567  604120    1    0    0     0       0          0
+--speed
+100
+--ping
+PING 10.0.5.254 (10.0.5.254): 56 data bytes
+
+--- 10.0.5.254 ping statistics ---
+5 packets transmitted, 4 packets received, 20% packet loss
+round-trip min/avg/max = 0.412/0.538/0.701 ms";
+        let stats = parse_network_stats(output, "eth0").unwrap();
+        assert_eq!(stats.link_speed, Some(100));
+        assert_eq!(stats.rx_errors, Some(12));
+        assert_eq!(stats.rx_dropped, Some(3));
+        assert_eq!(stats.tx_errors, Some(1));
+        assert_eq!(stats.tx_dropped, Some(0));
+        assert_eq!(stats.gateway_loss, Some(20.0));
+
+        let unplugged = "  eth0: 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n--speed\n-1\n--ping\n";
+        let stats = parse_network_stats(unplugged, "eth0").unwrap();
+        assert_eq!(stats.link_speed, None);
+        assert_eq!(stats.gateway_loss, None);
+        assert!(parse_network_stats(output, "eth1").is_err());
+    }
+
+    #[test]
+    fn test_host_key_check() {
+        assert!(matches!(HostKeyCheck::default().method(), ServerCheckMethod::DefaultKnownHostsFile));
+        assert!(matches!(HostKeyCheck::Pinned("AAAAC3Nza".into()).method(), ServerCheckMethod::PublicKey(key) if key == "AAAAC3Nza"));
+        assert!(matches!(HostKeyCheck::Insecure.method(), ServerCheckMethod::NoCheck));
+    }
+
+    #[test]
+    fn test_restart_network_detaches() {
+        // The session has to end before the link goes down or the restart reads as a failure
+        let command = Recovery::RestartNetwork.command();
+        assert!(command.contains("trap '' HUP"));
+        assert!(command.contains("& exit 0"));
+    }
+}
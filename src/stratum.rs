@@ -0,0 +1,82 @@
+use futures::future::join_all;
+use serde_json::{json, Value};
+
+use crate::Client;
+use crate::error::Error;
+use crate::miner::Pool;
+use crate::util::framing::Framing;
+
+/// Host and port from a pool URL, e.g. "stratum+tcp://btc.pool.example:3333", and whether it's TLS
+pub fn parse_pool_url(url: &str) -> Result<(String, u16, bool), Error> {
+    let invalid = || Error::InvalidConfig(format!("invalid pool URL {}", url));
+    let (scheme, rest) = match url.trim().split_once("://") {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => (String::new(), url.trim()),
+    };
+    let tls = scheme.contains("ssl") || scheme.contains("tls");
+    let rest = rest.split('/').next().unwrap_or_default();
+    let (host, port) = rest.rsplit_once(':').ok_or_else(invalid)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = port.parse::<u16>().map_err(|_| invalid())?;
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port, tls))
+}
+
+/// Connect through client and send mining.subscribe, Ok if a stratum answer comes back
+/// TLS pools are only checked for accepting the connection
+pub async fn probe_pool(client: &Client, url: &str) -> Result<(), Error> {
+    let (host, port, tls) = parse_pool_url(url)?;
+    if tls {
+        return client.connect(&host, port).await.map(drop);
+    }
+    let subscribe = json!({"id": 1, "method": "mining.subscribe", "params": []});
+    let resp = client.send_recv_framed(&host, port, &format!("{}\n", subscribe), Framing::Json).await?;
+    let resp: Value = serde_json::from_str(&resp).map_err(|_| Error::InvalidResponse)?;
+    if resp.get("result").is_some() || resp.get("method").is_some() {
+        Ok(())
+    } else {
+        Err(Error::InvalidResponse)
+    }
+}
+
+/// Probe every pool at once, the error for each that didn't answer
+pub async fn dead_pools(client: &Client, pools: &[Pool]) -> Vec<(String, Error)> {
+    join_all(pools.iter().map(|p| async move { (p.url.clone(), probe_pool(client, &p.url).await) }))
+        .await
+        .into_iter()
+        .filter_map(|(url, result)| result.err().map(|e| (url, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pool_url() {
+        assert_eq!(parse_pool_url("stratum+tcp://btc.pool.example:3333").unwrap(), ("btc.pool.example".into(), 3333, false));
+        assert_eq!(parse_pool_url("stratum+ssl://btc.pool.example:443").unwrap(), ("btc.pool.example".into(), 443, true));
+        assert_eq!(parse_pool_url("10.0.0.5:3333").unwrap(), ("10.0.0.5".into(), 3333, false));
+        assert_eq!(parse_pool_url("stratum+tcp://[fd00::5]:3333").unwrap(), ("fd00::5".into(), 3333, false));
+        assert!(parse_pool_url("stratum+tcp://btc.pool.example").is_err());
+        assert!(parse_pool_url("stratum+tcp://btc.pool.exmaple:33a3").is_err());
+        assert!(parse_pool_url("stratum+tcp://:3333").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_pool() {
+        let pool = |url: &str| Pool { url: url.into(), username: "worker.1".into(), password: None };
+        let script = crate::transport::ScriptedSocket::new()
+            .reply("mining.subscribe", r#"{"id":1,"result":[[["mining.notify","ae6812eb"]],"08000002",4],"error":null}"#);
+        let client = crate::ClientBuilder::new().socket_transport(script.clone()).build().unwrap();
+        probe_pool(&client, "stratum+tcp://btc.pool.example:3333").await.unwrap();
+        assert!(script.requests()[0].starts_with(r#"{"id":1,"method":"mining.subscribe""#));
+
+        // Nothing answers, so both pools are dead
+        let client = crate::ClientBuilder::new().socket_transport(crate::transport::ScriptedSocket::new()).build().unwrap();
+        let dead = dead_pools(&client, &[pool("stratum+tcp://btc.pool.example:3333"), pool("btc.pool.example:25")]).await;
+        assert_eq!(dead.len(), 2);
+    }
+}
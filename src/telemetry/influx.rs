@@ -0,0 +1,184 @@
+#[cfg(feature = "http")]
+use crate::error::Error;
+#[cfg(feature = "http")]
+use crate::SecretString;
+use crate::snapshot::MinerSnapshot;
+
+/// Measurement name used for every line
+pub const MEASUREMENT: &str = "miner";
+
+/// Tag keys/values and measurement names escape commas, spaces and equals signs
+fn escape_tag(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// String field values are quoted, so only quotes and backslashes need escaping
+fn escape_str(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// One line protocol point per snapshot, tagged by ip/vendor/model and the miner's tags with a nanosecond timestamp
+/// Unreachable miners still produce a point with `reachable=false` so gaps are visible
+pub fn to_line(snap: &MinerSnapshot) -> String {
+    let mut tags = format!("{},ip={},vendor={}", MEASUREMENT, escape_tag(&snap.ip), escape_tag(&snap.vendor));
+    if let Some(model) = &snap.model {
+        tags.push_str(&format!(",model={}", escape_tag(model)));
+    }
+    // Miner tags can't shadow the ones above, and line protocol has no empty tag values
+    for (key, value) in snap.tags.iter().filter(|(k, v)| !v.is_empty() && !["ip", "vendor", "model"].contains(&k.as_str())) {
+        tags.push_str(&format!(",{}={}", escape_tag(key), escape_tag(value)));
+    }
+
+    let mut fields = vec![format!("reachable={}", snap.reachable())];
+    if let Some(error) = &snap.error {
+        fields.push(format!("error={}", escape_str(error)));
+    }
+    fields.push(format!("quality={}", escape_str(&snap.quality.to_string())));
+    if let Some(state) = snap.power_state {
+        fields.push(format!("power_state={}", escape_str(&format!("{:?}", state))));
+    }
+    for (name, value) in [
+        ("hashrate", snap.hashrate),
+        ("power", snap.power),
+        ("efficiency", snap.efficiency),
+        ("performance_ratio", snap.performance_ratio),
+        ("temperature", snap.temperature),
+    ] {
+        if let Some(value) = value.filter(|v| v.is_finite()) {
+            fields.push(format!("{}={}", name, value));
+        }
+    }
+    if let Some(fans) = &snap.fan_speed {
+        for (i, speed) in fans.iter().enumerate() {
+            fields.push(format!("fan{}={}i", i, speed));
+        }
+    }
+
+    let ts = snap.taken_at.timestamp_nanos_opt().unwrap_or_default();
+    format!("{} {} {}", tags, fields.join(","), ts)
+}
+
+pub fn to_lines(snapshots: &[MinerSnapshot]) -> String {
+    snapshots.iter().map(to_line).collect::<Vec<_>>().join("\n")
+}
+
+/// Writes points to the InfluxDB v2 HTTP API
+#[cfg(feature = "http")]
+#[derive(Debug)]
+pub struct InfluxWriter {
+    http_client: reqwest::Client,
+    url: String,
+    org: String,
+    bucket: String,
+    token: SecretString,
+    timeout: std::time::Duration,
+}
+
+#[cfg(feature = "http")]
+impl InfluxWriter {
+    /// url is the server root, e.g. http://localhost:8086
+    pub fn new(url: &str, org: &str, bucket: &str, token: &str) -> InfluxWriter {
+        InfluxWriter {
+            http_client: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            org: org.to_string(),
+            bucket: bucket.to_string(),
+            token: token.into(),
+            timeout: std::time::Duration::from_secs(10),
+        }
+    }
+
+    /// How long to wait for the server before giving up on a write, default is 10 seconds
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub async fn write(&self, snapshots: &[MinerSnapshot]) -> Result<(), Error> {
+        if snapshots.is_empty() {
+            return Ok(());
+        }
+        let resp = self.http_client
+            .post(format!("{}/api/v2/write", self.url))
+            .query(&[("org", self.org.as_str()), ("bucket", self.bucket.as_str()), ("precision", "ns")])
+            .header("Authorization", format!("Token {}", self.token.expose()))
+            .body(to_lines(snapshots))
+            .timeout(self.timeout)
+            .send()
+            .await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::ApiCallFailed(format!("influx write returned {}", resp.status())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::snapshot::DataQuality;
+
+    fn snapshot() -> MinerSnapshot {
+        MinerSnapshot {
+            model: Some("S19 Pro".into()),
+            hashrate: Some(110.5),
+            efficiency: None,
+            temperature: Some(f64::NAN),
+            fan_speed: Some(vec![5400, 5520]),
+            taken_at: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            ..MinerSnapshot::test()
+        }
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(
+            to_line(&snapshot()),
+            r#"miner,ip=10.0.0.1,vendor=Antminer,model=S19\ Pro reachable=true,quality="Ok",power_state="Mining",hashrate=110.5,power=3250,fan0=5400i,fan1=5520i 1700000000000000000"#,
+        );
+    }
+
+    #[test]
+    fn test_tagged_line() {
+        let mut snap = snapshot();
+        snap.tags.insert("rack".into(), "B 12".into());
+        snap.tags.insert("ip".into(), "10.9.9.9".into());
+        snap.tags.insert("notes".into(), "".into());
+        assert!(to_line(&snap).starts_with(r#"miner,ip=10.0.0.1,vendor=Antminer,model=S19\ Pro,rack=B\ 12 reachable=true"#));
+    }
+
+    #[test]
+    fn test_unreachable_line() {
+        let mut snap = snapshot();
+        snap.model = None;
+        snap.power_state = None;
+        snap.hashrate = None;
+        snap.power = None;
+        snap.temperature = None;
+        snap.fan_speed = None;
+        snap.error = Some(r#"Unknown miner type "x""#.into());
+        assert_eq!(
+            to_line(&snap),
+            r#"miner,ip=10.0.0.1,vendor=Antminer reachable=false,error="Unknown miner type \"x\"",quality="Ok" 1700000000000000000"#,
+        );
+    }
+
+    #[test]
+    fn test_suspect_line() {
+        let mut snap = snapshot();
+        snap.quality = DataQuality::Suspect(vec!["Temperature 655.0C".into()]);
+        assert!(to_line(&snap).contains(r#",quality="Suspect: Temperature 655.0C","#));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_writer_hides_token() {
+        let writer = InfluxWriter::new("http://localhost:8086/", "org", "bucket", "s3cret");
+        assert!(!format!("{:?}", writer).contains("s3cret"));
+    }
+}
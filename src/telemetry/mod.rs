@@ -0,0 +1,5 @@
+//! Formatting snapshots for monitoring stacks
+
+pub mod influx;
+#[cfg(feature = "otel")]
+pub mod otel;
@@ -0,0 +1,97 @@
+use std::net::IpAddr;
+use chrono::FixedOffset;
+use std::sync::OnceLock;
+use async_trait::async_trait;
+use opentelemetry::{global, KeyValue};
+use opentelemetry::metrics::{Counter, Gauge, Histogram};
+use tokio::time::Duration;
+
+use crate::error::Error;
+use crate::miner::{Miner, MinerError, Pool, Profile, PowerState, HealthCondition, Capabilities, PendingChange, TuningStatus, PoolCounters, LogOptions, LogEntry, SystemStats, NetworkStats, CoolingDetail, FanMode, ChipCount, BoardTemps, TempReport, Resource, Tags};
+use crate::security::SecurityIndicator;
+use crate::backup::MinerConfigBackup;
+use crate::detection::DetectionInfo;
+
+struct Metrics {
+    api_calls: Counter<u64>,
+    api_errors: Counter<u64>,
+    detection_duration: Histogram<f64>,
+    hashrate: Gauge<f64>,
+}
+
+/// Instruments are created against the global meter provider the first time they're used
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| {
+        let meter = global::meter("libminer");
+        Metrics {
+            api_calls: meter.u64_counter("api_calls_total")
+                .with_description("Miner API calls by vendor and endpoint")
+                .init(),
+            api_errors: meter.u64_counter("api_errors_total")
+                .with_description("Failed miner API calls by vendor and endpoint")
+                .init(),
+            detection_duration: meter.f64_histogram("detection_duration")
+                .with_description("Time taken to detect a miner")
+                .with_unit("s")
+                .init(),
+            hashrate: meter.f64_gauge("hashrate")
+                .with_description("Last hashrate reading per miner")
+                .with_unit("TH/s")
+                .init(),
+        }
+    })
+}
+
+pub(crate) fn record_detection(duration: Duration, result: &Result<Box<dyn Miner + Send + Sync>, Error>) {
+    let vendor = match result {
+        Ok(miner) => miner.get_type(),
+        Err(_) => "none",
+    };
+    metrics().detection_duration.record(duration.as_secs_f64(), &[KeyValue::new("vendor", vendor)]);
+}
+
+/// Counts every trait call on the wrapped miner, the endpoint label is the Miner method name
+/// Client::get_miner wraps detected miners in this when the `otel` feature is on
+pub struct InstrumentedMiner {
+    miner: Box<dyn Miner + Send + Sync>,
+}
+
+impl InstrumentedMiner {
+    pub fn new(miner: Box<dyn Miner + Send + Sync>) -> InstrumentedMiner {
+        InstrumentedMiner { miner }
+    }
+
+    pub fn inner(&self) -> &(dyn Miner + Send + Sync) {
+        &*self.miner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut (dyn Miner + Send + Sync) {
+        &mut *self.miner
+    }
+
+    pub fn into_inner(self) -> Box<dyn Miner + Send + Sync> {
+        self.miner
+    }
+
+    fn record<T>(&self, endpoint: &'static str, result: &Result<T, Error>) {
+        let attrs = [
+            KeyValue::new("vendor", self.miner.get_type()),
+            KeyValue::new("endpoint", endpoint),
+        ];
+        metrics().api_calls.add(1, &attrs);
+        // NotSupported is a capability gap rather than a failure
+        if matches!(result, Err(e) if !matches!(e, Error::NotSupported)) {
+            metrics().api_errors.add(1, &attrs);
+        }
+    }
+
+    fn record_hashrate(&self, hashrate: f64) {
+        metrics().hashrate.record(hashrate, &[
+            KeyValue::new("vendor", self.miner.get_type()),
+            KeyValue::new("ip", self.miner.get_ip().to_string()),
+        ]);
+    }
+}
+
+delegate_miner!(InstrumentedMiner, instrumented);
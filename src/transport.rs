@@ -0,0 +1,285 @@
+//! What Client reaches miners over, so miner modules can be tested against scripted replies and
+//! other stacks can be swapped in, see ClientBuilder::socket_transport and http_transport
+
+use async_trait::async_trait;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::error::Error;
+use crate::util::resolver::{self, Resolver};
+use crate::util::stream::SocketStream;
+#[cfg(feature = "http")]
+use crate::Client;
+
+/// A connected socket API stream
+pub trait SocketIo: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T> SocketIo for T where T: AsyncRead + AsyncWrite + Send + Unpin {}
+
+/// Opens socket API connections, Client applies connect_timeout and request_timeout around it
+#[async_trait]
+pub trait SocketTransport: fmt::Debug + Send + Sync {
+    async fn connect(&self, ip: &str, port: u16) -> Result<Box<dyn SocketIo>, Error>;
+}
+
+/// Sends web API requests, requests are still built with reqwest so only sending is swapped
+#[cfg(feature = "http")]
+#[async_trait]
+pub trait HttpTransport: fmt::Debug + Send + Sync {
+    async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response, Error>;
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl HttpTransport for reqwest::Client {
+    async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response, Error> {
+        Ok(reqwest::Client::execute(self, request).await?)
+    }
+}
+
+/// Send a request built from Client's reqwest client through its HttpTransport
+#[cfg(feature = "http")]
+#[async_trait]
+pub(crate) trait SendVia {
+    async fn send_via(self, client: &Client) -> Result<reqwest::Response, Error>;
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl SendVia for reqwest::RequestBuilder {
+    async fn send_via(self, client: &Client) -> Result<reqwest::Response, Error> {
+        client.http.execute(self.build()?).await
+    }
+}
+
+/// The default, TCP through the DNS cache from the bound interface, in TLS for targets given socket_tls
+#[derive(Debug)]
+pub(crate) struct TcpTransport {
+    pub(crate) resolver: Arc<Resolver>,
+    pub(crate) local_address: Option<IpAddr>,
+    #[cfg(feature = "socket-tls")]
+    pub(crate) tls: crate::util::stream::TlsTargets,
+}
+
+#[async_trait]
+impl SocketTransport for TcpTransport {
+    async fn connect(&self, ip: &str, port: u16) -> Result<Box<dyn SocketIo>, Error> {
+        let addrs = self.resolver.resolve(ip).await?;
+        let stream = resolver::connect(&addrs, port, self.local_address).await?;
+        #[cfg(feature = "socket-tls")]
+        if let Some(tls) = self.tls.for_host(ip) {
+            return Ok(Box::new(tls.wrap(ip, stream).await?));
+        }
+        Ok(Box::new(SocketStream::Plain(stream)))
+    }
+}
+
+/// Canned socket API replies, the first rule whose needle is in the request answers it
+/// Requests nothing matches get an empty reply, as from a miner that closed the connection
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedSocket {
+    /// None never answers
+    rules: Arc<Vec<(String, Option<String>)>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl ScriptedSocket {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer requests containing needle, e.g. "\"summary\"", with reply
+    pub fn reply(mut self, needle: &str, reply: &str) -> Self {
+        Arc::make_mut(&mut self.rules).push((needle.to_string(), Some(reply.to_string())));
+        self
+    }
+
+    /// Take requests containing needle and never answer them, as a wedged miner does
+    pub fn hang(mut self, needle: &str) -> Self {
+        Arc::make_mut(&mut self.rules).push((needle.to_string(), None));
+        self
+    }
+
+    /// Every request sent so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[async_trait]
+impl SocketTransport for ScriptedSocket {
+    async fn connect(&self, _ip: &str, _port: u16) -> Result<Box<dyn SocketIo>, Error> {
+        Ok(Box::new(ScriptedStream {
+            script: self.clone(),
+            written: Vec::new(),
+            reply: None,
+        }))
+    }
+}
+
+/// One connection to a ScriptedSocket, the reply is picked on the first read
+struct ScriptedStream {
+    script: ScriptedSocket,
+    written: Vec<u8>,
+    /// None for a request that hangs
+    reply: Option<Option<(Vec<u8>, usize)>>,
+}
+
+impl AsyncRead for ScriptedStream {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let reply = this.reply.get_or_insert_with(|| {
+            let request = String::from_utf8_lossy(&this.written).to_string();
+            let reply = match this.script.rules.iter().find(|(needle, _)| request.contains(needle.as_str())) {
+                Some((_, reply)) => reply.clone().map(String::into_bytes),
+                None => Some(Vec::new()),
+            };
+            this.script.requests.lock().unwrap_or_else(|e| e.into_inner()).push(request);
+            reply.map(|reply| (reply, 0))
+        });
+        let Some((reply, pos)) = reply else {
+            return Poll::Pending;
+        };
+        let n = buf.remaining().min(reply.len() - *pos);
+        buf.put_slice(&reply[*pos..*pos + n]);
+        *pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ScriptedStream {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Canned web API replies by method and path, anything else is a 404
+/// Several replies to the same method and path are given in turn, the last one repeats
+#[cfg(feature = "http")]
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedHttp {
+    rules: Arc<Vec<(reqwest::Method, String, u16, String)>>,
+    requests: Arc<Mutex<Vec<(reqwest::Method, String, String)>>>,
+}
+
+#[cfg(feature = "http")]
+impl ScriptedHttp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer method requests to path, e.g. "/cgi-bin/summary.cgi", with status and body
+    pub fn reply(mut self, method: reqwest::Method, path: &str, status: u16, body: &str) -> Self {
+        Arc::make_mut(&mut self.rules).push((method, path.to_string(), status, body.to_string()));
+        self
+    }
+
+    /// Method and path of every request sent so far, in order
+    pub fn requests(&self) -> Vec<(reqwest::Method, String)> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).iter()
+            .map(|(method, path, _)| (method.clone(), path.clone()))
+            .collect()
+    }
+
+    /// Bodies of every request sent to path so far, in order
+    pub fn bodies(&self, path: &str) -> Vec<String> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner()).iter()
+            .filter(|(_, p, _)| p == path)
+            .map(|(_, _, body)| body.clone())
+            .collect()
+    }
+}
+
+#[cfg(feature = "http")]
+#[async_trait]
+impl HttpTransport for ScriptedHttp {
+    async fn execute(&self, request: reqwest::Request) -> Result<reqwest::Response, Error> {
+        let path = request.url().path().to_string();
+        let seen = {
+            let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+            let seen = requests.iter().filter(|(m, p, _)| m == request.method() && *p == path).count();
+            let body = request.body().and_then(|b| b.as_bytes()).map(|b| String::from_utf8_lossy(b).to_string()).unwrap_or_default();
+            requests.push((request.method().clone(), path.clone(), body));
+            seen
+        };
+        let matching: Vec<_> = self.rules.iter()
+            .filter(|(method, p, _, _)| method == request.method() && *p == path)
+            .collect();
+        let (status, body) = matching.get(seen.min(matching.len().saturating_sub(1)))
+            .map(|(_, _, status, body)| (*status, body.clone()))
+            .unwrap_or((404, String::new()));
+        let resp = http::Response::builder()
+            .status(status)
+            .body(body)
+            .map_err(|_| Error::InvalidResponse)?;
+        Ok(resp.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::framing::{Framing, read_framed};
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_scripted_socket() {
+        let script = ScriptedSocket::new()
+            .reply("\"summary\"", r#"{"STATUS":[{"STATUS":"S"}]}"#);
+        let mut stream = script.connect("10.0.0.1", 4028).await.unwrap();
+        stream.write_all(br#"{"command":"summary"}"#).await.unwrap();
+        let (buf, _) = read_framed(&mut stream, Framing::Eof).await.unwrap();
+        assert_eq!(buf, br#"{"STATUS":[{"STATUS":"S"}]}"#);
+
+        let mut stream = script.connect("10.0.0.1", 4028).await.unwrap();
+        stream.write_all(br#"{"command":"pools"}"#).await.unwrap();
+        let (buf, _) = read_framed(&mut stream, Framing::Eof).await.unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(script.requests().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scripted_socket_hang() {
+        let script = ScriptedSocket::new().hang("\"stats\"");
+        let mut stream = script.connect("10.0.0.1", 4028).await.unwrap();
+        stream.write_all(br#"{"command":"stats"}"#).await.unwrap();
+        let read = tokio::time::timeout(std::time::Duration::from_secs(60), read_framed(&mut stream, Framing::Eof)).await;
+        assert!(read.is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_scripted_http() {
+        use crate::ClientBuilder;
+
+        let script = ScriptedHttp::new()
+            .reply(reqwest::Method::GET, "/api/v1/status", 200, r#"{"miner_state":"mining"}"#)
+            .reply(reqwest::Method::GET, "/api/v1/status", 200, r#"{"miner_state":"stopped"}"#);
+        let client = ClientBuilder::new().http_transport(script.clone()).build().unwrap();
+        let resp = client.http_client.get("http://10.0.0.1/api/v1/status").send_via(&client).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), r#"{"miner_state":"mining"}"#);
+
+        let resp = client.http_client.post("http://10.0.0.1/api/v1/status").send_via(&client).await.unwrap();
+        assert_eq!(resp.status(), 404);
+        for _ in 0..2 {
+            let resp = client.http_client.get("http://10.0.0.1/api/v1/status").send_via(&client).await.unwrap();
+            assert_eq!(resp.text().await.unwrap(), r#"{"miner_state":"stopped"}"#);
+        }
+        assert_eq!(script.requests().len(), 4);
+    }
+}
@@ -0,0 +1,47 @@
+use std::net::Ipv4Addr;
+
+/// An IPv4 subnet such as "10.1.0.0/16"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Net {
+    net: u32,
+    mask: u32,
+}
+
+impl Ipv4Net {
+    /// None if it isn't an address with a prefix length of at most 32
+    pub fn parse(cidr: &str) -> Option<Self> {
+        let (net, bits) = cidr.trim().split_once('/')?;
+        let net = net.parse::<Ipv4Addr>().ok()?;
+        let bits = bits.parse::<u32>().ok().filter(|b| *b <= 32)?;
+        let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+        Some(Self {
+            net: u32::from(net) & mask,
+            mask,
+        })
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        u32::from(ip) & self.mask == self.net
+    }
+
+    /// Prefix length, higher is more specific
+    pub fn prefix_len(&self) -> u32 {
+        self.mask.count_ones()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let net = Ipv4Net::parse("10.1.2.3/16").unwrap();
+        assert!(net.contains("10.1.200.1".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert_eq!(net.prefix_len(), 16);
+        assert!(Ipv4Net::parse("0.0.0.0/0").unwrap().contains("192.168.1.1".parse().unwrap()));
+        assert!(Ipv4Net::parse("10.0.0.0/33").is_none());
+        assert!(Ipv4Net::parse("10.0.0.0").is_none());
+    }
+}
@@ -4,30 +4,36 @@ use async_trait::async_trait;
 use reqwest::{RequestBuilder, Response, StatusCode};
 use digest_auth::AuthContext;
 use crate::error::Error;
+use crate::Client;
+use crate::transport::SendVia;
 
 #[async_trait]
 pub trait WithDigestAuth {
-    async fn send_with_digest_auth(self, username: &str, password: &str) -> Result<Response, Error>;
+    async fn send_with_digest_auth(self, client: &Client, username: &str, password: &str) -> Result<Response, Error>;
 }
 
 #[async_trait]
 impl WithDigestAuth for RequestBuilder {
     //TODO: this can panic
-    async fn send_with_digest_auth(self, username: &str, password: &str) -> Result<Response, Error> {
+    async fn send_with_digest_auth(self, client: &Client, username: &str, password: &str) -> Result<Response, Error> {
         // Send a request to get the digest auth headers
         let req = self.try_clone().unwrap();//.send().await?;
-        let resp = req.send().await?;
+        let resp = req.send_via(client).await?;
         match resp.status() {
             StatusCode::UNAUTHORIZED => {
                 let request = self.try_clone().unwrap().build()?;
                 let uri = request.url().path();
                 let method = digest_auth::HttpMethod::from(request.method().as_str());
                 let body = request.body().and_then(|b| b.as_bytes());
-                let www_auth = resp.headers().get("www-authenticate").unwrap().to_str()?;
+                // Nothing to answer without a challenge, the 401 stands
+                let www_auth = match resp.headers().get("www-authenticate") {
+                    Some(www_auth) => www_auth.to_str()?,
+                    None => return Ok(resp),
+                };
                 let context = AuthContext::new_with_method(username, password, uri, body, method);
                 let mut prompt = digest_auth::parse(www_auth)?;
                 let auth_header = prompt.respond(&context)?;
-                Ok(self.header("Authorization", auth_header.to_header_string()).send().await?)
+                self.header("Authorization", auth_header.to_header_string()).send_via(client).await
             }
             _ => return Ok(resp),
         }
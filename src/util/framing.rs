@@ -0,0 +1,116 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// How to tell a socket API response has finished
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Framing {
+    /// Read until the miner closes the connection
+    Eof,
+    /// Stop at a NUL terminator or once a complete JSON object/array has arrived
+    /// For firmware that leaves the connection open after responding
+    Json,
+    /// A little endian u32 byte count followed by that many bytes, e.g. the Whatsminer v3 API
+    #[cfg(feature = "whatsminer")]
+    LengthPrefixed,
+}
+
+impl Framing {
+    /// Length of the first full response in buf, if there is one yet
+    pub fn end(&self, buf: &[u8]) -> Option<usize> {
+        match self {
+            Framing::Eof => None,
+            Framing::Json => {
+                let nul = buf.iter().position(|&b| b == 0).map(|i| i + 1);
+                match (nul, json_end(buf)) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                }
+            },
+            #[cfg(feature = "whatsminer")]
+            Framing::LengthPrefixed => {
+                let len = u32::from_le_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+                Some(4 + len).filter(|end| buf.len() >= *end)
+            },
+        }
+    }
+}
+
+/// Walks brackets outside of strings, anything not starting with { or [ never ends
+fn json_end(buf: &[u8]) -> Option<usize> {
+    let start = buf.iter().position(|b| !b.is_ascii_whitespace())?;
+    if !matches!(buf[start], b'{' | b'[') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, &b) in buf.iter().enumerate().skip(start) {
+        if in_str {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_str = false,
+                _ => {},
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_str = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Read one response, returning it along with anything that arrived after it
+pub async fn read_framed<R>(stream: &mut R, framing: Framing) -> std::io::Result<(Vec<u8>, Vec<u8>)>
+    where R: AsyncRead + Unpin
+{
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok((buf, vec![]));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(end) = framing.end(&buf) {
+            let rest = buf.split_off(end);
+            return Ok((buf, rest));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_framing() {
+        let f = Framing::Json;
+        assert_eq!(f.end(br#"{"STATUS":"S","Msg":{"a":[1,2]}}"#), Some(32));
+        assert_eq!(f.end(br#"{"STATUS":"S","Msg":{"a":[1,2]}"#), None);
+        // Brackets inside strings don't count
+        assert_eq!(f.end(br#"{"Msg":"}]"#), None);
+        assert_eq!(f.end(br#"{"Msg":"say \"}\""}"#), Some(19));
+        assert_eq!(f.end(b"STATUS=S,When=0|\0"), Some(17));
+        assert_eq!(f.end(b"STATUS=S,When=0|"), None);
+        assert_eq!(f.end(b"{}\0"), Some(2));
+        assert_eq!(Framing::Eof.end(b"{}"), None);
+    }
+
+    #[cfg(feature = "whatsminer")]
+    #[test]
+    fn test_length_prefixed_framing() {
+        let f = Framing::LengthPrefixed;
+        assert_eq!(f.end(b"\x02\x00\x00\x00{}extra"), Some(6));
+        assert_eq!(f.end(b"\x02\x00\x00\x00{"), None);
+        assert_eq!(f.end(b"\x02\x00"), None);
+    }
+}
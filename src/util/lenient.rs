@@ -0,0 +1,142 @@
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+use serde::de::DeserializeOwned;
+
+/// Missing fields filled in before giving up on a response
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+const MAX_PATCHES: usize = 32;
+/// Warnings kept until Client::parse_warnings takes them, oldest dropped first
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+const MAX_WARNINGS: usize = 1000;
+/// Tried in turn for a missing field until one deserializes as its type
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+const PLACEHOLDERS: [&str; 6] = ["null", "0", "\"\"", "false", "[]", "{}"];
+
+/// A response that only parsed after lenient parsing filled something in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseWarning {
+    pub ip: String,
+    /// CGI, path or command the response came from
+    pub endpoint: String,
+    pub message: String,
+}
+
+/// Warnings from every miner of one client
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ParseLog(Arc<Mutex<Vec<ParseWarning>>>);
+
+impl ParseLog {
+    #[cfg(any(feature = "antminer", feature = "whatsminer"))]
+    pub fn push(&self, warning: ParseWarning) {
+        let mut log = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if log.len() >= MAX_WARNINGS {
+            log.remove(0);
+        }
+        log.push(warning);
+    }
+
+    pub fn take(&self) -> Vec<ParseWarning> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+fn missing_field(e: &serde_json::Error) -> Option<String> {
+    let msg = e.to_string();
+    Some(msg.strip_prefix("missing field `")?.split('`').next()?.to_string())
+}
+
+/// Byte offset just past a line and column serde_json reported
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+fn offset(body: &str, line: usize, column: usize) -> Option<usize> {
+    let start: usize = body.split_inclusive('\n').take(line.checked_sub(1)?).map(str::len).sum();
+    Some(start + column).filter(|o| *o <= body.len())
+}
+
+/// Add "field": placeholder to the object closing before end, with where the insert starts and its length
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+fn patch(body: &str, end: usize, field: &str, placeholder: &str) -> Option<(String, usize, usize)> {
+    let close = body[..end].rfind('}')?;
+    let sep = if body[..close].trim_end().ends_with('{') { "" } else { "," };
+    let insert = format!("{}\"{}\":{}", sep, field, placeholder);
+    Some((format!("{}{}{}", &body[..close], insert, &body[close..]), close, insert.len()))
+}
+
+/// Parse, filling in fields missing from objects with the first placeholder their type accepts
+/// Unknown fields are already skipped by serde. Returns what was filled in, one message per field
+#[cfg(any(feature = "antminer", feature = "whatsminer"))]
+pub(crate) fn from_str_lenient<T: DeserializeOwned>(body: &str) -> Result<(T, Vec<String>), serde_json::Error> {
+    let mut body = body.to_string();
+    let mut patched = vec![];
+    loop {
+        let err = match serde_json::from_str::<T>(&body) {
+            Ok(value) => return Ok((value, patched)),
+            Err(e) => e,
+        };
+        let (field, end) = match (missing_field(&err), offset(&body, err.line(), err.column())) {
+            (Some(field), Some(end)) if patched.len() < MAX_PATCHES => (field, end),
+            _ => return Err(err),
+        };
+        // A placeholder fits when parsing no longer fails inside it
+        let fixed = PLACEHOLDERS.iter().find_map(|placeholder| {
+            let (candidate, start, len) = patch(&body, end, &field, placeholder)?;
+            match serde_json::from_str::<T>(&candidate) {
+                Err(e) if offset(&candidate, e.line(), e.column()).is_some_and(|o| o > start && o <= start + len) => None,
+                _ => Some((candidate, placeholder)),
+            }
+        });
+        match fixed {
+            Some((candidate, placeholder)) => {
+                patched.push(format!("missing field {} read as {}", field, placeholder));
+                body = candidate;
+            },
+            None => return Err(err),
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "antminer", feature = "whatsminer")))]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    struct Chain {
+        id: u32,
+        rate: f64,
+        sn: String,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Stats {
+        chains: Vec<Chain>,
+        enabled: bool,
+    }
+
+    #[test]
+    fn test_from_str_lenient() {
+        let body = r#"{"chains": [{"id": 1, "rate": 35.2, "new_field": 1}, {"id": 2, "sn": "B2"}]}"#;
+        assert!(serde_json::from_str::<Stats>(body).is_err());
+        let (stats, patched) = from_str_lenient::<Stats>(body).unwrap();
+        assert_eq!(stats.chains[0].rate, 35.2);
+        assert_eq!(stats.chains[0].sn, "");
+        assert_eq!(stats.chains[1].rate, 0.0);
+        assert!(!stats.enabled);
+        assert_eq!(patched.len(), 3);
+        assert_eq!(patched[0], "missing field sn read as \"\"");
+
+        let (chain, patched) = from_str_lenient::<Chain>("{}").unwrap();
+        assert_eq!(chain.id, 0);
+        assert_eq!(patched.len(), 3);
+        assert!(from_str_lenient::<Chain>(r#"{"id": "one", "rate": 1, "sn": ""}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_log() {
+        let log = ParseLog::default();
+        log.push(ParseWarning { ip: "10.0.0.1".into(), endpoint: "stats.cgi".into(), message: "missing field sn read as \"\"".into() });
+        assert_eq!(log.take().len(), 1);
+        assert!(log.take().is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::error::Error;
+use crate::util::cidr::Ipv4Net;
+
+/// Called with how long a caller waited for a connection permit, when it was over the threshold
+pub type SlowAcquireCallback = Arc<dyn Fn(Duration) + Send + Sync>;
+
+/// Where the max_connections limit stands
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ClientStats {
+    /// None when the client has no limit, the rest is then always zero
+    pub max_connections: Option<usize>,
+    /// Permits held by miner handles
+    pub active: usize,
+    /// Callers waiting for a permit
+    pub waiting: usize,
+    /// Held permits by Miner::get_type
+    pub in_flight: HashMap<&'static str, usize>,
+    /// Held permits by subnet limit, keyed by the CIDR it was configured with
+    pub subnets: HashMap<String, usize>,
+}
+
+/// A share of max_connections reserved to one site
+struct SubnetLimit {
+    cidr: String,
+    net: Ipv4Net,
+    max: usize,
+    semaphore: Arc<Semaphore>,
+}
+
+impl SubnetLimit {
+    fn parse(cidr: &str, max: usize) -> Result<Self, Error> {
+        let net = Ipv4Net::parse(cidr).ok_or_else(|| Error::InvalidConfig(format!("invalid subnet {}", cidr)))?;
+        if max == 0 {
+            return Err(Error::InvalidConfig(format!("subnet {} needs a limit above 0", cidr)));
+        }
+        Ok(Self {
+            cidr: cidr.trim().to_string(),
+            net,
+            max,
+            semaphore: Arc::new(Semaphore::new(max)),
+        })
+    }
+
+    fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.net.contains(ip)
+    }
+}
+
+/// The max_connections semaphore with the counters behind ClientStats
+/// Hosts in a limited subnet take a permit from it before the global one,
+/// so a slow site queues on its own share instead of holding permits others could use
+pub(crate) struct Limiter {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    /// Most specific first
+    subnets: Vec<SubnetLimit>,
+    waiting: AtomicUsize,
+    in_flight: Mutex<HashMap<&'static str, usize>>,
+    slow_acquire: Option<(Duration, SlowAcquireCallback)>,
+}
+
+impl std::fmt::Debug for Limiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Limiter")
+            .field("max", &self.max)
+            .field("waiting", &self.waiting)
+            .finish()
+    }
+}
+
+/// Keeps the waiting count right when an acquire is cancelled
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Limiter {
+    /// Subnets are IPv4 CIDRs with their share of max, hostnames and addresses outside them only take the global permit
+    pub fn new(max: usize, subnets: &[(String, usize)], slow_acquire: Option<(Duration, SlowAcquireCallback)>) -> Result<Self, Error> {
+        let mut subnets = subnets.iter()
+            .map(|(cidr, max)| SubnetLimit::parse(cidr, *max))
+            .collect::<Result<Vec<_>, _>>()?;
+        subnets.sort_by_key(|s| std::cmp::Reverse(s.net.prefix_len()));
+        Ok(Self {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+            subnets,
+            waiting: AtomicUsize::new(0),
+            in_flight: Mutex::new(HashMap::new()),
+            slow_acquire,
+        })
+    }
+
+    fn subnet(&self, ip: &str) -> Option<&SubnetLimit> {
+        let ip = ip.parse::<Ipv4Addr>().ok()?;
+        self.subnets.iter().find(|s| s.contains(ip))
+    }
+
+    /// The global permit, and the subnet one if ip is in a limited subnet
+    pub async fn acquire(&self, ip: &str) -> Result<(OwnedSemaphorePermit, Option<OwnedSemaphorePermit>), Error> {
+        let started = Instant::now();
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permits = {
+            let _waiting = Waiting(&self.waiting);
+            let subnet = match self.subnet(ip) {
+                Some(subnet) => Some(subnet.semaphore.clone().acquire_owned().await?),
+                None => None,
+            };
+            (self.semaphore.clone().acquire_owned().await?, subnet)
+        };
+        if let Some((threshold, callback)) = &self.slow_acquire {
+            let waited = started.elapsed();
+            if waited > *threshold {
+                callback(waited);
+            }
+        }
+        Ok(permits)
+    }
+
+    /// Count a permit against its vendor until the returned guard drops, which also releases the subnet permit
+    pub fn track(self: &Arc<Self>, vendor: &'static str, subnet: Option<OwnedSemaphorePermit>) -> InFlight {
+        *self.in_flight.lock().unwrap_or_else(|e| e.into_inner()).entry(vendor).or_default() += 1;
+        InFlight { limiter: self.clone(), vendor, _subnet: subnet }
+    }
+
+    pub fn stats(&self) -> ClientStats {
+        ClientStats {
+            max_connections: Some(self.max),
+            active: self.max - self.semaphore.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            in_flight: self.in_flight.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            subnets: self.subnets.iter()
+                .map(|s| (s.cidr.clone(), s.max - s.semaphore.available_permits()))
+                .collect(),
+        }
+    }
+}
+
+/// A held permit's share of the per-vendor count, and its subnet permit
+pub(crate) struct InFlight {
+    limiter: Arc<Limiter>,
+    vendor: &'static str,
+    _subnet: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut in_flight = self.limiter.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = in_flight.get_mut(self.vendor) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(self.vendor);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let limiter = Arc::new(Limiter::new(4, &[], None).unwrap());
+        let permit = limiter.semaphore.clone().try_acquire_owned().unwrap();
+        let a = limiter.track("Antminer", None);
+        let b = limiter.track("Antminer", None);
+        let w = limiter.track("Whatsminer", None);
+        let stats = limiter.stats();
+        assert_eq!(stats.max_connections, Some(4));
+        assert_eq!(stats.active, 1);
+        assert_eq!(stats.waiting, 0);
+        assert_eq!(stats.in_flight, HashMap::from([("Antminer", 2), ("Whatsminer", 1)]));
+
+        drop((a, w, permit));
+        let stats = limiter.stats();
+        assert_eq!(stats.active, 0);
+        assert_eq!(stats.in_flight, HashMap::from([("Antminer", 1)]));
+        drop(b);
+        assert!(limiter.stats().in_flight.is_empty());
+    }
+
+    #[test]
+    fn test_subnets() {
+        let subnets = vec![("10.1.0.0/16".to_string(), 8), ("10.1.2.0/24".to_string(), 2)];
+        let limiter = Arc::new(Limiter::new(16, &subnets, None).unwrap());
+        assert_eq!(limiter.subnet("10.1.2.7").unwrap().cidr, "10.1.2.0/24");
+        assert_eq!(limiter.subnet("10.1.9.7").unwrap().cidr, "10.1.0.0/16");
+        assert!(limiter.subnet("10.2.0.1").is_none());
+        assert!(limiter.subnet("miner-01.site").is_none());
+
+        let site = limiter.subnet("10.1.2.7").unwrap().semaphore.clone().try_acquire_owned().unwrap();
+        let held = limiter.track("Antminer", Some(site));
+        assert_eq!(limiter.stats().subnets["10.1.2.0/24"], 1);
+        drop(held);
+        assert_eq!(limiter.stats().subnets["10.1.2.0/24"], 0);
+
+        assert!(Limiter::new(16, &[("10.1.0.0".to_string(), 8)], None).is_err());
+        assert!(Limiter::new(16, &[("10.1.0.0/16".to_string(), 0)], None).is_err());
+    }
+}
@@ -46,16 +46,14 @@ pub fn bcrypt_hash64_decode(enc: &str, decbuf: &mut [u8]) -> Result<(), Error> {
 }
 
 pub fn md5_sha2_hash64_encode(bs: &[u8]) -> String {
-    let ngroups = (bs.len() + 2) / 3;
+    let ngroups = bs.len().div_ceil(3);
     let mut out = String::with_capacity(ngroups * 4);
     for g in 0..ngroups {
-	let mut g_idx = g * 3;
 	let mut enc = 0u32;
-	for _ in 0..3 {
+	for g_idx in g * 3..g * 3 + 3 {
 	    let b = (if g_idx < bs.len() { bs[g_idx] } else { 0 }) as u32;
 	    enc >>= 8;
 	    enc |= b << 16;
-	    g_idx += 1;
 	}
 	for _ in 0..4 {
 	    out.push(char::from_u32(CRYPT_HASH64[(enc & 0x3F) as usize] as u32).unwrap());
@@ -101,7 +99,7 @@ pub fn do_md5_crypt(pass: &[u8], salt: &str) -> Result<String, Error> {
     while plen > 0 {
 	match plen & 1 {
 	    0 => dgst_a.update(&pass[..1]),
-	    1 => dgst_a.update(&[0u8]),
+	    1 => dgst_a.update([0u8]),
 	    _ => unreachable!()
 	}
 	plen >>= 1;
@@ -114,7 +112,7 @@ pub fn do_md5_crypt(pass: &[u8], salt: &str) -> Result<String, Error> {
 	if r % 2 == 1 {
 	    dgst_a.update(pass);
 	} else {
-	    dgst_a.update(&hash_a);
+	    dgst_a.update(hash_a);
 	}
 	if r % 3 > 0 {
 	    dgst_a.update(salt.as_bytes());
@@ -125,7 +123,7 @@ pub fn do_md5_crypt(pass: &[u8], salt: &str) -> Result<String, Error> {
 	if r % 2 == 0 {
 	    dgst_a.update(pass);
 	} else {
-	    dgst_a.update(&hash_a);
+	    dgst_a.update(hash_a);
 	}
 	hash_a = dgst_a.finalize();
     }
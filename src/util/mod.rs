@@ -1,2 +1,15 @@
+#[cfg(feature = "antminer")]
 pub mod digest_auth;
-pub mod md5;
\ No newline at end of file
+#[cfg(feature = "whatsminer")]
+pub mod md5;
+pub mod framing;
+pub mod resolver;
+#[cfg(feature = "vnish")]
+pub mod ttl;
+pub mod secret;
+pub mod stream;
+pub mod limiter;
+pub mod cidr;
+pub mod lenient;
+#[cfg(feature = "antminer")]
+pub mod write_lock;
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::future::{select, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+
+use crate::error::Error;
+
+/// Which address family to try first when a hostname resolves to both
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressPreference {
+    Ipv4First,
+    Ipv6First,
+    Ipv4Only,
+    Ipv6Only,
+}
+
+/// How long a connection attempt gets before the next address is tried alongside it
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+struct Entry {
+    /// None for a failed lookup
+    addrs: Option<Vec<IpAddr>>,
+    expires: Instant,
+}
+
+/// Resolves hostnames for socket and web API connections, caching both answers and failures
+/// so a flapping DNS server doesn't add a lookup to every call
+#[derive(Debug)]
+pub(crate) struct Resolver {
+    ttl: Duration,
+    negative_ttl: Duration,
+    preference: AddressPreference,
+    cache: Mutex<HashMap<String, Entry>>,
+}
+
+impl Resolver {
+    pub fn new(ttl: Duration, negative_ttl: Duration, preference: AddressPreference) -> Self {
+        Resolver {
+            ttl,
+            negative_ttl,
+            preference,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Addresses for a host in the order they should be tried
+    pub async fn resolve(&self, host: &str) -> Result<Vec<IpAddr>, Error> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        if let Some(entry) = self.cache.lock().unwrap_or_else(|e| e.into_inner()).get(host) {
+            if entry.expires > Instant::now() {
+                return entry.addrs.clone().ok_or(Error::NoHostDetected);
+            }
+        }
+
+        let addrs = lookup_host((host, 0)).await
+            .ok()
+            .map(|addrs| order(addrs.map(|a| a.ip()).collect(), self.preference))
+            .filter(|addrs| !addrs.is_empty());
+        let ttl = if addrs.is_some() { self.ttl } else { self.negative_ttl };
+        self.store(host, addrs.clone(), ttl);
+        addrs.ok_or(Error::NoHostDetected)
+    }
+
+    /// Expired entries are dropped on the way, so hosts that are never looked up again don't pile up
+    fn store(&self, host: &str, addrs: Option<Vec<IpAddr>>, ttl: Duration) {
+        let now = Instant::now();
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.retain(|_, entry| entry.expires > now);
+        cache.insert(host.to_string(), Entry {
+            addrs,
+            expires: now + ttl,
+        });
+    }
+}
+
+/// The same cache and address order for reqwest, which races the addresses itself
+#[cfg(feature = "http")]
+pub(crate) struct HttpResolver(pub std::sync::Arc<Resolver>);
+
+#[cfg(feature = "http")]
+impl reqwest::dns::Resolve for HttpResolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let addrs = resolver.resolve(name.as_str()).await.map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+            // reqwest fills in the port from the URL
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Filter to the allowed families and interleave them, preferred family first
+pub(crate) fn order(addrs: Vec<IpAddr>, preference: AddressPreference) -> Vec<IpAddr> {
+    let (v4, v6): (Vec<IpAddr>, Vec<IpAddr>) = addrs.into_iter().partition(|a| a.is_ipv4());
+    let (first, second) = match preference {
+        AddressPreference::Ipv4Only => return v4,
+        AddressPreference::Ipv6Only => return v6,
+        AddressPreference::Ipv4First => (v4, v6),
+        AddressPreference::Ipv6First => (v6, v4),
+    };
+    let mut ordered = Vec::with_capacity(first.len() + second.len());
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+async fn connect_one(addr: SocketAddr, local: Option<IpAddr>) -> Result<TcpStream, Error> {
+    let local = match local {
+        Some(local) => local,
+        None => return TcpStream::connect(addr).await.map_err(|_| Error::NoHostDetected),
+    };
+    let socket = if local.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    // A failed bind means the address isn't on this host, not that the miner is gone
+    socket.bind(SocketAddr::new(local, 0))
+        .map_err(|e| Error::InvalidConfig(format!("cannot bind {}: {}", local, e)))?;
+    socket.connect(addr).await.map_err(|_| Error::NoHostDetected)
+}
+
+/// Happy eyeballs style connect, each address gets a head start before the next one joins the race
+/// The first connection to succeed wins, the rest are dropped
+pub(crate) async fn connect(addrs: &[IpAddr], port: u16, local: Option<IpAddr>) -> Result<TcpStream, Error> {
+    // Collected up front, an iterator holding the filter closure across the awaits below makes the
+    // future not Send
+    let addrs: Vec<SocketAddr> = addrs.iter()
+        .filter(|a| local.is_none_or(|l| l.is_ipv4() == a.is_ipv4()))
+        .map(|a| SocketAddr::new(*a, port))
+        .collect();
+    let mut addrs = addrs.into_iter().peekable();
+    let mut pending = FuturesUnordered::new();
+    let mut last_err = Error::NoHostDetected;
+    loop {
+        if let Some(addr) = addrs.next() {
+            pending.push(connect_one(addr, local));
+        }
+        if pending.is_empty() {
+            return Err(last_err);
+        }
+        if addrs.peek().is_some() {
+            match select(pending.next(), Box::pin(tokio::time::sleep(ATTEMPT_DELAY))).await {
+                Either::Left((Some(Ok(stream)), _)) => return Ok(stream),
+                Either::Left((Some(Err(e)), _)) => last_err = e,
+                // Either nothing is pending or the head start ran out, start the next address
+                Either::Left((None, _)) | Either::Right(_) => {},
+            }
+        } else {
+            match pending.next().await {
+                Some(Ok(stream)) => return Ok(stream),
+                Some(Err(e)) => last_err = e,
+                None => return Err(last_err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(s: &[&str]) -> Vec<IpAddr> {
+        s.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_order() {
+        let addrs = ips(&["10.0.0.1", "10.0.0.2", "fd00::1"]);
+        assert_eq!(order(addrs.clone(), AddressPreference::Ipv4First), ips(&["10.0.0.1", "fd00::1", "10.0.0.2"]));
+        assert_eq!(order(addrs.clone(), AddressPreference::Ipv6First), ips(&["fd00::1", "10.0.0.1", "10.0.0.2"]));
+        assert_eq!(order(addrs.clone(), AddressPreference::Ipv4Only), ips(&["10.0.0.1", "10.0.0.2"]));
+        assert_eq!(order(addrs, AddressPreference::Ipv6Only), ips(&["fd00::1"]));
+    }
+
+    #[test]
+    fn test_prune() {
+        let resolver = Resolver::new(Duration::from_secs(60), Duration::from_secs(5), AddressPreference::Ipv4First);
+        resolver.store("a.example", None, Duration::ZERO);
+        resolver.store("b.example", Some(ips(&["10.0.0.1"])), Duration::ZERO);
+        resolver.store("c.example", Some(ips(&["10.0.0.2"])), Duration::from_secs(60));
+        let cache = resolver.cache.lock().unwrap();
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec!["c.example"]);
+    }
+}
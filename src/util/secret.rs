@@ -0,0 +1,95 @@
+use std::fmt;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use zeroize::Zeroize;
+
+const REDACTED: &str = "***";
+
+/// A password or token that stays out of Debug output and serialized snapshots
+/// Serializes as "***", fields sent to a miner opt back in with `serialize_with = "expose"`
+/// The memory is zeroed on drop
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(secret: impl Into<String>) -> Self {
+        SecretString(secret.into())
+    }
+
+    /// The actual value, only for putting on the wire
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString({})", REDACTED)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(secret: String) -> Self {
+        SecretString(secret)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(secret: &str) -> Self {
+        SecretString(secret.to_string())
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString)
+    }
+}
+
+/// `serialize_with` for a field that carries the real value
+#[cfg(any(test, feature = "vnish"))]
+pub fn expose<S: Serializer>(secret: &SecretString, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(secret.expose())
+}
+
+/// `serialize_with` for an optional field that carries the real value
+pub fn expose_opt<S: Serializer>(secret: &Option<SecretString>, serializer: S) -> Result<S::Ok, S::Error> {
+    secret.as_ref().map(SecretString::expose).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Wire {
+        #[serde(serialize_with = "expose")]
+        pass: SecretString,
+        token: SecretString,
+    }
+
+    #[test]
+    fn test_redacted() {
+        let secret = SecretString::from("hunter2");
+        assert_eq!(format!("{:?}", secret), "SecretString(***)");
+        let wire = Wire { pass: secret.clone(), token: secret };
+        assert_eq!(serde_json::to_string(&wire).unwrap(), r#"{"pass":"hunter2","token":"***"}"#);
+        let back: SecretString = serde_json::from_str(r#""hunter2""#).unwrap();
+        assert_eq!(back.expose(), "hunter2");
+    }
+}
@@ -0,0 +1,195 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+#[cfg(feature = "socket-tls")]
+pub use tls::SocketTls;
+#[cfg(feature = "socket-tls")]
+pub(crate) use tls::TlsTargets;
+
+/// A socket API connection, wrapped in TLS when the site puts the port behind stunnel
+pub enum SocketStream {
+    Plain(TcpStream),
+    #[cfg(feature = "socket-tls")]
+    Tls(Box<tokio_openssl::SslStream<TcpStream>>),
+}
+
+impl AsyncRead for SocketStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SocketStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "socket-tls")]
+            SocketStream::Tls(s) => Pin::new(&mut **s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for SocketStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            SocketStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "socket-tls")]
+            SocketStream::Tls(s) => Pin::new(&mut **s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SocketStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "socket-tls")]
+            SocketStream::Tls(s) => Pin::new(&mut **s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            SocketStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "socket-tls")]
+            SocketStream::Tls(s) => Pin::new(&mut **s).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(feature = "socket-tls")]
+mod tls {
+    use std::fmt;
+    use std::path::PathBuf;
+    use std::pin::Pin;
+    use openssl::ssl::{SslConnector, SslFiletype, SslMethod, SslVerifyMode};
+    use tokio::net::TcpStream;
+
+    use super::SocketStream;
+    use crate::error::Error;
+    use crate::util::cidr::Ipv4Net;
+
+    fn tls_err(e: impl fmt::Display) -> Error {
+        Error::TlsError(e.to_string())
+    }
+
+    /// TLS settings for the cgminer socket API, see ClientBuilder::socket_tls
+    #[derive(Debug, Clone, Default)]
+    pub struct SocketTls {
+        sni: Option<String>,
+        ca_file: Option<PathBuf>,
+        client_cert: Option<(PathBuf, PathBuf)>,
+        accept_invalid_certs: bool,
+    }
+
+    impl SocketTls {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Name sent as SNI and checked against the certificate
+        /// Without it the address is used and the hostname isn't verified
+        pub fn sni(mut self, name: &str) -> Self {
+            self.sni = Some(name.to_string());
+            self
+        }
+
+        /// PEM file of CAs to trust on top of the system store
+        pub fn ca_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+            self.ca_file = Some(path.into());
+            self
+        }
+
+        /// PEM certificate chain and key for proxies that require client certificates
+        pub fn client_cert<P: Into<PathBuf>>(mut self, cert: P, key: P) -> Self {
+            self.client_cert = Some((cert.into(), key.into()));
+            self
+        }
+
+        /// Skip certificate verification, for self signed stunnel certificates
+        pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+            self.accept_invalid_certs = accept;
+            self
+        }
+
+        fn build(&self) -> Result<TlsConnector, Error> {
+            let mut builder = SslConnector::builder(SslMethod::tls()).map_err(tls_err)?;
+            if let Some(ca_file) = &self.ca_file {
+                builder.set_ca_file(ca_file).map_err(tls_err)?;
+            }
+            if let Some((cert, key)) = &self.client_cert {
+                builder.set_certificate_chain_file(cert).map_err(tls_err)?;
+                builder.set_private_key_file(key, SslFiletype::PEM).map_err(tls_err)?;
+            }
+            if self.accept_invalid_certs {
+                builder.set_verify(SslVerifyMode::NONE);
+            }
+            Ok(TlsConnector {
+                connector: builder.build(),
+                sni: self.sni.clone(),
+            })
+        }
+    }
+
+    /// Which hosts get which TLS settings, an exact host beats the narrowest subnet, which beats
+    /// the default for everything else
+    #[derive(Debug, Default)]
+    pub(crate) struct TlsTargets {
+        hosts: Vec<(String, TlsConnector)>,
+        subnets: Vec<(Ipv4Net, TlsConnector)>,
+        default: Option<TlsConnector>,
+    }
+
+    impl TlsTargets {
+        /// Targets are a host, an address or an IPv4 CIDR such as "10.1.0.0/16"
+        pub fn build(default: Option<&SocketTls>, targets: &[(String, SocketTls)]) -> Result<Self, Error> {
+            let mut built = TlsTargets {
+                default: default.map(SocketTls::build).transpose()?,
+                ..Default::default()
+            };
+            for (target, tls) in targets {
+                if target.contains('/') {
+                    let net = Ipv4Net::parse(target).ok_or_else(|| Error::InvalidConfig(format!("invalid subnet {}", target)))?;
+                    built.subnets.push((net, tls.build()?));
+                } else {
+                    built.hosts.push((target.trim().to_lowercase(), tls.build()?));
+                }
+            }
+            built.subnets.sort_by_key(|(net, _)| std::cmp::Reverse(net.prefix_len()));
+            Ok(built)
+        }
+
+        /// None if connections to host stay plain
+        pub fn for_host(&self, host: &str) -> Option<&TlsConnector> {
+            if let Some((_, tls)) = self.hosts.iter().find(|(h, _)| h.eq_ignore_ascii_case(host)) {
+                return Some(tls);
+            }
+            if let Ok(ip) = host.parse() {
+                if let Some((_, tls)) = self.subnets.iter().find(|(net, _)| net.contains(ip)) {
+                    return Some(tls);
+                }
+            }
+            self.default.as_ref()
+        }
+    }
+
+    pub(crate) struct TlsConnector {
+        connector: SslConnector,
+        sni: Option<String>,
+    }
+
+    impl fmt::Debug for TlsConnector {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("TlsConnector").field("sni", &self.sni).finish()
+        }
+    }
+
+    impl TlsConnector {
+        pub async fn wrap(&self, host: &str, stream: TcpStream) -> Result<SocketStream, Error> {
+            let mut config = self.connector.configure().map_err(tls_err)?;
+            if self.sni.is_none() {
+                config.set_use_server_name_indication(false);
+                config.set_verify_hostname(false);
+            }
+            let ssl = config.into_ssl(self.sni.as_deref().unwrap_or(host)).map_err(tls_err)?;
+            let mut stream = tokio_openssl::SslStream::new(ssl, stream).map_err(tls_err)?;
+            Pin::new(&mut stream).connect().await.map_err(tls_err)?;
+            Ok(SocketStream::Tls(Box::new(stream)))
+        }
+    }
+}
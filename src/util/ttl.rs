@@ -0,0 +1,76 @@
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+/// A cached response and when it was fetched
+#[derive(Debug)]
+pub struct Expiring<T> {
+    value: T,
+    fetched: Instant,
+}
+
+impl<T> Expiring<T> {
+    pub fn new(value: T) -> Self {
+        Expiring {
+            value,
+            fetched: Instant::now(),
+        }
+    }
+
+    fn is_stale(&self, ttl: Duration) -> bool {
+        self.fetched.elapsed() >= ttl
+    }
+}
+
+impl<T> Deref for Expiring<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+/// Cache slot for an API response that goes stale on its own, e.g. status that the
+/// miner changes without us writing anything
+#[derive(Debug)]
+pub struct TtlCell<T> {
+    ttl: Duration,
+    slot: Mutex<Option<Expiring<T>>>,
+}
+
+impl<T> TtlCell<T> {
+    pub fn new(ttl: Duration) -> Self {
+        TtlCell {
+            ttl,
+            slot: Mutex::new(None),
+        }
+    }
+
+    /// Lock the slot, emptying it first if the value has outlived the TTL
+    /// Callers fill an empty slot with Expiring::new
+    pub async fn lock(&self) -> MutexGuard<'_, Option<Expiring<T>>> {
+        let mut slot = self.slot.lock().await;
+        if slot.as_ref().is_some_and(|v| v.is_stale(self.ttl)) {
+            *slot = None;
+        }
+        slot
+    }
+
+    pub async fn invalidate(&self) {
+        *self.slot.lock().await = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale() {
+        let v = Expiring::new(1);
+        assert_eq!(*v, 1);
+        assert!(!v.is_stale(Duration::from_secs(60)));
+        assert!(v.is_stale(Duration::ZERO));
+    }
+}
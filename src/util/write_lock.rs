@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// One lock per miner address, shared by every clone of a Client
+/// Held across a read-modify-write of a miner's settings, so two handles to the same miner
+/// can't each write back a conf that's missing the other's change
+/// Entries are never removed, there's one per address written to
+#[derive(Debug, Clone, Default)]
+pub struct WriteLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+impl WriteLocks {
+    pub async fn lock(&self, ip: &str) -> OwnedMutexGuard<()> {
+        let lock = self.locks.lock().unwrap_or_else(|e| e.into_inner())
+            .entry(ip.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_locks() {
+        let locks = WriteLocks::default();
+        let shared = locks.clone();
+        let held = futures::executor::block_on(locks.lock("10.0.0.1"));
+        let same = shared.locks.lock().unwrap()["10.0.0.1"].clone();
+        assert!(same.try_lock().is_err());
+        let other = futures::executor::block_on(shared.lock("10.0.0.2"));
+        drop(held);
+        assert!(same.try_lock().is_ok());
+        drop(other);
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::Error;
+use crate::fleet::snapshots;
+use crate::miner::{Miner, PowerState, SharedMiner, Tags};
+use crate::snapshot::MinerSnapshot;
+
+/// Something worth telling an operator about, derived by comparing consecutive snapshots
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event")]
+pub enum MinerEvent {
+    Unreachable { ip: String, error: String, tags: Tags },
+    Recovered { ip: String, tags: Tags },
+    StateChanged { ip: String, from: PowerState, to: PowerState, tags: Tags },
+    Overheating { ip: String, temperature: f64, tags: Tags },
+    /// TH/s before and after
+    HashrateDropped { ip: String, from: f64, to: f64, tags: Tags },
+    /// Hashing below its ideal rate, as Miner::get_performance_ratio
+    Underperforming { ip: String, ratio: f64, tags: Tags },
+}
+
+impl MinerEvent {
+    pub fn ip(&self) -> &str {
+        match self {
+            MinerEvent::Unreachable { ip, .. } |
+            MinerEvent::Recovered { ip, .. } |
+            MinerEvent::StateChanged { ip, .. } |
+            MinerEvent::Overheating { ip, .. } |
+            MinerEvent::HashrateDropped { ip, .. } |
+            MinerEvent::Underperforming { ip, .. } => ip,
+        }
+    }
+
+    /// The miner's tags from the snapshot that raised the event
+    pub fn tags(&self) -> &Tags {
+        match self {
+            MinerEvent::Unreachable { tags, .. } |
+            MinerEvent::Recovered { tags, .. } |
+            MinerEvent::StateChanged { tags, .. } |
+            MinerEvent::Overheating { tags, .. } |
+            MinerEvent::HashrateDropped { tags, .. } |
+            MinerEvent::Underperforming { tags, .. } => tags,
+        }
+    }
+}
+
+impl fmt::Display for MinerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinerEvent::Unreachable { ip, error, .. } => write!(f, "{} is unreachable: {}", ip, error),
+            MinerEvent::Recovered { ip, .. } => write!(f, "{} is reachable again", ip),
+            MinerEvent::StateChanged { ip, from, to, .. } => write!(f, "{} changed from {:?} to {:?}", ip, from, to),
+            MinerEvent::Overheating { ip, temperature, .. } => write!(f, "{} is overheating at {:.1}C", ip, temperature),
+            MinerEvent::HashrateDropped { ip, from, to, .. } => write!(f, "{} hashrate dropped from {:.2} to {:.2} TH/s", ip, from, to),
+            MinerEvent::Underperforming { ip, ratio, .. } => write!(f, "{} is hashing at {:.0}% of its ideal rate", ip, ratio * 100.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthThresholds {
+    /// Celsius
+    pub max_temperature: f64,
+    /// Report a drop when hashrate falls below this fraction of the previous reading
+    pub min_hashrate_ratio: f64,
+    /// Report a miner hashing below this fraction of its ideal rate
+    pub min_performance_ratio: f64,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            max_temperature: 85.0,
+            min_hashrate_ratio: 0.8,
+            min_performance_ratio: 0.9,
+        }
+    }
+}
+
+/// Events between two snapshots of the same miner, prev is None the first time a miner is seen
+pub fn events(prev: Option<&MinerSnapshot>, next: &MinerSnapshot, thresholds: &HealthThresholds) -> Vec<MinerEvent> {
+    let ip = next.ip.clone();
+    let tags = next.tags.clone();
+    let mut events = vec![];
+
+    match (prev.map(|p| p.reachable()), next.reachable()) {
+        (Some(false), false) => return events,
+        (_, false) => {
+            events.push(MinerEvent::Unreachable { ip, error: next.error.clone().unwrap_or_default(), tags });
+            return events;
+        },
+        (Some(false), true) => events.push(MinerEvent::Recovered { ip: ip.clone(), tags: tags.clone() }),
+        _ => {},
+    }
+
+    // Only raise these on the edge, not every poll while they hold
+    let was_hot = prev.and_then(|p| p.temperature).is_some_and(|t| t > thresholds.max_temperature);
+    if let Some(temperature) = next.temperature {
+        if temperature > thresholds.max_temperature && !was_hot {
+            events.push(MinerEvent::Overheating { ip: ip.clone(), temperature, tags: tags.clone() });
+        }
+    }
+
+    // Ratios are meaningless while chains come up or the miner sleeps
+    let mining = next.power_state == Some(PowerState::Mining);
+    let was_slow = prev.and_then(|p| p.performance_ratio).is_some_and(|r| r < thresholds.min_performance_ratio);
+    if let (Some(ratio), true) = (next.performance_ratio, mining) {
+        if ratio < thresholds.min_performance_ratio && !was_slow {
+            events.push(MinerEvent::Underperforming { ip: ip.clone(), ratio, tags: tags.clone() });
+        }
+    }
+
+    if let Some(prev) = prev.filter(|p| p.reachable()) {
+        if let (Some(from), Some(to)) = (prev.power_state, next.power_state) {
+            if from != to {
+                events.push(MinerEvent::StateChanged { ip: ip.clone(), from, to, tags: tags.clone() });
+            }
+        }
+        // A miner that was told to sleep is supposed to stop hashing
+        let sleeping = next.power_state.is_some_and(|s| s != PowerState::Mining);
+        if let (Some(from), Some(to), false) = (prev.hashrate, next.hashrate, sleeping) {
+            if from > 0.0 && to < from * thresholds.min_hashrate_ratio {
+                events.push(MinerEvent::HashrateDropped { ip, from, to, tags });
+            }
+        }
+    }
+    events
+}
+
+/// Somewhere to deliver events, e.g. notify::Notifier
+#[async_trait]
+pub trait EventSink {
+    async fn send(&self, events: &[MinerEvent]) -> Result<(), Error>;
+}
+
+/// Keeps the last snapshot of each miner and turns each poll into events
+/// The caller decides how often to call poll
+pub struct Watcher {
+    thresholds: HealthThresholds,
+    last: HashMap<String, MinerSnapshot>,
+    sinks: Vec<Arc<dyn EventSink + Send + Sync>>,
+}
+
+impl Watcher {
+    pub fn new(thresholds: HealthThresholds) -> Watcher {
+        Watcher {
+            thresholds,
+            last: HashMap::new(),
+            sinks: vec![],
+        }
+    }
+
+    /// Deliver every future batch of events to this sink
+    pub fn subscribe(&mut self, sink: Arc<dyn EventSink + Send + Sync>) {
+        self.sinks.push(sink);
+    }
+
+    /// Snapshot the miners, send any events to the subscribers and return them
+    /// Miners left out of the list are forgotten, one that comes back counts as newly seen
+    /// A sink failing is logged, it doesn't stop the others
+    pub async fn poll(&mut self, miners: &[SharedMiner]) -> Vec<MinerEvent> {
+        let polled: HashSet<&str> = miners.iter().map(|m| m.get_ip()).collect();
+        self.last.retain(|ip, _| polled.contains(ip.as_str()));
+        let mut all = vec![];
+        for snap in snapshots(miners).await {
+            all.extend(events(self.last.get(&snap.ip), &snap, &self.thresholds));
+            self.last.insert(snap.ip.clone(), snap);
+        }
+        if !all.is_empty() {
+            for sink in &self.sinks {
+                if let Err(e) = sink.send(&all).await {
+                    warn!("Failed to deliver {} events: {}", all.len(), e);
+                }
+            }
+        }
+        all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(state: PowerState, hashrate: f64, temperature: f64) -> MinerSnapshot {
+        MinerSnapshot {
+            model: None,
+            power_state: Some(state),
+            hashrate: Some(hashrate),
+            power: None,
+            efficiency: None,
+            temperature: Some(temperature),
+            ..MinerSnapshot::test()
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let t = HealthThresholds::default();
+        let ok = snapshot(PowerState::Mining, 100.0, 70.0);
+        assert!(events(None, &ok, &t).is_empty());
+        assert!(events(Some(&ok), &ok, &t).is_empty());
+
+        let hot = snapshot(PowerState::Mining, 100.0, 90.0);
+        assert_eq!(events(Some(&ok), &hot, &t), vec![MinerEvent::Overheating { ip: "10.0.0.1".into(), temperature: 90.0, tags: Tags::new() }]);
+        assert!(events(Some(&hot), &hot, &t).is_empty());
+
+        let slow = snapshot(PowerState::Mining, 50.0, 70.0);
+        assert_eq!(events(Some(&ok), &slow, &t), vec![MinerEvent::HashrateDropped { ip: "10.0.0.1".into(), from: 100.0, to: 50.0, tags: Tags::new() }]);
+
+        let mut derated = ok.clone();
+        derated.performance_ratio = Some(0.75);
+        assert_eq!(events(Some(&ok), &derated, &t), vec![MinerEvent::Underperforming { ip: "10.0.0.1".into(), ratio: 0.75, tags: Tags::new() }]);
+        assert!(events(Some(&derated), &derated, &t).is_empty());
+
+        let asleep = snapshot(PowerState::Sleeping, 0.0, 30.0);
+        assert_eq!(events(Some(&ok), &asleep, &t), vec![MinerEvent::StateChanged { ip: "10.0.0.1".into(), from: PowerState::Mining, to: PowerState::Sleeping, tags: Tags::new() }]);
+
+        let mut down = ok.clone();
+        down.error = Some("Timeout".into());
+        assert_eq!(events(Some(&ok), &down, &t), vec![MinerEvent::Unreachable { ip: "10.0.0.1".into(), error: "Timeout".into(), tags: Tags::new() }]);
+        assert!(events(Some(&down), &down, &t).is_empty());
+        assert_eq!(events(Some(&down), &ok, &t), vec![MinerEvent::Recovered { ip: "10.0.0.1".into(), tags: Tags::new() }]);
+    }
+
+    #[tokio::test]
+    async fn test_forget_removed() {
+        let mut watcher = Watcher::new(HealthThresholds::default());
+        watcher.last.insert("10.0.0.1".into(), snapshot(PowerState::Mining, 100.0, 70.0));
+        assert!(watcher.poll(&[]).await.is_empty());
+        assert!(watcher.last.is_empty());
+    }
+}